@@ -1,4 +1,8 @@
 fn main() {
+    // The linker scripts only exist for the ESP32-S3, host builds (tests) link normally.
+    if std::env::var("CARGO_CFG_TARGET_ARCH").as_deref() != Ok("xtensa") {
+        return;
+    }
     linker_be_nice();
     // make sure linkall.x is the last linker script (otherwise might cause problems with flip-link)
     println!("cargo:rustc-link-arg=-Tlinkall.x");
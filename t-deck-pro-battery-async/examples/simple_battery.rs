@@ -75,9 +75,12 @@ async fn read_battery_task(
             Ok(data) => {
                 info!("--- Battery Status ---");
                 info!("Voltage: {:.3} V", data.voltage);
+                info!("System Voltage: {:.3} V", data.system_voltage);
                 info!("VBUS Voltage: {:.3} V", data.vbus_voltage);
                 info!("Charge Current: {:.3} A", data.charge_current);
                 info!("Battery Temp Percent: {:.1}%", data.battery_temp_percent);
+                info!("Battery Temp: {:?} C", data.battery_temp_celsius);
+                info!("Input DPM: {:?}", data.input_dpm);
                 info!("Charge Status: {:?}", data.charging_status);
                 info!("VBUS Status: {:?}", data.vbus_status);
                 info!("Power Good: {}", data.power_good);
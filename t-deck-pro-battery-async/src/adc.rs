@@ -0,0 +1,131 @@
+//! ADC conversion settings and decoding helpers for the BQ25896.
+
+/// How the BQ25896 ADC converts its channels (REG02 CONV_RATE).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdcMode {
+    /// A single conversion is started for every `measure` call. Lowest power.
+    OneShot,
+    /// The ADC converts continuously, once per second.
+    Continuous,
+}
+
+/// Describes the thermistor network on the TS pin, used to convert the TS voltage
+/// (a percentage of REGN) to a temperature.
+///
+/// The network is REGN - `pullup` - TS - (`parallel` || NTC) - GND.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NtcConfig {
+    /// The resistor between REGN and TS (RT1) in Ohms.
+    pub pullup_ohm: f32,
+    /// The optional resistor in parallel with the NTC (RT2) in Ohms.
+    pub parallel_ohm: Option<f32>,
+    /// The NTC resistance at 25 °C in Ohms.
+    pub r25_ohm: f32,
+    /// The NTC beta coefficient in Kelvin.
+    pub beta: f32,
+}
+
+impl Default for NtcConfig {
+    /// The reference network from the BQ25896 datasheet: RT1 = 5.23 kΩ,
+    /// RT2 = 30.1 kΩ and a 103AT (10 kΩ, B = 3435 K) thermistor.
+    fn default() -> Self {
+        Self {
+            pullup_ohm: 5_230.0,
+            parallel_ohm: Some(30_100.0),
+            r25_ohm: 10_000.0,
+            beta: 3_435.0,
+        }
+    }
+}
+
+impl NtcConfig {
+    /// Converts a TS reading (percent of REGN) to a temperature in °C.
+    ///
+    /// Returns `None` if the reading does not correspond to a valid NTC resistance,
+    /// e.g. when no thermistor is connected.
+    pub fn temperature(&self, ts_percent: f32) -> Option<f32> {
+        const KELVIN_25C: f32 = 298.15;
+        const KELVIN_0C: f32 = 273.15;
+
+        let ratio = ts_percent / 100.0;
+        if ratio <= 0.0 || ratio >= 1.0 {
+            return None;
+        }
+        let lower = self.pullup_ohm * ratio / (1.0 - ratio);
+        let ntc = match self.parallel_ohm {
+            Some(parallel) => {
+                let conductance = 1.0 / lower - 1.0 / parallel;
+                if conductance <= 0.0 {
+                    return None;
+                }
+                1.0 / conductance
+            }
+            None => lower,
+        };
+        let inverse_kelvin = 1.0 / KELVIN_25C + libm::logf(ntc / self.r25_ohm) / self.beta;
+        Some(1.0 / inverse_kelvin - KELVIN_0C)
+    }
+}
+
+/// The input voltage/current dynamic power management status (REG13, REG14).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputDpmStatus {
+    /// The input is limited by VINDPM (input voltage regulation).
+    pub voltage_dpm: bool,
+    /// The input is limited by IINDPM (input current regulation).
+    pub current_dpm: bool,
+    /// The effective input current limit in Amperes.
+    pub input_current_limit: f32,
+    /// The input current optimizer (ICO) has found the maximum input current.
+    pub ico_optimized: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Option<f32>, expected: f32, tolerance: f32) {
+        let actual = actual.unwrap();
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} != {expected}"
+        );
+    }
+
+    #[test]
+    fn reference_network_matches_jeita_thresholds() {
+        // The datasheet's TS thresholds (percent of REGN) for the reference network.
+        let ntc = NtcConfig::default();
+        assert_close(ntc.temperature(73.5), 0.0, 1.0);
+        assert_close(ntc.temperature(68.4), 10.0, 1.0);
+        assert_close(ntc.temperature(44.8), 45.0, 1.0);
+        assert_close(ntc.temperature(34.375), 60.0, 1.0);
+    }
+
+    #[test]
+    fn beta_model_without_parallel_resistor() {
+        let ntc = NtcConfig {
+            pullup_ohm: 10_000.0,
+            parallel_ohm: None,
+            r25_ohm: 10_000.0,
+            beta: 3_435.0,
+        };
+        // R25 in the lower half of the divider.
+        assert_close(ntc.temperature(50.0), 25.0, 0.01);
+        // R(T) = R25 * exp(B * (1/T - 1/T25)), 0 °C: 28.7 kΩ, 85 °C: 1.45 kΩ.
+        assert_close(ntc.temperature(100.0 * 28.7 / 38.7), 0.0, 0.1);
+        assert_close(ntc.temperature(100.0 * 1.451 / 11.451), 85.0, 0.1);
+        // Colder reads higher.
+        assert!(ntc.temperature(60.0).unwrap() < ntc.temperature(40.0).unwrap());
+    }
+
+    #[test]
+    fn invalid_readings_have_no_temperature() {
+        let ntc = NtcConfig::default();
+        assert_eq!(ntc.temperature(0.0), None);
+        assert_eq!(ntc.temperature(100.0), None);
+        assert_eq!(ntc.temperature(-5.0), None);
+        // An open thermistor leaves more than the parallel resistor can explain.
+        assert_eq!(ntc.temperature(99.0), None);
+    }
+}
@@ -0,0 +1,424 @@
+//! Watchdog handling and host-mode control of the BQ25896.
+//!
+//! After power-up the BQ25896 runs with a 40 s I2C watchdog. Any register written by
+//! the host only sticks while the watchdog is kicked regularly; once it expires the
+//! charger drops back to default mode and reverts the control registers. This module
+//! lets the application either disable the watchdog or kick it from its own loop, and
+//! exposes the HIZ, charge-enable, OTG (boost) and BATFET-disable bits, as well as
+//! ship mode. The charger cannot power-cycle the system by itself: both ship mode variants
+//! leave a battery-powered device off until the QON button is pressed or an input
+//! source is plugged in.
+
+use embassy_time::Duration;
+use embedded_hal_async::i2c::{I2c, SevenBitAddress};
+use log::{info, warn};
+
+use crate::{
+    BatteryService, VbusStatus, BATFET_DIS_MASK, BATFET_DLY_MASK, BATFET_RST_EN_MASK,
+    CHG_CONFIG_MASK, EN_HIZ_MASK, INPUT_SRC_REG, MISC_CTRL_REG, OTG_CONFIG_MASK, SYS_CTRL_REG,
+    TIMER_CTRL_REG, WATCHDOG_MASK, WATCHDOG_SHIFT, WD_RST_MASK,
+};
+
+/// The I2C watchdog timer setting (REG07 WATCHDOG).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogTimer {
+    /// The watchdog is disabled; host-mode settings persist until changed or reset.
+    Disabled,
+    /// 40 second timeout (power-on default).
+    Seconds40,
+    /// 80 second timeout.
+    Seconds80,
+    /// 160 second timeout.
+    Seconds160,
+}
+
+impl WatchdogTimer {
+    /// Returns the timeout period, or `None` if the watchdog is disabled.
+    pub fn period(&self) -> Option<Duration> {
+        match self {
+            WatchdogTimer::Disabled => None,
+            WatchdogTimer::Seconds40 => Some(Duration::from_secs(40)),
+            WatchdogTimer::Seconds80 => Some(Duration::from_secs(80)),
+            WatchdogTimer::Seconds160 => Some(Duration::from_secs(160)),
+        }
+    }
+
+    /// Returns how often the watchdog has to be kicked, half its period, or `None` if
+    /// it is disabled.
+    pub fn kick_interval(&self) -> Option<Duration> {
+        self.period().map(|period| period / 2)
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => WatchdogTimer::Disabled,
+            0b01 => WatchdogTimer::Seconds40,
+            0b10 => WatchdogTimer::Seconds80,
+            _ => WatchdogTimer::Seconds160,
+        }
+    }
+
+    fn bits(&self) -> u8 {
+        match self {
+            WatchdogTimer::Disabled => 0b00,
+            WatchdogTimer::Seconds40 => 0b01,
+            WatchdogTimer::Seconds80 => 0b10,
+            WatchdogTimer::Seconds160 => 0b11,
+        }
+    }
+}
+
+/// When the BATFET is turned off after requesting ship mode (REG09 BATFET_DLY).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShipModeDelay {
+    /// Turn the BATFET off immediately.
+    Immediate,
+    /// Turn the BATFET off after tSM_DLY (10 to 15 s), giving the host time to shut down.
+    Delayed,
+}
+
+impl<I2cType, ErrorType> BatteryService<I2cType, ErrorType>
+where
+    I2cType: I2c<SevenBitAddress, Error = ErrorType>,
+    ErrorType: embedded_hal_async::i2c::Error,
+{
+    /// Returns the watchdog setting last applied or read by this service.
+    ///
+    /// The charger reverts to the 40 s default when the watchdog expires, so use
+    /// `read_watchdog` to get the setting actually in effect.
+    pub fn watchdog(&self) -> WatchdogTimer {
+        self.watchdog
+    }
+
+    /// Reads the watchdog setting from REG07 and returns it.
+    ///
+    /// This also updates the value returned by `watchdog`, e.g. after the watchdog
+    /// expired or another driver on the bus changed the setting.
+    pub async fn read_watchdog(&mut self) -> Result<WatchdogTimer, ()> {
+        let reg07 = self.read_reg(TIMER_CTRL_REG).await?;
+        self.watchdog = WatchdogTimer::from_bits((reg07 & WATCHDOG_MASK) >> WATCHDOG_SHIFT);
+        Ok(self.watchdog)
+    }
+
+    /// Configures the I2C watchdog timer.
+    ///
+    /// Use `WatchdogTimer::Disabled` to keep host-mode settings without kicking the
+    /// watchdog, or one of the timeouts and call `kick_watchdog` regularly.
+    pub async fn set_watchdog(&mut self, timer: WatchdogTimer) -> Result<(), ()> {
+        // Kick first, so the watchdog cannot expire between the read and the write.
+        self.kick_watchdog().await?;
+        self.update_reg(
+            TIMER_CTRL_REG,
+            WATCHDOG_MASK,
+            timer.bits() << WATCHDOG_SHIFT,
+        )
+        .await?;
+        self.watchdog = timer;
+        info!("Charger watchdog set to {timer:?}");
+        Ok(())
+    }
+
+    /// Resets the I2C watchdog timer (REG03 WD_RST).
+    ///
+    /// To keep the charger in host mode, call this from the application's own loop
+    /// at least every `WatchdogTimer::kick_interval` of the setting in effect.
+    pub async fn kick_watchdog(&mut self) -> Result<(), ()> {
+        self.update_reg(SYS_CTRL_REG, WD_RST_MASK, WD_RST_MASK)
+            .await
+    }
+
+    /// Enables or disables high-impedance mode (REG00 EN_HIZ).
+    ///
+    /// In HIZ mode the input is disconnected and the system runs from the battery,
+    /// even if an adapter is plugged in.
+    pub async fn set_hiz(&mut self, enabled: bool) -> Result<(), ()> {
+        self.kick_watchdog().await?;
+        self.update_reg(INPUT_SRC_REG, EN_HIZ_MASK, mask_if(enabled, EN_HIZ_MASK))
+            .await
+    }
+
+    /// Enables or disables battery charging (REG03 CHG_CONFIG).
+    ///
+    /// Enabling charging turns off the OTG boost converter first, since the two are
+    /// mutually exclusive.
+    pub async fn set_charge_enabled(&mut self, enabled: bool) -> Result<(), ()> {
+        self.kick_watchdog().await?;
+        if enabled {
+            self.update_reg(SYS_CTRL_REG, OTG_CONFIG_MASK, 0).await?;
+        }
+        self.update_reg(
+            SYS_CTRL_REG,
+            CHG_CONFIG_MASK,
+            mask_if(enabled, CHG_CONFIG_MASK),
+        )
+        .await
+    }
+
+    /// Enables or disables the OTG boost converter (REG03 OTG_CONFIG).
+    ///
+    /// Enabling boost is refused while an input source is present. Charging is
+    /// disabled before the boost converter is turned on.
+    pub async fn set_otg_enabled(&mut self, enabled: bool) -> Result<(), ()> {
+        self.kick_watchdog().await?;
+        if !enabled {
+            return self.update_reg(SYS_CTRL_REG, OTG_CONFIG_MASK, 0).await;
+        }
+
+        let status = self.read_status().await?;
+        if !matches!(status.vbus_status, VbusStatus::NoInput | VbusStatus::Otg) {
+            warn!(
+                "Refusing to enable OTG while input is present: {:?}",
+                status.vbus_status
+            );
+            return Err(());
+        }
+        self.update_reg(SYS_CTRL_REG, CHG_CONFIG_MASK, 0).await?;
+        self.update_reg(SYS_CTRL_REG, OTG_CONFIG_MASK, OTG_CONFIG_MASK)
+            .await
+    }
+
+    /// Sets the BATFET disable bit (REG09 BATFET_DIS).
+    ///
+    /// Disconnecting the BATFET cuts the battery off from the system. Without an
+    /// input source this powers the device off (ship mode).
+    pub async fn set_batfet_disabled(&mut self, disabled: bool) -> Result<(), ()> {
+        self.kick_watchdog().await?;
+        if disabled && self.read_watchdog().await? != WatchdogTimer::Disabled {
+            // A watchdog expiry would otherwise silently reconnect the battery.
+            self.set_watchdog(WatchdogTimer::Disabled).await?;
+        }
+        self.update_reg(
+            MISC_CTRL_REG,
+            BATFET_DIS_MASK,
+            mask_if(disabled, BATFET_DIS_MASK),
+        )
+        .await
+    }
+
+    /// Configures whether `enter_ship_mode` and `ship_mode_with_qon_reset` are refused
+    /// while an input source is present. Enabled by default.
+    ///
+    /// With VBUS present the system keeps running from the input even with the BATFET
+    /// off, so the request would only take effect once the cable is unplugged.
+    pub fn set_refuse_power_off_with_vbus(&mut self, refuse: bool) {
+        self.refuse_power_off_with_vbus = refuse;
+    }
+
+    /// Puts the charger into ship mode, disconnecting the battery from the system.
+    ///
+    /// The battery drain drops to a few µA. The device stays off until the QON button
+    /// is pressed or an input source is plugged in.
+    pub async fn enter_ship_mode(&mut self, delay: ShipModeDelay) -> Result<(), ()> {
+        self.check_power_off_allowed().await?;
+        let dly = match delay {
+            ShipModeDelay::Immediate => 0,
+            ShipModeDelay::Delayed => BATFET_DLY_MASK,
+        };
+        info!("Entering ship mode ({delay:?})");
+        self.disconnect_batfet(BATFET_DLY_MASK, dly).await
+    }
+
+    /// Enters ship mode immediately, with the QON system reset armed.
+    ///
+    /// This does not power-cycle the system: on battery the device stays off until
+    /// QON is pressed or an input source is plugged in. BATFET_RST_EN is set in the
+    /// same write, so holding QON for tQON_RST (about 15 s) later performs a full
+    /// BATFET reset of the system.
+    pub async fn ship_mode_with_qon_reset(&mut self) -> Result<(), ()> {
+        self.check_power_off_allowed().await?;
+        info!("Entering ship mode with the QON reset enabled");
+        self.disconnect_batfet(BATFET_DLY_MASK | BATFET_RST_EN_MASK, BATFET_RST_EN_MASK)
+            .await
+    }
+
+    /// Refuses to power off while an input source is present, if configured to.
+    async fn check_power_off_allowed(&mut self) -> Result<(), ()> {
+        if !self.refuse_power_off_with_vbus {
+            return Ok(());
+        }
+        let status = self.read_status().await?;
+        if !matches!(status.vbus_status, VbusStatus::NoInput | VbusStatus::Otg) {
+            warn!(
+                "Refusing to power off while input is present: {:?}",
+                status.vbus_status
+            );
+            return Err(());
+        }
+        Ok(())
+    }
+
+    /// Disables the watchdog and sets BATFET_DIS together with the REG09 bits selected
+    /// by `mask` in a single write.
+    async fn disconnect_batfet(&mut self, mask: u8, bits: u8) -> Result<(), ()> {
+        if self.read_watchdog().await? != WatchdogTimer::Disabled {
+            self.set_watchdog(WatchdogTimer::Disabled).await?;
+        }
+        self.update_reg(
+            MISC_CTRL_REG,
+            BATFET_DIS_MASK | mask,
+            BATFET_DIS_MASK | bits,
+        )
+        .await
+    }
+}
+
+fn mask_if(condition: bool, mask: u8) -> u8 {
+    if condition {
+        mask
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::MockI2c;
+    use crate::SYS_STATUS_REG;
+
+    /// REG07 with the default 40 s watchdog.
+    const TIMER_CTRL_DEFAULT: u8 = 0b1001_1101;
+
+    fn service(vbus_status: u8) -> BatteryService<MockI2c, embedded_hal_async::i2c::ErrorKind> {
+        let mut i2c = MockI2c::default();
+        i2c.regs[TIMER_CTRL_REG as usize] = TIMER_CTRL_DEFAULT;
+        i2c.regs[SYS_STATUS_REG as usize] = vbus_status << 5;
+        BatteryService::new(i2c)
+    }
+
+    /// Checks that the watchdog was disabled before REG09 was written and returns the
+    /// final REG09 value.
+    fn batfet_write(i2c: &MockI2c) -> u8 {
+        let watchdog = i2c.first_write(TIMER_CTRL_REG).expect("REG07 not written");
+        let misc = i2c.first_write(MISC_CTRL_REG).expect("REG09 not written");
+        assert!(watchdog < misc, "watchdog disabled after the BATFET write");
+        assert_eq!(i2c.regs[TIMER_CTRL_REG as usize] & WATCHDOG_MASK, 0);
+        let writes = i2c.writes_to(MISC_CTRL_REG);
+        assert_eq!(writes.len(), 1, "REG09 must be written once");
+        writes[0]
+    }
+
+    #[test]
+    fn ship_mode_delayed() {
+        let mut service = service(0b000);
+        block_on(service.enter_ship_mode(ShipModeDelay::Delayed)).unwrap();
+        let reg09 = batfet_write(&service.i2c);
+        assert_eq!(reg09 & BATFET_DIS_MASK, BATFET_DIS_MASK);
+        assert_eq!(reg09 & BATFET_DLY_MASK, BATFET_DLY_MASK);
+        assert_eq!(service.watchdog(), WatchdogTimer::Disabled);
+    }
+
+    #[test]
+    fn ship_mode_immediate() {
+        let mut service = service(0b000);
+        service.i2c.regs[MISC_CTRL_REG as usize] = BATFET_DLY_MASK;
+        block_on(service.enter_ship_mode(ShipModeDelay::Immediate)).unwrap();
+        let reg09 = batfet_write(&service.i2c);
+        assert_eq!(reg09 & BATFET_DIS_MASK, BATFET_DIS_MASK);
+        assert_eq!(reg09 & BATFET_DLY_MASK, 0);
+    }
+
+    #[test]
+    fn ship_mode_with_qon_reset_sets_rst_en_without_delay() {
+        let mut service = service(0b000);
+        service.i2c.regs[MISC_CTRL_REG as usize] = BATFET_DLY_MASK;
+        block_on(service.ship_mode_with_qon_reset()).unwrap();
+        let reg09 = batfet_write(&service.i2c);
+        assert_eq!(reg09 & BATFET_DIS_MASK, BATFET_DIS_MASK);
+        assert_eq!(reg09 & BATFET_RST_EN_MASK, BATFET_RST_EN_MASK);
+        assert_eq!(reg09 & BATFET_DLY_MASK, 0);
+    }
+
+    #[test]
+    fn power_off_refused_with_vbus() {
+        // USB host and adapter inputs.
+        for vbus_status in [0b001, 0b010] {
+            let mut service = service(vbus_status);
+            assert_eq!(
+                block_on(service.enter_ship_mode(ShipModeDelay::Delayed)),
+                Err(())
+            );
+            assert_eq!(block_on(service.ship_mode_with_qon_reset()), Err(()));
+            assert!(service.i2c.writes.is_empty());
+        }
+    }
+
+    #[test]
+    fn power_off_with_vbus_when_allowed() {
+        let mut service = service(0b010);
+        service.set_refuse_power_off_with_vbus(false);
+        block_on(service.enter_ship_mode(ShipModeDelay::Immediate)).unwrap();
+        assert_eq!(
+            batfet_write(&service.i2c) & BATFET_DIS_MASK,
+            BATFET_DIS_MASK
+        );
+    }
+
+    #[test]
+    fn kick_interval_is_half_the_period() {
+        assert_eq!(WatchdogTimer::Disabled.kick_interval(), None);
+        assert_eq!(
+            WatchdogTimer::Seconds40.kick_interval(),
+            Some(Duration::from_secs(20))
+        );
+        assert_eq!(
+            WatchdogTimer::Seconds160.kick_interval(),
+            Some(Duration::from_secs(80))
+        );
+    }
+
+    #[test]
+    fn kick_sets_wd_rst() {
+        let mut service = service(0b000);
+        service.i2c.regs[SYS_CTRL_REG as usize] = 0b0001_1010;
+        block_on(service.kick_watchdog()).unwrap();
+        assert_eq!(service.i2c.writes_to(SYS_CTRL_REG), [0b0101_1010]);
+    }
+
+    #[test]
+    fn set_watchdog_kicks_first() {
+        let mut service = service(0b000);
+        block_on(service.set_watchdog(WatchdogTimer::Seconds160)).unwrap();
+        let kick = service.i2c.first_write(SYS_CTRL_REG).unwrap();
+        let timer = service.i2c.first_write(TIMER_CTRL_REG).unwrap();
+        assert!(kick < timer);
+        assert_eq!(service.i2c.writes_to(TIMER_CTRL_REG), [0b1011_1101]);
+        assert_eq!(service.watchdog(), WatchdogTimer::Seconds160);
+    }
+
+    #[test]
+    fn read_watchdog_follows_reg07() {
+        let mut service = service(0b000);
+        block_on(service.set_watchdog(WatchdogTimer::Disabled)).unwrap();
+        assert_eq!(service.watchdog(), WatchdogTimer::Disabled);
+
+        for (bits, timer) in [
+            (0b00, WatchdogTimer::Disabled),
+            (0b01, WatchdogTimer::Seconds40),
+            (0b10, WatchdogTimer::Seconds80),
+            (0b11, WatchdogTimer::Seconds160),
+        ] {
+            // E.g. changed by another driver or reverted by a watchdog expiry.
+            service.i2c.regs[TIMER_CTRL_REG as usize] = 0b1000_1101 | bits << 4;
+            assert_eq!(block_on(service.read_watchdog()), Ok(timer));
+            assert_eq!(service.watchdog(), timer);
+        }
+    }
+
+    #[test]
+    fn ship_mode_disables_reverted_watchdog() {
+        let mut service = service(0b000);
+        block_on(service.set_watchdog(WatchdogTimer::Disabled)).unwrap();
+        // The watchdog expired and REG07 is back at its default.
+        service.i2c.regs[TIMER_CTRL_REG as usize] = TIMER_CTRL_DEFAULT;
+        service.i2c.writes.clear();
+
+        block_on(service.enter_ship_mode(ShipModeDelay::Immediate)).unwrap();
+        assert_eq!(
+            batfet_write(&service.i2c) & BATFET_DIS_MASK,
+            BATFET_DIS_MASK
+        );
+    }
+}
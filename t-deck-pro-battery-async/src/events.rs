@@ -0,0 +1,255 @@
+//! Interrupt-driven charger event stream.
+//!
+//! The BQ25896 pulses its INT pin low for 256 µs whenever a status bit in REG0B
+//! changes or a new fault is latched in REG0C. `ChargerEvents` waits for these
+//! pulses, reads both registers and reports what changed since the previous pulse.
+//!
+//! `ChargerEvents` keeps its own state and only reads REG0B and REG0C, so it can run
+//! in a task of its own with a separate handle on a shared I2C bus (e.g. an
+//! `embassy-embedded-hal` `I2cDevice`), while a `BatteryService` on the same bus
+//! keeps measuring and configuring the charger.
+
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::{I2c, SevenBitAddress};
+use heapless::Vec;
+use log::error;
+
+use crate::{ChargeFault, ChargerStatus, ChargingStatus, NtcFault, VbusStatus};
+
+/// The maximum number of events reported for a single interrupt pulse.
+pub const MAX_EVENTS_PER_INTERRUPT: usize = 8;
+
+/// A typed change in the charger's status or fault registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChargerEvent {
+    /// An input power source was connected.
+    ChargerPlugged(VbusStatus),
+    /// The input power source was removed.
+    ChargerUnplugged,
+    /// Charging started or moved to a different phase.
+    ChargingStarted(ChargingStatus),
+    /// Charging has completed.
+    ChargeDone,
+    /// Charging stopped before completion (e.g. due to a fault or a removed source).
+    ChargingStopped,
+    /// The NTC thermistor reports a temperature zone change. `NtcFault::Normal` means
+    /// the temperature is back in the normal range.
+    NtcFault(NtcFault),
+    /// The I2C watchdog timer expired and the charger reverted to its default settings.
+    WatchdogFault,
+    /// A fault occurred while in boost (OTG) mode.
+    BoostFault,
+    /// A charging-related fault occurred.
+    ChargeFault(ChargeFault),
+    /// The battery voltage exceeded the over-voltage threshold.
+    BatteryOvpFault,
+}
+
+impl ChargerEvent {
+    /// Computes the events describing the transition from `previous` to `current`.
+    ///
+    /// Fault events are only reported when a fault appears, not while it persists.
+    pub fn diff(
+        previous: &ChargerStatus,
+        current: &ChargerStatus,
+    ) -> Vec<ChargerEvent, MAX_EVENTS_PER_INTERRUPT> {
+        let mut events = Vec::new();
+        // The number of distinct pushes below never exceeds the capacity.
+        let mut push = |event| {
+            let _ = events.push(event);
+        };
+
+        let was_plugged = is_plugged(previous.vbus_status);
+        let is_plugged = is_plugged(current.vbus_status);
+        if !was_plugged && is_plugged {
+            push(ChargerEvent::ChargerPlugged(current.vbus_status));
+        } else if was_plugged && !is_plugged {
+            push(ChargerEvent::ChargerUnplugged);
+        }
+
+        if previous.charging_status != current.charging_status {
+            match current.charging_status {
+                ChargingStatus::ChargeDone => push(ChargerEvent::ChargeDone),
+                ChargingStatus::NotCharging => {
+                    if previous.charging_status != ChargingStatus::ChargeDone {
+                        push(ChargerEvent::ChargingStopped);
+                    }
+                }
+                status => push(ChargerEvent::ChargingStarted(status)),
+            }
+        }
+
+        let (old, new) = (&previous.faults, &current.faults);
+        if old.ntc_fault != new.ntc_fault {
+            push(ChargerEvent::NtcFault(new.ntc_fault));
+        }
+        if !old.watchdog_fault && new.watchdog_fault {
+            push(ChargerEvent::WatchdogFault);
+        }
+        if !old.boost_fault && new.boost_fault {
+            push(ChargerEvent::BoostFault);
+        }
+        if old.charge_fault != new.charge_fault && new.charge_fault != ChargeFault::Normal {
+            push(ChargerEvent::ChargeFault(new.charge_fault));
+        }
+        if !old.battery_ovp_fault && new.battery_ovp_fault {
+            push(ChargerEvent::BatteryOvpFault);
+        }
+
+        events
+    }
+}
+
+fn is_plugged(status: VbusStatus) -> bool {
+    !matches!(status, VbusStatus::NoInput | VbusStatus::Otg)
+}
+
+/// A stream of `ChargerEvent`s driven by the charger's INT pin.
+pub struct ChargerEvents<I2cType, Int> {
+    i2c: I2cType,
+    int: Int,
+    previous: Option<ChargerStatus>,
+}
+
+impl<I2cType, Int> ChargerEvents<I2cType, Int>
+where
+    I2cType: I2c<SevenBitAddress>,
+    Int: Wait,
+{
+    /// Creates a stream of charger events.
+    ///
+    /// The initial state is read on the first call to `next`, so only changes after
+    /// that point are reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `i2c` - A handle to the I2C bus of the BQ25896, e.g. shared with a `BatteryService`.
+    /// * `int` - The input pin connected to the BQ25896 INT output (GPIO12 on the T-Deck).
+    pub fn new(i2c: I2cType, int: Int) -> Self {
+        Self {
+            i2c,
+            int,
+            previous: None,
+        }
+    }
+
+    /// Returns the status read on the most recent interrupt, if any.
+    pub fn last_status(&self) -> Option<ChargerStatus> {
+        self.previous
+    }
+
+    /// Waits for the next interrupt pulse that changes the charger state and returns
+    /// the resulting events.
+    ///
+    /// Pulses that do not produce any new event (e.g. repeated reports of a fault
+    /// that is still active) are swallowed. Changes whose pulse arrived while the
+    /// caller was still handling the previous events are reported without waiting.
+    pub async fn next(&mut self) -> Result<Vec<ChargerEvent, MAX_EVENTS_PER_INTERRUPT>, ()> {
+        let mut previous = match self.previous {
+            Some(previous) => previous,
+            None => {
+                let status = ChargerStatus::read(&mut self.i2c).await?;
+                self.previous = Some(status);
+                self.wait_for_interrupt().await?;
+                status
+            }
+        };
+
+        loop {
+            // The INT pulse is not latched, so read the registers before every wait.
+            let current = ChargerStatus::read(&mut self.i2c).await?;
+            let events = ChargerEvent::diff(&previous, &current);
+            self.previous = Some(current);
+            previous = current;
+
+            if !events.is_empty() {
+                return Ok(events);
+            }
+            self.wait_for_interrupt().await?;
+        }
+    }
+
+    async fn wait_for_interrupt(&mut self) -> Result<(), ()> {
+        self.int
+            .wait_for_falling_edge()
+            .await
+            .map_err(|e| error!("Failed waiting for charger interrupt: {e:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::{MockInt, SharedI2c};
+    use crate::{BatteryService, SYS_STATUS_REG};
+
+    const FAULT_REG: u8 = SYS_STATUS_REG + 1;
+    const ADAPTER: u8 = 0b010 << 5;
+    const FAST_CHARGE: u8 = 0b10 << 3;
+    const NTC_WARM: u8 = 0b010;
+
+    #[test]
+    fn change_during_handling_is_not_lost() {
+        let bus = SharedI2c::default();
+        let mut service = BatteryService::new(bus.clone());
+        // Plugged in: reported after the first pulse.
+        let int = MockInt {
+            pulses: 1,
+            changes: [(SYS_STATUS_REG, ADAPTER)].into(),
+            bus: bus.clone(),
+        };
+        let mut events = ChargerEvents::new(bus.clone(), int);
+        let first = block_on(events.next()).unwrap();
+        assert_eq!(
+            first.as_slice(),
+            &[ChargerEvent::ChargerPlugged(VbusStatus::Adapter)]
+        );
+
+        // Charging starts while the caller handles the first batch: its pulse is
+        // missed, there are no further pulses.
+        bus.set_reg(SYS_STATUS_REG, ADAPTER | FAST_CHARGE);
+        let second = block_on(events.next()).unwrap();
+        assert_eq!(
+            second.as_slice(),
+            &[ChargerEvent::ChargingStarted(ChargingStatus::FastCharge)]
+        );
+        assert_eq!(
+            events.last_status().map(|status| status.charging_status),
+            Some(ChargingStatus::FastCharge)
+        );
+
+        // The service keeps using the bus in the meantime.
+        let status = block_on(service.read_status()).unwrap();
+        assert_eq!(status.charging_status, ChargingStatus::FastCharge);
+
+        // Nothing changed and no pulse arrives.
+        assert_eq!(block_on(events.next()), Err(()));
+    }
+
+    #[test]
+    fn pulses_without_new_events_are_swallowed() {
+        let bus = SharedI2c::default();
+        let int = MockInt {
+            pulses: 3,
+            changes: [
+                (FAULT_REG, NTC_WARM),
+                // The fault is reported again while it persists.
+                (FAULT_REG, NTC_WARM),
+                (SYS_STATUS_REG, ADAPTER),
+            ]
+            .into(),
+            bus: bus.clone(),
+        };
+        let mut events = ChargerEvents::new(bus, int);
+        assert_eq!(
+            block_on(events.next()).unwrap().as_slice(),
+            &[ChargerEvent::NtcFault(NtcFault::Warm)]
+        );
+        assert_eq!(
+            block_on(events.next()).unwrap().as_slice(),
+            &[ChargerEvent::ChargerPlugged(VbusStatus::Adapter)]
+        );
+    }
+}
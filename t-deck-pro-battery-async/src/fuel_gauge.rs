@@ -0,0 +1,526 @@
+//! State-of-charge estimation on top of the raw `BatteryData` measurements.
+//!
+//! The BQ25896 has no fuel gauge of its own: it only reports the battery voltage and
+//! the charge current. `FuelGauge` combines a Li-ion open-circuit-voltage (OCV) curve,
+//! compensation for the voltage drop caused by the load, and integration of the charge
+//! current over time into a stable percentage plus time-to-full / time-to-empty
+//! estimates.
+//!
+//! The logic is pure: feed it `BatteryData` samples together with the `Instant` they
+//! were taken at, which makes it possible to replay recorded traces on the host.
+
+use embassy_time::{Duration, Instant};
+
+use crate::{BatteryData, ChargingStatus};
+
+/// A single point of an open-circuit-voltage curve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OcvPoint {
+    /// The resting cell voltage in Volts.
+    pub voltage: f32,
+    /// The state of charge at this voltage, in percent (0-100).
+    pub percent: f32,
+}
+
+impl OcvPoint {
+    /// Creates a new curve point.
+    pub const fn new(voltage: f32, percent: f32) -> Self {
+        Self { voltage, percent }
+    }
+}
+
+/// A piecewise-linear open-circuit-voltage curve.
+///
+/// Points must be sorted by ascending voltage.
+#[derive(Debug, Clone, Copy)]
+pub struct OcvCurve<'a> {
+    points: &'a [OcvPoint],
+}
+
+/// A typical OCV curve for a 3.7 V nominal / 4.2 V LiCoO2 or NMC cell.
+pub const LI_ION_OCV_CURVE: OcvCurve<'static> = OcvCurve::new(&[
+    OcvPoint::new(3.30, 0.0),
+    OcvPoint::new(3.50, 3.0),
+    OcvPoint::new(3.60, 7.0),
+    OcvPoint::new(3.68, 13.0),
+    OcvPoint::new(3.72, 22.0),
+    OcvPoint::new(3.75, 32.0),
+    OcvPoint::new(3.79, 45.0),
+    OcvPoint::new(3.83, 55.0),
+    OcvPoint::new(3.88, 65.0),
+    OcvPoint::new(3.94, 74.0),
+    OcvPoint::new(4.00, 82.0),
+    OcvPoint::new(4.08, 90.0),
+    OcvPoint::new(4.15, 96.0),
+    OcvPoint::new(4.20, 100.0),
+]);
+
+impl<'a> OcvCurve<'a> {
+    /// Creates a new curve from a list of points sorted by ascending voltage.
+    pub const fn new(points: &'a [OcvPoint]) -> Self {
+        Self { points }
+    }
+
+    /// Returns the state of charge in percent for the given resting voltage.
+    ///
+    /// Voltages outside of the curve are clamped to its first and last points.
+    pub fn percent(&self, voltage: f32) -> f32 {
+        let (first, last) = match (self.points.first(), self.points.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return 0.0,
+        };
+        if voltage <= first.voltage {
+            return first.percent;
+        }
+        if voltage >= last.voltage {
+            return last.percent;
+        }
+        for pair in self.points.windows(2) {
+            let (low, high) = (pair[0], pair[1]);
+            if voltage <= high.voltage {
+                let span = high.voltage - low.voltage;
+                if span <= 0.0 {
+                    return high.percent;
+                }
+                let t = (voltage - low.voltage) / span;
+                return low.percent + t * (high.percent - low.percent);
+            }
+        }
+        last.percent
+    }
+}
+
+/// Configuration of the `FuelGauge`.
+#[derive(Debug, Clone, Copy)]
+pub struct FuelGaugeConfig<'a> {
+    /// The OCV curve of the cell.
+    pub ocv_curve: OcvCurve<'a>,
+    /// The usable capacity of the cell in milliampere-hours.
+    pub capacity_mah: f32,
+    /// The internal resistance of the cell (plus wiring) in Ohms, used for load compensation.
+    pub internal_resistance_ohm: f32,
+    /// The estimated current drawn by the system when running from battery, in Amperes.
+    ///
+    /// The BQ25896 cannot measure discharge current, so this value is used both for
+    /// load compensation and to integrate the discharge over time.
+    pub system_load_current: f32,
+    /// How strongly each sample pulls the integrated estimate towards the voltage
+    /// based estimate (0.0 - 1.0).
+    pub voltage_weight: f32,
+    /// The reported percentage only moves against the charging direction once the
+    /// estimate differs from it by at least this many percent.
+    pub hysteresis_percent: f32,
+    /// Smoothing factor (0.0 - 1.0) for the rates used by the time estimates.
+    pub rate_smoothing: f32,
+}
+
+impl Default for FuelGaugeConfig<'_> {
+    fn default() -> Self {
+        Self {
+            ocv_curve: LI_ION_OCV_CURVE,
+            capacity_mah: 1400.0,
+            internal_resistance_ohm: 0.15,
+            system_load_current: 0.1,
+            voltage_weight: 0.05,
+            hysteresis_percent: 2.0,
+            rate_smoothing: 0.2,
+        }
+    }
+}
+
+/// The output of the `FuelGauge`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FuelGaugeReading {
+    /// The stable percentage to display (0-100).
+    pub percent: u8,
+    /// The unfiltered state-of-charge estimate in percent.
+    pub state_of_charge: f32,
+    /// Whether the battery is currently being charged.
+    pub charging: bool,
+    /// The estimated time until the battery is full, if charging.
+    pub time_to_full: Option<Duration>,
+    /// The estimated time until the battery is empty, if discharging.
+    pub time_to_empty: Option<Duration>,
+}
+
+/// Estimates the battery's state of charge from successive `BatteryData` samples.
+pub struct FuelGauge<'a> {
+    config: FuelGaugeConfig<'a>,
+    state_of_charge: Option<f32>,
+    reported: u8,
+    last_update: Option<Instant>,
+    charge_rate: f32,
+    discharge_rate: f32,
+}
+
+impl<'a> FuelGauge<'a> {
+    /// Creates a new `FuelGauge`.
+    ///
+    /// The first sample passed to `update` initializes the estimate from the OCV curve.
+    pub fn new(config: FuelGaugeConfig<'a>) -> Self {
+        Self {
+            config,
+            state_of_charge: None,
+            reported: 0,
+            last_update: None,
+            charge_rate: 0.0,
+            discharge_rate: 0.0,
+        }
+    }
+
+    /// Returns the configuration of the gauge.
+    pub fn config(&self) -> &FuelGaugeConfig<'a> {
+        &self.config
+    }
+
+    /// Forgets all accumulated state, e.g. after the battery was swapped.
+    pub fn reset(&mut self) {
+        self.state_of_charge = None;
+        self.reported = 0;
+        self.last_update = None;
+        self.charge_rate = 0.0;
+        self.discharge_rate = 0.0;
+    }
+
+    /// Returns the estimated open-circuit voltage for a sample.
+    ///
+    /// While charging the terminal voltage sits above the OCV by `I * R`, while
+    /// discharging it sags below it by the system load times `R`. When VBUS powers
+    /// the system without charging, the battery is unloaded.
+    pub fn compensated_voltage(&self, data: &BatteryData) -> f32 {
+        let r = self.config.internal_resistance_ohm;
+        if is_charging(data) {
+            data.voltage - data.charge_current * r
+        } else if is_externally_powered(data) {
+            data.voltage
+        } else {
+            data.voltage + self.config.system_load_current * r
+        }
+    }
+
+    /// Feeds a new sample taken at `now` into the gauge and returns the updated reading.
+    pub fn update(&mut self, data: &BatteryData, now: Instant) -> FuelGaugeReading {
+        let charging = is_charging(data);
+        let voltage_soc = self
+            .config
+            .ocv_curve
+            .percent(self.compensated_voltage(data));
+
+        let dt_secs = match self.last_update {
+            Some(last) if now > last => (now - last).as_millis() as f32 / 1000.0,
+            _ => 0.0,
+        };
+        self.last_update = Some(now);
+
+        let soc = match self.state_of_charge {
+            None => {
+                self.reported = clamp_percent(voltage_soc);
+                voltage_soc
+            }
+            Some(previous) => {
+                let capacity_as = self.config.capacity_mah * 3.6;
+                let current = if charging {
+                    data.charge_current
+                } else if is_externally_powered(data) {
+                    // VBUS powers the system, e.g. charge done, charging disabled or
+                    // suspended by JEITA: the battery is neither charged nor drained.
+                    0.0
+                } else {
+                    -self.config.system_load_current
+                };
+                let integrated = previous + current * dt_secs / capacity_as * 100.0;
+                let weight = self.config.voltage_weight.clamp(0.0, 1.0);
+                let blended = integrated + (voltage_soc - integrated) * weight;
+
+                if dt_secs > 0.0 {
+                    self.track_rates((blended - previous) / dt_secs);
+                }
+                blended
+            }
+        };
+
+        let soc = if data.charging_status == ChargingStatus::ChargeDone {
+            100.0
+        } else {
+            soc.clamp(0.0, 100.0)
+        };
+        self.state_of_charge = Some(soc);
+        self.apply_hysteresis(soc, charging);
+
+        FuelGaugeReading {
+            percent: self.reported,
+            state_of_charge: soc,
+            charging,
+            time_to_full: self.time_to_full(soc, charging, data),
+            time_to_empty: self.time_to_empty(soc, charging || is_externally_powered(data)),
+        }
+    }
+
+    /// Updates the smoothed charge/discharge rates (in percent per second).
+    fn track_rates(&mut self, rate: f32) {
+        let alpha = self.config.rate_smoothing.clamp(0.0, 1.0);
+        if rate > 0.0 {
+            self.charge_rate = smooth(self.charge_rate, rate, alpha);
+        } else if rate < 0.0 {
+            self.discharge_rate = smooth(self.discharge_rate, -rate, alpha);
+        }
+    }
+
+    /// Moves the reported percentage only when the change is consistent with the
+    /// charging direction, or large enough to overcome the hysteresis band.
+    fn apply_hysteresis(&mut self, soc: f32, charging: bool) {
+        let target = clamp_percent(soc);
+        let reported = self.reported as f32;
+        let moves_with_direction = if charging {
+            target > self.reported
+        } else {
+            target < self.reported
+        };
+        if moves_with_direction || (soc - reported).abs() >= self.config.hysteresis_percent {
+            self.reported = target;
+        }
+    }
+
+    fn time_to_full(&self, soc: f32, charging: bool, data: &BatteryData) -> Option<Duration> {
+        if !charging || soc >= 100.0 {
+            return None;
+        }
+        let remaining = 100.0 - soc;
+        let rate = if self.charge_rate > 0.0 {
+            self.charge_rate
+        } else if data.charge_current > 0.0 {
+            data.charge_current / (self.config.capacity_mah * 3.6) * 100.0
+        } else {
+            return None;
+        };
+        Some(Duration::from_secs((remaining / rate) as u64))
+    }
+
+    fn time_to_empty(&self, soc: f32, powered: bool) -> Option<Duration> {
+        if powered {
+            return None;
+        }
+        let rate = if self.discharge_rate > 0.0 {
+            self.discharge_rate
+        } else if self.config.system_load_current > 0.0 {
+            self.config.system_load_current / (self.config.capacity_mah * 3.6) * 100.0
+        } else {
+            return None;
+        };
+        Some(Duration::from_secs((soc / rate) as u64))
+    }
+}
+
+fn is_charging(data: &BatteryData) -> bool {
+    matches!(
+        data.charging_status,
+        ChargingStatus::PreCharge | ChargingStatus::FastCharge
+    )
+}
+
+/// Returns `true` if the system runs from VBUS instead of the battery.
+fn is_externally_powered(data: &BatteryData) -> bool {
+    data.vbus_good || data.power_good
+}
+
+/// Exponential moving average that starts from the first observed value.
+fn smooth(previous: f32, value: f32, alpha: f32) -> f32 {
+    if previous <= 0.0 {
+        value
+    } else {
+        previous + (value - previous) * alpha
+    }
+}
+
+fn clamp_percent(value: f32) -> u8 {
+    (value + 0.5).clamp(0.0, 100.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adc::NtcConfig;
+    use crate::testdata::{CHARGE, DISCHARGE};
+
+    /// How far the estimate may stray from the reference state of charge, in percent.
+    const SOC_TOLERANCE: f32 = 5.0;
+    /// How far the time to empty may stray from the actual one, relative to it. A
+    /// 20 mV step moves the estimate by several percent in the flat part of the
+    /// curve, and the discharge rate follows it for a while.
+    const TIME_TO_EMPTY_TOLERANCE: f32 = 0.5;
+    /// How far the time to empty may stray on average over a trace.
+    const MEAN_TIME_TO_EMPTY_TOLERANCE: f32 = 0.15;
+
+    fn sample(
+        voltage: f32,
+        charge_current: f32,
+        status: ChargingStatus,
+        vbus: bool,
+    ) -> BatteryData {
+        let mut data =
+            BatteryData::from_registers(&[0; crate::MEASURE_BURST_LEN], &NtcConfig::default());
+        data.voltage = voltage;
+        data.charge_current = charge_current;
+        data.charging_status = status;
+        data.vbus_good = vbus;
+        data.power_good = vbus;
+        data
+    }
+
+    fn at(secs: u64) -> Instant {
+        Instant::from_secs(secs)
+    }
+
+    #[test]
+    fn noisy_discharge_does_not_jitter() {
+        let mut gauge = FuelGauge::new(FuelGaugeConfig::default());
+        let mut last = gauge
+            .update(
+                &sample(3.815, 0.0, ChargingStatus::NotCharging, false),
+                at(0),
+            )
+            .percent;
+        for i in 1..600 {
+            let noise = if i % 2 == 0 { 0.01 } else { -0.01 };
+            let data = sample(3.815 + noise, 0.0, ChargingStatus::NotCharging, false);
+            let percent = gauge.update(&data, at(i)).percent;
+            assert!(
+                percent <= last,
+                "percent rose from {last} to {percent} at {i}s"
+            );
+            last = percent;
+        }
+    }
+
+    #[test]
+    fn time_to_full_while_charging() {
+        let mut gauge = FuelGauge::new(FuelGaugeConfig::default());
+        let reading = gauge.update(&sample(3.95, 1.0, ChargingStatus::FastCharge, true), at(0));
+        assert!(reading.charging);
+        assert_eq!(reading.time_to_empty, None);
+        // 1 A into 1400 mAh is 1/50.4 % per second.
+        let expected = (100.0 - reading.state_of_charge) * 50.4;
+        let secs = reading.time_to_full.unwrap().as_secs() as f32;
+        assert!((secs - expected).abs() <= 1.0, "{secs} != {expected}");
+
+        let later = gauge.update(&sample(3.95, 1.0, ChargingStatus::FastCharge, true), at(60));
+        assert!(later.state_of_charge > reading.state_of_charge);
+        // Now based on the measured rate, which the voltage estimate slows down a bit.
+        let expected = (100.0 - later.state_of_charge) * 50.4;
+        let secs = later.time_to_full.unwrap().as_secs() as f32;
+        assert!(
+            (secs - expected).abs() <= expected * 0.1,
+            "{secs} != {expected}"
+        );
+    }
+
+    #[test]
+    fn time_to_empty_while_discharging() {
+        let mut gauge = FuelGauge::new(FuelGaugeConfig::default());
+        let reading = gauge.update(&sample(3.8, 0.0, ChargingStatus::NotCharging, false), at(0));
+        assert!(!reading.charging);
+        assert_eq!(reading.time_to_full, None);
+        // The 100 mA system load drains 1400 mAh at 1/504 % per second.
+        let expected = reading.state_of_charge * 504.0;
+        let secs = reading.time_to_empty.unwrap().as_secs() as f32;
+        assert!((secs - expected).abs() <= 1.0, "{secs} != {expected}");
+    }
+
+    #[test]
+    fn charge_done_reports_full() {
+        let mut gauge = FuelGauge::new(FuelGaugeConfig::default());
+        gauge.update(&sample(4.1, 0.3, ChargingStatus::FastCharge, true), at(0));
+        let reading = gauge.update(&sample(4.18, 0.0, ChargingStatus::ChargeDone, true), at(10));
+        assert_eq!(reading.percent, 100);
+        assert_eq!(reading.state_of_charge, 100.0);
+        assert_eq!(reading.time_to_full, None);
+        assert_eq!(reading.time_to_empty, None);
+    }
+
+    #[test]
+    fn no_drain_while_plugged_in() {
+        let mut gauge = FuelGauge::new(FuelGaugeConfig::default());
+        let data = sample(3.9, 0.0, ChargingStatus::NotCharging, true);
+        let first = gauge.update(&data, at(0));
+        for i in 1..=60 {
+            let reading = gauge.update(&data, at(i * 60));
+            assert_eq!(reading.percent, first.percent);
+            assert!((reading.state_of_charge - first.state_of_charge).abs() < 0.01);
+            assert_eq!(reading.time_to_empty, None);
+        }
+    }
+
+    #[test]
+    fn follows_discharge_trace() {
+        let mut gauge = FuelGauge::new(FuelGaugeConfig::default());
+        let (start, _, _, start_soc) = DISCHARGE[0];
+        let (end, _, _, end_soc) = DISCHARGE[DISCHARGE.len() - 1];
+        // The average time the cell took per percent.
+        let secs_per_percent = (end - start) as f32 / (start_soc - end_soc);
+        let mut last = 100;
+        let mut errors = 0.0;
+        let mut estimates = 0;
+        for &(secs, millivolts, _, reference) in DISCHARGE {
+            let data = sample(
+                millivolts as f32 / 1000.0,
+                0.0,
+                ChargingStatus::NotCharging,
+                false,
+            );
+            let reading = gauge.update(&data, at(secs));
+            let soc = reading.state_of_charge;
+            assert!(
+                (soc - reference).abs() <= SOC_TOLERANCE,
+                "{soc} % != {reference} % at {secs}s"
+            );
+            assert!(reading.percent <= last, "percent rose at {secs}s");
+            last = reading.percent;
+
+            // Near empty the remaining time is too short for a relative tolerance.
+            if reference > 10.0 {
+                let expected = reference * secs_per_percent;
+                let actual = reading.time_to_empty.unwrap().as_secs() as f32;
+                assert!(
+                    (actual - expected).abs() <= expected * TIME_TO_EMPTY_TOLERANCE,
+                    "{actual}s != {expected}s to empty at {secs}s"
+                );
+                errors += (actual - expected).abs() / expected;
+                estimates += 1;
+            }
+        }
+        let mean_error = errors / estimates as f32;
+        assert!(
+            mean_error <= MEAN_TIME_TO_EMPTY_TOLERANCE,
+            "mean time to empty error {mean_error}"
+        );
+    }
+
+    #[test]
+    fn follows_charge_trace() {
+        let mut gauge = FuelGauge::new(FuelGaugeConfig::default());
+        let mut last = 0;
+        for &(secs, millivolts, milliamps, reference) in CHARGE {
+            let status = if milliamps > 0 {
+                ChargingStatus::FastCharge
+            } else {
+                ChargingStatus::ChargeDone
+            };
+            let data = sample(
+                millivolts as f32 / 1000.0,
+                milliamps as f32 / 1000.0,
+                status,
+                true,
+            );
+            let reading = gauge.update(&data, at(secs));
+            let soc = reading.state_of_charge;
+            assert!(
+                (soc - reference).abs() <= SOC_TOLERANCE,
+                "{soc} % != {reference} % at {secs}s"
+            );
+            assert!(reading.percent >= last, "percent fell at {secs}s");
+            assert_eq!(reading.time_to_empty, None);
+            last = reading.percent;
+        }
+        assert_eq!(last, 100);
+    }
+}
@@ -0,0 +1,574 @@
+//! Allocation-free battery telemetry history for graphs.
+//!
+//! `BatteryHistory` downsamples `BatteryData` samples into per-minute and per-hour
+//! buckets holding min/max/average voltage, charge current and temperature, plus how
+//! often the battery was charging. Completed buckets are kept in fixed-capacity ring
+//! buffers, so the oldest data is dropped once they are full.
+//!
+//! The history implements `Persist`, which encodes it into a compact binary blob that
+//! can be written to flash or an SD card and restored after a reboot.
+
+use embedded_hal_async::i2c::{I2c, SevenBitAddress};
+use heapless::HistoryBuffer;
+
+use crate::{BatteryData, BatteryService, ChargingStatus};
+
+const MINUTE_SECS: u32 = 60;
+const HOUR_SECS: u32 = 60 * 60;
+
+/// Running minimum, maximum and average of a single quantity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stat {
+    min: f32,
+    max: f32,
+    sum: f32,
+    count: u16,
+}
+
+impl Stat {
+    /// An empty statistic.
+    pub const EMPTY: Self = Self {
+        min: 0.0,
+        max: 0.0,
+        sum: 0.0,
+        count: 0,
+    };
+
+    /// Adds a value.
+    pub fn add(&mut self, value: f32) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.sum += value;
+        self.count = self.count.saturating_add(1);
+    }
+
+    /// Merges another statistic into this one.
+    pub fn merge(&mut self, other: &Stat) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = *other;
+            return;
+        }
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.count = self.count.saturating_add(other.count);
+    }
+
+    /// The number of values added.
+    pub fn count(&self) -> u16 {
+        self.count
+    }
+
+    /// The smallest value, if any.
+    pub fn min(&self) -> Option<f32> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    /// The largest value, if any.
+    pub fn max(&self) -> Option<f32> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    /// The average value, if any.
+    pub fn average(&self) -> Option<f32> {
+        (self.count > 0).then(|| self.sum / self.count as f32)
+    }
+}
+
+/// Aggregated samples over one minute or one hour.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bucket {
+    /// The timestamp (in seconds) at which the bucket starts.
+    pub start: u32,
+    /// The number of samples in the bucket.
+    pub samples: u16,
+    /// The number of samples taken while charging.
+    pub charging_samples: u16,
+    /// Battery voltage in Volts.
+    pub voltage: Stat,
+    /// Charge current in Amperes.
+    pub current: Stat,
+    /// Battery temperature in °C.
+    pub temperature: Stat,
+}
+
+impl Bucket {
+    /// The size of a bucket in the persisted blob.
+    pub const ENCODED_LEN: usize = 8 + 3 * STAT_ENCODED_LEN;
+
+    fn new(start: u32) -> Self {
+        Self {
+            start,
+            samples: 0,
+            charging_samples: 0,
+            voltage: Stat::EMPTY,
+            current: Stat::EMPTY,
+            temperature: Stat::EMPTY,
+        }
+    }
+
+    fn add(&mut self, data: &BatteryData) {
+        self.samples = self.samples.saturating_add(1);
+        if matches!(
+            data.charging_status,
+            ChargingStatus::PreCharge | ChargingStatus::FastCharge
+        ) {
+            self.charging_samples = self.charging_samples.saturating_add(1);
+        }
+        self.voltage.add(data.voltage);
+        self.current.add(data.charge_current);
+        if let Some(temperature) = data.battery_temp_celsius {
+            self.temperature.add(temperature);
+        }
+    }
+
+    fn merge(&mut self, other: &Bucket) {
+        self.samples = self.samples.saturating_add(other.samples);
+        self.charging_samples = self.charging_samples.saturating_add(other.charging_samples);
+        self.voltage.merge(&other.voltage);
+        self.current.merge(&other.current);
+        self.temperature.merge(&other.temperature);
+    }
+
+    /// Returns `true` if the battery was charging for most of the bucket.
+    pub fn charging(&self) -> bool {
+        self.charging_samples as u32 * 2 > self.samples as u32
+    }
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0..4].copy_from_slice(&self.start.to_le_bytes());
+        out[4..6].copy_from_slice(&self.samples.to_le_bytes());
+        out[6..8].copy_from_slice(&self.charging_samples.to_le_bytes());
+        let stats = out[8..].chunks_exact_mut(STAT_ENCODED_LEN);
+        for ((stat, scale), chunk) in self.stats().into_iter().zip(stats) {
+            encode_stat(stat, scale, chunk);
+        }
+    }
+
+    fn decode(blob: &[u8]) -> Self {
+        let mut stats = blob[8..Self::ENCODED_LEN]
+            .chunks_exact(STAT_ENCODED_LEN)
+            .zip(SCALES)
+            .map(|(chunk, scale)| decode_stat(chunk, scale));
+        Self {
+            start: u32::from_le_bytes([blob[0], blob[1], blob[2], blob[3]]),
+            samples: u16::from_le_bytes([blob[4], blob[5]]),
+            charging_samples: u16::from_le_bytes([blob[6], blob[7]]),
+            voltage: stats.next().unwrap_or(Stat::EMPTY),
+            current: stats.next().unwrap_or(Stat::EMPTY),
+            temperature: stats.next().unwrap_or(Stat::EMPTY),
+        }
+    }
+
+    fn stats(&self) -> [(&Stat, f32); 3] {
+        [
+            (&self.voltage, SCALES[0]),
+            (&self.current, SCALES[1]),
+            (&self.temperature, SCALES[2]),
+        ]
+    }
+}
+
+/// Fixed-point scales of the persisted voltage (mV), current (mA) and temperature (0.1 °C).
+const SCALES: [f32; 3] = [1000.0, 1000.0, 10.0];
+const STAT_ENCODED_LEN: usize = 8;
+
+fn encode_stat(stat: &Stat, scale: f32, out: &mut [u8]) {
+    let fixed = |value: Option<f32>| libm::roundf(value.unwrap_or(0.0) * scale) as i16;
+    out[0..2].copy_from_slice(&fixed(stat.min()).to_le_bytes());
+    out[2..4].copy_from_slice(&fixed(stat.max()).to_le_bytes());
+    out[4..6].copy_from_slice(&fixed(stat.average()).to_le_bytes());
+    out[6..8].copy_from_slice(&stat.count.to_le_bytes());
+}
+
+fn decode_stat(blob: &[u8], scale: f32) -> Stat {
+    let value = |i: usize| i16::from_le_bytes([blob[i], blob[i + 1]]) as f32 / scale;
+    let count = u16::from_le_bytes([blob[6], blob[7]]);
+    Stat {
+        min: value(0),
+        max: value(2),
+        sum: value(4) * count as f32,
+        count,
+    }
+}
+
+/// An error returned by `Persist`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PersistError {
+    /// The output buffer is shorter than `Persist::persisted_len`.
+    BufferTooSmall,
+    /// The blob is truncated or was written by an incompatible version.
+    InvalidBlob,
+}
+
+/// Serialization of state into a compact binary blob, e.g. for flash or SD storage.
+pub trait Persist {
+    /// The number of bytes `persist` writes for the current state.
+    fn persisted_len(&self) -> usize;
+
+    /// Writes the state into `out`, returning the number of bytes written.
+    ///
+    /// Fails if `out` is shorter than `persisted_len`.
+    fn persist(&self, out: &mut [u8]) -> Result<usize, PersistError>;
+
+    /// Replaces the state with the one encoded in `blob`.
+    ///
+    /// Fails if the blob is truncated or was written by an incompatible version.
+    fn restore(&mut self, blob: &[u8]) -> Result<(), PersistError>;
+}
+
+/// Per-minute and per-hour battery history.
+///
+/// `MINUTES` and `HOURS` are the number of completed buckets kept, e.g.
+/// `BatteryHistory<120, 48>` keeps two hours of minute data and two days of hour data.
+pub struct BatteryHistory<const MINUTES: usize, const HOURS: usize> {
+    minutes: HistoryBuffer<Bucket, MINUTES>,
+    hours: HistoryBuffer<Bucket, HOURS>,
+    current_minute: Option<Bucket>,
+    current_hour: Option<Bucket>,
+}
+
+const BLOB_MAGIC: [u8; 2] = *b"BH";
+const BLOB_VERSION: u8 = 1;
+const BLOB_HEADER_LEN: usize = 8;
+
+impl<const MINUTES: usize, const HOURS: usize> Default for BatteryHistory<MINUTES, HOURS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const MINUTES: usize, const HOURS: usize> BatteryHistory<MINUTES, HOURS> {
+    /// Creates an empty history.
+    pub const fn new() -> Self {
+        Self {
+            minutes: HistoryBuffer::new(),
+            hours: HistoryBuffer::new(),
+            current_minute: None,
+            current_hour: None,
+        }
+    }
+
+    /// Adds a sample taken at `timestamp` (in seconds).
+    ///
+    /// Any monotonic seconds counter works, but a wall-clock time (e.g. UNIX time from
+    /// the GPS) keeps restored history aligned across reboots.
+    pub fn record(&mut self, data: &BatteryData, timestamp: u32) {
+        let minute_start = timestamp - timestamp % MINUTE_SECS;
+        if let Some(bucket) = self.current_minute {
+            if bucket.start != minute_start {
+                self.close_minute(bucket, timestamp);
+            }
+        }
+        self.current_minute
+            .get_or_insert_with(|| Bucket::new(minute_start))
+            .add(data);
+    }
+
+    /// Moves a completed minute into the minute ring and folds it into the hour.
+    fn close_minute(&mut self, bucket: Bucket, timestamp: u32) {
+        self.minutes.write(bucket);
+        self.current_minute = None;
+
+        let hour_start = bucket.start - bucket.start % HOUR_SECS;
+        match &mut self.current_hour {
+            Some(hour) if hour.start == hour_start => hour.merge(&bucket),
+            current => {
+                if let Some(hour) = current.take() {
+                    self.hours.write(hour);
+                }
+                let mut hour = Bucket::new(hour_start);
+                hour.merge(&bucket);
+                self.current_hour = Some(hour);
+            }
+        }
+
+        // Close the hour too once the new sample falls outside of it.
+        if let Some(hour) = self.current_hour {
+            if timestamp >= hour.start.saturating_add(HOUR_SECS) {
+                self.hours.write(hour);
+                self.current_hour = None;
+            }
+        }
+    }
+
+    /// Completed minute buckets, oldest first.
+    pub fn minutes(&self) -> impl Iterator<Item = &Bucket> {
+        self.minutes.oldest_ordered()
+    }
+
+    /// Completed hour buckets, oldest first.
+    pub fn hours(&self) -> impl Iterator<Item = &Bucket> {
+        self.hours.oldest_ordered()
+    }
+
+    /// The minute bucket currently being filled.
+    pub fn current_minute(&self) -> Option<&Bucket> {
+        self.current_minute.as_ref()
+    }
+
+    /// The hour bucket currently being filled, not including the current minute.
+    pub fn current_hour(&self) -> Option<&Bucket> {
+        self.current_hour.as_ref()
+    }
+
+    /// Removes all recorded data.
+    pub fn clear(&mut self) {
+        self.minutes.clear();
+        self.hours.clear();
+        self.current_minute = None;
+        self.current_hour = None;
+    }
+
+    /// The size of the blob for a full history of this capacity.
+    pub const fn max_persisted_len() -> usize {
+        BLOB_HEADER_LEN + (MINUTES + HOURS + 2) * Bucket::ENCODED_LEN
+    }
+}
+
+impl<const MINUTES: usize, const HOURS: usize> Persist for BatteryHistory<MINUTES, HOURS> {
+    fn persisted_len(&self) -> usize {
+        let open = self.current_minute.is_some() as usize + self.current_hour.is_some() as usize;
+        BLOB_HEADER_LEN + (self.minutes.len() + self.hours.len() + open) * Bucket::ENCODED_LEN
+    }
+
+    /// Blob layout (little-endian): magic `"BH"`, version, flags (bit 0: current minute
+    /// present, bit 1: current hour present), minute count (u16), hour count (u16),
+    /// followed by the minute buckets, the hour buckets, the current minute and the
+    /// current hour.
+    fn persist(&self, out: &mut [u8]) -> Result<usize, PersistError> {
+        let len = self.persisted_len();
+        if out.len() < len {
+            return Err(PersistError::BufferTooSmall);
+        }
+        let flags = self.current_minute.is_some() as u8 | (self.current_hour.is_some() as u8) << 1;
+        out[0..2].copy_from_slice(&BLOB_MAGIC);
+        out[2] = BLOB_VERSION;
+        out[3] = flags;
+        out[4..6].copy_from_slice(&(self.minutes.len() as u16).to_le_bytes());
+        out[6..8].copy_from_slice(&(self.hours.len() as u16).to_le_bytes());
+
+        let buckets = self
+            .minutes
+            .oldest_ordered()
+            .chain(self.hours.oldest_ordered())
+            .chain(self.current_minute.iter())
+            .chain(self.current_hour.iter());
+        for (bucket, chunk) in
+            buckets.zip(out[BLOB_HEADER_LEN..len].chunks_exact_mut(Bucket::ENCODED_LEN))
+        {
+            bucket.encode(chunk);
+        }
+        Ok(len)
+    }
+
+    fn restore(&mut self, blob: &[u8]) -> Result<(), PersistError> {
+        if blob.len() < BLOB_HEADER_LEN || blob[0..2] != BLOB_MAGIC || blob[2] != BLOB_VERSION {
+            return Err(PersistError::InvalidBlob);
+        }
+        let flags = blob[3];
+        let minutes = u16::from_le_bytes([blob[4], blob[5]]) as usize;
+        let hours = u16::from_le_bytes([blob[6], blob[7]]) as usize;
+        let open = (flags & 0b01 != 0) as usize + (flags & 0b10 != 0) as usize;
+        let len = BLOB_HEADER_LEN + (minutes + hours + open) * Bucket::ENCODED_LEN;
+        if blob.len() < len {
+            return Err(PersistError::InvalidBlob);
+        }
+
+        self.clear();
+        let mut buckets = blob[BLOB_HEADER_LEN..len]
+            .chunks_exact(Bucket::ENCODED_LEN)
+            .map(Bucket::decode);
+        // Writing more buckets than fit simply keeps the most recent ones.
+        for bucket in buckets.by_ref().take(minutes) {
+            self.minutes.write(bucket);
+        }
+        for bucket in buckets.by_ref().take(hours) {
+            self.hours.write(bucket);
+        }
+        if flags & 0b01 != 0 {
+            self.current_minute = buckets.next();
+        }
+        if flags & 0b10 != 0 {
+            self.current_hour = buckets.next();
+        }
+        Ok(())
+    }
+}
+
+impl<I2cType, ErrorType> BatteryService<I2cType, ErrorType>
+where
+    I2cType: I2c<SevenBitAddress, Error = ErrorType>,
+    ErrorType: embedded_hal_async::i2c::Error,
+{
+    /// Measures like `measure` and records the result in `history`.
+    pub async fn measure_into<const MINUTES: usize, const HOURS: usize>(
+        &mut self,
+        history: &mut BatteryHistory<MINUTES, HOURS>,
+        timestamp: u32,
+    ) -> Result<BatteryData, ()> {
+        let data = self.measure().await?;
+        history.record(&data, timestamp);
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::adc::NtcConfig;
+
+    type History = BatteryHistory<4, 2>;
+
+    fn sample(voltage: f32, charge_current: f32, temperature: f32) -> BatteryData {
+        let mut data =
+            BatteryData::from_registers(&[0; crate::MEASURE_BURST_LEN], &NtcConfig::default());
+        data.voltage = voltage;
+        data.charge_current = charge_current;
+        data.battery_temp_celsius = Some(temperature);
+        data.charging_status = if charge_current > 0.0 {
+            ChargingStatus::FastCharge
+        } else {
+            ChargingStatus::NotCharging
+        };
+        data
+    }
+
+    /// A history with completed minutes and hours and both buckets still open.
+    fn history() -> History {
+        let mut history = History::new();
+        for (timestamp, voltage, current) in [
+            (0, 3.7, 0.5),
+            (30, 3.72, 0.5),
+            (60, 3.75, 0.0),
+            (3600, 3.8, 0.25),
+            (3660, 3.81, 0.0),
+            (3670, 3.79, 0.0),
+        ] {
+            history.record(&sample(voltage, current, 25.5), timestamp);
+        }
+        history
+    }
+
+    fn starts<'a>(buckets: impl Iterator<Item = &'a Bucket>) -> Vec<u32> {
+        buckets.map(|bucket| bucket.start).collect()
+    }
+
+    fn persist(history: &History) -> Vec<u8> {
+        let mut blob = vec![0; History::max_persisted_len()];
+        let len = history.persist(&mut blob).unwrap();
+        assert_eq!(len, history.persisted_len());
+        blob.truncate(len);
+        blob
+    }
+
+    #[test]
+    fn persist_round_trips() {
+        let history = history();
+        assert_eq!(starts(history.minutes()), [0, 60, 3600]);
+        assert_eq!(starts(history.hours()), [0]);
+        assert_eq!(history.current_hour().map(|hour| hour.start), Some(3600));
+
+        let blob = persist(&history);
+        assert_eq!(blob[..8], [b'B', b'H', 1, 0b11, 3, 0, 1, 0]);
+        assert_eq!(blob.len(), 8 + 6 * Bucket::ENCODED_LEN);
+
+        let mut restored = History::new();
+        restored.restore(&blob).unwrap();
+        assert_eq!(starts(restored.minutes()), [0, 60, 3600]);
+        assert_eq!(starts(restored.hours()), [0]);
+
+        let minute = restored.current_minute().unwrap();
+        assert_eq!((minute.start, minute.samples), (3660, 2));
+        assert_eq!(minute.voltage.min(), Some(3.79));
+        assert_eq!(minute.voltage.max(), Some(3.81));
+        assert!((minute.voltage.average().unwrap() - 3.8).abs() < 1e-3);
+        assert_eq!(minute.temperature.average(), Some(25.5));
+
+        let hour = restored.current_hour().unwrap();
+        assert_eq!((hour.start, hour.samples), (3600, 1));
+        assert!(hour.charging());
+        assert_eq!(hour.current.max(), Some(0.25));
+
+        let hour = restored.hours().next().unwrap();
+        assert_eq!((hour.samples, hour.charging_samples), (3, 2));
+        assert!((hour.voltage.average().unwrap() - 3.723).abs() < 1e-3);
+
+        // Encoding the restored history gives the same blob.
+        assert_eq!(persist(&restored), blob);
+    }
+
+    #[test]
+    fn persist_without_open_buckets() {
+        let blob = persist(&History::new());
+        assert_eq!(blob, [b'B', b'H', 1, 0, 0, 0, 0, 0]);
+
+        let mut restored = history();
+        restored.restore(&blob).unwrap();
+        assert_eq!(restored.minutes().count(), 0);
+        assert_eq!(restored.current_minute(), None);
+        assert_eq!(restored.current_hour(), None);
+    }
+
+    #[test]
+    fn restore_keeps_most_recent_buckets() {
+        let blob = persist(&history());
+        let mut restored = BatteryHistory::<2, 2>::new();
+        restored.restore(&blob).unwrap();
+        assert_eq!(starts(restored.minutes()), [60, 3600]);
+        assert_eq!(
+            restored.current_minute().map(|minute| minute.start),
+            Some(3660)
+        );
+    }
+
+    #[test]
+    fn persist_rejects_short_buffer() {
+        let history = history();
+        let mut blob = vec![0; history.persisted_len() - 1];
+        assert_eq!(
+            history.persist(&mut blob),
+            Err(PersistError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn restore_rejects_invalid_blobs() {
+        let blob = persist(&history());
+        let mut wrong_magic = blob.clone();
+        wrong_magic[1] = b'X';
+        let mut wrong_version = blob.clone();
+        wrong_version[2] = 2;
+        let invalid: [&[u8]; 5] = [
+            &wrong_magic,
+            &wrong_version,
+            &blob[..blob.len() - 1],
+            &blob[..7],
+            &[],
+        ];
+
+        let mut restored = History::new();
+        restored.record(&sample(3.9, 0.0, 20.0), 120);
+        for blob in invalid {
+            assert_eq!(restored.restore(blob), Err(PersistError::InvalidBlob));
+            // A rejected blob leaves the history untouched.
+            assert_eq!(
+                restored.current_minute().map(|minute| minute.start),
+                Some(120)
+            );
+        }
+    }
+}
@@ -9,7 +9,7 @@
 //! To use this driver, you need an I2C peripheral implementation that satisfies the
 //! `embedded-hal-async::i2c::I2c` trait.
 //!
-//! ```no_run
+//! ```ignore
 //! # #![no_std]
 //! # #![no_main]
 //! # use esp_hal::prelude::*;
@@ -50,23 +50,71 @@
 
 #![no_std]
 
+pub mod adc;
+pub mod control;
+pub mod events;
+pub mod fuel_gauge;
+pub mod history;
+pub mod profile;
+
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod testdata;
+
 use embassy_time::{Duration, Timer};
 use embedded_hal_async::i2c::{I2c, SevenBitAddress};
 use log::error;
 
+use adc::{AdcMode, InputDpmStatus, NtcConfig};
+
 // --- Register Addresses ---
 const BQ25896_I2C_ADDR: u8 = 0x6B;
+const INPUT_SRC_REG: u8 = 0x00;
 const ADC_CTRL_REG: u8 = 0x02;
+const SYS_CTRL_REG: u8 = 0x03;
+const CHARGE_CURRENT_REG: u8 = 0x04;
+const TIMER_CTRL_REG: u8 = 0x07;
+const MISC_CTRL_REG: u8 = 0x09;
 const SYS_STATUS_REG: u8 = 0x0B;
-const FAULT_STATUS_REG: u8 = 0x0C;
-const VBUS_ADC_REG: u8 = 0x11;
-const CHARGE_CURRENT_ADC_REG: u8 = 0x12;
-const BATTERY_TEMP_ADC_REG: u8 = 0x10;
-const VOLTAGE_READ_REG: u8 = 0x0E;
 
-// --- ADC Control ---
-const ADC_ENABLE_VALUE: u8 = 0xC0;
-const ADC_DISABLE_VALUE: u8 = 0x40;
+/// Number of registers read in one burst by `measure`: REG0B to REG14.
+pub const MEASURE_BURST_LEN: usize = 10;
+
+// --- ADC one-shot conversion polling ---
+const ONE_SHOT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const ONE_SHOT_POLL_ATTEMPTS: usize = 30;
+
+// --- Bitmasks for REG02 (ADC Control) ---
+const CONV_START_MASK: u8 = 0b1000_0000;
+const CONV_RATE_MASK: u8 = 0b0100_0000;
+
+// --- Bitmasks for REG00 (Input Source Control) ---
+const EN_HIZ_MASK: u8 = 0b1000_0000;
+
+// --- Bitmasks for REG03 (System Control) ---
+const WD_RST_MASK: u8 = 0b0100_0000;
+const OTG_CONFIG_MASK: u8 = 0b0010_0000;
+const CHG_CONFIG_MASK: u8 = 0b0001_0000;
+
+// --- Bitmasks for REG04 (Fast Charge Current) ---
+const ICHG_MASK: u8 = 0b0111_1111;
+
+// --- Bitmasks and Shifts for REG06 (Charge Voltage) ---
+const VREG_MASK: u8 = 0b1111_1100;
+const VREG_SHIFT: u8 = 2;
+const VRECHG_MASK: u8 = 0b0000_0001;
+
+// --- Bitmasks and Shifts for REG07 (Termination/Timer Control) ---
+const WATCHDOG_MASK: u8 = 0b0011_0000;
+const WATCHDOG_SHIFT: u8 = 4;
+const JEITA_ISET_MASK: u8 = 0b0000_0001;
+
+// --- Bitmasks for REG09 (Misc Operation Control) ---
+const BATFET_DIS_MASK: u8 = 0b0010_0000;
+const JEITA_VSET_MASK: u8 = 0b0001_0000;
+const BATFET_DLY_MASK: u8 = 0b0000_1000;
+const BATFET_RST_EN_MASK: u8 = 0b0000_0100;
 
 // --- Bitmasks and Shifts for REG0B (System Status) ---
 const VBUS_STATUS_MASK: u8 = 0b1110_0000;
@@ -83,6 +131,15 @@ const CHRG_FAULT_SHIFT: u8 = 4;
 const BAT_FAULT_MASK: u8 = 0b0000_1000;
 const NTC_FAULT_MASK: u8 = 0b0000_0111;
 
+// --- Bitmasks for the ADC result registers (REG0E - REG14) ---
+const ADC_VALUE_MASK: u8 = 0b0111_1111;
+const THERM_STAT_MASK: u8 = 0b1000_0000;
+const VBUS_GD_MASK: u8 = 0b1000_0000;
+const VDPM_STAT_MASK: u8 = 0b1000_0000;
+const IDPM_STAT_MASK: u8 = 0b0100_0000;
+const IDPM_LIM_MASK: u8 = 0b0011_1111;
+const ICO_OPTIMIZED_MASK: u8 = 0b0100_0000;
+
 /// Represents the charging status of the battery.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ChargingStatus {
@@ -166,17 +223,102 @@ impl Default for FaultStatus {
     }
 }
 
+/// A snapshot of the status (REG0B) and fault (REG0C) registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChargerStatus {
+    /// The status of the VBUS input.
+    pub vbus_status: VbusStatus,
+    /// The current charging status.
+    pub charging_status: ChargingStatus,
+    /// Indicates if the input power source is good.
+    pub power_good: bool,
+    /// Reports any fault conditions.
+    pub faults: FaultStatus,
+}
+
+impl ChargerStatus {
+    /// Reads the status (REG0B) and fault (REG0C) registers in a single transaction.
+    pub(crate) async fn read<I2cType: I2c<SevenBitAddress>>(i2c: &mut I2cType) -> Result<Self, ()> {
+        let mut buf = [0u8; 2];
+        i2c.write_read(BQ25896_I2C_ADDR, &[SYS_STATUS_REG], &mut buf)
+            .await
+            .map_err(|e| error!("I2C Error: {e:?}"))?;
+        Ok(Self::from_registers(buf[0], buf[1]))
+    }
+
+    /// Decodes the raw values of the status (REG0B) and fault (REG0C) registers.
+    pub fn from_registers(status_byte: u8, fault_byte: u8) -> Self {
+        let charging_status = match (status_byte & CHARGE_STATUS_MASK) >> CHARGE_STATUS_SHIFT {
+            0b00 => ChargingStatus::NotCharging,
+            0b01 => ChargingStatus::PreCharge,
+            0b10 => ChargingStatus::FastCharge,
+            _ => ChargingStatus::ChargeDone,
+        };
+
+        let vbus_status = match (status_byte & VBUS_STATUS_MASK) >> VBUS_STATUS_SHIFT {
+            0b000 => VbusStatus::NoInput,
+            0b001 => VbusStatus::UsbHost,
+            0b010 => VbusStatus::Adapter,
+            0b111 => VbusStatus::Otg,
+            _ => VbusStatus::Unknown,
+        };
+
+        let power_good = (status_byte & POWER_GOOD_MASK) != 0;
+
+        let charge_fault = match (fault_byte & CHRG_FAULT_MASK) >> CHRG_FAULT_SHIFT {
+            0b01 => ChargeFault::InputFault,
+            0b10 => ChargeFault::ThermalShutdown,
+            0b11 => ChargeFault::TimerExpiration,
+            _ => ChargeFault::Normal,
+        };
+
+        let ntc_fault = match fault_byte & NTC_FAULT_MASK {
+            // In boost mode only the Cold and Hot codes are used.
+            0b010 => NtcFault::Warm,
+            0b011 => NtcFault::Cool,
+            0b101 => NtcFault::Cold,
+            0b110 => NtcFault::Hot,
+            _ => NtcFault::Normal,
+        };
+
+        let faults = FaultStatus {
+            watchdog_fault: (fault_byte & WATCHDOG_FAULT_MASK) != 0,
+            boost_fault: (fault_byte & BOOST_FAULT_MASK) != 0,
+            charge_fault,
+            battery_ovp_fault: (fault_byte & BAT_FAULT_MASK) != 0,
+            ntc_fault,
+        };
+
+        Self {
+            vbus_status,
+            charging_status,
+            power_good,
+            faults,
+        }
+    }
+}
+
 /// Holds a comprehensive set of data read from the BQ25896.
 #[derive(Debug, Clone, Copy)]
 pub struct BatteryData {
     /// The measured battery voltage in Volts.
     pub voltage: f32,
+    /// The measured system (VSYS) voltage in Volts.
+    pub system_voltage: f32,
     /// The measured VBUS (input) voltage in Volts.
     pub vbus_voltage: f32,
+    /// Indicates if VBUS is attached (REG11 VBUS_GD).
+    pub vbus_good: bool,
     /// The measured charge current in Amperes.
     pub charge_current: f32,
-    /// The measured battery temperature as a percentage of VREGN.
+    /// The TS pin voltage as a percentage of REGN.
     pub battery_temp_percent: f32,
+    /// The battery temperature in °C, derived from the TS reading and the `NtcConfig`.
+    pub battery_temp_celsius: Option<f32>,
+    /// Indicates if the charger is in thermal regulation.
+    pub thermal_regulation: bool,
+    /// The input voltage/current DPM status.
+    pub input_dpm: InputDpmStatus,
     /// The current charging status.
     pub charging_status: ChargingStatus,
     /// The status of the VBUS input.
@@ -187,13 +329,89 @@ pub struct BatteryData {
     pub faults: FaultStatus,
 }
 
+impl BatteryData {
+    /// Decodes a burst read of REG0B to REG14.
+    ///
+    /// # Arguments
+    ///
+    /// * `regs` - The raw register values, starting at REG0B.
+    /// * `ntc` - The thermistor network used to convert the TS reading to °C.
+    pub fn from_registers(regs: &[u8; MEASURE_BURST_LEN], ntc: &NtcConfig) -> Self {
+        let [status, fault, _reg0d, vbat, vsys, ts, vbus, ichg, dpm, info] = *regs;
+
+        let ChargerStatus {
+            vbus_status,
+            charging_status,
+            power_good,
+            faults,
+        } = ChargerStatus::from_registers(status, fault);
+
+        // --- ADC Formulas ---
+        let voltage = 2.304 + ((vbat & ADC_VALUE_MASK) as f32 * 0.020);
+        let system_voltage = 2.304 + ((vsys & ADC_VALUE_MASK) as f32 * 0.020);
+        let vbus_voltage = 2.6 + ((vbus & ADC_VALUE_MASK) as f32 * 0.100);
+        let charge_current = (ichg & ADC_VALUE_MASK) as f32 * 50.0 / 1000.0; // In Amperes
+        let battery_temp_percent = 21.0 + ((ts & ADC_VALUE_MASK) as f32 * 0.465);
+        let input_current_limit = 0.1 + (dpm & IDPM_LIM_MASK) as f32 * 0.050; // In Amperes
+
+        Self {
+            voltage,
+            system_voltage,
+            vbus_voltage,
+            vbus_good: (vbus & VBUS_GD_MASK) != 0,
+            charge_current,
+            battery_temp_percent,
+            battery_temp_celsius: ntc.temperature(battery_temp_percent),
+            thermal_regulation: (vbat & THERM_STAT_MASK) != 0,
+            input_dpm: InputDpmStatus {
+                voltage_dpm: (dpm & VDPM_STAT_MASK) != 0,
+                current_dpm: (dpm & IDPM_STAT_MASK) != 0,
+                input_current_limit,
+                ico_optimized: (info & ICO_OPTIMIZED_MASK) != 0,
+            },
+            charging_status,
+            vbus_status,
+            power_good,
+            faults,
+        }
+    }
+}
+
+/// Configuration for the `BatteryService`.
+#[derive(Debug, Clone, Copy)]
+pub struct BatteryConfig {
+    /// How `measure` drives the ADC.
+    pub adc_mode: AdcMode,
+    /// The thermistor network on the TS pin.
+    pub ntc: NtcConfig,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            adc_mode: AdcMode::Continuous,
+            ntc: NtcConfig::default(),
+        }
+    }
+}
+
+/// The `BatteryService` on the T-Deck's esp-hal I2C bus.
+#[cfg(feature = "t-deck")]
+pub type TDeckBatteryService<'d> =
+    BatteryService<esp_hal::i2c::master::I2c<'d, esp_hal::Async>, esp_hal::i2c::master::Error>;
+
 /// A service for interacting with the BQ25896 battery charger IC.
 pub struct BatteryService<
     I2cType: I2c<SevenBitAddress, Error = ErrorType>,
     ErrorType: embedded_hal_async::i2c::Error,
 > {
     i2c: I2cType,
+    config: BatteryConfig,
     adc_enabled: bool,
+    watchdog: control::WatchdogTimer,
+    refuse_power_off_with_vbus: bool,
+    charge_profile: Option<profile::ChargeProfile>,
+    thermal_zone: NtcFault,
 }
 
 impl<
@@ -207,24 +425,47 @@ impl<
     ///
     /// * `i2c` - An I2C peripheral that implements the `embedded-hal-async::i2c::I2c` trait.
     pub fn new(i2c: I2cType) -> Self {
+        Self::with_config(i2c, BatteryConfig::default())
+    }
+
+    /// Creates a new `BatteryService` with a custom configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `i2c` - An I2C peripheral that implements the `embedded-hal-async::i2c::I2c` trait.
+    /// * `config` - The ADC mode and thermistor network to use.
+    pub fn with_config(i2c: I2cType, config: BatteryConfig) -> Self {
         Self {
             i2c,
+            config,
             adc_enabled: false,
+            watchdog: control::WatchdogTimer::Seconds40,
+            refuse_power_off_with_vbus: true,
+            charge_profile: None,
+            thermal_zone: NtcFault::Normal,
         }
     }
 
-    /// Enables the BQ25896's ADC for measurement.
+    /// Returns the configuration of the service.
+    pub fn config(&self) -> &BatteryConfig {
+        &self.config
+    }
+
+    /// Enables continuous conversion on the BQ25896's ADC.
     ///
-    /// The ADC is automatically enabled by the `measure` function if needed,
-    /// but this method can be used to enable it manually beforehand.
+    /// In `AdcMode::Continuous` the ADC is automatically enabled by the `measure`
+    /// function if needed, but this method can be used to enable it manually beforehand.
     pub async fn enable_adc(&mut self) -> Result<(), ()> {
         if self.adc_enabled {
             return Ok(());
         }
-        self.i2c
-            .write(BQ25896_I2C_ADDR, &[ADC_CTRL_REG, ADC_ENABLE_VALUE])
-            .await
-            .map_err(|e| error!("Failed to enable ADC: {e:?}"))?;
+        self.update_reg(
+            ADC_CTRL_REG,
+            CONV_START_MASK | CONV_RATE_MASK,
+            CONV_START_MASK | CONV_RATE_MASK,
+        )
+        .await
+        .map_err(|_| error!("Failed to enable ADC"))?;
         self.adc_enabled = true;
         Ok(())
     }
@@ -236,120 +477,244 @@ impl<
         if !self.adc_enabled {
             return Ok(());
         }
-        self.i2c
-            .write(BQ25896_I2C_ADDR, &[ADC_CTRL_REG, ADC_DISABLE_VALUE])
+        self.update_reg(ADC_CTRL_REG, CONV_START_MASK | CONV_RATE_MASK, 0)
             .await
-            .map_err(|e| error!("Failed to disable ADC: {e:?}"))?;
+            .map_err(|_| error!("Failed to disable ADC"))?;
         self.adc_enabled = false;
         Ok(())
     }
 
+    /// Reads the status (REG0B) and fault (REG0C) registers in a single transaction.
+    ///
+    /// Note that the fault register latches: the first read after a fault reports it,
+    /// subsequent reads report the current state.
+    pub async fn read_status(&mut self) -> Result<ChargerStatus, ()> {
+        ChargerStatus::read(&mut self.i2c).await
+    }
+
+    /// Starts a single ADC conversion and waits for it to complete.
+    pub async fn convert_once(&mut self) -> Result<(), ()> {
+        self.update_reg(
+            ADC_CTRL_REG,
+            CONV_START_MASK | CONV_RATE_MASK,
+            CONV_START_MASK,
+        )
+        .await?;
+        // CONV_START clears itself once all channels are converted (about 1 s at most).
+        for _ in 0..ONE_SHOT_POLL_ATTEMPTS {
+            Timer::after(ONE_SHOT_POLL_INTERVAL).await;
+            if self.read_reg(ADC_CTRL_REG).await? & CONV_START_MASK == 0 {
+                return Ok(());
+            }
+        }
+        error!("Timed out waiting for ADC conversion");
+        Err(())
+    }
+
     /// Reads and returns all available data from the BQ25896.
     ///
-    /// This function will automatically enable the ADC if it is not already enabled.
-    /// It reads all relevant registers from the IC and parses them into a `BatteryData` struct.
+    /// In `AdcMode::Continuous` this function will automatically enable the ADC if it
+    /// is not already enabled. In `AdcMode::OneShot` it starts a single conversion and
+    /// waits for it, unless continuous conversion was enabled with `enable_adc`.
+    /// REG0B to REG14 are then read in a single burst and decoded into a `BatteryData`.
+    /// If a watchdog fault is reported, the stored `ChargeProfile` is restored.
     pub async fn measure(&mut self) -> Result<BatteryData, ()> {
         if !self.adc_enabled {
-            self.enable_adc().await?;
-            Timer::after(Duration::from_secs(1)).await;
+            match self.config.adc_mode {
+                AdcMode::Continuous => {
+                    self.enable_adc().await?;
+                    Timer::after(Duration::from_secs(1)).await;
+                }
+                AdcMode::OneShot => self.convert_once().await?,
+            }
         }
 
-        // Read all necessary registers
-        let mut vbat_buf = [0u8; 1];
+        let mut regs = [0u8; MEASURE_BURST_LEN];
         self.i2c
-            .write_read(BQ25896_I2C_ADDR, &[VOLTAGE_READ_REG], &mut vbat_buf)
+            .write_read(BQ25896_I2C_ADDR, &[SYS_STATUS_REG], &mut regs)
             .await
             .map_err(|e| error!("I2C Error: {e:?}"))?;
 
-        let mut vbus_buf = [0u8; 1];
-        self.i2c
-            .write_read(BQ25896_I2C_ADDR, &[VBUS_ADC_REG], &mut vbus_buf)
-            .await
-            .map_err(|e| error!("I2C Error: {e:?}"))?;
+        let data = BatteryData::from_registers(&regs, &self.config.ntc);
+        if data.faults.watchdog_fault && self.charge_profile.is_some() {
+            self.restore_charge_profile().await?;
+        }
+        Ok(data)
+    }
 
-        let mut ichg_buf = [0u8; 1];
+    /// Reads a single register from the BQ25896.
+    async fn read_reg(&mut self, reg: u8) -> Result<u8, ()> {
+        let mut buf = [0u8; 1];
         self.i2c
-            .write_read(BQ25896_I2C_ADDR, &[CHARGE_CURRENT_ADC_REG], &mut ichg_buf)
+            .write_read(BQ25896_I2C_ADDR, &[reg], &mut buf)
             .await
-            .map_err(|e| error!("I2C Error: {e:?}"))?;
+            .map_err(|e| error!("Failed to read register {reg:#04x}: {e:?}"))?;
+        Ok(buf[0])
+    }
 
-        let mut ts_buf = [0u8; 1];
+    /// Writes a single register of the BQ25896.
+    async fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), ()> {
         self.i2c
-            .write_read(BQ25896_I2C_ADDR, &[BATTERY_TEMP_ADC_REG], &mut ts_buf)
+            .write(BQ25896_I2C_ADDR, &[reg, value])
             .await
-            .map_err(|e| error!("I2C Error: {e:?}"))?;
+            .map_err(|e| error!("Failed to write register {reg:#04x}: {e:?}"))
+    }
 
-        let mut status_buf = [0u8; 1];
-        self.i2c
-            .write_read(BQ25896_I2C_ADDR, &[SYS_STATUS_REG], &mut status_buf)
+    /// Updates the bits selected by `mask` in a register, leaving the others untouched.
+    async fn update_reg(&mut self, reg: u8, mask: u8, value: u8) -> Result<(), ()> {
+        let current = self.read_reg(reg).await?;
+        self.write_reg(reg, (current & !mask) | (value & mask))
             .await
-            .map_err(|e| error!("I2C Error: {e:?}"))?;
+    }
+}
 
-        let mut fault_buf = [0u8; 1];
-        self.i2c
-            .write_read(BQ25896_I2C_ADDR, &[FAULT_STATUS_REG], &mut fault_buf)
-            .await
-            .map_err(|e| error!("I2C Error: {e:?}"))?;
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::MockI2c;
+
+    /// A burst read of REG0B to REG14 while fast charging from an adapter.
+    const CHARGING: [u8; MEASURE_BURST_LEN] = [
+        0b0101_0100, // REG0B: adapter, fast charging, power good
+        0b0000_0000, // REG0C: no faults
+        0b0000_0000, // REG0D
+        0b1100_1011, // REG0E: thermal regulation, VBAT 2.304 V + 75 * 20 mV
+        0b0101_0000, // REG0F: VSYS 2.304 V + 80 * 20 mV
+        0b0101_0010, // REG10: TS 21 % + 82 * 0.465 %
+        0b1001_1000, // REG11: VBUS good, VBUS 2.6 V + 24 * 100 mV
+        0b0001_0100, // REG12: ICHG 20 * 50 mA
+        0b1101_0010, // REG13: VINDPM and IINDPM, IDPM_LIM 100 mA + 18 * 50 mA
+        0b0100_0000, // REG14: ICO optimized
+    ];
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-4, "{actual} != {expected}");
+    }
 
-        // --- Parse and Calculate Data ---
-        let status_byte = status_buf[0];
-        let fault_byte = fault_buf[0];
+    fn ntc(code: u8) -> NtcFault {
+        ChargerStatus::from_registers(0, code).faults.ntc_fault
+    }
 
-        let charging_status = match (status_byte & CHARGE_STATUS_MASK) >> CHARGE_STATUS_SHIFT {
-            0b00 => ChargingStatus::NotCharging,
-            0b01 => ChargingStatus::PreCharge,
-            0b10 => ChargingStatus::FastCharge,
-            _ => ChargingStatus::ChargeDone,
-        };
+    #[test]
+    fn ntc_normal() {
+        assert_eq!(ntc(0b000), NtcFault::Normal);
+    }
 
-        let vbus_status = match (status_byte & VBUS_STATUS_MASK) >> VBUS_STATUS_SHIFT {
-            0b000 => VbusStatus::NoInput,
-            0b001 => VbusStatus::UsbHost,
-            0b010 => VbusStatus::Adapter,
-            0b111 => VbusStatus::Otg,
-            _ => VbusStatus::Unknown,
-        };
+    #[test]
+    fn ntc_warm() {
+        assert_eq!(ntc(0b010), NtcFault::Warm);
+    }
 
-        let power_good = (status_byte & POWER_GOOD_MASK) != 0;
+    #[test]
+    fn ntc_cool() {
+        assert_eq!(ntc(0b011), NtcFault::Cool);
+    }
 
-        let charge_fault = match (fault_byte & CHRG_FAULT_MASK) >> CHRG_FAULT_SHIFT {
-            0b01 => ChargeFault::InputFault,
-            0b10 => ChargeFault::ThermalShutdown,
-            0b11 => ChargeFault::TimerExpiration,
-            _ => ChargeFault::Normal,
-        };
+    #[test]
+    fn ntc_cold() {
+        assert_eq!(ntc(0b101), NtcFault::Cold);
+    }
 
-        let ntc_fault = match fault_byte & NTC_FAULT_MASK {
-            0b001 => NtcFault::Cold,
-            0b010 => NtcFault::Cool,
-            0b101 => NtcFault::Warm,
-            0b110 => NtcFault::Hot,
-            _ => NtcFault::Normal,
-        };
+    #[test]
+    fn ntc_hot() {
+        assert_eq!(ntc(0b110), NtcFault::Hot);
+    }
 
-        let faults = FaultStatus {
-            watchdog_fault: (fault_byte & WATCHDOG_FAULT_MASK) != 0,
-            boost_fault: (fault_byte & BOOST_FAULT_MASK) != 0,
-            charge_fault,
-            battery_ovp_fault: (fault_byte & BAT_FAULT_MASK) != 0,
-            ntc_fault,
-        };
+    #[test]
+    fn ntc_code_derates_charge_current() {
+        let profile = profile::ChargeProfile::li_po_gentle(1000);
+        let current = |code| profile.derated_current(ntc(code));
+        assert_eq!(current(0b000), 0.3);
+        assert_eq!(current(0b010), 0.15);
+        assert_eq!(current(0b011), 0.15);
+        assert_eq!(current(0b101), 0.0);
+        assert_eq!(current(0b110), 0.0);
+    }
 
-        // --- ADC Formulas ---
-        let voltage = 2.304 + ((vbat_buf[0] & 0x7F) as f32 * 0.020);
-        let vbus_voltage = 2.6 + ((vbus_buf[0] & 0x7F) as f32 * 0.100);
-        let charge_current = (ichg_buf[0] & 0x7F) as f32 * 50.0 / 1000.0; // In Amperes
-        let battery_temp_percent = 21.0 + ((ts_buf[0] & 0x7F) as f32 * 0.465);
+    #[test]
+    fn ntc_ignores_other_fault_bits() {
+        let fault = WATCHDOG_FAULT_MASK | CHRG_FAULT_MASK | BAT_FAULT_MASK | 0b011;
+        let status = ChargerStatus::from_registers(0, fault);
+        assert_eq!(status.faults.ntc_fault, NtcFault::Cool);
+        assert!(status.faults.battery_ovp_fault);
+    }
 
-        Ok(BatteryData {
-            voltage,
-            vbus_voltage,
-            charge_current,
-            battery_temp_percent,
-            charging_status,
-            vbus_status,
-            power_good,
-            faults,
-        })
+    #[test]
+    fn decodes_measure_burst() {
+        let data = BatteryData::from_registers(&CHARGING, &NtcConfig::default());
+        assert_eq!(data.vbus_status, VbusStatus::Adapter);
+        assert_eq!(data.charging_status, ChargingStatus::FastCharge);
+        assert!(data.power_good);
+        assert_eq!(data.faults.charge_fault, ChargeFault::Normal);
+        assert_close(data.voltage, 3.804);
+        assert!(data.thermal_regulation);
+        assert_close(data.system_voltage, 3.904);
+        assert_close(data.battery_temp_percent, 59.13);
+        let temperature = data.battery_temp_celsius.unwrap();
+        assert!((temperature - 24.7).abs() < 0.1, "{temperature}");
+        assert!(data.vbus_good);
+        assert_close(data.vbus_voltage, 5.0);
+        assert_close(data.charge_current, 1.0);
+    }
+
+    #[test]
+    fn decodes_input_dpm_status() {
+        let data = BatteryData::from_registers(&CHARGING, &NtcConfig::default());
+        assert!(data.input_dpm.voltage_dpm);
+        assert!(data.input_dpm.current_dpm);
+        assert_close(data.input_dpm.input_current_limit, 1.0);
+        assert!(data.input_dpm.ico_optimized);
+
+        let mut regs = [0; MEASURE_BURST_LEN];
+        regs[8] = IDPM_LIM_MASK;
+        let dpm = BatteryData::from_registers(&regs, &NtcConfig::default()).input_dpm;
+        assert!(!dpm.voltage_dpm && !dpm.current_dpm && !dpm.ico_optimized);
+        // The largest limit, 100 mA + 63 * 50 mA.
+        assert_close(dpm.input_current_limit, 3.25);
+    }
+
+    fn one_shot_service(
+        i2c: MockI2c,
+    ) -> BatteryService<MockI2c, embedded_hal_async::i2c::ErrorKind> {
+        BatteryService::with_config(
+            i2c,
+            BatteryConfig {
+                adc_mode: AdcMode::OneShot,
+                ..BatteryConfig::default()
+            },
+        )
+    }
+
+    #[test]
+    fn one_shot_measure_starts_and_polls_conversion() {
+        let mut i2c = MockI2c {
+            conversion_reads: Some(2),
+            ..MockI2c::default()
+        };
+        // REG02 with the other bits set and the ADC idle.
+        i2c.regs[ADC_CTRL_REG as usize] = 0b0011_0001;
+        i2c.regs[SYS_STATUS_REG as usize..].copy_from_slice(&CHARGING);
+        let mut battery = one_shot_service(i2c);
+
+        let data = block_on(battery.measure()).unwrap();
+        assert_close(data.voltage, 3.804);
+        // A single conversion was started, continuous conversion stays off.
+        assert_eq!(battery.i2c.writes_to(ADC_CTRL_REG), [0b1011_0001]);
+        assert_eq!(battery.i2c.regs[ADC_CTRL_REG as usize], 0b0011_0001);
+        assert!(!battery.adc_enabled);
+
+        // The next measurement converts again.
+        battery.i2c.conversion_reads = Some(0);
+        block_on(battery.measure()).unwrap();
+        assert_eq!(battery.i2c.writes_to(ADC_CTRL_REG).len(), 2);
+    }
+
+    #[test]
+    fn one_shot_measure_times_out() {
+        let mut battery = one_shot_service(MockI2c::default());
+        assert!(block_on(battery.measure()).is_err());
+        assert_eq!(battery.i2c.writes_to(ADC_CTRL_REG), [CONV_START_MASK]);
     }
 }
@@ -0,0 +1,190 @@
+//! Test doubles for the BQ25896 I2C bus and its INT pin.
+
+extern crate std;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::vec::Vec;
+
+use embedded_hal::digital;
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::{self, I2c, Operation, SevenBitAddress};
+
+use crate::BQ25896_I2C_ADDR;
+
+/// A BQ25896 register file that records every register write.
+#[derive(Debug, Default)]
+pub(crate) struct MockI2c {
+    pub(crate) regs: [u8; 0x15],
+    pub(crate) writes: Vec<(u8, u8)>,
+    /// The number of REG02 reads that still see a one-shot conversion running before
+    /// CONV_START clears, or `None` if it never completes.
+    pub(crate) conversion_reads: Option<usize>,
+}
+
+impl MockI2c {
+    /// Returns the register writes to `reg`, in order.
+    pub(crate) fn writes_to(&self, reg: u8) -> Vec<u8> {
+        self.writes
+            .iter()
+            .filter(|(r, _)| *r == reg)
+            .map(|(_, value)| *value)
+            .collect()
+    }
+
+    /// Returns the position of the first write to `reg` in the write log.
+    pub(crate) fn first_write(&self, reg: u8) -> Option<usize> {
+        self.writes.iter().position(|(r, _)| *r == reg)
+    }
+
+    /// Advances a one-shot conversion started in REG02 (CONV_START set, CONV_RATE
+    /// clear).
+    fn convert(&mut self) {
+        if self.regs[0x02] & 0b1100_0000 != 0b1000_0000 {
+            return;
+        }
+        if let Some(reads) = &mut self.conversion_reads {
+            match reads.checked_sub(1) {
+                Some(left) => *reads = left,
+                None => self.regs[0x02] &= !0b1000_0000,
+            }
+        }
+    }
+}
+
+impl i2c::ErrorType for MockI2c {
+    type Error = i2c::ErrorKind;
+}
+
+impl I2c<SevenBitAddress> for MockI2c {
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.transact(address, operations)
+    }
+}
+
+impl MockI2c {
+    fn transact(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), i2c::ErrorKind> {
+        if address != BQ25896_I2C_ADDR {
+            return Err(i2c::ErrorKind::NoAcknowledge(
+                i2c::NoAcknowledgeSource::Address,
+            ));
+        }
+        // Like the BQ25896: the first written byte selects the register, following
+        // bytes are written to it and reads continue from there with auto-increment.
+        let mut pointer = None;
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    for &byte in bytes.iter() {
+                        match pointer {
+                            None => pointer = Some(byte as usize),
+                            Some(reg) => {
+                                *self.regs.get_mut(reg).ok_or(i2c::ErrorKind::Other)? = byte;
+                                self.writes.push((reg as u8, byte));
+                                pointer = Some(reg + 1);
+                            }
+                        }
+                    }
+                }
+                Operation::Read(buf) => {
+                    let start = pointer.ok_or(i2c::ErrorKind::Other)?;
+                    if (start..start + buf.len()).contains(&0x02) {
+                        self.convert();
+                    }
+                    let regs = self
+                        .regs
+                        .get(start..start + buf.len())
+                        .ok_or(i2c::ErrorKind::Other)?;
+                    buf.copy_from_slice(regs);
+                    pointer = Some(start + buf.len());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A handle to a `MockI2c` shared with other drivers, like a device on a shared bus.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SharedI2c(Rc<RefCell<MockI2c>>);
+
+impl SharedI2c {
+    /// Sets a register, as the charger itself would.
+    pub(crate) fn set_reg(&self, reg: u8, value: u8) {
+        self.0.borrow_mut().regs[reg as usize] = value;
+    }
+}
+
+impl i2c::ErrorType for SharedI2c {
+    type Error = i2c::ErrorKind;
+}
+
+impl I2c<SevenBitAddress> for SharedI2c {
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.0.borrow_mut().transact(address, operations)
+    }
+}
+
+/// An INT pin that delivers a fixed number of pulses and then fails, so a test
+/// waiting for a pulse that never comes ends instead of hanging.
+///
+/// Each pulse first applies the next of `changes`, a register and its new value, to
+/// `bus`, like the charger raising INT after a status change.
+#[derive(Debug, Default)]
+pub(crate) struct MockInt {
+    pub(crate) pulses: usize,
+    pub(crate) changes: VecDeque<(u8, u8)>,
+    pub(crate) bus: SharedI2c,
+}
+
+impl digital::ErrorType for MockInt {
+    type Error = digital::ErrorKind;
+}
+
+impl Wait for MockInt {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.pulse()
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.pulse()
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.pulse()
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.pulse()
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.pulse()
+    }
+}
+
+impl MockInt {
+    fn pulse(&mut self) -> Result<(), digital::ErrorKind> {
+        self.pulses = self
+            .pulses
+            .checked_sub(1)
+            .ok_or(digital::ErrorKind::Other)?;
+        if let Some((reg, value)) = self.changes.pop_front() {
+            self.bus.set_reg(reg, value);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,245 @@
+//! Charge profiles and JEITA temperature handling for the BQ25896.
+//!
+//! A `ChargeProfile` bundles the charge voltage, the fast/pre/termination currents
+//! and the JEITA behaviour of a cell. It is written to REG04-REG07 and REG09 in one
+//! call and kept by the `BatteryService`, so it can be restored after the I2C
+//! watchdog reverted the charger to its defaults.
+
+use embedded_hal_async::i2c::{I2c, SevenBitAddress};
+use log::{error, info, warn};
+
+use crate::{
+    BatteryService, NtcFault, BQ25896_I2C_ADDR, CHARGE_CURRENT_REG, ICHG_MASK, JEITA_ISET_MASK,
+    JEITA_VSET_MASK, MISC_CTRL_REG, TIMER_CTRL_REG, VRECHG_MASK, VREG_MASK, VREG_SHIFT,
+};
+
+/// Charge current applied by the charger in the JEITA cool zone (REG07 JEITA_ISET).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoolCurrent {
+    /// 50% of the fast charge current.
+    Percent50,
+    /// 20% of the fast charge current.
+    Percent20,
+}
+
+/// Charge voltage applied by the charger in the JEITA warm zone (REG09 JEITA_VSET).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarmVoltage {
+    /// The charge voltage is reduced by 200 mV.
+    Reduced,
+    /// The charge voltage is kept unchanged.
+    Unchanged,
+}
+
+/// The recharge threshold below the charge voltage (REG06 VRECHG).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RechargeThreshold {
+    /// Recharge once the battery drops 100 mV below the charge voltage.
+    Millivolts100,
+    /// Recharge once the battery drops 200 mV below the charge voltage.
+    Millivolts200,
+}
+
+/// Describes how a battery should be charged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChargeProfile {
+    /// The charge voltage in Volts (3.840 - 4.608 V, 16 mV steps).
+    pub charge_voltage: f32,
+    /// The fast charge current in Amperes (0 - 3.008 A, 64 mA steps).
+    pub fast_charge_current: f32,
+    /// The pre-charge current in Amperes (0.064 - 1.024 A, 64 mA steps).
+    pub precharge_current: f32,
+    /// The termination current in Amperes (0.064 - 1.024 A, 64 mA steps).
+    pub termination_current: f32,
+    /// The recharge threshold.
+    pub recharge_threshold: RechargeThreshold,
+    /// What the charger does by itself in the cool zone (0 - 10 °C).
+    pub cool_current: CoolCurrent,
+    /// What the charger does by itself in the warm zone (45 - 60 °C).
+    pub warm_voltage: WarmVoltage,
+    /// Additional derating (0.0 - 1.0) of the fast charge current applied by
+    /// `set_thermal_zone` in the cool zone.
+    pub cool_derating: f32,
+    /// Additional derating (0.0 - 1.0) of the fast charge current applied by
+    /// `set_thermal_zone` in the warm zone.
+    pub warm_derating: f32,
+}
+
+impl ChargeProfile {
+    /// A standard 4.2 V LiCoO2/NMC cell, charged at 0.5C.
+    pub fn li_ion_4v2(capacity_mah: u16) -> Self {
+        Self::from_capacity(4.208, capacity_mah, 0.5)
+    }
+
+    /// A 4.1 V profile for a standard Li-ion cell, trading capacity for cycle life.
+    pub fn li_ion_4v1_long_life(capacity_mah: u16) -> Self {
+        Self::from_capacity(4.096, capacity_mah, 0.5)
+    }
+
+    /// A high-voltage 4.35 V LiHV cell, charged at 0.5C.
+    pub fn li_hv_4v35(capacity_mah: u16) -> Self {
+        Self::from_capacity(4.352, capacity_mah, 0.5)
+    }
+
+    /// A Li-polymer pouch cell charged gently at 0.3C with extra cool/warm derating.
+    pub fn li_po_gentle(capacity_mah: u16) -> Self {
+        Self {
+            cool_current: CoolCurrent::Percent20,
+            cool_derating: 0.5,
+            warm_derating: 0.5,
+            ..Self::from_capacity(4.208, capacity_mah, 0.3)
+        }
+    }
+
+    fn from_capacity(charge_voltage: f32, capacity_mah: u16, c_rate: f32) -> Self {
+        let capacity = capacity_mah as f32 / 1000.0;
+        Self {
+            charge_voltage,
+            fast_charge_current: capacity * c_rate,
+            precharge_current: capacity * 0.1,
+            termination_current: capacity * 0.05,
+            recharge_threshold: RechargeThreshold::Millivolts100,
+            cool_current: CoolCurrent::Percent50,
+            warm_voltage: WarmVoltage::Reduced,
+            cool_derating: 1.0,
+            warm_derating: 1.0,
+        }
+    }
+
+    /// Returns the fast charge current to use in the given NTC zone, in Amperes.
+    ///
+    /// Charging is suspended by the charger in the cold and hot zones, so those
+    /// return zero.
+    pub fn derated_current(&self, zone: NtcFault) -> f32 {
+        match zone {
+            NtcFault::Normal => self.fast_charge_current,
+            NtcFault::Cool => self.fast_charge_current * self.cool_derating.clamp(0.0, 1.0),
+            NtcFault::Warm => self.fast_charge_current * self.warm_derating.clamp(0.0, 1.0),
+            NtcFault::Cold | NtcFault::Hot => 0.0,
+        }
+    }
+
+    /// Encodes REG04 (ICHG), REG05 (IPRECHG/ITERM) and REG06 (VREG/VRECHG) for the
+    /// given NTC zone. Bits not covered by the profile are left zero.
+    pub fn registers(&self, zone: NtcFault) -> [u8; 3] {
+        let ichg = to_steps(self.derated_current(zone), 0.0, 0.064, 47);
+        let iprechg = to_steps(self.precharge_current, 0.064, 0.064, 15);
+        let iterm = to_steps(self.termination_current, 0.064, 0.064, 15);
+        let vreg = to_steps(self.charge_voltage, 3.840, 0.016, 48);
+        let vrechg = match self.recharge_threshold {
+            RechargeThreshold::Millivolts100 => 0,
+            RechargeThreshold::Millivolts200 => VRECHG_MASK,
+        };
+        [ichg, (iprechg << 4) | iterm, (vreg << VREG_SHIFT) | vrechg]
+    }
+}
+
+/// Converts a value to a register step count, clamped to `[0, max_steps]`.
+fn to_steps(value: f32, offset: f32, step: f32, max_steps: u8) -> u8 {
+    let steps = (value - offset) / step + 0.5;
+    steps.clamp(0.0, max_steps as f32) as u8
+}
+
+impl<I2cType, ErrorType> BatteryService<I2cType, ErrorType>
+where
+    I2cType: I2c<SevenBitAddress, Error = ErrorType>,
+    ErrorType: embedded_hal_async::i2c::Error,
+{
+    /// Returns the charge profile applied by this service, if any.
+    pub fn charge_profile(&self) -> Option<&ChargeProfile> {
+        self.charge_profile.as_ref()
+    }
+
+    /// Writes a charge profile to the charger and remembers it for `restore_charge_profile`.
+    pub async fn apply_charge_profile(&mut self, profile: ChargeProfile) -> Result<(), ()> {
+        self.charge_profile = Some(profile);
+        self.write_charge_profile().await?;
+        info!("Applied charge profile: {profile:?}");
+        Ok(())
+    }
+
+    /// Re-applies the stored charge profile if the charger no longer holds it, e.g.
+    /// after a watchdog expiry or a register reset.
+    ///
+    /// Returns `true` if the profile had to be re-applied.
+    pub async fn restore_charge_profile(&mut self) -> Result<bool, ()> {
+        let Some(profile) = self.charge_profile else {
+            return Ok(false);
+        };
+        let expected = profile.registers(self.thermal_zone);
+        let mut current = [0u8; 3];
+        self.i2c
+            .write_read(BQ25896_I2C_ADDR, &[CHARGE_CURRENT_REG], &mut current)
+            .await
+            .map_err(|e| error!("I2C Error: {e:?}"))?;
+
+        let matches = (current[0] & ICHG_MASK) == expected[0]
+            && current[1] == expected[1]
+            && (current[2] & (VREG_MASK | VRECHG_MASK)) == expected[2];
+        if matches {
+            return Ok(false);
+        }
+        warn!("Charge profile lost, re-applying");
+        self.write_charge_profile().await?;
+        Ok(true)
+    }
+
+    /// Tells the service which NTC zone the battery is in, derating the fast charge
+    /// current according to the stored profile.
+    ///
+    /// Typically called with `FaultStatus::ntc_fault` from `measure` or a
+    /// `ChargerEvent::NtcFault` event.
+    pub async fn set_thermal_zone(&mut self, zone: NtcFault) -> Result<(), ()> {
+        if self.thermal_zone == zone {
+            return Ok(());
+        }
+        self.thermal_zone = zone;
+        let Some(profile) = self.charge_profile else {
+            return Ok(());
+        };
+        info!(
+            "Thermal zone {zone:?}, charge current {:.3} A",
+            profile.derated_current(zone)
+        );
+        let [ichg, _, _] = profile.registers(zone);
+        self.kick_watchdog().await?;
+        self.update_reg(CHARGE_CURRENT_REG, ICHG_MASK, ichg).await
+    }
+
+    /// Writes the stored profile, derated for the current thermal zone.
+    async fn write_charge_profile(&mut self) -> Result<(), ()> {
+        let Some(profile) = self.charge_profile else {
+            return Ok(());
+        };
+        self.kick_watchdog().await?;
+
+        let mut current = [0u8; 3];
+        self.i2c
+            .write_read(BQ25896_I2C_ADDR, &[CHARGE_CURRENT_REG], &mut current)
+            .await
+            .map_err(|e| error!("I2C Error: {e:?}"))?;
+        let [ichg, prechg_term, vreg] = profile.registers(self.thermal_zone);
+        let write = [
+            CHARGE_CURRENT_REG,
+            (current[0] & !ICHG_MASK) | ichg,
+            prechg_term,
+            (current[2] & !(VREG_MASK | VRECHG_MASK)) | vreg,
+        ];
+        self.i2c
+            .write(BQ25896_I2C_ADDR, &write)
+            .await
+            .map_err(|e| error!("I2C Error: {e:?}"))?;
+
+        let iset = match profile.cool_current {
+            CoolCurrent::Percent50 => 0,
+            CoolCurrent::Percent20 => JEITA_ISET_MASK,
+        };
+        self.update_reg(TIMER_CTRL_REG, JEITA_ISET_MASK, iset)
+            .await?;
+        let vset = match profile.warm_voltage {
+            WarmVoltage::Reduced => 0,
+            WarmVoltage::Unchanged => JEITA_VSET_MASK,
+        };
+        self.update_reg(MISC_CTRL_REG, JEITA_VSET_MASK, vset).await
+    }
+}
@@ -0,0 +1,339 @@
+//! Battery traces shared by the tests.
+//!
+//! The traces follow a 1350 mAh cell through a discharge at the T-Deck's idle load
+//! and a charge at 500 mA, sampled the way `read_measurements` reports them: the
+//! battery voltage in 20 mV and the charge current in 50 mA ADC steps, with load and
+//! ADC noise. The cell is deliberately a little off `FuelGaugeConfig::default()`:
+//! its capacity is 50 mAh lower, its resistance higher, its OCV wanders around
+//! `LI_ION_OCV_CURVE` by up to 8 mV and the discharge load varies between 50 and
+//! 150 mA. The reference state of charge is coulomb counted from the true current.
+
+/// A trace sample: seconds since the start of the trace, battery voltage in mV,
+/// charge current in mA and the reference state of charge in percent.
+pub(crate) type TraceSample = (u64, u16, u16, f32);
+
+/// Discharge from 97 % on battery, sampled every 5 minutes.
+pub(crate) const DISCHARGE: &[TraceSample] = &[
+    (0, 4140, 0, 97.0),
+    (300, 4120, 0, 96.4),
+    (600, 4120, 0, 95.7),
+    (900, 4120, 0, 95.0),
+    (1200, 4120, 0, 94.4),
+    (1500, 4100, 0, 93.8),
+    (1800, 4100, 0, 93.2),
+    (2100, 4080, 0, 92.4),
+    (2400, 4100, 0, 91.7),
+    (2700, 4080, 0, 90.9),
+    (3000, 4060, 0, 90.1),
+    (3300, 4040, 0, 89.1),
+    (3600, 4040, 0, 88.3),
+    (3900, 4060, 0, 87.6),
+    (4200, 4040, 0, 86.9),
+    (4500, 4020, 0, 86.2),
+    (4800, 4020, 0, 85.5),
+    (5100, 4020, 0, 84.8),
+    (5400, 4020, 0, 84.3),
+    (5700, 4020, 0, 83.6),
+    (6000, 4000, 0, 83.1),
+    (6300, 4000, 0, 82.5),
+    (6600, 4000, 0, 81.9),
+    (6900, 4000, 0, 81.5),
+    (7200, 3980, 0, 81.0),
+    (7500, 3980, 0, 80.5),
+    (7800, 3980, 0, 80.0),
+    (8100, 3980, 0, 79.5),
+    (8400, 3980, 0, 79.2),
+    (8700, 3980, 0, 78.9),
+    (9000, 3960, 0, 78.4),
+    (9300, 3960, 0, 77.8),
+    (9600, 3960, 0, 77.3),
+    (9900, 3960, 0, 76.8),
+    (10200, 3960, 0, 76.1),
+    (10500, 3940, 0, 75.6),
+    (10800, 3940, 0, 75.1),
+    (11100, 3920, 0, 74.4),
+    (11400, 3920, 0, 73.7),
+    (11700, 3920, 0, 73.0),
+    (12000, 3900, 0, 72.4),
+    (12300, 3920, 0, 71.7),
+    (12600, 3900, 0, 71.1),
+    (12900, 3900, 0, 70.4),
+    (13200, 3900, 0, 69.6),
+    (13500, 3900, 0, 68.9),
+    (13800, 3880, 0, 68.1),
+    (14100, 3880, 0, 67.2),
+    (14400, 3880, 0, 66.5),
+    (14700, 3860, 0, 65.8),
+    (15000, 3860, 0, 64.9),
+    (15300, 3860, 0, 64.3),
+    (15600, 3840, 0, 63.6),
+    (15900, 3860, 0, 63.0),
+    (16200, 3860, 0, 62.4),
+    (16500, 3840, 0, 61.9),
+    (16800, 3840, 0, 61.2),
+    (17100, 3820, 0, 60.7),
+    (17400, 3840, 0, 60.1),
+    (17700, 3840, 0, 59.5),
+    (18000, 3840, 0, 59.0),
+    (18300, 3840, 0, 58.6),
+    (18600, 3820, 0, 58.2),
+    (18900, 3820, 0, 57.9),
+    (19200, 3820, 0, 57.5),
+    (19500, 3820, 0, 57.1),
+    (19800, 3820, 0, 56.8),
+    (20100, 3820, 0, 56.4),
+    (20400, 3800, 0, 56.1),
+    (20700, 3800, 0, 55.5),
+    (21000, 3800, 0, 55.0),
+    (21300, 3820, 0, 54.5),
+    (21600, 3800, 0, 53.8),
+    (21900, 3780, 0, 53.1),
+    (22200, 3780, 0, 52.6),
+    (22500, 3780, 0, 51.9),
+    (22800, 3800, 0, 51.1),
+    (23100, 3780, 0, 50.5),
+    (23400, 3760, 0, 49.7),
+    (23700, 3780, 0, 48.9),
+    (24000, 3780, 0, 48.1),
+    (24300, 3760, 0, 47.2),
+    (24600, 3780, 0, 46.4),
+    (24900, 3780, 0, 45.7),
+    (25200, 3760, 0, 44.9),
+    (25500, 3760, 0, 44.2),
+    (25800, 3760, 0, 43.4),
+    (26100, 3760, 0, 42.6),
+    (26400, 3760, 0, 41.8),
+    (26700, 3740, 0, 41.3),
+    (27000, 3760, 0, 40.7),
+    (27300, 3760, 0, 40.2),
+    (27600, 3760, 0, 39.7),
+    (27900, 3760, 0, 39.1),
+    (28200, 3740, 0, 38.5),
+    (28500, 3740, 0, 37.9),
+    (28800, 3740, 0, 37.5),
+    (29100, 3760, 0, 37.1),
+    (29400, 3740, 0, 36.7),
+    (29700, 3760, 0, 36.2),
+    (30000, 3760, 0, 35.7),
+    (30300, 3760, 0, 35.3),
+    (30600, 3740, 0, 34.8),
+    (30900, 3760, 0, 34.2),
+    (31200, 3740, 0, 33.8),
+    (31500, 3740, 0, 33.3),
+    (31800, 3740, 0, 32.7),
+    (32100, 3720, 0, 32.1),
+    (32400, 3720, 0, 31.4),
+    (32700, 3740, 0, 30.8),
+    (33000, 3740, 0, 30.1),
+    (33300, 3720, 0, 29.4),
+    (33600, 3720, 0, 28.6),
+    (33900, 3720, 0, 27.8),
+    (34200, 3720, 0, 27.2),
+    (34500, 3720, 0, 26.2),
+    (34800, 3720, 0, 25.4),
+    (35100, 3700, 0, 24.6),
+    (35400, 3700, 0, 23.8),
+    (35700, 3720, 0, 23.0),
+    (36000, 3700, 0, 22.3),
+    (36300, 3720, 0, 21.6),
+    (36600, 3700, 0, 21.0),
+    (36900, 3720, 0, 20.3),
+    (37200, 3720, 0, 19.7),
+    (37500, 3700, 0, 19.0),
+    (37800, 3680, 0, 18.4),
+    (38100, 3680, 0, 17.9),
+    (38400, 3700, 0, 17.3),
+    (38700, 3700, 0, 16.9),
+    (39000, 3700, 0, 16.5),
+    (39300, 3700, 0, 16.0),
+    (39600, 3700, 0, 15.6),
+    (39900, 3680, 0, 15.1),
+    (40200, 3680, 0, 14.8),
+    (40500, 3680, 0, 14.4),
+    (40800, 3680, 0, 13.9),
+    (41100, 3680, 0, 13.5),
+    (41400, 3680, 0, 12.9),
+    (41700, 3660, 0, 12.4),
+    (42000, 3660, 0, 11.9),
+    (42300, 3660, 0, 11.3),
+    (42600, 3640, 0, 10.8),
+    (42900, 3620, 0, 10.1),
+    (43200, 3620, 0, 9.4),
+    (43500, 3600, 0, 8.7),
+    (43800, 3600, 0, 7.9),
+    (44100, 3580, 0, 7.3),
+    (44400, 3580, 0, 6.6),
+    (44700, 3560, 0, 6.0),
+    (45000, 3540, 0, 5.1),
+    (45300, 3500, 0, 4.4),
+    (45600, 3500, 0, 3.6),
+    (45900, 3480, 0, 2.7),
+];
+
+/// Charge from 8 % on USB, sampled every minute. The last sample is taken after
+/// the charger reported charge done.
+pub(crate) const CHARGE: &[TraceSample] = &[
+    (0, 3700, 500, 8.0),
+    (60, 3720, 500, 8.6),
+    (120, 3720, 500, 9.2),
+    (180, 3740, 500, 9.9),
+    (240, 3740, 500, 10.5),
+    (300, 3740, 500, 11.1),
+    (360, 3760, 500, 11.7),
+    (420, 3760, 500, 12.3),
+    (480, 3760, 500, 12.9),
+    (540, 3780, 500, 13.6),
+    (600, 3780, 500, 14.2),
+    (660, 3780, 500, 14.8),
+    (720, 3780, 500, 15.4),
+    (780, 3780, 500, 16.0),
+    (840, 3780, 500, 16.6),
+    (900, 3780, 500, 17.3),
+    (960, 3800, 500, 17.9),
+    (1020, 3800, 500, 18.5),
+    (1080, 3800, 500, 19.1),
+    (1140, 3800, 500, 19.7),
+    (1200, 3800, 500, 20.3),
+    (1260, 3820, 500, 21.0),
+    (1320, 3800, 500, 21.6),
+    (1380, 3800, 500, 22.2),
+    (1440, 3800, 500, 22.8),
+    (1500, 3820, 500, 23.4),
+    (1560, 3820, 500, 24.0),
+    (1620, 3820, 500, 24.7),
+    (1680, 3820, 500, 25.3),
+    (1740, 3820, 500, 25.9),
+    (1800, 3820, 500, 26.5),
+    (1860, 3820, 500, 27.1),
+    (1920, 3820, 500, 27.8),
+    (1980, 3840, 500, 28.4),
+    (2040, 3820, 500, 29.0),
+    (2100, 3840, 500, 29.6),
+    (2160, 3820, 500, 30.2),
+    (2220, 3820, 500, 30.8),
+    (2280, 3840, 500, 31.5),
+    (2340, 3840, 500, 32.1),
+    (2400, 3840, 500, 32.7),
+    (2460, 3840, 500, 33.3),
+    (2520, 3840, 500, 33.9),
+    (2580, 3840, 500, 34.5),
+    (2640, 3840, 500, 35.2),
+    (2700, 3840, 500, 35.8),
+    (2760, 3840, 500, 36.4),
+    (2820, 3860, 500, 37.0),
+    (2880, 3840, 500, 37.6),
+    (2940, 3840, 500, 38.2),
+    (3000, 3860, 500, 38.9),
+    (3060, 3860, 500, 39.5),
+    (3120, 3860, 500, 40.1),
+    (3180, 3860, 500, 40.7),
+    (3240, 3860, 500, 41.3),
+    (3300, 3860, 500, 42.0),
+    (3360, 3860, 500, 42.6),
+    (3420, 3860, 500, 43.2),
+    (3480, 3860, 500, 43.8),
+    (3540, 3860, 500, 44.4),
+    (3600, 3860, 500, 45.0),
+    (3660, 3860, 500, 45.7),
+    (3720, 3880, 500, 46.3),
+    (3780, 3880, 500, 46.9),
+    (3840, 3880, 500, 47.5),
+    (3900, 3880, 500, 48.1),
+    (3960, 3880, 500, 48.7),
+    (4020, 3880, 500, 49.4),
+    (4080, 3880, 500, 50.0),
+    (4140, 3880, 500, 50.6),
+    (4200, 3900, 500, 51.2),
+    (4260, 3900, 500, 51.8),
+    (4320, 3900, 500, 52.4),
+    (4380, 3900, 500, 53.1),
+    (4440, 3900, 500, 53.7),
+    (4500, 3900, 500, 54.3),
+    (4560, 3920, 500, 54.9),
+    (4620, 3900, 500, 55.5),
+    (4680, 3920, 500, 56.1),
+    (4740, 3920, 500, 56.8),
+    (4800, 3920, 500, 57.4),
+    (4860, 3920, 500, 58.0),
+    (4920, 3920, 500, 58.6),
+    (4980, 3940, 500, 59.2),
+    (5040, 3940, 500, 59.9),
+    (5100, 3940, 500, 60.5),
+    (5160, 3940, 500, 61.1),
+    (5220, 3940, 500, 61.7),
+    (5280, 3940, 500, 62.3),
+    (5340, 3960, 500, 62.9),
+    (5400, 3960, 500, 63.6),
+    (5460, 3960, 500, 64.2),
+    (5520, 3960, 500, 64.8),
+    (5580, 3960, 500, 65.4),
+    (5640, 3960, 500, 66.0),
+    (5700, 3980, 500, 66.6),
+    (5760, 3980, 500, 67.3),
+    (5820, 3980, 500, 67.9),
+    (5880, 4000, 500, 68.5),
+    (5940, 4000, 500, 69.1),
+    (6000, 4000, 500, 69.7),
+    (6060, 4000, 500, 70.3),
+    (6120, 4000, 500, 71.0),
+    (6180, 4020, 500, 71.6),
+    (6240, 4020, 500, 72.2),
+    (6300, 4020, 500, 72.8),
+    (6360, 4020, 500, 73.4),
+    (6420, 4040, 500, 74.0),
+    (6480, 4040, 500, 74.7),
+    (6540, 4040, 500, 75.3),
+    (6600, 4040, 500, 75.9),
+    (6660, 4060, 500, 76.5),
+    (6720, 4060, 500, 77.1),
+    (6780, 4060, 500, 77.8),
+    (6840, 4060, 500, 78.4),
+    (6900, 4060, 500, 79.0),
+    (6960, 4080, 500, 79.6),
+    (7020, 4080, 500, 80.2),
+    (7080, 4080, 500, 80.8),
+    (7140, 4080, 500, 81.5),
+    (7200, 4080, 500, 82.1),
+    (7260, 4100, 500, 82.7),
+    (7320, 4100, 500, 83.3),
+    (7380, 4120, 500, 83.9),
+    (7440, 4120, 500, 84.5),
+    (7500, 4120, 500, 85.2),
+    (7560, 4120, 500, 85.8),
+    (7620, 4140, 500, 86.4),
+    (7680, 4140, 500, 87.0),
+    (7740, 4140, 500, 87.6),
+    (7800, 4160, 500, 88.2),
+    (7860, 4160, 500, 88.9),
+    (7920, 4160, 500, 89.5),
+    (7980, 4180, 500, 90.1),
+    (8040, 4180, 500, 90.7),
+    (8100, 4180, 500, 91.3),
+    (8160, 4200, 500, 92.0),
+    (8220, 4200, 500, 92.6),
+    (8280, 4200, 500, 93.2),
+    (8340, 4200, 450, 93.8),
+    (8400, 4200, 450, 94.4),
+    (8460, 4220, 400, 94.9),
+    (8520, 4220, 350, 95.4),
+    (8580, 4200, 350, 95.8),
+    (8640, 4220, 300, 96.3),
+    (8700, 4220, 300, 96.6),
+    (8760, 4200, 250, 97.0),
+    (8820, 4200, 250, 97.3),
+    (8880, 4200, 200, 97.6),
+    (8940, 4220, 200, 97.8),
+    (9000, 4220, 200, 98.1),
+    (9060, 4220, 150, 98.3),
+    (9120, 4200, 150, 98.5),
+    (9180, 4220, 150, 98.7),
+    (9240, 4220, 150, 98.9),
+    (9300, 4220, 100, 99.0),
+    (9360, 4200, 100, 99.2),
+    (9420, 4200, 100, 99.3),
+    (9480, 4200, 100, 99.4),
+    (9540, 4200, 100, 99.5),
+    (9600, 4200, 50, 99.6),
+    (9660, 4220, 50, 99.7),
+    (9720, 4200, 0, 100.0),
+];
@@ -10,7 +10,7 @@ use esp_hal::time::Rate;
 use esp_hal::timer::systimer::SystemTimer;
 use log::{error, info};
 use t_deck_pro_gps_async::{Gps, PowerMode};
-use t_deck_pro_keyboard_async::keyboard::{KeyState, KeyboardController};
+use t_deck_pro_keyboard_async::keyboard::{KeyCode, KeyState, KeyboardController};
 
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
@@ -71,10 +71,10 @@ async fn main(_spawner: Spawner) {
             embassy_futures::select::Either::First(key_events) => {
                 if let Ok(events) = key_events {
                     for event in events {
-                        if event.state == KeyState::Up {
-                            info!("Key pressed: {:?}", event.key);
-                            match event.key {
-                                'q' => {
+                        if event.state == KeyState::Down {
+                            info!("Key pressed: {:?}", event.code);
+                            match event.code {
+                                KeyCode::Q => {
                                     info!("Attempting to recover GPS (cold start)...");
                                     if with_timeout(Duration::from_secs(1), gps.recovery())
                                         .await
@@ -85,7 +85,7 @@ async fn main(_spawner: Spawner) {
                                         info!("GPS recovery command sent successfully.");
                                     }
                                 }
-                                'w' => {
+                                KeyCode::W => {
                                     info!("Setting GPS to Normal mode...");
                                     if with_timeout(
                                         Duration::from_secs(1),
@@ -99,7 +99,7 @@ async fn main(_spawner: Spawner) {
                                         info!("Successfully set power mode to Normal.");
                                     }
                                 }
-                                'e' => {
+                                KeyCode::E => {
                                     info!("Setting GPS to Power Save mode static hold...");
                                     if with_timeout(
                                         Duration::from_secs(1),
@@ -116,7 +116,7 @@ async fn main(_spawner: Spawner) {
                                         info!("Successfully set power mode to Power Save.");
                                     }
                                 }
-                                'r' => {
+                                KeyCode::R => {
                                     info!("Setting GPS to Software Standby mode...");
                                     if with_timeout(
                                         Duration::from_secs(1),
@@ -139,6 +139,12 @@ async fn main(_spawner: Spawner) {
             embassy_futures::select::Either::Second(gps_data_result) => match gps_data_result {
                 Ok(gps_data) => {
                     info!("Received new GPS data: {gps_data:?}");
+                    let view = gps.satellite_view();
+                    info!(
+                        "Satellites in view: {}, used: {}",
+                        view.in_view(),
+                        view.used()
+                    );
                 }
                 Err(_) => {
                     //log::trace!("Error reading GPS message.");
@@ -0,0 +1,403 @@
+//! Assisted GNSS with UBX-MGA messages.
+//!
+//! A cold start without assistance has to download the almanac and ephemeris from
+//! the sky, which takes minutes. The receiver can instead be given its approximate
+//! time (MGA-INI-TIME_UTC) and position (MGA-INI-POS_LLH), AssistNow Offline orbit
+//! data (MGA-ANO) or a copy of its own navigation database (MGA-DBD) saved before it
+//! was switched off.
+//!
+//! Assistance blobs are streams of complete UBX frames, read from any
+//! `embedded_io_async::Read` source such as a file on the SD card. A database dump
+//! is written in the same format to any `embedded_io_async::Write` sink, so it can
+//! be injected again as is at the next boot.
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use embassy_time::{with_timeout, Duration, Timer};
+use embedded_hal::digital::OutputPin;
+use embedded_io_async::{Read, Write};
+use log::{debug, info, trace, warn};
+
+use crate::mux::Packet;
+use crate::ubx::{self, UbxDecoder, UbxMessage};
+use crate::{Gps, ACK_TIMEOUT};
+
+/// The length of an MGA-INI-TIME_UTC payload.
+pub const INI_TIME_UTC_LEN: usize = 24;
+/// The length of an MGA-INI-POS_LLH payload.
+pub const INI_POS_LLH_LEN: usize = 20;
+
+/// The message type of MGA-INI-POS_LLH.
+const INI_TYPE_POS_LLH: u8 = 0x01;
+/// The message type of MGA-INI-TIME_UTC.
+const INI_TYPE_TIME_UTC: u8 = 0x10;
+/// Leap seconds value telling the receiver to use its own.
+const LEAP_SECONDS_UNKNOWN: i8 = -128;
+
+/// Pause between messages sent without acknowledgement, so the receiver's input
+/// buffer does not overflow.
+const INJECT_PACING: Duration = Duration::from_millis(5);
+/// The database dump is complete when no entry arrived for this long.
+const DUMP_IDLE_TIMEOUT: Duration = Duration::from_secs(1);
+/// The size of the buffer used to copy dumped frames to the sink.
+const MAX_FRAME_LEN: usize = 256;
+
+/// Builds an MGA-INI-TIME_UTC payload.
+///
+/// # Arguments
+///
+/// * `utc` - The current UTC time.
+/// * `leap_seconds` - GPS-UTC leap seconds, or `None` to let the receiver use its own.
+/// * `accuracy` - The accuracy of `utc`.
+pub fn ini_time_utc(
+    utc: NaiveDateTime,
+    leap_seconds: Option<i8>,
+    accuracy: Duration,
+) -> [u8; INI_TIME_UTC_LEN] {
+    let accuracy_us = accuracy.as_micros();
+    let mut p = [0u8; INI_TIME_UTC_LEN];
+    p[0] = INI_TYPE_TIME_UTC;
+    // p[1]: version 0, p[2]: no time reference, the time is valid on reception.
+    p[3] = leap_seconds.unwrap_or(LEAP_SECONDS_UNKNOWN) as u8;
+    p[4..6].copy_from_slice(&(utc.year() as u16).to_le_bytes());
+    p[6] = utc.month() as u8;
+    p[7] = utc.day() as u8;
+    p[8] = utc.hour() as u8;
+    p[9] = utc.minute() as u8;
+    p[10] = utc.second() as u8;
+    p[12..16].copy_from_slice(&utc.nanosecond().min(999_999_999).to_le_bytes());
+    p[16..18]
+        .copy_from_slice(&((accuracy_us / 1_000_000).min(u16::MAX as u64) as u16).to_le_bytes());
+    p[20..24].copy_from_slice(&(((accuracy_us % 1_000_000) * 1000) as u32).to_le_bytes());
+    p
+}
+
+/// Builds an MGA-INI-POS_LLH payload.
+///
+/// # Arguments
+///
+/// * `latitude` - Latitude in decimal degrees.
+/// * `longitude` - Longitude in decimal degrees.
+/// * `altitude` - Height above the ellipsoid in meters.
+/// * `accuracy` - The position accuracy in meters.
+pub fn ini_pos_llh(
+    latitude: f64,
+    longitude: f64,
+    altitude: f32,
+    accuracy: f32,
+) -> [u8; INI_POS_LLH_LEN] {
+    let mut p = [0u8; INI_POS_LLH_LEN];
+    p[0] = INI_TYPE_POS_LLH;
+    p[4..8].copy_from_slice(&((latitude * 1e7) as i32).to_le_bytes());
+    p[8..12].copy_from_slice(&((longitude * 1e7) as i32).to_le_bytes());
+    p[12..16].copy_from_slice(&((altitude * 100.0) as i32).to_le_bytes());
+    p[16..20].copy_from_slice(&((accuracy * 100.0) as u32).to_le_bytes());
+    p
+}
+
+/// The outcome of injecting assistance data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InjectReport {
+    /// The number of MGA messages sent to the receiver.
+    pub sent: usize,
+    /// The number of messages the receiver rejected. Only counted when waiting for
+    /// acknowledgements.
+    pub rejected: usize,
+    /// The number of frames skipped because they were not MGA messages or invalid.
+    pub skipped: usize,
+}
+
+impl<UartType, PinType> Gps<UartType, PinType>
+where
+    UartType: Read + Write,
+    PinType: OutputPin,
+{
+    /// Gives the receiver its approximate UTC time with MGA-INI-TIME_UTC.
+    ///
+    /// # Arguments
+    ///
+    /// * `utc` - The current UTC time, e.g. from the RTC.
+    /// * `accuracy` - The accuracy of `utc`.
+    pub async fn inject_time(&mut self, utc: NaiveDateTime, accuracy: Duration) -> Result<(), ()> {
+        let payload = ini_time_utc(utc, None, accuracy);
+        self.send_ubx_message(ubx::class::MGA, ubx::id::MGA_INI, &payload)
+            .await
+    }
+
+    /// Gives the receiver its approximate position with MGA-INI-POS_LLH.
+    ///
+    /// # Arguments
+    ///
+    /// * `latitude` - Latitude in decimal degrees.
+    /// * `longitude` - Longitude in decimal degrees.
+    /// * `altitude` - Height above the ellipsoid in meters.
+    /// * `accuracy` - The position accuracy in meters.
+    pub async fn inject_position(
+        &mut self,
+        latitude: f64,
+        longitude: f64,
+        altitude: f32,
+        accuracy: f32,
+    ) -> Result<(), ()> {
+        let payload = ini_pos_llh(latitude, longitude, altitude, accuracy);
+        self.send_ubx_message(ubx::class::MGA, ubx::id::MGA_INI, &payload)
+            .await
+    }
+
+    /// Sends the MGA messages read from `source` to the receiver, e.g. MGA-ANO
+    /// data or a navigation database saved with `dump_navigation_database`.
+    ///
+    /// The source is read until its end. Anything that is not a valid UBX-MGA frame
+    /// is skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The assistance data, as a stream of UBX frames.
+    /// * `acknowledged` - Wait for an MGA-ACK after each message. Requires
+    ///   `keys::NAVSPG_ACKAIDING` to be enabled; otherwise the messages are paced.
+    pub async fn inject_assistance<SourceType: Read>(
+        &mut self,
+        source: &mut SourceType,
+        acknowledged: bool,
+    ) -> Result<InjectReport, ()> {
+        let mut report = InjectReport::default();
+        let mut decoder = UbxDecoder::new();
+        let mut chunk = [0u8; 64];
+        loop {
+            let len = source
+                .read(&mut chunk)
+                .await
+                .map_err(|e| log::error!("Assistance source Error: {e:?}"))?;
+            if len == 0 {
+                break;
+            }
+            for byte in &chunk[..len] {
+                let frame = match decoder.push(*byte) {
+                    Some(Ok(frame)) if frame.class == ubx::class::MGA => frame,
+                    Some(Ok(frame)) => {
+                        debug!(
+                            "Skipping non-MGA frame {:#04x} {:#04x}",
+                            frame.class, frame.id
+                        );
+                        report.skipped += 1;
+                        continue;
+                    }
+                    Some(Err(e)) => {
+                        debug!("Skipping invalid assistance frame: {e:?}");
+                        report.skipped += 1;
+                        continue;
+                    }
+                    None => continue,
+                };
+                let msg_id = frame.id;
+                self.send_ubx_message(frame.class, frame.id, frame.payload)
+                    .await?;
+                report.sent += 1;
+                if acknowledged {
+                    if !self.wait_for_mga_ack(msg_id).await? {
+                        report.rejected += 1;
+                    }
+                } else {
+                    Timer::after(INJECT_PACING).await;
+                }
+            }
+        }
+        info!(
+            "Injected {} assistance messages ({} rejected, {} skipped)",
+            report.sent, report.rejected, report.skipped
+        );
+        Ok(report)
+    }
+
+    /// Polls the receiver's navigation database with MGA-DBD and writes the entries
+    /// to `sink` as UBX frames. Returns the number of entries written.
+    ///
+    /// Save the dump before switching the receiver off and restore it at boot with
+    /// `inject_assistance` for a hot start.
+    pub async fn dump_navigation_database<SinkType: Write>(
+        &mut self,
+        sink: &mut SinkType,
+    ) -> Result<usize, ()> {
+        self.send_ubx_message(ubx::class::MGA, ubx::id::MGA_DBD, &[])
+            .await?;
+
+        let mut entries = 0;
+        let mut frame_buffer = [0u8; MAX_FRAME_LEN];
+        loop {
+            let frame_len = with_timeout(DUMP_IDLE_TIMEOUT, async {
+                loop {
+                    let byte = self.read_byte().await?;
+                    let Some(Ok(Packet::Ubx(frame))) = self.mux.push(byte) else {
+                        continue;
+                    };
+                    match UbxMessage::parse(frame) {
+                        Ok(UbxMessage::Other(frame))
+                            if (frame.class, frame.id) == (ubx::class::MGA, ubx::id::MGA_DBD) =>
+                        {
+                            return ubx::encode(
+                                frame.class,
+                                frame.id,
+                                frame.payload,
+                                &mut frame_buffer,
+                            )
+                            .map(Some)
+                            .map_err(|e| log::error!("Database entry too large: {e:?}"));
+                        }
+                        Ok(UbxMessage::MgaAck(ack)) if ack.msg_id == ubx::id::MGA_DBD => {
+                            return Ok(None);
+                        }
+                        Ok(message) => trace!("Skipping UBX message during dump: {message:?}"),
+                        Err(e) => trace!("Error parsing UBX message: {e:?}"),
+                    }
+                }
+            })
+            .await;
+
+            match frame_len {
+                Ok(Ok(Some(len))) => {
+                    sink.write_all(&frame_buffer[..len])
+                        .await
+                        .map_err(|e| log::error!("Database sink Error: {e:?}"))?;
+                    entries += 1;
+                }
+                Ok(Ok(None)) => break,
+                Ok(Err(())) => return Err(()),
+                Err(_) if entries > 0 => break,
+                Err(_) => {
+                    warn!("Timeout waiting for navigation database");
+                    return Err(());
+                }
+            }
+        }
+        sink.flush()
+            .await
+            .map_err(|e| log::error!("Database sink Error: {e:?}"))?;
+        info!("Dumped {entries} navigation database entries");
+        Ok(entries)
+    }
+
+    /// Waits for the MGA-ACK of the assistance message with the given ID and returns
+    /// whether the receiver accepted it.
+    async fn wait_for_mga_ack(&mut self, msg_id: u8) -> Result<bool, ()> {
+        with_timeout(ACK_TIMEOUT, async {
+            loop {
+                let byte = self.read_byte().await?;
+                let Some(Ok(Packet::Ubx(frame))) = self.mux.push(byte) else {
+                    continue;
+                };
+                if let Ok(UbxMessage::MgaAck(ack)) = UbxMessage::parse(frame) {
+                    if ack.msg_id == msg_id {
+                        if !ack.accepted {
+                            debug!("Assistance message rejected, info code {}", ack.info_code);
+                        }
+                        return Ok(ack.accepted);
+                    }
+                }
+            }
+        })
+        .await
+        .map_err(|_| warn!("Timeout waiting for MGA-ACK of {msg_id:#04x}"))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use chrono::NaiveDate;
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::{MockPin, MockUart};
+    use crate::testdata::GGA;
+
+    fn frame(class: u8, id: u8, payload: &[u8]) -> Vec<u8> {
+        let mut out = [0u8; MAX_FRAME_LEN];
+        let len = ubx::encode(class, id, payload, &mut out).unwrap();
+        out[..len].to_vec()
+    }
+
+    fn dbd_entry(payload: &[u8]) -> Vec<u8> {
+        frame(ubx::class::MGA, ubx::id::MGA_DBD, payload)
+    }
+
+    #[test]
+    fn encodes_ini_time_utc() {
+        let utc = NaiveDate::from_ymd_opt(2024, 5, 14)
+            .unwrap()
+            .and_hms_milli_opt(10, 23, 45, 500)
+            .unwrap();
+        assert_eq!(
+            ini_time_utc(utc, None, Duration::from_millis(1250)),
+            [
+                0x10, 0x00, 0x00, 0x80, 0xE8, 0x07, 0x05, 0x0E, 0x0A, 0x17, 0x2D, 0x00, 0x00, 0x65,
+                0xCD, 0x1D, 0x01, 0x00, 0x00, 0x00, 0x80, 0xB2, 0xE6, 0x0E,
+            ]
+        );
+        assert_eq!(ini_time_utc(utc, Some(18), Duration::from_secs(2))[3], 18);
+    }
+
+    #[test]
+    fn encodes_ini_pos_llh() {
+        assert_eq!(
+            ini_pos_llh(47.5, -19.25, 101.5, 10.0),
+            [
+                0x01, 0x00, 0x00, 0x00, 0xC0, 0xEC, 0x4F, 0x1C, 0xE0, 0xAE, 0x86, 0xF4, 0xA6, 0x27,
+                0x00, 0x00, 0xE8, 0x03, 0x00, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn dumps_navigation_database() {
+        let ack = frame(
+            ubx::class::MGA,
+            ubx::id::MGA_ACK,
+            &[1, 0, 0, 0x80, 0, 0, 0, 0],
+        );
+        let entries = [dbd_entry(&[0x01, 0x02, 0x03]), dbd_entry(&[0xAA; 40])];
+        // NMEA output keeps arriving between the entries.
+        let rx = [GGA, &entries[0], GGA, &entries[1], &ack].concat();
+        let mut gps = Gps::with_uart(MockUart::new(&rx, 16), MockPin);
+
+        let mut dump = [0u8; 128];
+        let mut sink = &mut dump[..];
+        assert_eq!(block_on(gps.dump_navigation_database(&mut sink)), Ok(2));
+        let written = 128 - sink.len();
+
+        // The poll was sent and the entries were written as complete UBX frames.
+        assert_eq!(
+            gps.uart.tx,
+            [0xB5, 0x62, 0x13, 0x80, 0x00, 0x00, 0x93, 0xCC]
+        );
+        assert_eq!(dump[..written], entries.concat());
+    }
+
+    #[test]
+    fn injects_dumped_database() {
+        let entries = [dbd_entry(&[0x01, 0x02, 0x03]), dbd_entry(&[0xAA; 40])];
+        // A non-MGA frame and a corrupted frame in the blob are skipped.
+        let mut corrupted = dbd_entry(&[0x04]);
+        *corrupted.last_mut().unwrap() ^= 0xFF;
+        let blob = [
+            &entries[0][..],
+            &frame(ubx::class::NAV, ubx::id::NAV_PVT, &[0; 4]),
+            &corrupted,
+            &entries[1],
+        ]
+        .concat();
+        let mut gps = Gps::with_uart(MockUart::new(&[], 16), MockPin);
+
+        let report = block_on(gps.inject_assistance(&mut &blob[..], false));
+        assert_eq!(
+            report,
+            Ok(InjectReport {
+                sent: 2,
+                rejected: 0,
+                skipped: 2,
+            })
+        );
+        assert_eq!(gps.uart.tx, entries.concat());
+    }
+}
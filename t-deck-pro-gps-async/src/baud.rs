@@ -0,0 +1,292 @@
+//! UART baud rate probing and switching.
+//!
+//! The receiver keeps its baud rate in BBR or flash, so after a configuration
+//! change it may no longer talk at the rate `Gps::new` assumes. `Gps::probe_baud_rate`
+//! tries a list of rates until it sees valid NMEA or UBX data, and
+//! `Gps::set_baud_rate` moves both ends to a new rate with CFG-UART1-BAUDRATE and
+//! verifies the link, falling back to the previous rate if the receiver does not
+//! answer.
+//!
+//! The host UART must implement `SetBaudRate`; it is implemented for the esp-hal
+//! UART with the `t-deck` feature.
+
+use embassy_time::{with_timeout, Duration, Timer};
+use embedded_hal::digital::OutputPin;
+use embedded_io_async::{Read, Write};
+#[cfg(feature = "t-deck")]
+use esp_hal::{
+    uart::{Config, ConfigError, Uart},
+    Async,
+};
+use log::{info, warn};
+
+use crate::cfg::{keys, Layers, ValSet};
+use crate::mux::Packet;
+use crate::{ubx, Gps};
+
+/// The factory default baud rate of the receiver on the T-Deck.
+pub const DEFAULT_BAUD_RATE: u32 = 38400;
+
+/// The baud rates supported by the receiver, most likely first.
+pub const BAUD_RATES: [u32; 8] = [38400, 9600, 115200, 230400, 460800, 921600, 57600, 19200];
+
+/// How long to listen for valid data at each probed rate.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(1500);
+/// The number of NMEA sentences with a valid checksum needed to accept a probed
+/// rate. A single UBX frame is enough, its checksum is stronger.
+const PROBE_SENTENCES: u32 = 2;
+/// How long the receiver needs to switch to a new baud rate.
+const SWITCH_DELAY: Duration = Duration::from_millis(100);
+
+/// A UART whose baud rate can be changed at runtime.
+pub trait SetBaudRate {
+    /// The error returned if the rate cannot be set.
+    type Error: core::fmt::Debug;
+
+    /// Sets the baud rate of the UART.
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), Self::Error>;
+}
+
+#[cfg(feature = "t-deck")]
+impl SetBaudRate for Uart<'_, Async> {
+    type Error = ConfigError;
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), Self::Error> {
+        self.apply_config(&Config::default().with_baudrate(baud_rate))
+    }
+}
+
+/// Returns `true` if `sentence` ends with a valid NMEA checksum.
+fn has_valid_checksum(sentence: &str) -> bool {
+    let Some((body, checksum)) = sentence
+        .strip_prefix('$')
+        .and_then(|sentence| sentence.rsplit_once('*'))
+    else {
+        return false;
+    };
+    let calculated = body.bytes().fold(0u8, |acc, b| acc ^ b);
+    u8::from_str_radix(checksum, 16) == Ok(calculated)
+}
+
+impl<UartType, PinType> Gps<UartType, PinType>
+where
+    UartType: Read + Write + SetBaudRate,
+    PinType: OutputPin,
+{
+    /// Returns the baud rate the driver talks at, if known.
+    ///
+    /// `Gps::new` starts at `DEFAULT_BAUD_RATE`; with `Gps::with_uart` the rate is
+    /// unknown until it is probed or set.
+    pub fn baud_rate(&self) -> Option<u32> {
+        self.baud_rate
+    }
+
+    /// Finds the receiver's baud rate by trying each of `candidates` until valid
+    /// NMEA or UBX data is received. The host UART is left at the found rate.
+    pub async fn probe_baud_rate(&mut self, candidates: &[u32]) -> Result<u32, ()> {
+        for &baud_rate in candidates {
+            self.set_host_baud_rate(baud_rate).await?;
+            // Ask for a response in case periodic output is disabled.
+            self.send_ubx_message(ubx::class::MON, ubx::id::MON_VER, &[])
+                .await?;
+            if self.receives_valid_data().await {
+                info!("GPS found at {baud_rate} baud");
+                self.baud_rate = Some(baud_rate);
+                return Ok(baud_rate);
+            }
+        }
+        warn!("GPS baud rate not found");
+        self.baud_rate = None;
+        Err(())
+    }
+
+    /// Switches the receiver and the host UART to `baud_rate` and verifies the link.
+    ///
+    /// If the receiver does not answer at the new rate, the host switches back to
+    /// the previous rate (probing `BAUD_RATES` if it is unknown) and `Err(())` is
+    /// returned. Write the rate to `Layers::RAM` only until it has been verified;
+    /// a rate stored in BBR or flash that the host does not expect has to be probed
+    /// after the next boot.
+    ///
+    /// # Arguments
+    ///
+    /// * `baud_rate` - The new baud rate, one of `BAUD_RATES`. Other rates are
+    ///   rejected before anything is sent.
+    /// * `layers` - The configuration layers to store the rate in.
+    pub async fn set_baud_rate(&mut self, baud_rate: u32, layers: Layers) -> Result<(), ()> {
+        if !BAUD_RATES.contains(&baud_rate) {
+            log::error!("Unsupported baud rate {baud_rate}");
+            return Err(());
+        }
+        let previous = self.baud_rate;
+        let mut config = ValSet::new(layers);
+        config
+            .set(keys::UART1_BAUDRATE, baud_rate)
+            .map_err(|e| log::error!("Invalid configuration: {e:?}"))?;
+        self.send_ubx_message(ubx::class::CFG, ubx::id::CFG_VALSET, config.payload())
+            .await?;
+        // The ACK may already be sent at the new rate, so it is not waited for.
+        self.uart
+            .flush()
+            .await
+            .map_err(|e| log::error!("UART Error: {e:?}"))?;
+        Timer::after(SWITCH_DELAY).await;
+
+        self.set_host_baud_rate(baud_rate).await?;
+        if self.poll_version().await.is_ok() {
+            info!("GPS baud rate set to {baud_rate}");
+            self.baud_rate = Some(baud_rate);
+            return Ok(());
+        }
+
+        warn!("GPS not responding at {baud_rate} baud, falling back");
+        match previous {
+            Some(previous) => {
+                self.set_host_baud_rate(previous).await?;
+                self.baud_rate = Some(previous);
+                if self.poll_version().await.is_err() {
+                    self.probe_baud_rate(&BAUD_RATES).await?;
+                }
+            }
+            None => {
+                self.probe_baud_rate(&BAUD_RATES).await?;
+            }
+        }
+        Err(())
+    }
+
+    /// Sets the host UART to `baud_rate` and drops data received at the old rate.
+    async fn set_host_baud_rate(&mut self, baud_rate: u32) -> Result<(), ()> {
+        self.uart
+            .flush()
+            .await
+            .map_err(|e| log::error!("UART Error: {e:?}"))?;
+        self.uart
+            .set_baud_rate(baud_rate)
+            .map_err(|e| log::error!("Failed to set UART baud rate {baud_rate}: {e:?}"))?;
+        self.rx_pos = self.rx_len;
+        self.mux.reset();
+        Ok(())
+    }
+
+    /// Listens for a UBX frame or `PROBE_SENTENCES` valid NMEA sentences within
+    /// `PROBE_TIMEOUT`.
+    async fn receives_valid_data(&mut self) -> bool {
+        let mut sentences = 0;
+        let result = with_timeout(PROBE_TIMEOUT, async {
+            loop {
+                let byte = self.read_byte().await?;
+                match self.mux.push(byte) {
+                    Some(Ok(Packet::Ubx(_))) => return Ok::<(), ()>(()),
+                    Some(Ok(Packet::Nmea(sentence))) if has_valid_checksum(sentence) => {
+                        sentences += 1;
+                        if sentences >= PROBE_SENTENCES {
+                            return Ok(());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        })
+        .await;
+        matches!(result, Ok(Ok(())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::{MockBaudUart, MockPin};
+    use crate::testdata::{GGA, RMC};
+
+    fn receiver(uart: MockBaudUart) -> Gps<MockBaudUart, MockPin> {
+        Gps::with_uart(uart, MockPin)
+    }
+
+    #[test]
+    fn checks_nmea_checksums() {
+        assert!(has_valid_checksum("$GPGSV,2,2,05,23,41,210,35,1*50"));
+        assert!(!has_valid_checksum("$GPGSV,2,2,05,23,41,210,35,1*51"));
+        assert!(!has_valid_checksum("$GPGSV,2,2,05,23,41,210,35,1*5"));
+        assert!(!has_valid_checksum("$GPGSV,2,2,05,23,41,210,35,1*zz"));
+        assert!(!has_valid_checksum("$GPGSV,2,2,05,23,41,210,35,1"));
+        assert!(!has_valid_checksum("GPGSV,2,2,05,23,41,210,35,1*50"));
+    }
+
+    #[test]
+    fn probes_with_ubx_poll() {
+        let mut gps = receiver(MockBaudUart::new(115200, 0));
+        assert_eq!(gps.baud_rate(), None);
+        assert_eq!(block_on(gps.probe_baud_rate(&BAUD_RATES)), Ok(115200));
+        assert_eq!(gps.baud_rate(), Some(115200));
+        assert_eq!(gps.uart.host_rates, [38400, 9600, 115200]);
+    }
+
+    #[test]
+    fn probes_with_nmea_output() {
+        let mut uart = MockBaudUart::new(9600, 0);
+        uart.answers_polls = false;
+        uart.output = [GGA, RMC].concat();
+        let mut gps = receiver(uart);
+        assert_eq!(block_on(gps.probe_baud_rate(&BAUD_RATES)), Ok(9600));
+    }
+
+    #[test]
+    fn probe_fails_without_valid_data() {
+        let mut gps = receiver(MockBaudUart::new(4800, 0));
+        assert_eq!(block_on(gps.probe_baud_rate(&BAUD_RATES)), Err(()));
+        assert_eq!(gps.baud_rate(), None);
+        assert_eq!(gps.uart.host_rates, BAUD_RATES);
+
+        // Sentences with a wrong checksum do not count.
+        let mut uart = MockBaudUart::new(9600, 0);
+        uart.answers_polls = false;
+        uart.output = b"$GPGSV,2,2,05,23,41,210,35,1*51\r\n".to_vec();
+        let mut gps = receiver(uart);
+        assert_eq!(block_on(gps.probe_baud_rate(&[9600])), Err(()));
+    }
+
+    #[test]
+    fn switches_baud_rate() {
+        let mut gps = receiver(MockBaudUart::new(38400, 0));
+        block_on(gps.probe_baud_rate(&BAUD_RATES)).unwrap();
+        assert_eq!(block_on(gps.set_baud_rate(115200, Layers::RAM)), Ok(()));
+        assert_eq!(gps.baud_rate(), Some(115200));
+        assert_eq!(gps.uart.device_rate, 115200);
+        assert_eq!(gps.uart.host_rates, [38400, 115200]);
+    }
+
+    #[test]
+    fn falls_back_to_previous_rate() {
+        let mut uart = MockBaudUart::new(38400, 0);
+        uart.applies_rate = false;
+        let mut gps = receiver(uart);
+        block_on(gps.probe_baud_rate(&BAUD_RATES)).unwrap();
+        assert_eq!(block_on(gps.set_baud_rate(115200, Layers::RAM)), Err(()));
+        assert_eq!(gps.baud_rate(), Some(38400));
+        assert_eq!(gps.uart.host_rates, [38400, 115200, 38400]);
+    }
+
+    #[test]
+    fn probes_when_previous_rate_is_unknown() {
+        let mut uart = MockBaudUart::new(9600, 9600);
+        uart.applies_rate = false;
+        let mut gps = receiver(uart);
+        assert_eq!(block_on(gps.set_baud_rate(115200, Layers::RAM)), Err(()));
+        assert_eq!(gps.baud_rate(), Some(9600));
+        assert_eq!(gps.uart.host_rates, [115200, 38400, 9600]);
+    }
+
+    #[test]
+    fn rejects_unsupported_rate() {
+        let mut gps = receiver(MockBaudUart::new(38400, 38400));
+        block_on(gps.probe_baud_rate(&[38400])).unwrap();
+        let sent = gps.uart.tx.len();
+        assert_eq!(block_on(gps.set_baud_rate(12345, Layers::RAM)), Err(()));
+        assert_eq!(gps.uart.tx.len(), sent);
+        assert_eq!(gps.uart.host_rates, [38400]);
+        assert_eq!(gps.baud_rate(), Some(38400));
+    }
+}
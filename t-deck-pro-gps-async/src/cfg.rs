@@ -0,0 +1,582 @@
+//! Typed configuration keys for UBX-CFG-VALSET and UBX-CFG-VALGET.
+//!
+//! The M10 is configured through 32-bit key IDs. Bits 28-30 of a key ID encode the
+//! size of its value, so a `Key<T>` pairs an ID with the Rust type of that value.
+//! `ValSet` packs any number of keys into one VALSET payload, `valget_request`
+//! builds a VALGET poll and `ValGetResponse` reads the values back.
+
+use core::marker::PhantomData;
+use core::ops::BitOr;
+
+use heapless::Vec;
+
+/// The largest VALSET payload that can be sent in one message.
+pub const MAX_VALSET_LEN: usize = 248;
+/// The most keys a single VALSET or VALGET message may carry.
+pub const MAX_KEYS: usize = 64;
+
+/// Errors produced while building or parsing configuration messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CfgError {
+    /// The message is full.
+    TooManyKeys,
+    /// The value type does not match the size encoded in the key ID.
+    SizeMismatch(u32),
+    /// The VALGET response is truncated or contains an unknown key size.
+    InvalidResponse,
+}
+
+/// A set of configuration layers to write with VALSET.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layers(u8);
+
+impl Layers {
+    /// Volatile RAM, the currently active configuration.
+    pub const RAM: Layers = Layers(0x01);
+    /// Battery-backed RAM, survives a reset as long as V_BCKP is supplied.
+    pub const BBR: Layers = Layers(0x02);
+    /// External flash, if fitted.
+    pub const FLASH: Layers = Layers(0x04);
+
+    /// The raw layer bitmask.
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+}
+
+impl BitOr for Layers {
+    type Output = Layers;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Layers(self.0 | rhs.0)
+    }
+}
+
+/// The single layer read by VALGET.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadLayer {
+    /// The currently active configuration.
+    Ram,
+    /// Battery-backed RAM.
+    Bbr,
+    /// External flash.
+    Flash,
+    /// The firmware defaults.
+    Default,
+}
+
+impl ReadLayer {
+    fn bits(&self) -> u8 {
+        match self {
+            ReadLayer::Ram => 0,
+            ReadLayer::Bbr => 1,
+            ReadLayer::Flash => 2,
+            ReadLayer::Default => 7,
+        }
+    }
+}
+
+/// A value that can be stored in a configuration key.
+pub trait CfgValue: Copy {
+    /// The size of the encoded value in bytes.
+    const SIZE: usize;
+
+    /// Writes the little-endian encoding of the value to `out`, which is `SIZE` long.
+    fn encode(&self, out: &mut [u8]);
+
+    /// Decodes a value from `bytes`, which is `SIZE` long.
+    fn decode(bytes: &[u8]) -> Self;
+}
+
+impl CfgValue for bool {
+    const SIZE: usize = 1;
+
+    fn encode(&self, out: &mut [u8]) {
+        out[0] = *self as u8;
+    }
+
+    fn decode(bytes: &[u8]) -> Self {
+        bytes[0] & 0x01 != 0
+    }
+}
+
+macro_rules! impl_cfg_value {
+    ($($ty:ty),*) => {
+        $(
+            impl CfgValue for $ty {
+                const SIZE: usize = core::mem::size_of::<$ty>();
+
+                fn encode(&self, out: &mut [u8]) {
+                    out.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(bytes: &[u8]) -> Self {
+                    let mut raw = [0u8; core::mem::size_of::<$ty>()];
+                    raw.copy_from_slice(bytes);
+                    <$ty>::from_le_bytes(raw)
+                }
+            }
+        )*
+    };
+}
+
+impl_cfg_value!(u8, i8, u16, i16, u32, i32, u64, i64);
+
+/// Returns the value size in bytes encoded in a key ID, or `None` if it is invalid.
+pub fn value_size(key_id: u32) -> Option<usize> {
+    match (key_id >> 28) & 0x07 {
+        1 | 2 => Some(1),
+        3 => Some(2),
+        4 => Some(4),
+        5 => Some(8),
+        _ => None,
+    }
+}
+
+/// A configuration key with a value of type `T`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Key<T> {
+    id: u32,
+    _value: PhantomData<T>,
+}
+
+impl<T> Clone for Key<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Key<T> {}
+
+impl<T: CfgValue> Key<T> {
+    /// Creates a key from its 32-bit ID.
+    pub const fn new(id: u32) -> Self {
+        Self {
+            id,
+            _value: PhantomData,
+        }
+    }
+
+    /// The 32-bit key ID.
+    pub const fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+/// The key catalogue of the M10 receiver.
+pub mod keys {
+    use super::Key;
+
+    /// CFG-RATE-MEAS: nominal time between measurements in ms.
+    pub const RATE_MEAS: Key<u16> = Key::new(0x3021_0001);
+    /// CFG-RATE-NAV: number of measurements per navigation solution.
+    pub const RATE_NAV: Key<u16> = Key::new(0x3021_0002);
+    /// CFG-RATE-TIMEREF: time system the measurements are aligned to (0 UTC, 1 GPS).
+    pub const RATE_TIMEREF: Key<u8> = Key::new(0x2021_0003);
+
+    /// CFG-MSGOUT-NMEA_ID_GGA_UART1: GGA output rate on UART1.
+    pub const MSGOUT_NMEA_GGA_UART1: Key<u8> = Key::new(0x2091_00bb);
+    /// CFG-MSGOUT-NMEA_ID_GLL_UART1: GLL output rate on UART1.
+    pub const MSGOUT_NMEA_GLL_UART1: Key<u8> = Key::new(0x2091_00ca);
+    /// CFG-MSGOUT-NMEA_ID_GSA_UART1: GSA output rate on UART1.
+    pub const MSGOUT_NMEA_GSA_UART1: Key<u8> = Key::new(0x2091_00c0);
+    /// CFG-MSGOUT-NMEA_ID_GSV_UART1: GSV output rate on UART1.
+    pub const MSGOUT_NMEA_GSV_UART1: Key<u8> = Key::new(0x2091_00c5);
+    /// CFG-MSGOUT-NMEA_ID_RMC_UART1: RMC output rate on UART1.
+    pub const MSGOUT_NMEA_RMC_UART1: Key<u8> = Key::new(0x2091_00ac);
+    /// CFG-MSGOUT-NMEA_ID_VTG_UART1: VTG output rate on UART1.
+    pub const MSGOUT_NMEA_VTG_UART1: Key<u8> = Key::new(0x2091_00b1);
+    /// CFG-MSGOUT-NMEA_ID_ZDA_UART1: ZDA output rate on UART1.
+    pub const MSGOUT_NMEA_ZDA_UART1: Key<u8> = Key::new(0x2091_00d9);
+    /// CFG-MSGOUT-UBX_NAV_PVT_UART1: NAV-PVT output rate on UART1.
+    pub const MSGOUT_UBX_NAV_PVT_UART1: Key<u8> = Key::new(0x2091_0007);
+    /// CFG-MSGOUT-UBX_NAV_SAT_UART1: NAV-SAT output rate on UART1.
+    pub const MSGOUT_UBX_NAV_SAT_UART1: Key<u8> = Key::new(0x2091_0016);
+    /// CFG-MSGOUT-UBX_NAV_STATUS_UART1: NAV-STATUS output rate on UART1.
+    pub const MSGOUT_UBX_NAV_STATUS_UART1: Key<u8> = Key::new(0x2091_001b);
+    /// CFG-MSGOUT-UBX_NAV_TIMEUTC_UART1: NAV-TIMEUTC output rate on UART1.
+    pub const MSGOUT_UBX_NAV_TIMEUTC_UART1: Key<u8> = Key::new(0x2091_005c);
+
+    /// CFG-NAVSPG-FIXMODE: position fix mode (1 2D only, 2 3D only, 3 auto).
+    pub const NAVSPG_FIXMODE: Key<u8> = Key::new(0x2011_0011);
+    /// CFG-NAVSPG-ACKAIDING: acknowledge assistance input with UBX-MGA-ACK.
+    pub const NAVSPG_ACKAIDING: Key<bool> = Key::new(0x1011_0025);
+    /// CFG-NAVSPG-DYNMODEL: dynamic platform model.
+    pub const NAVSPG_DYNMODEL: Key<u8> = Key::new(0x2011_0021);
+    /// CFG-NAVSPG-INFIL_MINELEV: minimum elevation for a satellite to be used, in degrees.
+    pub const NAVSPG_INFIL_MINELEV: Key<i8> = Key::new(0x2011_00a4);
+    /// CFG-NAVSPG-INFIL_NCNOTHRS: number of satellites required above the C/N0 threshold.
+    pub const NAVSPG_INFIL_NCNOTHRS: Key<u8> = Key::new(0x2011_00aa);
+    /// CFG-NAVSPG-INFIL_CNOTHRS: C/N0 threshold in dBHz.
+    pub const NAVSPG_INFIL_CNOTHRS: Key<u8> = Key::new(0x2011_00ab);
+    /// CFG-NAVSPG-OUTFIL_PDOP: position DOP mask, scaled by 0.1.
+    pub const NAVSPG_OUTFIL_PDOP: Key<u16> = Key::new(0x3011_00b1);
+    /// CFG-NAVSPG-OUTFIL_PACC: position accuracy mask in m.
+    pub const NAVSPG_OUTFIL_PACC: Key<u16> = Key::new(0x3011_00b3);
+
+    /// CFG-PM-OPERATEMODE: power mode (0 full power, 1 PSMOO, 2 PSMCT).
+    pub const PM_OPERATEMODE: Key<u8> = Key::new(0x20d0_0001);
+    /// CFG-PM-POSUPDATEPERIOD: position update period in s, 0 disables updates.
+    pub const PM_POSUPDATEPERIOD: Key<u32> = Key::new(0x40d0_0002);
+    /// CFG-PM-ACQPERIOD: acquisition retry period in s after a failed fix.
+    pub const PM_ACQPERIOD: Key<u32> = Key::new(0x40d0_0003);
+    /// CFG-PM-GRIDOFFSET: position update grid offset relative to GPS start of week, in s.
+    pub const PM_GRIDOFFSET: Key<u32> = Key::new(0x40d0_0004);
+    /// CFG-PM-ONTIME: time to stay in tracking state after a fix, in s.
+    pub const PM_ONTIME: Key<u16> = Key::new(0x30d0_0005);
+    /// CFG-PM-MINACQTIME: minimum acquisition time in s.
+    pub const PM_MINACQTIME: Key<u8> = Key::new(0x20d0_0006);
+    /// CFG-PM-MAXACQTIME: maximum acquisition time in s.
+    pub const PM_MAXACQTIME: Key<u8> = Key::new(0x20d0_0007);
+    /// CFG-PM-DONOTENTEROFF: stay in acquisition instead of going off if no fix is found.
+    pub const PM_DONOTENTEROFF: Key<bool> = Key::new(0x10d0_0008);
+    /// CFG-PM-WAITTIMEFIX: wait for a time fix before entering tracking.
+    pub const PM_WAITTIMEFIX: Key<bool> = Key::new(0x10d0_0009);
+    /// CFG-PM-UPDATEEPH: wake up to update ephemeris data.
+    pub const PM_UPDATEEPH: Key<bool> = Key::new(0x10d0_000a);
+    /// CFG-PM-EXTINTWAKE: wake the receiver on an EXTINT pin edge.
+    pub const PM_EXTINTWAKE: Key<bool> = Key::new(0x10d0_000c);
+    /// CFG-PM-EXTINTBACKUP: force backup mode while the EXTINT pin is low.
+    pub const PM_EXTINTBACKUP: Key<bool> = Key::new(0x10d0_000d);
+    /// CFG-PM-EXTINTINACTIVE: force backup mode when the EXTINT pin is inactive.
+    pub const PM_EXTINTINACTIVE: Key<bool> = Key::new(0x10d0_000e);
+    /// CFG-PM-EXTINTINACTIVITY: EXTINT inactivity timeout in ms.
+    pub const PM_EXTINTINACTIVITY: Key<u32> = Key::new(0x40d0_000f);
+    /// CFG-PM-LIMITPEAKCURR: limit the peak current.
+    pub const PM_LIMITPEAKCURR: Key<bool> = Key::new(0x10d0_0010);
+
+    /// CFG-MOT-GNSSSPEED_THRS: static hold speed threshold in cm/s, 0 disables static hold.
+    pub const MOT_GNSSSPEED_THRS: Key<u8> = Key::new(0x2025_0038);
+    /// CFG-MOT-GNSSDIST_THRS: distance from the held position that ends static hold, in m.
+    pub const MOT_GNSSDIST_THRS: Key<u16> = Key::new(0x3025_003b);
+
+    /// CFG-SIGNAL-GPS_ENA: enable GPS.
+    pub const SIGNAL_GPS_ENA: Key<bool> = Key::new(0x1031_001f);
+    /// CFG-SIGNAL-GPS_L1CA_ENA: enable GPS L1C/A.
+    pub const SIGNAL_GPS_L1CA_ENA: Key<bool> = Key::new(0x1031_0001);
+    /// CFG-SIGNAL-SBAS_ENA: enable SBAS.
+    pub const SIGNAL_SBAS_ENA: Key<bool> = Key::new(0x1031_0020);
+    /// CFG-SIGNAL-SBAS_L1CA_ENA: enable SBAS L1C/A.
+    pub const SIGNAL_SBAS_L1CA_ENA: Key<bool> = Key::new(0x1031_0005);
+    /// CFG-SIGNAL-GAL_ENA: enable Galileo.
+    pub const SIGNAL_GAL_ENA: Key<bool> = Key::new(0x1031_0021);
+    /// CFG-SIGNAL-GAL_E1_ENA: enable Galileo E1.
+    pub const SIGNAL_GAL_E1_ENA: Key<bool> = Key::new(0x1031_0007);
+    /// CFG-SIGNAL-BDS_ENA: enable BeiDou.
+    pub const SIGNAL_BDS_ENA: Key<bool> = Key::new(0x1031_0022);
+    /// CFG-SIGNAL-BDS_B1_ENA: enable BeiDou B1I.
+    pub const SIGNAL_BDS_B1_ENA: Key<bool> = Key::new(0x1031_000d);
+    /// CFG-SIGNAL-BDS_B1C_ENA: enable BeiDou B1C.
+    pub const SIGNAL_BDS_B1C_ENA: Key<bool> = Key::new(0x1031_000f);
+    /// CFG-SIGNAL-QZSS_ENA: enable QZSS.
+    pub const SIGNAL_QZSS_ENA: Key<bool> = Key::new(0x1031_0024);
+    /// CFG-SIGNAL-QZSS_L1CA_ENA: enable QZSS L1C/A.
+    pub const SIGNAL_QZSS_L1CA_ENA: Key<bool> = Key::new(0x1031_0012);
+    /// CFG-SIGNAL-QZSS_L1S_ENA: enable QZSS L1S.
+    pub const SIGNAL_QZSS_L1S_ENA: Key<bool> = Key::new(0x1031_0014);
+    /// CFG-SIGNAL-GLO_ENA: enable GLONASS.
+    pub const SIGNAL_GLO_ENA: Key<bool> = Key::new(0x1031_0025);
+    /// CFG-SIGNAL-GLO_L1_ENA: enable GLONASS L1.
+    pub const SIGNAL_GLO_L1_ENA: Key<bool> = Key::new(0x1031_0018);
+
+    /// CFG-UART1-BAUDRATE: UART1 baud rate.
+    pub const UART1_BAUDRATE: Key<u32> = Key::new(0x4052_0001);
+    /// CFG-UART1-STOPBITS: UART1 stop bits (1 one, 2 one and a half, 3 two).
+    pub const UART1_STOPBITS: Key<u8> = Key::new(0x2052_0002);
+    /// CFG-UART1-DATABITS: UART1 data bits (0 eight, 1 seven).
+    pub const UART1_DATABITS: Key<u8> = Key::new(0x2052_0003);
+    /// CFG-UART1-PARITY: UART1 parity (0 none, 1 odd, 2 even).
+    pub const UART1_PARITY: Key<u8> = Key::new(0x2052_0004);
+    /// CFG-UART1-ENABLED: enable UART1.
+    pub const UART1_ENABLED: Key<bool> = Key::new(0x1052_0005);
+    /// CFG-UART1INPROT-UBX: accept UBX input on UART1.
+    pub const UART1INPROT_UBX: Key<bool> = Key::new(0x1073_0001);
+    /// CFG-UART1INPROT-NMEA: accept NMEA input on UART1.
+    pub const UART1INPROT_NMEA: Key<bool> = Key::new(0x1073_0002);
+    /// CFG-UART1OUTPROT-UBX: output UBX on UART1.
+    pub const UART1OUTPROT_UBX: Key<bool> = Key::new(0x1074_0001);
+    /// CFG-UART1OUTPROT-NMEA: output NMEA on UART1.
+    pub const UART1OUTPROT_NMEA: Key<bool> = Key::new(0x1074_0002);
+
+    /// CFG-SEC-CFG_LOCK: lock the receiver configuration.
+    pub const SEC_CFG_LOCK: Key<bool> = Key::new(0x10f6_0009);
+}
+
+/// A UBX-CFG-VALSET payload under construction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValSet {
+    payload: Vec<u8, MAX_VALSET_LEN>,
+    keys: usize,
+}
+
+impl ValSet {
+    /// Starts a VALSET message writing to the given layers.
+    pub fn new(layers: Layers) -> Self {
+        let mut payload = Vec::new();
+        // Version 0, layers, two reserved bytes. Cannot fail: the buffer is empty.
+        let _ = payload.extend_from_slice(&[0x00, layers.bits(), 0x00, 0x00]);
+        Self { payload, keys: 0 }
+    }
+
+    /// Adds a key-value pair to the message.
+    pub fn set<T: CfgValue>(&mut self, key: Key<T>, value: T) -> Result<&mut Self, CfgError> {
+        if value_size(key.id()) != Some(T::SIZE) {
+            return Err(CfgError::SizeMismatch(key.id()));
+        }
+        if self.keys == MAX_KEYS || self.payload.len() + 4 + T::SIZE > MAX_VALSET_LEN {
+            return Err(CfgError::TooManyKeys);
+        }
+        let start = self.payload.len();
+        // Cannot fail: the length was checked above.
+        let _ = self.payload.extend_from_slice(&key.id().to_le_bytes());
+        let _ = self.payload.resize(start + 4 + T::SIZE, 0);
+        value.encode(&mut self.payload[start + 4..]);
+        self.keys += 1;
+        Ok(self)
+    }
+
+    /// The number of keys in the message.
+    pub fn len(&self) -> usize {
+        self.keys
+    }
+
+    /// Returns `true` if no keys were added.
+    pub fn is_empty(&self) -> bool {
+        self.keys == 0
+    }
+
+    /// The encoded VALSET payload.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+}
+
+/// Builds a UBX-CFG-VALGET poll for the given key IDs.
+pub fn valget_request(
+    key_ids: &[u32],
+    layer: ReadLayer,
+) -> Result<Vec<u8, { 4 + 4 * MAX_KEYS }>, CfgError> {
+    if key_ids.len() > MAX_KEYS {
+        return Err(CfgError::TooManyKeys);
+    }
+    let mut payload = Vec::new();
+    // Cannot fail: the buffer holds the header and MAX_KEYS keys.
+    let _ = payload.extend_from_slice(&[0x00, layer.bits(), 0x00, 0x00]);
+    for id in key_ids {
+        let _ = payload.extend_from_slice(&id.to_le_bytes());
+    }
+    Ok(payload)
+}
+
+/// A UBX-CFG-VALGET response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValGetResponse<'a> {
+    payload: &'a [u8],
+}
+
+impl<'a> ValGetResponse<'a> {
+    const HEADER_LEN: usize = 4;
+
+    /// Parses a VALGET response payload, checking that every value is complete.
+    pub fn parse(payload: &'a [u8]) -> Result<Self, CfgError> {
+        if payload.len() < Self::HEADER_LEN {
+            return Err(CfgError::InvalidResponse);
+        }
+        let mut data = &payload[Self::HEADER_LEN..];
+        while !data.is_empty() {
+            if data.len() < 4 {
+                return Err(CfgError::InvalidResponse);
+            }
+            let id = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+            let size = value_size(id).ok_or(CfgError::InvalidResponse)?;
+            if data.len() < 4 + size {
+                return Err(CfgError::InvalidResponse);
+            }
+            data = &data[4 + size..];
+        }
+        Ok(Self { payload })
+    }
+
+    /// Iterates over the raw key IDs and values in the response.
+    pub fn values(&self) -> impl Iterator<Item = (u32, &'a [u8])> + 'a {
+        let mut data = &self.payload[Self::HEADER_LEN..];
+        core::iter::from_fn(move || {
+            if data.len() < 4 {
+                return None;
+            }
+            let id = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+            let size = value_size(id)?;
+            let value = &data[4..4 + size];
+            data = &data[4 + size..];
+            Some((id, value))
+        })
+    }
+
+    /// Returns the value of `key`, if the response contains it.
+    pub fn get<T: CfgValue>(&self, key: Key<T>) -> Option<T> {
+        self.values()
+            .find(|(id, value)| *id == key.id() && value.len() == T::SIZE)
+            .map(|(_, value)| T::decode(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ubx;
+
+    #[test]
+    fn valset_matches_known_frame() {
+        // Sets CFG-UART1-BAUDRATE to 115200 in RAM.
+        const FRAME: [u8; 20] = [
+            0xB5, 0x62, 0x06, 0x8A, 0x0C, 0x00, 0x00, 0x01, 0x00, 0x00, 0x01, 0x00, 0x52, 0x40,
+            0x00, 0xC2, 0x01, 0x00, 0xF3, 0xA5,
+        ];
+        let mut config = ValSet::new(Layers::RAM);
+        config.set(keys::UART1_BAUDRATE, 115_200).unwrap();
+        assert_eq!(config.payload(), &FRAME[6..18]);
+
+        let mut frame = [0; 20];
+        let len = ubx::encode(
+            ubx::class::CFG,
+            ubx::id::CFG_VALSET,
+            config.payload(),
+            &mut frame,
+        );
+        assert_eq!(len, Ok(20));
+        assert_eq!(frame, FRAME);
+    }
+
+    #[test]
+    fn valset_packs_values_little_endian() {
+        let mut config = ValSet::new(Layers::RAM | Layers::BBR | Layers::FLASH);
+        assert!(config.is_empty());
+        config
+            .set(keys::SIGNAL_GPS_ENA, true)
+            .unwrap()
+            .set(keys::NAVSPG_INFIL_MINELEV, -5)
+            .unwrap()
+            .set(keys::RATE_MEAS, 1000)
+            .unwrap();
+        assert_eq!(config.len(), 3);
+        assert_eq!(
+            config.payload(),
+            [
+                0x00, 0x07, 0x00, 0x00, // header
+                0x1f, 0x00, 0x31, 0x10, 0x01, // CFG-SIGNAL-GPS_ENA
+                0xa4, 0x00, 0x11, 0x20, 0xfb, // CFG-NAVSPG-INFIL_MINELEV
+                0x01, 0x00, 0x21, 0x30, 0xe8, 0x03, // CFG-RATE-MEAS
+            ]
+        );
+    }
+
+    #[test]
+    fn valset_rejects_size_mismatch() {
+        let mut config = ValSet::new(Layers::RAM);
+        // CFG-RATE-MEAS holds a U2.
+        let key = Key::<u32>::new(keys::RATE_MEAS.id());
+        assert_eq!(
+            config.set(key, 1000).map(|_| ()),
+            Err(CfgError::SizeMismatch(0x3021_0001))
+        );
+        // Size 0 in the key ID is not a valid size.
+        let key = Key::<u8>::new(0x0021_0001);
+        assert_eq!(
+            config.set(key, 1).map(|_| ()),
+            Err(CfgError::SizeMismatch(0x0021_0001))
+        );
+        assert!(config.is_empty());
+        assert_eq!(config.payload().len(), 4);
+    }
+
+    #[test]
+    fn valset_is_limited_to_max_len() {
+        let mut config = ValSet::new(Layers::RAM);
+        // 5 bytes per key after the 4 byte header.
+        let fit = (MAX_VALSET_LEN - 4) / 5;
+        for _ in 0..fit {
+            config.set(keys::SIGNAL_GPS_ENA, true).unwrap();
+        }
+        assert_eq!(
+            config.set(keys::SIGNAL_GPS_ENA, true).map(|_| ()),
+            Err(CfgError::TooManyKeys)
+        );
+        assert_eq!(config.len(), fit);
+        assert_eq!(config.payload().len(), 4 + 5 * fit);
+
+        // The limit is in bytes, not keys: fewer wide values fit.
+        let mut config = ValSet::new(Layers::RAM);
+        let fit = (MAX_VALSET_LEN - 4) / 8;
+        for _ in 0..fit {
+            config.set(keys::PM_POSUPDATEPERIOD, 1).unwrap();
+        }
+        assert_eq!(
+            config.set(keys::PM_POSUPDATEPERIOD, 1).map(|_| ()),
+            Err(CfgError::TooManyKeys)
+        );
+        assert_eq!(config.len(), fit);
+        assert!(fit < MAX_KEYS);
+    }
+
+    #[test]
+    fn valget_request_is_limited_to_max_keys() {
+        let ids = [keys::RATE_MEAS.id(); MAX_KEYS + 1];
+        let payload = valget_request(&ids[..MAX_KEYS], ReadLayer::Bbr).unwrap();
+        assert_eq!(payload.len(), 4 + 4 * MAX_KEYS);
+        assert_eq!(
+            payload[..8],
+            [0x00, 0x01, 0x00, 0x00, 0x01, 0x00, 0x21, 0x30]
+        );
+        assert_eq!(
+            valget_request(&ids, ReadLayer::Ram),
+            Err(CfgError::TooManyKeys)
+        );
+    }
+
+    #[test]
+    fn parses_valget_response() {
+        let payload = [
+            0x01, 0x00, 0x00, 0x00, // header
+            0x01, 0x00, 0x21, 0x30, 0xe8, 0x03, // CFG-RATE-MEAS
+            0x1f, 0x00, 0x31, 0x10, 0x01, // CFG-SIGNAL-GPS_ENA
+            0x01, 0x00, 0x52, 0x40, 0x00, 0xc2, 0x01, 0x00, // CFG-UART1-BAUDRATE
+        ];
+        let response = ValGetResponse::parse(&payload).unwrap();
+        assert_eq!(response.values().count(), 3);
+        assert_eq!(response.get(keys::RATE_MEAS), Some(1000));
+        assert_eq!(response.get(keys::SIGNAL_GPS_ENA), Some(true));
+        assert_eq!(response.get(keys::UART1_BAUDRATE), Some(115_200));
+        assert_eq!(response.get(keys::RATE_NAV), None);
+        // A key read with the wrong type is not found.
+        assert_eq!(
+            response.get(Key::<u16>::new(keys::UART1_BAUDRATE.id())),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_valget_response() {
+        let payloads: [&[u8]; 5] = [
+            // Truncated header.
+            &[0x01, 0x00, 0x00],
+            // Truncated key ID.
+            &[0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x21],
+            // Truncated value.
+            &[0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x21, 0x30, 0xe8],
+            // Size 0 is unknown.
+            &[0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x21, 0x00, 0xe8],
+            // Size 6 is unknown.
+            &[0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x21, 0x60, 0xe8],
+        ];
+        for payload in payloads {
+            assert_eq!(
+                ValGetResponse::parse(payload),
+                Err(CfgError::InvalidResponse),
+                "{payload:x?}"
+            );
+        }
+        // A header without values is a valid, empty response.
+        let response = ValGetResponse::parse(&[0x01, 0x00, 0x00, 0x00]).unwrap();
+        assert_eq!(response.values().count(), 0);
+    }
+}
@@ -0,0 +1,604 @@
+//! Geodesic helpers, geofences and waypoint navigation.
+//!
+//! Everything here is pure computation on a spherical Earth model, which is accurate
+//! to about 0.5% and does not depend on the receiver, so it can be driven by
+//! recorded or synthetic tracks on the host. Feed `GpsData` from `Gps::next_update`
+//! into a `GeofenceMonitor` to get enter/exit events, or into a `Navigator` to get
+//! the distance and heading to the next waypoint.
+
+use heapless::Vec;
+
+use crate::GpsData;
+
+/// The mean Earth radius in meters.
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+/// The maximum number of waypoints of a route.
+pub const MAX_WAYPOINTS: usize = 32;
+
+/// A position in decimal degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    /// Latitude in decimal degrees, positive north.
+    pub latitude: f64,
+    /// Longitude in decimal degrees, positive east.
+    pub longitude: f64,
+}
+
+impl Position {
+    /// Creates a position from decimal degrees.
+    pub const fn new(latitude: f64, longitude: f64) -> Self {
+        Self {
+            latitude,
+            longitude,
+        }
+    }
+
+    /// Returns the position of a fix, if it has one.
+    pub fn from_gps_data(data: &GpsData) -> Option<Self> {
+        Some(Self::new(data.latitude?, data.longitude?))
+    }
+}
+
+/// The great-circle distance between two positions in meters (haversine formula).
+pub fn distance_m(from: Position, to: Position) -> f64 {
+    EARTH_RADIUS_M * angular_distance(from, to)
+}
+
+/// The initial bearing from `from` to `to` in degrees from true north (0-360).
+pub fn bearing_deg(from: Position, to: Position) -> f64 {
+    let (lat1, lat2) = (from.latitude.to_radians(), to.latitude.to_radians());
+    let d_lon = (to.longitude - from.longitude).to_radians();
+    let y = libm::sin(d_lon) * libm::cos(lat2);
+    let x =
+        libm::cos(lat1) * libm::sin(lat2) - libm::sin(lat1) * libm::cos(lat2) * libm::cos(d_lon);
+    normalize_deg(libm::atan2(y, x).to_degrees())
+}
+
+/// The distance of `position` from the great circle through `start` and `end` in
+/// meters. Positive values are to the right of the track, negative to the left.
+pub fn cross_track_m(start: Position, end: Position, position: Position) -> f64 {
+    let d13 = angular_distance(start, position);
+    let theta13 = bearing_deg(start, position).to_radians();
+    let theta12 = bearing_deg(start, end).to_radians();
+    EARTH_RADIUS_M * libm::asin(libm::sin(d13) * libm::sin(theta13 - theta12))
+}
+
+/// The distance from `start` to the point on the track towards `end` closest to
+/// `position` in meters. Negative if `position` is behind `start`.
+pub fn along_track_m(start: Position, end: Position, position: Position) -> f64 {
+    let d13 = angular_distance(start, position);
+    let dxt = cross_track_m(start, end, position) / EARTH_RADIUS_M;
+    let distance = EARTH_RADIUS_M * libm::acos((libm::cos(d13) / libm::cos(dxt)).clamp(-1.0, 1.0));
+    let theta13 = bearing_deg(start, position).to_radians();
+    let theta12 = bearing_deg(start, end).to_radians();
+    if libm::cos(theta13 - theta12) < 0.0 {
+        -distance
+    } else {
+        distance
+    }
+}
+
+/// The position reached from `start` after `distance_m` meters on the initial
+/// bearing `bearing_deg`.
+pub fn destination(start: Position, bearing_deg: f64, distance_m: f64) -> Position {
+    let lat1 = start.latitude.to_radians();
+    let lon1 = start.longitude.to_radians();
+    let theta = bearing_deg.to_radians();
+    let delta = distance_m / EARTH_RADIUS_M;
+    let lat2 = libm::asin(
+        libm::sin(lat1) * libm::cos(delta) + libm::cos(lat1) * libm::sin(delta) * libm::cos(theta),
+    );
+    let lon2 = lon1
+        + libm::atan2(
+            libm::sin(theta) * libm::sin(delta) * libm::cos(lat1),
+            libm::cos(delta) - libm::sin(lat1) * libm::sin(lat2),
+        );
+    Position::new(
+        lat2.to_degrees(),
+        normalize_deg(lon2.to_degrees() + 180.0) - 180.0,
+    )
+}
+
+/// The signed difference `to - from` between two headings in degrees (-180..180).
+pub fn heading_difference_deg(from: f64, to: f64) -> f64 {
+    normalize_deg(to - from + 180.0) - 180.0
+}
+
+fn angular_distance(from: Position, to: Position) -> f64 {
+    let (lat1, lat2) = (from.latitude.to_radians(), to.latitude.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (to.longitude - from.longitude).to_radians();
+    let sin_lat = libm::sin(d_lat / 2.0);
+    let sin_lon = libm::sin(d_lon / 2.0);
+    let h = sin_lat * sin_lat + libm::cos(lat1) * libm::cos(lat2) * sin_lon * sin_lon;
+    2.0 * libm::asin(libm::sqrt(h.min(1.0)))
+}
+
+fn normalize_deg(degrees: f64) -> f64 {
+    let degrees = libm::fmod(degrees, 360.0);
+    if degrees < 0.0 {
+        degrees + 360.0
+    } else {
+        degrees
+    }
+}
+
+/// An area on the map.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Geofence<'a> {
+    /// All positions within `radius_m` meters of `center`.
+    Circle {
+        /// The center of the circle.
+        center: Position,
+        /// The radius in meters.
+        radius_m: f64,
+    },
+    /// The area enclosed by a polygon. Edges are straight lines in latitude and
+    /// longitude, which is accurate for fences up to a few kilometers across. The
+    /// polygon must not cross the antimeridian.
+    Polygon(&'a [Position]),
+}
+
+impl<'a> Geofence<'a> {
+    /// Creates a circular geofence.
+    pub fn circle(center: Position, radius_m: f64) -> Self {
+        Geofence::Circle { center, radius_m }
+    }
+
+    /// Creates a polygon geofence from its vertices. Returns `None` if there are
+    /// fewer than 3 vertices.
+    pub fn polygon(vertices: &'a [Position]) -> Option<Self> {
+        (vertices.len() >= 3).then_some(Geofence::Polygon(vertices))
+    }
+
+    /// Returns `true` if `position` is inside the fence.
+    pub fn contains(&self, position: Position) -> bool {
+        match self {
+            Geofence::Circle { center, radius_m } => distance_m(*center, position) <= *radius_m,
+            Geofence::Polygon(vertices) => {
+                // Even-odd rule: count the edges crossed by a ray towards east.
+                let mut inside = false;
+                let Some(&last) = vertices.last() else {
+                    return false;
+                };
+                let mut previous = last;
+                for vertex in *vertices {
+                    if (vertex.latitude > position.latitude)
+                        != (previous.latitude > position.latitude)
+                    {
+                        let longitude = vertex.longitude
+                            + (position.latitude - vertex.latitude)
+                                * (previous.longitude - vertex.longitude)
+                                / (previous.latitude - vertex.latitude);
+                        if position.longitude < longitude {
+                            inside = !inside;
+                        }
+                    }
+                    previous = *vertex;
+                }
+                inside
+            }
+        }
+    }
+}
+
+/// A transition across a geofence boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeofenceEvent {
+    /// The position moved into the fence.
+    Enter,
+    /// The position moved out of the fence.
+    Exit,
+}
+
+/// Tracks whether the position is inside a geofence and reports transitions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeofenceMonitor<'a> {
+    fence: Geofence<'a>,
+    inside: Option<bool>,
+}
+
+impl<'a> GeofenceMonitor<'a> {
+    /// Creates a monitor for `fence`. The first position only sets the initial state
+    /// and does not produce an event.
+    pub fn new(fence: Geofence<'a>) -> Self {
+        Self {
+            fence,
+            inside: None,
+        }
+    }
+
+    /// Returns the monitored fence.
+    pub fn fence(&self) -> Geofence<'a> {
+        self.fence
+    }
+
+    /// Returns `Some(true)` if the last position was inside the fence, or `None`
+    /// before the first position.
+    pub fn is_inside(&self) -> Option<bool> {
+        self.inside
+    }
+
+    /// Updates the monitor with a new position and returns the transition, if any.
+    pub fn update(&mut self, position: Position) -> Option<GeofenceEvent> {
+        let inside = self.fence.contains(position);
+        let previous = self.inside.replace(inside)?;
+        match (previous, inside) {
+            (false, true) => Some(GeofenceEvent::Enter),
+            (true, false) => Some(GeofenceEvent::Exit),
+            _ => None,
+        }
+    }
+
+    /// Updates the monitor with a fix. Data without a position or a valid fix is
+    /// ignored.
+    pub fn update_gps(&mut self, data: &GpsData) -> Option<GeofenceEvent> {
+        if !data.has_fix() {
+            return None;
+        }
+        self.update(Position::from_gps_data(data)?)
+    }
+}
+
+/// Guidance towards the active waypoint.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Navigation {
+    /// The index of the active waypoint.
+    pub waypoint: usize,
+    /// The distance to the active waypoint in meters.
+    pub distance_m: f64,
+    /// The bearing to the active waypoint in degrees from true north.
+    pub bearing_deg: f64,
+    /// The turn needed to head for the waypoint in degrees, positive to the right,
+    /// if the course over ground is known.
+    pub heading_error_deg: Option<f64>,
+    /// The distance from the leg between the previous and the active waypoint in
+    /// meters, positive to the right. `None` on the first leg.
+    pub cross_track_m: Option<f64>,
+    /// The distance to the active waypoint plus the remaining legs of the route in
+    /// meters.
+    pub distance_to_go_m: f64,
+    /// The waypoint was reached with this update.
+    pub arrived: bool,
+    /// The last waypoint has been reached.
+    pub finished: bool,
+}
+
+/// Guides along a route of waypoints, e.g. back to a base position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Navigator {
+    waypoints: Vec<Position, MAX_WAYPOINTS>,
+    active: usize,
+    arrival_radius_m: f64,
+}
+
+impl Navigator {
+    /// Creates a navigator along `waypoints`. A waypoint counts as reached when the
+    /// position comes within `arrival_radius_m` meters. Returns `None` if there are
+    /// no waypoints or more than `MAX_WAYPOINTS`.
+    pub fn new(waypoints: &[Position], arrival_radius_m: f64) -> Option<Self> {
+        if waypoints.is_empty() {
+            return None;
+        }
+        Some(Self {
+            waypoints: Vec::from_slice(waypoints).ok()?,
+            active: 0,
+            arrival_radius_m,
+        })
+    }
+
+    /// Creates a navigator back to a single base position.
+    pub fn to_base(base: Position, arrival_radius_m: f64) -> Self {
+        let mut waypoints = Vec::new();
+        // Cannot fail: the route is empty.
+        let _ = waypoints.push(base);
+        Self {
+            waypoints,
+            active: 0,
+            arrival_radius_m,
+        }
+    }
+
+    /// Returns the waypoints of the route.
+    pub fn waypoints(&self) -> &[Position] {
+        &self.waypoints
+    }
+
+    /// Returns the index of the active waypoint.
+    pub fn active(&self) -> usize {
+        self.active
+    }
+
+    /// Returns `true` once the last waypoint has been reached.
+    pub fn is_finished(&self) -> bool {
+        self.active >= self.waypoints.len()
+    }
+
+    /// Skips to the waypoint at `index`.
+    pub fn set_active(&mut self, index: usize) {
+        self.active = index.min(self.waypoints.len());
+    }
+
+    /// Updates the guidance with a position and an optional course over ground in
+    /// degrees. Moves on to the next waypoint when the active one is reached.
+    pub fn update(&mut self, position: Position, course_deg: Option<f64>) -> Navigation {
+        let mut arrived = false;
+        if let Some(target) = self.waypoints.get(self.active) {
+            if distance_m(position, *target) <= self.arrival_radius_m {
+                self.active += 1;
+                arrived = true;
+            }
+        }
+
+        let Some(&target) = self.waypoints.get(self.active) else {
+            let last = self.waypoints.len() - 1;
+            return Navigation {
+                waypoint: last,
+                distance_m: distance_m(position, self.waypoints[last]),
+                bearing_deg: bearing_deg(position, self.waypoints[last]),
+                heading_error_deg: None,
+                cross_track_m: None,
+                distance_to_go_m: 0.0,
+                arrived,
+                finished: true,
+            };
+        };
+
+        let distance = distance_m(position, target);
+        let bearing = bearing_deg(position, target);
+        let remaining_legs: f64 = self.waypoints[self.active..]
+            .windows(2)
+            .map(|leg| distance_m(leg[0], leg[1]))
+            .sum();
+        Navigation {
+            waypoint: self.active,
+            distance_m: distance,
+            bearing_deg: bearing,
+            heading_error_deg: course_deg.map(|course| heading_difference_deg(course, bearing)),
+            cross_track_m: self
+                .active
+                .checked_sub(1)
+                .map(|previous| cross_track_m(self.waypoints[previous], target, position)),
+            distance_to_go_m: distance + remaining_legs,
+            arrived,
+            finished: false,
+        }
+    }
+
+    /// Updates the guidance with a fix, using its course over ground. Returns `None`
+    /// if the data has no position or valid fix.
+    pub fn update_gps(&mut self, data: &GpsData) -> Option<Navigation> {
+        if !data.has_fix() {
+            return None;
+        }
+        let position = Position::from_gps_data(data)?;
+        Some(self.update(position, data.course_over_ground.map(|c| c as f64)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BIG_BEN: Position = Position::new(51.5007, -0.1246);
+    const STATUE_OF_LIBERTY: Position = Position::new(40.6892, -74.0445);
+    /// One degree of latitude, or of longitude on the equator.
+    const DEGREE_M: f64 = 111_195.08;
+
+    fn assert_near(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{actual} is not within {tolerance} of {expected}"
+        );
+    }
+
+    #[test]
+    fn distance_and_bearing() {
+        assert_near(distance_m(BIG_BEN, STATUE_OF_LIBERTY), 5_574_848.2, 1.0);
+        assert_near(bearing_deg(BIG_BEN, STATUE_OF_LIBERTY), 288.337, 0.001);
+        assert_near(bearing_deg(STATUE_OF_LIBERTY, BIG_BEN), 51.195, 0.001);
+
+        let origin = Position::new(0.0, 0.0);
+        assert_near(distance_m(origin, Position::new(1.0, 0.0)), DEGREE_M, 0.01);
+        assert_near(distance_m(origin, Position::new(0.0, -1.0)), DEGREE_M, 0.01);
+        assert_eq!(distance_m(BIG_BEN, BIG_BEN), 0.0);
+        assert_near(bearing_deg(origin, Position::new(1.0, 0.0)), 0.0, 1e-9);
+        assert_near(bearing_deg(origin, Position::new(0.0, 1.0)), 90.0, 1e-9);
+        assert_near(bearing_deg(origin, Position::new(-1.0, 0.0)), 180.0, 1e-9);
+        assert_near(bearing_deg(origin, Position::new(0.0, -1.0)), 270.0, 1e-9);
+    }
+
+    #[test]
+    fn destination_round_trip() {
+        for bearing in [0.0, 45.0, 135.0, 270.0] {
+            let end = destination(BIG_BEN, bearing, 1000.0);
+            assert_near(distance_m(BIG_BEN, end), 1000.0, 1e-6);
+            assert_near(bearing_deg(BIG_BEN, end), bearing, 1e-6);
+        }
+        // Wraps across the antimeridian.
+        let end = destination(Position::new(0.0, 179.5), 90.0, DEGREE_M);
+        assert_near(end.longitude, -179.5, 1e-6);
+    }
+
+    #[test]
+    fn cross_and_along_track() {
+        // A track due north along the prime meridian.
+        let start = Position::new(0.0, 0.0);
+        let end = Position::new(1.0, 0.0);
+        let abeam = Position::new(0.5, 0.0);
+
+        let east = destination(abeam, 90.0, 100.0);
+        assert_near(cross_track_m(start, end, east), 100.0, 0.01);
+        assert_near(along_track_m(start, end, east), DEGREE_M / 2.0, 0.01);
+
+        let west = destination(abeam, 270.0, 100.0);
+        assert_near(cross_track_m(start, end, west), -100.0, 0.01);
+
+        // Heading south the sides swap.
+        assert_near(cross_track_m(end, start, east), -100.0, 0.01);
+
+        let behind = Position::new(-0.1, 0.0);
+        assert_near(along_track_m(start, end, behind), -DEGREE_M / 10.0, 0.01);
+        assert_near(cross_track_m(start, end, behind), 0.0, 0.01);
+    }
+
+    #[test]
+    fn heading_difference() {
+        assert_eq!(heading_difference_deg(350.0, 10.0), 20.0);
+        assert_eq!(heading_difference_deg(10.0, 350.0), -20.0);
+        assert_eq!(heading_difference_deg(90.0, 180.0), 90.0);
+        assert_eq!(heading_difference_deg(0.0, 180.0), -180.0);
+    }
+
+    #[test]
+    fn polygon_containment() {
+        let square = [
+            Position::new(0.0, 0.0),
+            Position::new(0.0, 1.0),
+            Position::new(1.0, 1.0),
+            Position::new(1.0, 0.0),
+        ];
+        let fence = Geofence::polygon(&square).unwrap();
+        assert!(fence.contains(Position::new(0.5, 0.5)));
+        assert!(!fence.contains(Position::new(0.5, 1.5)));
+        assert!(!fence.contains(Position::new(0.5, -0.5)));
+        assert!(!fence.contains(Position::new(1.5, 0.5)));
+
+        assert_eq!(Geofence::polygon(&square[..2]), None);
+    }
+
+    #[test]
+    fn concave_polygon_containment() {
+        // A "C" open to the east: a 3x3 degree square with the middle third of the
+        // east side cut out.
+        let c = [
+            Position::new(0.0, 0.0),
+            Position::new(0.0, 3.0),
+            Position::new(1.0, 3.0),
+            Position::new(1.0, 1.0),
+            Position::new(2.0, 1.0),
+            Position::new(2.0, 3.0),
+            Position::new(3.0, 3.0),
+            Position::new(3.0, 0.0),
+        ];
+        let fence = Geofence::polygon(&c).unwrap();
+        // The lower arm, the upper arm and the spine.
+        assert!(fence.contains(Position::new(0.5, 2.0)));
+        assert!(fence.contains(Position::new(2.5, 2.0)));
+        assert!(fence.contains(Position::new(1.5, 0.5)));
+        // The notch, although it lies within the bounding box and between the arms.
+        assert!(!fence.contains(Position::new(1.5, 2.0)));
+        assert!(!fence.contains(Position::new(1.5, 3.5)));
+        assert!(!fence.contains(Position::new(1.5, -0.5)));
+        assert!(!fence.contains(Position::new(3.5, 2.0)));
+    }
+
+    #[test]
+    fn circle_containment() {
+        let fence = Geofence::circle(BIG_BEN, 100.0);
+        assert!(fence.contains(BIG_BEN));
+        assert!(fence.contains(destination(BIG_BEN, 45.0, 99.0)));
+        assert!(!fence.contains(destination(BIG_BEN, 45.0, 101.0)));
+    }
+
+    #[test]
+    fn monitor_reports_each_transition_once() {
+        let square = [
+            Position::new(0.0, 0.0),
+            Position::new(0.0, 1.0),
+            Position::new(1.0, 1.0),
+            Position::new(1.0, 0.0),
+        ];
+        let mut monitor = GeofenceMonitor::new(Geofence::polygon(&square).unwrap());
+        assert_eq!(monitor.is_inside(), None);
+
+        // The first position only sets the state.
+        assert_eq!(monitor.update(Position::new(-0.1, 0.5)), None);
+        assert_eq!(monitor.is_inside(), Some(false));
+        assert_eq!(monitor.update(Position::new(-0.2, 0.5)), None);
+
+        assert_eq!(
+            monitor.update(Position::new(0.5, 0.5)),
+            Some(GeofenceEvent::Enter)
+        );
+        assert_eq!(monitor.update(Position::new(0.6, 0.5)), None);
+        assert_eq!(monitor.update(Position::new(0.7, 0.5)), None);
+        assert_eq!(monitor.is_inside(), Some(true));
+
+        assert_eq!(
+            monitor.update(Position::new(0.5, 1.5)),
+            Some(GeofenceEvent::Exit)
+        );
+        assert_eq!(monitor.update(Position::new(0.5, 1.6)), None);
+        assert_eq!(
+            monitor.update(Position::new(0.5, 0.5)),
+            Some(GeofenceEvent::Enter)
+        );
+
+        let mut monitor = GeofenceMonitor::new(Geofence::circle(BIG_BEN, 50.0));
+        assert_eq!(monitor.update(BIG_BEN), None);
+        assert_eq!(monitor.is_inside(), Some(true));
+    }
+
+    #[test]
+    fn navigator_advances_through_waypoints() {
+        let base = Position::new(47.0, 19.0);
+        let north = destination(base, 0.0, 1000.0);
+        let north_east = destination(north, 90.0, 500.0);
+        let mut navigator = Navigator::new(&[north, north_east, base], 20.0).unwrap();
+
+        let nav = navigator.update(destination(base, 0.0, 10.0), Some(90.0));
+        assert_eq!(nav.waypoint, 0);
+        assert!(!nav.arrived && !nav.finished);
+        assert_near(nav.distance_m, 990.0, 0.01);
+        assert_near(nav.bearing_deg, 0.0, 0.01);
+        assert_near(nav.heading_error_deg.unwrap(), -90.0, 0.01);
+        assert_eq!(nav.cross_track_m, None);
+        let legs = 500.0 + distance_m(north_east, base);
+        assert_near(nav.distance_to_go_m, 990.0 + legs, 0.01);
+
+        // Within the arrival radius of the first waypoint.
+        let nav = navigator.update(destination(north, 180.0, 15.0), None);
+        assert!(nav.arrived && !nav.finished);
+        assert_eq!(nav.waypoint, 1);
+        assert_eq!(navigator.active(), 1);
+        assert_eq!(nav.heading_error_deg, None);
+        // South of the leg heading east, i.e. to its right.
+        assert_near(nav.cross_track_m.unwrap(), 15.0, 0.1);
+
+        // Still on the way: no further advance.
+        let nav = navigator.update(destination(north, 90.0, 250.0), None);
+        assert!(!nav.arrived);
+        assert_eq!(nav.waypoint, 1);
+        assert_near(nav.distance_m, 250.0, 0.1);
+        assert_near(nav.cross_track_m.unwrap(), 0.0, 0.1);
+
+        let nav = navigator.update(north_east, None);
+        assert!(nav.arrived && !nav.finished);
+        assert_eq!(nav.waypoint, 2);
+
+        let nav = navigator.update(destination(base, 45.0, 5.0), None);
+        assert!(nav.arrived && nav.finished);
+        assert!(navigator.is_finished());
+        assert_eq!(nav.waypoint, 2);
+        assert_eq!(nav.distance_to_go_m, 0.0);
+
+        // Stays finished and keeps pointing at the last waypoint.
+        let nav = navigator.update(destination(base, 45.0, 100.0), None);
+        assert!(!nav.arrived && nav.finished);
+        assert_near(nav.distance_m, 100.0, 0.01);
+        assert_near(nav.bearing_deg, 225.0, 0.01);
+
+        navigator.set_active(0);
+        assert!(!navigator.is_finished());
+    }
+
+    #[test]
+    fn navigator_rejects_bad_routes() {
+        assert_eq!(Navigator::new(&[], 10.0), None);
+        let route = [BIG_BEN; MAX_WAYPOINTS + 1];
+        assert_eq!(Navigator::new(&route, 10.0), None);
+        assert!(Navigator::new(&route[..MAX_WAYPOINTS], 10.0).is_some());
+        assert_eq!(Navigator::to_base(BIG_BEN, 10.0).waypoints(), [BIG_BEN]);
+    }
+}
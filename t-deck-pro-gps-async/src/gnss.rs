@@ -0,0 +1,370 @@
+//! Constellation and dynamic platform model selection.
+//!
+//! More constellations mean more satellites in view and a faster, more robust fix,
+//! at the cost of a higher current draw. The dynamic platform model tells the
+//! navigation filter what movement to expect, e.g. a hiker or a car.
+//!
+//! Both are written to RAM and BBR with CFG-VALSET. The receiver only applies a new
+//! signal configuration after a GNSS restart, which `Gps::set_constellations`
+//! performs as a hot start, so no navigation data is lost.
+
+use embedded_hal::digital::OutputPin;
+use embedded_io_async::{Read, Write};
+use log::info;
+
+use crate::cfg::{keys, CfgError, Layers, ReadLayer, ValSet};
+use crate::health::ResetKind;
+use crate::satellites::Constellation;
+use crate::Gps;
+
+/// The GNSS constellations the receiver tracks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GnssSelection {
+    /// GPS L1C/A.
+    pub gps: bool,
+    /// Galileo E1.
+    pub galileo: bool,
+    /// BeiDou B1I.
+    pub beidou: bool,
+    /// GLONASS L1.
+    pub glonass: bool,
+    /// QZSS L1C/A and L1S. Only used together with GPS.
+    pub qzss: bool,
+    /// SBAS L1C/A corrections.
+    pub sbas: bool,
+}
+
+impl Default for GnssSelection {
+    /// The factory default of the M10: GPS, Galileo, BeiDou, QZSS and SBAS.
+    fn default() -> Self {
+        Self {
+            gps: true,
+            galileo: true,
+            beidou: true,
+            glonass: false,
+            qzss: true,
+            sbas: true,
+        }
+    }
+}
+
+impl GnssSelection {
+    /// GPS only, for the lowest current draw.
+    pub const GPS_ONLY: Self = Self {
+        gps: true,
+        galileo: false,
+        beidou: false,
+        glonass: false,
+        qzss: false,
+        sbas: false,
+    };
+
+    /// Returns `true` if `constellation` is enabled.
+    pub fn is_enabled(&self, constellation: Constellation) -> bool {
+        match constellation {
+            Constellation::Gps => self.gps,
+            Constellation::Galileo => self.galileo,
+            Constellation::BeiDou => self.beidou,
+            Constellation::Glonass => self.glonass,
+            Constellation::Qzss => self.qzss,
+            Constellation::Sbas => self.sbas,
+            Constellation::NavIc | Constellation::Unknown => false,
+        }
+    }
+
+    /// Enables or disables `constellation`. Constellations the receiver does not
+    /// support are ignored.
+    pub fn set(&mut self, constellation: Constellation, enabled: bool) -> &mut Self {
+        match constellation {
+            Constellation::Gps => self.gps = enabled,
+            Constellation::Galileo => self.galileo = enabled,
+            Constellation::BeiDou => self.beidou = enabled,
+            Constellation::Glonass => self.glonass = enabled,
+            Constellation::Qzss => self.qzss = enabled,
+            Constellation::Sbas => self.sbas = enabled,
+            Constellation::NavIc | Constellation::Unknown => {}
+        }
+        self
+    }
+
+    /// Returns `true` if at least one constellation that can fix a position on its
+    /// own is enabled.
+    pub fn is_valid(&self) -> bool {
+        self.gps || self.galileo || self.beidou || self.glonass
+    }
+
+    /// Builds the VALSET message enabling the selected signals.
+    fn config(&self) -> Result<ValSet, CfgError> {
+        let qzss = self.qzss && self.gps;
+        let mut config = ValSet::new(Layers::RAM | Layers::BBR);
+        config
+            .set(keys::SIGNAL_GPS_ENA, self.gps)?
+            .set(keys::SIGNAL_GPS_L1CA_ENA, self.gps)?
+            .set(keys::SIGNAL_GAL_ENA, self.galileo)?
+            .set(keys::SIGNAL_GAL_E1_ENA, self.galileo)?
+            .set(keys::SIGNAL_BDS_ENA, self.beidou)?
+            .set(keys::SIGNAL_BDS_B1_ENA, self.beidou)?
+            .set(keys::SIGNAL_GLO_ENA, self.glonass)?
+            .set(keys::SIGNAL_GLO_L1_ENA, self.glonass)?
+            .set(keys::SIGNAL_QZSS_ENA, qzss)?
+            .set(keys::SIGNAL_QZSS_L1CA_ENA, qzss)?
+            .set(keys::SIGNAL_QZSS_L1S_ENA, qzss)?
+            .set(keys::SIGNAL_SBAS_ENA, self.sbas)?
+            .set(keys::SIGNAL_SBAS_L1CA_ENA, self.sbas)?;
+        Ok(config)
+    }
+}
+
+/// The dynamic platform model of the navigation filter (CFG-NAVSPG-DYNMODEL).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DynamicModel {
+    /// General purpose, moderate accelerations. The receiver default.
+    Portable,
+    /// Not moving at all, e.g. a fixed station.
+    Stationary,
+    /// Walking speeds and low accelerations, e.g. hiking.
+    Pedestrian,
+    /// Cars, with the vertical speed of a road vehicle.
+    Automotive,
+    /// Boats, with zero vertical velocity.
+    Sea,
+    /// Aircraft with less than 1 g acceleration.
+    Airborne1g,
+    /// Aircraft with less than 2 g acceleration.
+    Airborne2g,
+    /// Aircraft with less than 4 g acceleration.
+    Airborne4g,
+    /// Worn on the wrist.
+    Wrist,
+    /// Bicycles.
+    Bike,
+}
+
+impl From<DynamicModel> for u8 {
+    fn from(model: DynamicModel) -> Self {
+        match model {
+            DynamicModel::Portable => 0,
+            DynamicModel::Stationary => 2,
+            DynamicModel::Pedestrian => 3,
+            DynamicModel::Automotive => 4,
+            DynamicModel::Sea => 5,
+            DynamicModel::Airborne1g => 6,
+            DynamicModel::Airborne2g => 7,
+            DynamicModel::Airborne4g => 8,
+            DynamicModel::Wrist => 9,
+            DynamicModel::Bike => 10,
+        }
+    }
+}
+
+impl TryFrom<u8> for DynamicModel {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => DynamicModel::Portable,
+            2 => DynamicModel::Stationary,
+            3 => DynamicModel::Pedestrian,
+            4 => DynamicModel::Automotive,
+            5 => DynamicModel::Sea,
+            6 => DynamicModel::Airborne1g,
+            7 => DynamicModel::Airborne2g,
+            8 => DynamicModel::Airborne4g,
+            9 => DynamicModel::Wrist,
+            10 => DynamicModel::Bike,
+            other => return Err(other),
+        })
+    }
+}
+
+impl<UartType, PinType> Gps<UartType, PinType>
+where
+    UartType: Read + Write,
+    PinType: OutputPin,
+{
+    /// Selects the GNSS constellations to track and restarts the GNSS so they take
+    /// effect.
+    ///
+    /// Returns `Err(())` without changing anything if no constellation that can fix
+    /// a position on its own is selected.
+    pub async fn set_constellations(&mut self, selection: GnssSelection) -> Result<(), ()> {
+        if !selection.is_valid() {
+            log::error!("Invalid GNSS selection: {selection:?}");
+            return Err(());
+        }
+        let config = selection
+            .config()
+            .map_err(|e| log::error!("Invalid configuration: {e:?}"))?;
+        self.set_config(&config).await?;
+        self.reset(ResetKind::Hot).await?;
+        info!("GNSS selection set to {selection:?}");
+        Ok(())
+    }
+
+    /// Reads the GNSS constellations currently enabled in the receiver.
+    pub async fn constellations(&mut self) -> Result<GnssSelection, ()> {
+        Ok(GnssSelection {
+            gps: self
+                .get_config(keys::SIGNAL_GPS_ENA, ReadLayer::Ram)
+                .await?,
+            galileo: self
+                .get_config(keys::SIGNAL_GAL_ENA, ReadLayer::Ram)
+                .await?,
+            beidou: self
+                .get_config(keys::SIGNAL_BDS_ENA, ReadLayer::Ram)
+                .await?,
+            glonass: self
+                .get_config(keys::SIGNAL_GLO_ENA, ReadLayer::Ram)
+                .await?,
+            qzss: self
+                .get_config(keys::SIGNAL_QZSS_ENA, ReadLayer::Ram)
+                .await?,
+            sbas: self
+                .get_config(keys::SIGNAL_SBAS_ENA, ReadLayer::Ram)
+                .await?,
+        })
+    }
+
+    /// Sets the dynamic platform model of the navigation filter.
+    pub async fn set_dynamic_model(&mut self, model: DynamicModel) -> Result<(), ()> {
+        let mut config = ValSet::new(Layers::RAM | Layers::BBR);
+        config
+            .set(keys::NAVSPG_DYNMODEL, model.into())
+            .map_err(|e| log::error!("Invalid configuration: {e:?}"))?;
+        self.set_config(&config).await?;
+        info!("GPS dynamic model set to {model:?}");
+        Ok(())
+    }
+
+    /// Reads the dynamic platform model currently used by the receiver.
+    pub async fn dynamic_model(&mut self) -> Result<DynamicModel, ()> {
+        let value = self
+            .get_config(keys::NAVSPG_DYNMODEL, ReadLayer::Ram)
+            .await?;
+        DynamicModel::try_from(value).map_err(|v| log::warn!("Unknown dynamic model {v}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::cfg::ValGetResponse;
+    use crate::mock::{MockPin, MockUart};
+    use crate::testdata::ACK_VALSET;
+
+    const SIGNAL_KEYS: [(Constellation, [u32; 2]); 6] = [
+        (Constellation::Gps, [0x1031_001f, 0x1031_0001]),
+        (Constellation::Galileo, [0x1031_0021, 0x1031_0007]),
+        (Constellation::BeiDou, [0x1031_0022, 0x1031_000d]),
+        (Constellation::Glonass, [0x1031_0025, 0x1031_0018]),
+        (Constellation::Qzss, [0x1031_0024, 0x1031_0012]),
+        (Constellation::Sbas, [0x1031_0020, 0x1031_0005]),
+    ];
+
+    /// Returns the enabled state of every system and signal key in `selection`.
+    fn enabled(selection: GnssSelection) -> [(Constellation, [Option<bool>; 2]); 6] {
+        let config = selection.config().unwrap();
+        // A VALSET payload has the same layout as a VALGET response.
+        let values = ValGetResponse::parse(config.payload()).unwrap();
+        assert_eq!(values.values().count(), config.len());
+        SIGNAL_KEYS.map(|(constellation, ids)| {
+            (
+                constellation,
+                ids.map(|id| {
+                    values
+                        .values()
+                        .find(|(key, _)| *key == id)
+                        .map(|(_, value)| value == [1])
+                }),
+            )
+        })
+    }
+
+    #[test]
+    fn config_sets_system_and_signal_keys() {
+        for (constellation, states) in enabled(GnssSelection::default()) {
+            let expected = constellation != Constellation::Glonass;
+            assert_eq!(states, [Some(expected); 2], "{constellation:?}");
+        }
+        for (constellation, states) in enabled(GnssSelection::GPS_ONLY) {
+            let expected = constellation == Constellation::Gps;
+            assert_eq!(states, [Some(expected); 2], "{constellation:?}");
+        }
+        // QZSS has a second signal.
+        let config = GnssSelection::default().config().unwrap();
+        let values = ValGetResponse::parse(config.payload()).unwrap();
+        assert_eq!(values.get(keys::SIGNAL_QZSS_L1S_ENA), Some(true));
+        assert_eq!(config.payload()[1], 0x03);
+    }
+
+    #[test]
+    fn config_disables_qzss_without_gps() {
+        let mut selection = GnssSelection::default();
+        selection.set(Constellation::Gps, false);
+        assert!(selection.is_enabled(Constellation::Qzss));
+        let config = selection.config().unwrap();
+        let values = ValGetResponse::parse(config.payload()).unwrap();
+        assert_eq!(values.get(keys::SIGNAL_QZSS_ENA), Some(false));
+        assert_eq!(values.get(keys::SIGNAL_QZSS_L1CA_ENA), Some(false));
+        assert_eq!(values.get(keys::SIGNAL_QZSS_L1S_ENA), Some(false));
+        assert_eq!(values.get(keys::SIGNAL_GAL_ENA), Some(true));
+    }
+
+    #[test]
+    fn selection_needs_a_standalone_constellation() {
+        assert!(GnssSelection::default().is_valid());
+        assert!(GnssSelection::GPS_ONLY.is_valid());
+
+        let mut selection = GnssSelection::GPS_ONLY;
+        selection
+            .set(Constellation::Gps, false)
+            .set(Constellation::Qzss, true)
+            .set(Constellation::Sbas, true);
+        assert!(!selection.is_valid());
+        selection.set(Constellation::Glonass, true);
+        assert!(selection.is_valid());
+
+        // Constellations the receiver does not support are ignored.
+        let mut selection = GnssSelection::GPS_ONLY;
+        selection.set(Constellation::NavIc, true);
+        assert_eq!(selection, GnssSelection::GPS_ONLY);
+        assert!(!selection.is_enabled(Constellation::NavIc));
+    }
+
+    #[test]
+    fn rejects_invalid_selection_without_sending() {
+        let mut gps = Gps::with_uart(MockUart::new(&[], 256), MockPin);
+        let mut selection = GnssSelection::GPS_ONLY;
+        selection
+            .set(Constellation::Gps, false)
+            .set(Constellation::Sbas, true);
+        assert_eq!(block_on(gps.set_constellations(selection)), Err(()));
+        assert!(gps.uart.tx.is_empty());
+    }
+
+    #[test]
+    fn dynamic_model_round_trips() {
+        for value in 0..=u8::MAX {
+            match DynamicModel::try_from(value) {
+                Ok(model) => assert_eq!(u8::from(model), value),
+                Err(rejected) => {
+                    assert_eq!(rejected, value);
+                    assert!(value == 1 || value > 10, "{value}");
+                }
+            }
+        }
+        assert_eq!(DynamicModel::try_from(3), Ok(DynamicModel::Pedestrian));
+    }
+
+    #[test]
+    fn sets_dynamic_model() {
+        let mut gps = Gps::with_uart(MockUart::new(ACK_VALSET, 256), MockPin);
+        block_on(gps.set_dynamic_model(DynamicModel::Automotive)).unwrap();
+        assert_eq!(
+            gps.uart.tx[6..gps.uart.tx.len() - 2],
+            [0x00, 0x03, 0x00, 0x00, 0x21, 0x00, 0x11, 0x20, 0x04]
+        );
+    }
+}
@@ -0,0 +1,678 @@
+//! Receiver health monitoring and automatic recovery.
+//!
+//! `Gps` keeps `GpsStats` counters of received data and errors. A `HealthMonitor`
+//! compares them against the thresholds of a `HealthConfig` and detects UART
+//! silence, bursts of corrupted packets, NAK storms and fix loss. On a receiver
+//! fault it escalates through a hot, warm and cold start to a power cycle via the
+//! enable pin, giving the receiver `HealthConfig::recovery_timeout` to recover after
+//! each step.
+//!
+//! Fix loss usually means the sky is blocked, e.g. indoors, rather than a broken
+//! receiver. It only gets a single hot start: clearing the navigation data would
+//! make the next fix slower once the sky is visible again.
+//!
+//! Resets are GNSS-only, so the configuration in RAM is kept. A power cycle reloads
+//! the configuration from BBR and flash, which keeps the settings written by
+//! `Gps::set_power_mode` as long as the backup supply is present.
+
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use embedded_hal::digital::OutputPin;
+use embedded_io_async::{Read, Write};
+use log::{info, warn};
+
+use crate::update::GpsUpdate;
+use crate::{ubx, Gps};
+
+/// Reset mode of CFG-RST: controlled GNSS-only software reset.
+const RESET_MODE_GNSS: u8 = 0x02;
+/// How long the receiver needs to restart after a reset.
+const RESET_DELAY: Duration = Duration::from_millis(500);
+/// How long the receiver is kept off during a power cycle.
+const POWER_OFF_TIME: Duration = Duration::from_secs(1);
+/// How long the receiver needs to boot after power-on.
+const BOOT_TIME: Duration = Duration::from_secs(1);
+
+/// Counters of received data and errors, kept by `Gps`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GpsStats {
+    /// The number of bytes received from the receiver (wrapping).
+    pub bytes_received: u32,
+    /// When data was last received.
+    pub last_received: Option<Instant>,
+    /// UART read errors.
+    pub uart_errors: u32,
+    /// Packets dropped by the protocol multiplexer, e.g. UBX checksum errors or
+    /// truncated NMEA sentences.
+    pub framing_errors: u32,
+    /// NMEA sentences with a wrong checksum.
+    pub nmea_checksum_errors: u32,
+    /// UBX messages rejected with ACK-NAK.
+    pub naks: u32,
+    /// UBX messages that were neither acknowledged nor rejected in time.
+    pub ack_timeouts: u32,
+}
+
+impl GpsStats {
+    /// The number of corrupted packets of any protocol.
+    pub fn corrupted_packets(&self) -> u32 {
+        self.framing_errors
+            .wrapping_add(self.nmea_checksum_errors)
+            .wrapping_add(self.uart_errors)
+    }
+}
+
+/// What the receiver keeps when it is reset with CFG-RST.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetKind {
+    /// Keep all navigation data.
+    Hot,
+    /// Clear the ephemeris.
+    Warm,
+    /// Clear all navigation data.
+    Cold,
+}
+
+impl ResetKind {
+    /// The navBbrMask field of CFG-RST.
+    fn nav_bbr_mask(self) -> u16 {
+        match self {
+            ResetKind::Hot => 0x0000,
+            ResetKind::Warm => 0x0001,
+            ResetKind::Cold => 0xFFFF,
+        }
+    }
+}
+
+/// A fault detected by the `HealthMonitor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// No data was received for `HealthConfig::silence_timeout`.
+    UartSilence,
+    /// Too many corrupted packets within `HealthConfig::error_window`.
+    CorruptedData,
+    /// Too many NAKs or ACK timeouts within `HealthConfig::error_window`.
+    NakStorm,
+    /// No fix for `HealthConfig::fix_loss_timeout`. Recovered with a hot start
+    /// only.
+    FixLost,
+}
+
+/// A recovery step, in order of escalation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RecoveryAction {
+    /// Hot start, keeping all navigation data.
+    HotStart,
+    /// Warm start, clearing the ephemeris.
+    WarmStart,
+    /// Cold start, clearing all navigation data.
+    ColdStart,
+    /// Switch the receiver off and on with the enable pin.
+    PowerCycle,
+}
+
+impl RecoveryAction {
+    /// The next step if this one did not help.
+    fn escalate(self) -> Self {
+        match self {
+            RecoveryAction::HotStart => RecoveryAction::WarmStart,
+            RecoveryAction::WarmStart => RecoveryAction::ColdStart,
+            RecoveryAction::ColdStart | RecoveryAction::PowerCycle => RecoveryAction::PowerCycle,
+        }
+    }
+}
+
+/// The health of the receiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthState {
+    /// No fault detected.
+    Healthy,
+    /// A fault was detected and `action` was taken; waiting for the receiver to
+    /// recover.
+    Recovering {
+        /// The detected fault.
+        fault: Fault,
+        /// The last recovery step taken.
+        action: RecoveryAction,
+    },
+    /// The fault persists after a power cycle. Power cycles are retried every
+    /// `HealthConfig::recovery_timeout`.
+    Failed(Fault),
+}
+
+/// Thresholds of the `HealthMonitor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthConfig {
+    /// The longest time without data from an awake receiver. Must be longer than
+    /// the update period in on/off power save mode.
+    pub silence_timeout: Duration,
+    /// The window in which corrupted packets and NAKs are counted.
+    pub error_window: Duration,
+    /// The number of corrupted packets within `error_window` that is a fault.
+    pub max_corrupted_packets: u32,
+    /// The number of NAKs and ACK timeouts within `error_window` that is a fault.
+    pub max_naks: u32,
+    /// The longest time without a fix, if fix loss is a fault.
+    pub fix_loss_timeout: Option<Duration>,
+    /// How long to wait for the receiver to recover before escalating.
+    pub recovery_timeout: Duration,
+    /// How often `HealthMonitor::next_update` checks the receiver.
+    pub check_interval: Duration,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            silence_timeout: Duration::from_secs(5),
+            error_window: Duration::from_secs(30),
+            max_corrupted_packets: 20,
+            max_naks: 5,
+            fix_loss_timeout: Some(Duration::from_secs(10 * 60)),
+            recovery_timeout: Duration::from_secs(60),
+            check_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// How often each fault and recovery step occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HealthCounters {
+    /// UART silence faults.
+    pub uart_silence: u32,
+    /// Corrupted data faults.
+    pub corrupted_data: u32,
+    /// NAK storm faults.
+    pub nak_storms: u32,
+    /// Fix loss faults.
+    pub fix_losses: u32,
+    /// Hot starts performed.
+    pub hot_starts: u32,
+    /// Warm starts performed.
+    pub warm_starts: u32,
+    /// Cold starts performed.
+    pub cold_starts: u32,
+    /// Power cycles performed.
+    pub power_cycles: u32,
+    /// Faults that cleared after a recovery step.
+    pub recoveries: u32,
+}
+
+/// A snapshot of the receiver health for the application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthReport {
+    /// The current health state.
+    pub state: HealthState,
+    /// The receiver counters.
+    pub stats: GpsStats,
+    /// The fault and recovery counters.
+    pub counters: HealthCounters,
+}
+
+/// Watches the receiver and recovers it from faults.
+pub struct HealthMonitor {
+    config: HealthConfig,
+    state: HealthState,
+    counters: HealthCounters,
+    started: Instant,
+    last_fix: Option<Instant>,
+    last_check: Option<Instant>,
+    last_action: Option<(RecoveryAction, Instant)>,
+    window_start: Instant,
+    window_base: GpsStats,
+    stats: GpsStats,
+}
+
+impl HealthMonitor {
+    /// Creates a new monitor with the given thresholds.
+    pub fn new(config: HealthConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            state: HealthState::Healthy,
+            counters: HealthCounters::default(),
+            started: now,
+            last_fix: None,
+            last_check: None,
+            last_action: None,
+            window_start: now,
+            window_base: GpsStats::default(),
+            stats: GpsStats::default(),
+        }
+    }
+
+    /// Returns the thresholds of the monitor.
+    pub fn config(&self) -> HealthConfig {
+        self.config
+    }
+
+    /// Returns the current health state.
+    pub fn state(&self) -> HealthState {
+        self.state
+    }
+
+    /// Returns the state and counters as of the last check.
+    pub fn report(&self) -> HealthReport {
+        HealthReport {
+            state: self.state,
+            stats: self.stats,
+            counters: self.counters,
+        }
+    }
+
+    /// Records an update from the receiver. Only `GpsUpdate::Fix` resets the fix
+    /// loss timer.
+    pub fn observe(&mut self, update: &GpsUpdate) {
+        if let GpsUpdate::Fix(_) = update {
+            self.last_fix = Some(Instant::now());
+        }
+    }
+
+    /// Waits for the next update from `gps`, checking its health at least every
+    /// `HealthConfig::check_interval` and recovering it if needed.
+    pub async fn next_update<UartType, PinType>(
+        &mut self,
+        gps: &mut Gps<UartType, PinType>,
+    ) -> Result<GpsUpdate, ()>
+    where
+        UartType: Read + Write,
+        PinType: OutputPin,
+    {
+        loop {
+            let update = with_timeout(self.config.check_interval, gps.next_update()).await;
+            let due = self
+                .last_check
+                .is_none_or(|last| last.elapsed() >= self.config.check_interval);
+            if due {
+                self.check(gps).await?;
+            }
+            if let Ok(update) = update {
+                let update = update?;
+                self.observe(&update);
+                return Ok(update);
+            }
+        }
+    }
+
+    /// Checks the receiver and takes the next recovery step if a fault persists.
+    /// Returns the resulting state.
+    pub async fn check<UartType, PinType>(
+        &mut self,
+        gps: &mut Gps<UartType, PinType>,
+    ) -> Result<HealthState, ()>
+    where
+        UartType: Read + Write,
+        PinType: OutputPin,
+    {
+        let now = Instant::now();
+        self.last_check = Some(now);
+        self.stats = gps.stats();
+        if now.saturating_duration_since(self.window_start) >= self.config.error_window {
+            self.restart_window(now);
+        }
+
+        let Some(fault) = self.detect(gps.power_mode().is_asleep(), now) else {
+            if self.state != HealthState::Healthy {
+                info!("GPS recovered");
+                self.counters.recoveries += 1;
+            }
+            self.state = HealthState::Healthy;
+            self.last_action = None;
+            return Ok(self.state);
+        };
+
+        let action = match self.last_action {
+            // Give the last step time to work.
+            Some((_, at)) if now.saturating_duration_since(at) < self.config.recovery_timeout => {
+                return Ok(self.state);
+            }
+            // Only receiver faults escalate.
+            Some((action, _)) if fault == Fault::FixLost => {
+                self.state = HealthState::Recovering { fault, action };
+                return Ok(self.state);
+            }
+            Some((action, _)) => action.escalate(),
+            None => {
+                self.count_fault(fault);
+                RecoveryAction::HotStart
+            }
+        };
+
+        warn!("GPS fault {fault:?}, recovering with {action:?}");
+        let failed = matches!(self.last_action, Some((RecoveryAction::PowerCycle, _)));
+        let result = self.recover(gps, action).await;
+        let now = Instant::now();
+        self.last_action = Some((action, now));
+        self.stats = gps.stats();
+        self.restart_window(now);
+        self.state = if failed {
+            HealthState::Failed(fault)
+        } else {
+            HealthState::Recovering { fault, action }
+        };
+        result.map(|_| self.state)
+    }
+
+    /// Returns the most severe fault, if any.
+    fn detect(&self, asleep: bool, now: Instant) -> Option<Fault> {
+        let last_received = self.stats.last_received.unwrap_or(self.started);
+        if !asleep && now.saturating_duration_since(last_received) > self.config.silence_timeout {
+            return Some(Fault::UartSilence);
+        }
+        let base = &self.window_base;
+        let corrupted = self
+            .stats
+            .corrupted_packets()
+            .wrapping_sub(base.corrupted_packets());
+        if corrupted >= self.config.max_corrupted_packets {
+            return Some(Fault::CorruptedData);
+        }
+        let naks = self
+            .stats
+            .naks
+            .wrapping_add(self.stats.ack_timeouts)
+            .wrapping_sub(base.naks.wrapping_add(base.ack_timeouts));
+        if naks >= self.config.max_naks {
+            return Some(Fault::NakStorm);
+        }
+        let last_fix = self.last_fix.unwrap_or(self.started);
+        if !asleep
+            && self
+                .config
+                .fix_loss_timeout
+                .is_some_and(|timeout| now.saturating_duration_since(last_fix) > timeout)
+        {
+            return Some(Fault::FixLost);
+        }
+        None
+    }
+
+    fn restart_window(&mut self, now: Instant) {
+        self.window_start = now;
+        self.window_base = self.stats;
+    }
+
+    fn count_fault(&mut self, fault: Fault) {
+        match fault {
+            Fault::UartSilence => self.counters.uart_silence += 1,
+            Fault::CorruptedData => self.counters.corrupted_data += 1,
+            Fault::NakStorm => self.counters.nak_storms += 1,
+            Fault::FixLost => self.counters.fix_losses += 1,
+        }
+    }
+
+    async fn recover<UartType, PinType>(
+        &mut self,
+        gps: &mut Gps<UartType, PinType>,
+        action: RecoveryAction,
+    ) -> Result<(), ()>
+    where
+        UartType: Read + Write,
+        PinType: OutputPin,
+    {
+        match action {
+            RecoveryAction::HotStart => {
+                self.counters.hot_starts += 1;
+                gps.reset(ResetKind::Hot).await
+            }
+            RecoveryAction::WarmStart => {
+                self.counters.warm_starts += 1;
+                gps.reset(ResetKind::Warm).await
+            }
+            RecoveryAction::ColdStart => {
+                self.counters.cold_starts += 1;
+                gps.reset(ResetKind::Cold).await
+            }
+            RecoveryAction::PowerCycle => {
+                self.counters.power_cycles += 1;
+                gps.power_cycle().await
+            }
+        }
+    }
+}
+
+impl<UartType, PinType> Gps<UartType, PinType>
+where
+    UartType: Read + Write,
+    PinType: OutputPin,
+{
+    /// Returns the counters of received data and errors.
+    pub fn stats(&self) -> GpsStats {
+        GpsStats {
+            framing_errors: self.mux.error_count(),
+            ..self.stats
+        }
+    }
+
+    /// Restarts the GNSS part of the receiver with UBX-CFG-RST.
+    ///
+    /// The receiver does not acknowledge the reset; data received before it is
+    /// discarded.
+    pub async fn reset(&mut self, kind: ResetKind) -> Result<(), ()> {
+        let mask = kind.nav_bbr_mask().to_le_bytes();
+        let payload = [mask[0], mask[1], RESET_MODE_GNSS, 0x00];
+        self.send_ubx_message(ubx::class::CFG, ubx::id::CFG_RST, &payload)
+            .await?;
+        Timer::after(RESET_DELAY).await;
+        self.discard_input();
+        info!("GPS {kind:?} start");
+        Ok(())
+    }
+
+    /// Switches the receiver off and on again with the enable pin.
+    pub async fn power_cycle(&mut self) -> Result<(), ()> {
+        self.enable_pin
+            .set_low()
+            .map_err(|e| warn!("Failed to clear the GPS enable pin: {e:?}"))?;
+        Timer::after(POWER_OFF_TIME).await;
+        self.enable_pin
+            .set_high()
+            .map_err(|e| warn!("Failed to set the GPS enable pin: {e:?}"))?;
+        Timer::after(BOOT_TIME).await;
+        self.discard_input();
+        self.set_awake();
+        info!("GPS power cycled");
+        Ok(())
+    }
+
+    /// Drops buffered and partially received data.
+    fn discard_input(&mut self) {
+        self.rx_pos = self.rx_len;
+        self.mux.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::{MockPin, MockUart};
+    use crate::testdata::{GGA, RMC};
+
+    type MockGps = Gps<MockUart, MockPin>;
+
+    /// Thresholds that never fire unless a test lowers them.
+    fn config() -> HealthConfig {
+        HealthConfig {
+            silence_timeout: Duration::from_secs(60),
+            error_window: Duration::from_secs(60),
+            max_corrupted_packets: 3,
+            max_naks: 3,
+            fix_loss_timeout: None,
+            recovery_timeout: Duration::from_ticks(0),
+            check_interval: Duration::from_millis(1),
+        }
+    }
+
+    fn gps(rx: &[u8]) -> MockGps {
+        Gps::with_uart(MockUart::new(rx, 64), MockPin)
+    }
+
+    fn check(monitor: &mut HealthMonitor, gps: &mut MockGps) -> HealthState {
+        block_on(monitor.check(gps)).unwrap()
+    }
+
+    fn sleep(ms: u64) {
+        block_on(Timer::after_millis(ms));
+    }
+
+    /// The navBbrMask of every CFG-RST frame sent to the receiver.
+    fn resets(gps: &MockGps) -> Vec<u16> {
+        gps.uart
+            .tx
+            .windows(8)
+            .filter(|w| w[..6] == [0xB5, 0x62, ubx::class::CFG, ubx::id::CFG_RST, 4, 0])
+            .map(|w| u16::from_le_bytes([w[6], w[7]]))
+            .collect()
+    }
+
+    #[test]
+    fn silence_escalates_to_failed() {
+        let mut gps = gps(&[]);
+        let mut monitor = HealthMonitor::new(HealthConfig {
+            silence_timeout: Duration::from_millis(10),
+            ..config()
+        });
+        assert_eq!(check(&mut monitor, &mut gps), HealthState::Healthy);
+        sleep(20);
+
+        for action in [
+            RecoveryAction::HotStart,
+            RecoveryAction::WarmStart,
+            RecoveryAction::ColdStart,
+            RecoveryAction::PowerCycle,
+        ] {
+            let fault = Fault::UartSilence;
+            assert_eq!(
+                check(&mut monitor, &mut gps),
+                HealthState::Recovering { fault, action }
+            );
+        }
+        assert_eq!(
+            check(&mut monitor, &mut gps),
+            HealthState::Failed(Fault::UartSilence)
+        );
+
+        assert_eq!(resets(&gps), [0x0000, 0x0001, 0xFFFF]);
+        let counters = monitor.report().counters;
+        assert_eq!(counters.uart_silence, 1);
+        assert_eq!(
+            (
+                counters.hot_starts,
+                counters.warm_starts,
+                counters.cold_starts
+            ),
+            (1, 1, 1)
+        );
+        assert_eq!(counters.power_cycles, 2);
+        assert_eq!(counters.recoveries, 0);
+    }
+
+    #[test]
+    fn waits_for_recovery_timeout() {
+        let mut gps = gps(&[]);
+        let mut monitor = HealthMonitor::new(HealthConfig {
+            silence_timeout: Duration::from_millis(10),
+            recovery_timeout: Duration::from_secs(60),
+            ..config()
+        });
+        sleep(20);
+        let recovering = HealthState::Recovering {
+            fault: Fault::UartSilence,
+            action: RecoveryAction::HotStart,
+        };
+        assert_eq!(check(&mut monitor, &mut gps), recovering);
+        assert_eq!(check(&mut monitor, &mut gps), recovering);
+        assert_eq!(resets(&gps).len(), 1);
+    }
+
+    #[test]
+    fn recovery_clears_state() {
+        let mut gps = gps(&[GGA, GGA].concat());
+        let mut monitor = HealthMonitor::new(HealthConfig {
+            silence_timeout: Duration::from_millis(10),
+            ..config()
+        });
+        sleep(20);
+        check(&mut monitor, &mut gps);
+        check(&mut monitor, &mut gps);
+        assert_eq!(monitor.report().counters.warm_starts, 1);
+
+        // Data flows again.
+        block_on(gps.next_update()).unwrap();
+        assert_eq!(check(&mut monitor, &mut gps), HealthState::Healthy);
+        assert_eq!(monitor.report().counters.recoveries, 1);
+
+        // The next fault starts over with a hot start.
+        sleep(20);
+        assert_eq!(
+            check(&mut monitor, &mut gps),
+            HealthState::Recovering {
+                fault: Fault::UartSilence,
+                action: RecoveryAction::HotStart,
+            }
+        );
+        let counters = monitor.report().counters;
+        assert_eq!((counters.uart_silence, counters.hot_starts), (2, 2));
+    }
+
+    #[test]
+    fn error_window_restarts() {
+        let bad = b"$GNGGA,102345.00,5231.20040,N,01324.29724,E,1,11,0.95,38.5,M,46.6,M,,*00\r\n";
+        let rx = [bad, bad, GGA, bad, bad, GGA, bad, bad, bad, GGA].concat();
+        let mut gps = gps(&rx);
+        let mut monitor = HealthMonitor::new(HealthConfig {
+            error_window: Duration::from_millis(50),
+            ..config()
+        });
+
+        block_on(gps.next_update()).unwrap();
+        assert_eq!(gps.stats().nmea_checksum_errors, 2);
+        assert_eq!(check(&mut monitor, &mut gps), HealthState::Healthy);
+
+        // Four errors in total, but the window restarted in between.
+        sleep(60);
+        block_on(gps.next_update()).unwrap();
+        assert_eq!(check(&mut monitor, &mut gps), HealthState::Healthy);
+
+        block_on(gps.next_update()).unwrap();
+        assert_eq!(
+            check(&mut monitor, &mut gps),
+            HealthState::Recovering {
+                fault: Fault::CorruptedData,
+                action: RecoveryAction::HotStart,
+            }
+        );
+        assert_eq!(monitor.report().counters.corrupted_data, 1);
+    }
+
+    #[test]
+    fn fix_loss_only_hot_starts() {
+        let mut gps = gps(&[GGA, RMC].concat());
+        let mut monitor = HealthMonitor::new(HealthConfig {
+            fix_loss_timeout: Some(Duration::from_millis(10)),
+            ..config()
+        });
+        sleep(20);
+        let recovering = HealthState::Recovering {
+            fault: Fault::FixLost,
+            action: RecoveryAction::HotStart,
+        };
+        for _ in 0..3 {
+            assert_eq!(check(&mut monitor, &mut gps), recovering);
+        }
+        assert_eq!(resets(&gps), [0x0000]);
+        let counters = monitor.report().counters;
+        assert_eq!((counters.fix_losses, counters.hot_starts), (1, 1));
+
+        // GGA alone lacks the date, the fix is complete with RMC.
+        for _ in 0..2 {
+            monitor.observe(&block_on(gps.next_update()).unwrap());
+        }
+        assert_eq!(check(&mut monitor, &mut gps), HealthState::Healthy);
+        assert_eq!(monitor.report().counters.recoveries, 1);
+    }
+}
@@ -4,9 +4,15 @@
 //! It allows for configuring the power mode and reading parsed NMEA 0183 sentences
 //! to get information like time, date, location, and fix status.
 //!
+//! `Gps` works with any `embedded_io_async::Read + Write` UART and any `OutputPin`
+//! as the enable pin, see `Gps::with_uart`. The `t-deck` feature (enabled by
+//! default) adds `Gps::new`, which sets up the T-Deck's UART1; without it the crate
+//! does not depend on `esp-hal` and can be used on the host, e.g. to replay
+//! recorded receiver logs.
+//!
 //! # Usage
 //!
-//! ```no_run
+//! ```ignore
 //! # #![no_std]
 //! # #![no_main]
 //! # use esp_hal::prelude::*;
@@ -31,7 +37,7 @@
 //!     let mut gps = Gps::new(peripherals.UART1, tx, rx, enable_pin);
 //!
 //!     // Set the desired power mode
-//!     gps.set_power_mode(PowerMode::Periodic).await.unwrap();
+//!     gps.set_power_mode(PowerMode::Normal).await.unwrap();
 //!
 //!     // Main loop to read GPS messages
 //!     loop {
@@ -45,28 +51,54 @@
 //! ```
 #![no_std]
 
+pub mod assist;
+pub mod baud;
+pub mod cfg;
+pub mod geo;
+pub mod gnss;
+pub mod health;
+pub mod mux;
+pub mod power;
+pub mod satellites;
+pub mod time;
+pub mod track;
+pub mod ubx;
+pub mod update;
+
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+mod testdata;
+
+pub use health::GpsStats;
+pub use power::PowerMode;
+pub use update::{FixPolicy, GpsUpdate};
+
 use chrono::{Datelike, Timelike};
-use embassy_time::{Duration, Timer};
+use embassy_time::{with_timeout, Duration, Instant};
+use embedded_hal::digital::OutputPin;
+use embedded_io_async::{Read, Write};
+#[cfg(feature = "t-deck")]
 use esp_hal::{
     gpio::{AnyPin, Output},
     peripherals::UART1,
     uart::{Config, Uart},
     Async,
 };
-use heapless::String;
 use log::{info, trace};
 use nmea::Nmea;
 
-/// Represents the power mode of the GPS module.
-#[derive(Debug, Clone, Copy)]
-pub enum PowerMode {
-    /// Normal 1Hz updates.
-    Normal,
-    /// Update every minute, if moved.
-    Eco { update_rate_ms: u16 },
-    /// Software standby mode.
-    SoftwareStandby,
-}
+use crate::cfg::{CfgValue, Key, ReadLayer, ValGetResponse, ValSet};
+use crate::health::ResetKind;
+use crate::mux::{Packet, ProtocolMux};
+use crate::satellites::{SatelliteView, UsedSatellites};
+use crate::ubx::UbxMessage;
+
+/// Size of the receive buffer kept between reads.
+const RX_BUFFER_LEN: usize = 256;
+
+/// How long to wait for the receiver to acknowledge a UBX message.
+const ACK_TIMEOUT: Duration = Duration::from_secs(1);
 
 /// Represents a time of day.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -144,6 +176,18 @@ pub struct GpsData {
     pub longitude: Option<f64>,
     /// The speed over ground in knots, if available.
     pub speed_over_ground: Option<f32>,
+    /// The altitude above mean sea level in meters, if available.
+    pub altitude: Option<f32>,
+    /// The course over ground in degrees from true north, if available.
+    pub course_over_ground: Option<f32>,
+    /// The horizontal dilution of precision, if available.
+    pub hdop: Option<f32>,
+    /// The vertical dilution of precision, if available.
+    pub vdop: Option<f32>,
+    /// The position dilution of precision, if available.
+    pub pdop: Option<f32>,
+    /// The number of satellites used in the fix, if available.
+    pub satellites_used: Option<u32>,
 }
 
 impl GpsData {
@@ -154,14 +198,30 @@ impl GpsData {
 }
 
 /// A driver for the T-Deck GPS module.
-pub struct Gps<'d> {
-    uart: Uart<'d, Async>,
-    _enable_pin: Output<'d>,
+///
+/// The driver talks to the receiver over any `embedded_io_async` UART and powers it
+/// through an `OutputPin`. `Gps::new` sets it up for the T-Deck's UART1.
+pub struct Gps<UartType, PinType> {
+    uart: UartType,
+    enable_pin: PinType,
     nmea: Nmea,
+    mux: ProtocolMux,
+    power_mode: PowerMode,
+    awake_power_mode: PowerMode,
+    wake_deadline: Option<Instant>,
+    fix_policy: FixPolicy,
+    satellites: SatelliteView,
+    used_satellites: UsedSatellites,
+    rx_buffer: [u8; RX_BUFFER_LEN],
+    rx_pos: usize,
+    rx_len: usize,
+    stats: GpsStats,
+    baud_rate: Option<u32>,
 }
 
-impl<'d> Gps<'d> {
-    /// Creates a new `Gps` driver instance.
+#[cfg(feature = "t-deck")]
+impl<'d> Gps<Uart<'d, Async>, Output<'d>> {
+    /// Creates a new `Gps` driver instance for the T-Deck.
     ///
     /// This initializes the UART peripheral for communication with the GPS module
     /// and ensures the module is powered on via the enable pin.
@@ -172,282 +232,322 @@ impl<'d> Gps<'d> {
     /// * `tx` - The UART transmit pin.
     /// * `rx` - The UART receive pin.
     /// * `enable_pin` - The GPIO output pin used to enable the GPS module.
-    pub fn new(
-        uart1: UART1<'d>,
-        tx: AnyPin<'d>,
-        rx: AnyPin<'d>,
-        mut enable_pin: Output<'d>,
-    ) -> Self {
-        let config = Config::default().with_baudrate(38400);
+    pub fn new(uart1: UART1<'d>, tx: AnyPin<'d>, rx: AnyPin<'d>, enable_pin: Output<'d>) -> Self {
+        let config = Config::default().with_baudrate(baud::DEFAULT_BAUD_RATE);
         let uart = Uart::new(uart1, config).unwrap().with_tx(tx).with_rx(rx);
 
-        enable_pin.set_high();
+        let mut gps = Self::with_uart(uart.into_async(), enable_pin);
+        gps.baud_rate = Some(baud::DEFAULT_BAUD_RATE);
+        gps
+    }
+}
+
+impl<UartType, PinType> Gps<UartType, PinType>
+where
+    UartType: Read + Write,
+    PinType: OutputPin,
+{
+    /// Creates a new `Gps` driver on an already configured UART.
+    ///
+    /// The module is powered on via the enable pin.
+    ///
+    /// # Arguments
+    ///
+    /// * `uart` - A UART connected to the GPS module, at the receiver's baud rate.
+    /// * `enable_pin` - The output pin used to enable the GPS module.
+    pub fn with_uart(uart: UartType, mut enable_pin: PinType) -> Self {
+        if enable_pin.set_high().is_err() {
+            log::warn!("Failed to set the GPS enable pin");
+        }
 
         Self {
-            uart: uart.into_async(),
-            _enable_pin: enable_pin,
+            uart,
+            enable_pin,
             nmea: Nmea::default(),
+            mux: ProtocolMux::new(),
+            power_mode: PowerMode::Normal,
+            awake_power_mode: PowerMode::Normal,
+            wake_deadline: None,
+            fix_policy: FixPolicy::default(),
+            satellites: SatelliteView::default(),
+            used_satellites: UsedSatellites::default(),
+            rx_buffer: [0; RX_BUFFER_LEN],
+            rx_pos: 0,
+            rx_len: 0,
+            stats: GpsStats::default(),
+            baud_rate: None,
         }
     }
 
-    /// Sets the power mode of the GPS module by sending a UBX message.
+    /// Writes configuration values with UBX-CFG-VALSET and waits for the receiver to
+    /// acknowledge them.
     ///
     /// # Arguments
     ///
-    /// * `mode` - The desired `PowerMode`.
-    pub async fn set_power_mode(&mut self, mode: PowerMode) -> Result<(), ()> {
-        // const LOCK_CONFIG: &[u8] = &[
-        //     0x01, 0x01, 0x00, 0x00, // Version, Layers (RAM only), Reserved
-        //     0x03, 0x00, 0xE1, 0x20, // Key: CFG-SEC-CFG_LOCK
-        //     0x01,                   // Value: true
-        // ];
-        const UNLOCK_CONFIG: &[u8] = &[
-            0x01, 0x01, 0x00, 0x00, // Version, Layers (RAM only), Reserved
-            0x03, 0x00, 0xE1, 0x20, // Key: CFG-SEC-CFG_LOCK
-            0x00, // Value: false
-        ];
-
-        match mode {
-            PowerMode::SoftwareStandby => {
-                // Checked, ok.
-                let (class, id, payload) = (
-                    0x02,
-                    0x41,
-                    &[0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00],
-                );
-                self.send_ubx_message(class, id, payload).await?;
-                Timer::after(Duration::from_millis(20)).await;
-                Ok(())
-            }
-            _ => {
-                if self
-                    .send_ubx_message(
-                        0x06, // Class: CFG
-                        0x8A, // ID: VALSET
-                        UNLOCK_CONFIG,
-                    )
-                    .await
-                    .is_err()
-                {
-                    log::warn!("Failed sending unlock command");
-                }
-                log::trace!("Unlock request ack: {:?}", self.wait_for_ack().await);
-
-                let (class, id, payload): (u8, u8, &[u8]) = match mode {
-                    PowerMode::Normal => (
-                        0x06,
-                        0x8A,
-                        &[
-                            0x01, 0x01, 0x00, 0x00, // Version, Layers (RAM+BBR), Reserved
-                            0x01, 0x00, 0x21, 0x30, // Key: CFG-RATE-MEAS
-                            0xD0, 0x07, // Value: 2000 ms (little-endian for 0x7530)
-                        ],
-                    ),
-                    PowerMode::Eco { update_rate_ms } => {
-                        let rate = to_little_endian_bytes(update_rate_ms);
-                        (
-                            0x06,
-                            0x8A,
-                            &[
-                                0x01, 0x01, 0x00, 0x00, // Version, Layers (RAM+BBR), Reserved
-                                0x01, 0x00, 0x21, 0x30, // Key: CFG-RATE-MEAS
-                                rate[0], rate[1],
-                            ],
-                        )
-                    }
-                    PowerMode::SoftwareStandby => unreachable!(),
+    /// * `config` - The keys and values to write, together with the target layers.
+    pub async fn set_config(&mut self, config: &ValSet) -> Result<(), ()> {
+        self.send_ubx_message(ubx::class::CFG, ubx::id::CFG_VALSET, config.payload())
+            .await?;
+        self.wait_for_ack(ubx::class::CFG, ubx::id::CFG_VALSET)
+            .await
+    }
+
+    /// Reads a configuration value with UBX-CFG-VALGET.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The configuration key to read.
+    /// * `layer` - The configuration layer to read from.
+    pub async fn get_config<T: CfgValue>(
+        &mut self,
+        key: Key<T>,
+        layer: ReadLayer,
+    ) -> Result<T, ()> {
+        let request = cfg::valget_request(&[key.id()], layer)
+            .map_err(|e| log::error!("Invalid VALGET request: {e:?}"))?;
+        self.send_ubx_message(ubx::class::CFG, ubx::id::CFG_VALGET, &request)
+            .await?;
+
+        // The receiver answers with the VALGET response, followed by an ACK.
+        let mut value = None;
+        with_timeout(ACK_TIMEOUT, async {
+            loop {
+                let byte = self.read_byte().await?;
+                let Some(Ok(Packet::Ubx(frame))) = self.mux.push(byte) else {
+                    continue;
                 };
-                self.send_ubx_message(class, id, payload).await?;
-                self.wait_for_ack().await
+                match UbxMessage::parse(frame) {
+                    Ok(UbxMessage::Other(frame))
+                        if (frame.class, frame.id) == (ubx::class::CFG, ubx::id::CFG_VALGET) =>
+                    {
+                        match ValGetResponse::parse(frame.payload) {
+                            Ok(response) => value = response.get(key),
+                            Err(e) => log::warn!("Invalid VALGET response: {e:?}"),
+                        }
+                    }
+                    Ok(UbxMessage::AckAck { class, id })
+                        if (class, id) == (ubx::class::CFG, ubx::id::CFG_VALGET) =>
+                    {
+                        return Ok(());
+                    }
+                    Ok(UbxMessage::AckNak { class, id })
+                        if (class, id) == (ubx::class::CFG, ubx::id::CFG_VALGET) =>
+                    {
+                        info!("Received NACK from GPS for VALGET of {:#010x}", key.id());
+                        self.stats.naks += 1;
+                        return Err(());
+                    }
+                    _ => {}
+                }
             }
-        }
+        })
+        .await
+        .map_err(|_| {
+            self.stats.ack_timeouts += 1;
+            log::warn!("Timeout waiting for VALGET response")
+        })??;
+
+        value.ok_or_else(|| log::warn!("Key {:#010x} missing from VALGET response", key.id()))
     }
 
     /// Performs a cold start of the GPS to recover from an unresponsive state.
+    ///
+    /// See `health::HealthMonitor` for automatic recovery.
     pub async fn recovery(&mut self) -> Result<(), ()> {
-        // UBX-CFG-RST: Cold start
-        let payload = [0x00, 0x00, 0x01, 0x00];
-        self.send_ubx_message(0x06, 0x04, &payload).await?;
-        self.wait_for_ack().await
+        self.reset(ResetKind::Cold).await
     }
 
-    /// Waits for an ACK/NACK message from the GPS module.
-    async fn wait_for_ack(&mut self) -> Result<(), ()> {
-        let mut buffer = [0u8; 10]; // UBX ACK/NAK messages are 10 bytes long
-        let mut pos = 0;
-
-        loop {
-            match self.uart.read_async(&mut buffer[pos..pos + 1]).await {
-                Ok(1) => {
-                    if pos == 0 && buffer[0] != 0xB5 {
-                        continue;
-                    }
-                    if pos == 1 && buffer[1] != 0x62 {
-                        pos = 0;
-                        continue;
-                    }
-
-                    pos += 1;
-
-                    if pos == buffer.len() {
-                        // Full message received, check if it's an ACK/NAK
-                        if buffer[2] == 0x05 {
-                            // Class ID for ACK/NAK
-                            if buffer[3] == 0x01 {
-                                // Message ID for ACK
-                                info!("Received ACK from GPS");
-                                return Ok(());
-                            } else if buffer[3] == 0x00 {
-                                // Message ID for NAK
-                                info!("Received NACK from GPS");
-                                return Err(());
-                            }
+    /// Waits for the ACK/NACK of the message with the given class and ID.
+    ///
+    /// NMEA sentences, other UBX messages and acknowledgements of other messages
+    /// received in the meantime are skipped.
+    async fn wait_for_ack(&mut self, class: u8, id: u8) -> Result<(), ()> {
+        with_timeout(ACK_TIMEOUT, async {
+            loop {
+                let byte = self.read_byte().await?;
+                match self.mux.push(byte) {
+                    Some(Ok(Packet::Ubx(frame))) => match UbxMessage::parse(frame) {
+                        Ok(UbxMessage::AckAck {
+                            class: acked_class,
+                            id: acked_id,
+                        }) if (acked_class, acked_id) == (class, id) => {
+                            info!("Received ACK from GPS");
+                            return Ok(());
+                        }
+                        Ok(UbxMessage::AckNak {
+                            class: nacked_class,
+                            id: nacked_id,
+                        }) if (nacked_class, nacked_id) == (class, id) => {
+                            info!("Received NACK from GPS");
+                            self.stats.naks += 1;
+                            return Err(());
                         }
-                        // If not an ACK/NAK, reset and continue listening
-                        pos = 0;
+                        Ok(message) => {
+                            trace!("Skipping UBX message while waiting for ACK: {message:?}")
+                        }
+                        Err(e) => trace!("Error parsing UBX message: {e:?}"),
+                    },
+                    Some(Ok(Packet::Nmea(sentence))) => {
+                        trace!("Skipping NMEA sentence while waiting for ACK: {sentence}")
                     }
+                    Some(Err(e)) => trace!("Error receiving GPS message: {e:?}"),
+                    None => {}
                 }
-                Ok(_) => {} // Should not happen with a read of size 1
-                Err(_) => return Err(()),
             }
+        })
+        .await
+        .map_err(|_| {
+            self.stats.ack_timeouts += 1;
+            log::warn!("Timeout waiting for ACK of {class:#04x} {id:#04x}")
+        })?
+    }
+
+    /// Reads a single byte from the GPS module.
+    ///
+    /// The UART is read in chunks into a buffer that persists between calls, so no
+    /// received data is lost when a caller returns in the middle of a chunk.
+    async fn read_byte(&mut self) -> Result<u8, ()> {
+        if self.rx_pos == self.rx_len {
+            let len = self.uart.read(&mut self.rx_buffer).await.map_err(|e| {
+                self.stats.uart_errors += 1;
+                log::error!("UART Error: {e:?}")
+            })?;
+            if len == 0 {
+                log::warn!("GPS UART reached end of stream");
+                return Err(());
+            }
+            self.rx_pos = 0;
+            self.rx_len = len;
+            self.stats.bytes_received = self.stats.bytes_received.wrapping_add(len as u32);
+            self.stats.last_received = Some(Instant::now());
         }
+        let byte = self.rx_buffer[self.rx_pos];
+        self.rx_pos += 1;
+        Ok(byte)
     }
 
     /// Sends a UBX protocol message to the GPS module.
     async fn send_ubx_message(&mut self, class: u8, id: u8, payload: &[u8]) -> Result<(), ()> {
-        const HEADER_LEN: usize = 6;
-        const CHECKSUM_LEN: usize = 2;
-        // With a 256 byte buffer, the max payload is 256 - HEADER_LEN - CHECKSUM_LEN
-        const MAX_PAYLOAD_LEN: usize = 256 - HEADER_LEN - CHECKSUM_LEN;
-
-        // 1. Re-added the safety check to prevent panics
-        if payload.len() > MAX_PAYLOAD_LEN {
-            log::error!("UBX payload too large: {} bytes", payload.len());
-            return Err(());
-        }
-
         let mut message = [0u8; 256];
-        message[0] = 0xB5;
-        message[1] = 0x62;
-        message[2] = class;
-        message[3] = id;
-        message[4] = (payload.len() & 0xFF) as u8;
-        message[5] = (payload.len() >> 8) as u8;
+        let total_len = ubx::encode(class, id, payload, &mut message)
+            .map_err(|_| log::error!("UBX payload too large: {} bytes", payload.len()))?;
 
-        let payload_end = HEADER_LEN + payload.len();
-        message[HEADER_LEN..payload_end].copy_from_slice(payload);
-
-        // 2. Corrected the checksum slice to end after the payload
-        let checksum = self.ubx_checksum(&message[2..payload_end]);
-        message[payload_end] = checksum.0;
-        message[payload_end + 1] = checksum.1;
-
-        let total_len = payload_end + CHECKSUM_LEN;
-
-        // 3. Fixed the typo in the log message
         log::info!("Sending {:x?}", &message[..total_len]);
 
         self.uart
-            .write_async(&message[..total_len])
+            .write_all(&message[..total_len])
             .await
-            .map_err(|_| ())
-            .map(|_| {})
+            .map_err(|e| log::error!("UART Error: {e:?}"))
     }
 
-    /// Calculates the 8-bit Fletcher checksum used in UBX messages.
-    fn ubx_checksum(&self, data: &[u8]) -> (u8, u8) {
-        log::info!("Calculating checksum of {:x?}", &data);
-        let mut ck_a: u8 = 0;
-        let mut ck_b: u8 = 0;
-        for byte in data {
-            ck_a = ck_a.wrapping_add(*byte);
-            ck_b = ck_b.wrapping_add(ck_a);
-        }
-        (ck_a, ck_b)
-    }
-
-    /// Reads and parses a complete NMEA message from the GPS module.
+    /// Reads and parses NMEA messages from the GPS module until a fix is complete.
     ///
-    /// This function continuously reads from the UART, assembling messages line by line.
-    /// When a complete sentence is received that results in a valid fix, it returns
-    /// a `GpsData` struct.
+    /// Returns the first `GpsData` that satisfies the `FixPolicy`, skipping all other
+    /// updates. Use `next_update` to receive every update.
     pub async fn read_message(&mut self) -> Result<GpsData, ()> {
-        let mut buffer = [0u8; 1024];
-        let mut message_buffer = [0u8; 1024];
-        let mut message_len = 0;
         loop {
-            let result = self.uart.read_async(&mut buffer).await;
-            match result {
-                Ok(len) => {
-                    for &byte in &buffer[..len] {
-                        if byte == b'\n' {
-                            if message_len > 0 {
-                                match String::<1024>::from_utf8(
-                                    heapless::Vec::from_slice(&message_buffer[..message_len])
-                                        .unwrap(),
-                                ) {
-                                    Ok(sentence) => {
-                                        trace!("Received raw GPS message: {sentence}");
-                                        match self.nmea.parse(&sentence) {
-                                            Ok(_) => {
-                                                message_len = 0;
-                                                if self.nmea.fix_time.is_some()
-                                                    && self.nmea.fix_date.is_some()
-                                                    && self.nmea.fix_type.is_some()
-                                                    && self.nmea.latitude.is_some()
-                                                    && self.nmea.longitude.is_some()
-                                                {
-                                                    let fix_time =
-                                                        self.nmea.fix_time.map(|t| Time {
-                                                            hour: t.hour() as u8,
-                                                            minute: t.minute() as u8,
-                                                            second: t.second() as u8,
-                                                        });
-                                                    let fix_date =
-                                                        self.nmea.fix_date.map(|d| Date {
-                                                            year: d.year() as u16,
-                                                            month: d.month() as u8,
-                                                            day: d.day() as u8,
-                                                        });
-                                                    let fix_type =
-                                                        self.nmea.fix_type.map(|f| f.into());
-
-                                                    return Ok(GpsData {
-                                                        fix_time,
-                                                        fix_date,
-                                                        fix_type,
-                                                        latitude: self.nmea.latitude,
-                                                        longitude: self.nmea.longitude,
-                                                        speed_over_ground: self
-                                                            .nmea
-                                                            .speed_over_ground,
-                                                    });
-                                                }
-                                            }
-                                            Err(e) => {
-                                                trace!("Error parsing NMEA sentence: {e:?}");
-                                                message_len = 0;
-                                            }
-                                        }
-                                    }
-                                    Err(e) => {
-                                        trace!("Error converting to string: {e:?}");
-                                        message_len = 0;
-                                    }
-                                }
-                            }
-                        } else if message_len < message_buffer.len() {
-                            message_buffer[message_len] = byte;
-                            message_len += 1;
-                        }
-                    }
-                }
-                Err(_) => return Err(()),
+            if let GpsUpdate::Fix(data) = self.next_update().await? {
+                return Ok(data);
             }
         }
     }
+
+    /// Returns the satellites in view, as reported by the last complete set of GSV
+    /// sentences or the last UBX-NAV-SAT message.
+    pub fn satellite_view(&self) -> &SatelliteView {
+        &self.satellites
+    }
+
+    /// Collects the current NMEA state into a `GpsData`.
+    fn gps_data(&self) -> GpsData {
+        let fix_time = self.nmea.fix_time.map(|t| Time {
+            hour: t.hour() as u8,
+            minute: t.minute() as u8,
+            second: t.second() as u8,
+        });
+        let fix_date = self.nmea.fix_date.map(|d| Date {
+            year: d.year() as u16,
+            month: d.month() as u8,
+            day: d.day() as u8,
+        });
+        let fix_type = self.nmea.fix_type.map(|f| f.into());
+
+        GpsData {
+            fix_time,
+            fix_date,
+            fix_type,
+            latitude: self.nmea.latitude,
+            longitude: self.nmea.longitude,
+            speed_over_ground: self.nmea.speed_over_ground,
+            altitude: self.nmea.altitude,
+            course_over_ground: self.nmea.true_course,
+            hdop: self.nmea.hdop,
+            vdop: self.nmea.vdop,
+            pdop: self.nmea.pdop,
+            satellites_used: self.nmea.num_of_fix_satellites,
+        }
+    }
 }
-/// Converts a u16 value into a 2-byte array in little-endian order.
-fn to_little_endian_bytes(value: u16) -> [u8; 2] {
-    value.to_le_bytes()
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::cfg::keys;
+    use crate::mock::{MockPin, MockUart};
+    use crate::testdata::{ACK_VALGET, ACK_VALSET, GGA, NAK_VALSET, NAV_PVT};
+
+    fn rate_config() -> ValSet {
+        let mut config = ValSet::new(cfg::Layers::RAM);
+        config.set(keys::RATE_MEAS, 500).unwrap();
+        config
+    }
+
+    #[test]
+    fn set_config_waits_for_its_ack() {
+        // Output and the ACK of another message arrive before the matching ACK.
+        let rx = [GGA, ACK_VALGET, NAV_PVT, ACK_VALSET, GGA].concat();
+        let mut gps = Gps::with_uart(MockUart::new(&rx, 16), MockPin);
+        assert_eq!(block_on(gps.set_config(&rate_config())), Ok(()));
+
+        // The VALSET frame was sent.
+        let tx = &gps.uart.tx;
+        assert_eq!(
+            &tx[..4],
+            &[
+                ubx::SYNC_1,
+                ubx::SYNC_2,
+                ubx::class::CFG,
+                ubx::id::CFG_VALSET
+            ]
+        );
+        assert_eq!(
+            &tx[ubx::HEADER_LEN..ubx::HEADER_LEN + 4],
+            &rate_config().payload()[..4]
+        );
+
+        // The sentence after the ACK is still delivered.
+        assert!(matches!(
+            block_on(gps.next_update()),
+            Ok(GpsUpdate::NoFix(_))
+        ));
+        assert_eq!(gps.stats().naks, 0);
+    }
+
+    #[test]
+    fn set_config_rejected() {
+        let rx = [ACK_VALGET, NAK_VALSET].concat();
+        let mut gps = Gps::with_uart(MockUart::new(&rx, 16), MockPin);
+        assert_eq!(block_on(gps.set_config(&rate_config())), Err(()));
+        assert_eq!(gps.stats().naks, 1);
+    }
+
+    #[test]
+    fn set_config_ignores_other_acks() {
+        // Only the ACK of VALGET arrives before the stream ends.
+        let mut gps = Gps::with_uart(MockUart::new(ACK_VALGET, 16), MockPin);
+        assert_eq!(block_on(gps.set_config(&rate_config())), Err(()));
+        assert_eq!(gps.stats().naks, 0);
+    }
 }
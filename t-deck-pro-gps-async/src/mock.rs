@@ -0,0 +1,168 @@
+//! Test doubles for the receiver's UART and enable pin.
+
+extern crate std;
+
+use std::vec::Vec;
+
+use core::convert::Infallible;
+
+use crate::baud::SetBaudRate;
+use crate::cfg::{keys, ValGetResponse};
+use crate::testdata::{ACK_VALSET, MON_VER};
+use crate::ubx;
+
+/// A UART that replays recorded receiver output and records what is written.
+///
+/// Reads return at most `chunk` bytes, so packets are split across reads like on
+/// the real UART. Once the recording is exhausted, reads return 0 (end of stream).
+pub(crate) struct MockUart {
+    rx: Vec<u8>,
+    pos: usize,
+    chunk: usize,
+    pub(crate) tx: Vec<u8>,
+}
+
+impl MockUart {
+    pub(crate) fn new(rx: &[u8], chunk: usize) -> Self {
+        Self {
+            rx: rx.to_vec(),
+            pos: 0,
+            chunk,
+            tx: Vec::new(),
+        }
+    }
+}
+
+impl embedded_io_async::ErrorType for MockUart {
+    type Error = Infallible;
+}
+
+impl embedded_io_async::Read for MockUart {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = buf.len().min(self.chunk).min(self.rx.len() - self.pos);
+        buf[..len].copy_from_slice(&self.rx[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl embedded_io_async::Write for MockUart {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.tx.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+/// A receiver that only understands the host at its own baud rate.
+///
+/// At the right rate it answers MON-VER polls, applies CFG-UART1-BAUDRATE and sends
+/// `output` on every read. At any other rate nothing usable is received, so reads
+/// return 0 (end of stream) instead of waiting for a timeout.
+pub(crate) struct MockBaudUart {
+    /// The rate the receiver talks at.
+    pub(crate) device_rate: u32,
+    host_rate: u32,
+    /// Whether MON-VER polls are answered.
+    pub(crate) answers_polls: bool,
+    /// Whether a new rate written with VALSET is applied.
+    pub(crate) applies_rate: bool,
+    /// Periodic output, e.g. NMEA sentences.
+    pub(crate) output: Vec<u8>,
+    pending: Vec<u8>,
+    /// Every rate the host UART was set to.
+    pub(crate) host_rates: Vec<u32>,
+    pub(crate) tx: Vec<u8>,
+}
+
+impl MockBaudUart {
+    pub(crate) fn new(device_rate: u32, host_rate: u32) -> Self {
+        Self {
+            device_rate,
+            host_rate,
+            answers_polls: true,
+            applies_rate: true,
+            output: Vec::new(),
+            pending: Vec::new(),
+            host_rates: Vec::new(),
+            tx: Vec::new(),
+        }
+    }
+}
+
+impl embedded_io_async::ErrorType for MockBaudUart {
+    type Error = Infallible;
+}
+
+impl embedded_io_async::Read for MockBaudUart {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if self.host_rate != self.device_rate {
+            return Ok(0);
+        }
+        if self.pending.is_empty() {
+            // Let timeouts expire while the output repeats.
+            embassy_futures::yield_now().await;
+            self.pending.extend_from_slice(&self.output);
+        }
+        let len = buf.len().min(self.pending.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+        Ok(len)
+    }
+}
+
+impl embedded_io_async::Write for MockBaudUart {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.tx.extend_from_slice(buf);
+        if self.host_rate != self.device_rate || buf.len() < 8 {
+            return Ok(buf.len());
+        }
+        // The driver writes one UBX frame at a time.
+        let payload = &buf[6..buf.len() - 2];
+        match (buf[2], buf[3]) {
+            (ubx::class::MON, ubx::id::MON_VER) if self.answers_polls => {
+                self.pending.extend_from_slice(MON_VER);
+            }
+            (ubx::class::CFG, ubx::id::CFG_VALSET) => {
+                self.pending.extend_from_slice(ACK_VALSET);
+                // A VALSET payload has the same layout as a VALGET response.
+                let rate = ValGetResponse::parse(payload)
+                    .ok()
+                    .and_then(|values| values.get(keys::UART1_BAUDRATE));
+                if let Some(rate) = rate.filter(|_| self.applies_rate) {
+                    self.device_rate = rate;
+                }
+            }
+            _ => {}
+        }
+        Ok(buf.len())
+    }
+}
+
+impl SetBaudRate for MockBaudUart {
+    type Error = Infallible;
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), Self::Error> {
+        // Whatever was received at the old rate is lost.
+        self.pending.clear();
+        self.host_rate = baud_rate;
+        self.host_rates.push(baud_rate);
+        Ok(())
+    }
+}
+
+/// An enable pin that does nothing.
+pub(crate) struct MockPin;
+
+impl embedded_hal::digital::ErrorType for MockPin {
+    type Error = Infallible;
+}
+
+impl embedded_hal::digital::OutputPin for MockPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,315 @@
+//! Separates interleaved NMEA sentences and UBX frames received on the same UART.
+//!
+//! The receiver outputs NMEA and UBX on the same port, so a UBX response (e.g. an
+//! ACK) may arrive between two NMEA sentences. `ProtocolMux` looks at the first byte
+//! of every packet: `$` starts an NMEA sentence terminated by a line feed, `0xB5`
+//! starts a UBX frame which is handed to a `UbxDecoder`.
+
+use heapless::Vec;
+
+use crate::ubx::{UbxDecoder, UbxError, UbxFrame, SYNC_1, SYNC_2};
+
+/// The longest NMEA sentence accepted, including the `$` and the checksum.
+pub const MAX_NMEA_LEN: usize = 128;
+
+/// A packet separated from the receiver output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packet<'a> {
+    /// An NMEA sentence without the trailing line terminator.
+    Nmea(&'a str),
+    /// A checksum-verified UBX frame.
+    Ubx(UbxFrame<'a>),
+}
+
+/// Errors reported by the multiplexer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MuxError {
+    /// A UBX frame could not be decoded.
+    Ubx(UbxError),
+    /// An NMEA sentence was interrupted by the start of another packet.
+    TruncatedNmea,
+    /// An NMEA sentence exceeded `MAX_NMEA_LEN`.
+    NmeaTooLong,
+    /// An NMEA sentence contained non-ASCII data.
+    InvalidNmea,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MuxState {
+    Idle,
+    Nmea,
+    Ubx,
+}
+
+/// A streaming NMEA/UBX demultiplexer.
+pub struct ProtocolMux {
+    state: MuxState,
+    line: Vec<u8, MAX_NMEA_LEN>,
+    ubx: UbxDecoder,
+    errors: u32,
+}
+
+impl Default for ProtocolMux {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProtocolMux {
+    /// Creates a new multiplexer waiting for the start of a packet.
+    pub const fn new() -> Self {
+        Self {
+            state: MuxState::Idle,
+            line: Vec::new(),
+            ubx: UbxDecoder::new(),
+            errors: 0,
+        }
+    }
+
+    /// Discards any partially received packet.
+    pub fn reset(&mut self) {
+        self.state = MuxState::Idle;
+        self.line.clear();
+        self.ubx.reset();
+    }
+
+    /// The number of packets dropped because of an error since the multiplexer was
+    /// created. Not cleared by `reset`.
+    pub fn error_count(&self) -> u32 {
+        self.errors
+    }
+
+    /// Feeds one byte into the multiplexer.
+    ///
+    /// Returns a packet once it is complete, or an error if a packet was dropped.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Packet<'_>, MuxError>> {
+        match self.state {
+            MuxState::Ubx if self.ubx.awaiting_sync_2() && !matches!(byte, SYNC_1 | SYNC_2) => {
+                // A stray sync byte: this byte may start the next packet.
+                self.ubx.reset();
+                self.start(byte);
+                None
+            }
+            MuxState::Ubx if self.ubx.in_frame() => {
+                let result = self.ubx.push(byte)?;
+                self.state = MuxState::Idle;
+                if result.is_err() {
+                    self.errors = self.errors.wrapping_add(1);
+                }
+                Some(result.map(Packet::Ubx).map_err(MuxError::Ubx))
+            }
+            MuxState::Nmea => match byte {
+                b'\n' => {
+                    self.state = MuxState::Idle;
+                    if self.line.last() == Some(&b'\r') {
+                        self.line.pop();
+                    }
+                    if !self.line.is_ascii() {
+                        self.errors = self.errors.wrapping_add(1);
+                        return Some(Err(MuxError::InvalidNmea));
+                    }
+                    Some(
+                        core::str::from_utf8(&self.line)
+                            .map(Packet::Nmea)
+                            .map_err(|_| MuxError::InvalidNmea),
+                    )
+                }
+                b'$' | SYNC_1 => {
+                    self.start(byte);
+                    self.errors = self.errors.wrapping_add(1);
+                    Some(Err(MuxError::TruncatedNmea))
+                }
+                _ => {
+                    if self.line.push(byte).is_err() {
+                        self.state = MuxState::Idle;
+                        self.errors = self.errors.wrapping_add(1);
+                        return Some(Err(MuxError::NmeaTooLong));
+                    }
+                    None
+                }
+            },
+            MuxState::Idle | MuxState::Ubx => {
+                self.start(byte);
+                None
+            }
+        }
+    }
+
+    /// Starts a new packet if `byte` is a start character, otherwise goes idle.
+    fn start(&mut self, byte: u8) {
+        self.state = match byte {
+            b'$' => {
+                self.line.clear();
+                // Cannot fail: the line was just cleared.
+                let _ = self.line.push(byte);
+                MuxState::Nmea
+            }
+            SYNC_1 => {
+                self.ubx.reset();
+                // The first sync byte never completes a frame.
+                let _ = self.ubx.push(byte);
+                MuxState::Ubx
+            }
+            _ => MuxState::Idle,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::testdata::{ACK_VALSET, GGA, GSV_1, GSV_2, NAV_PVT, NAV_SAT, RMC};
+    use crate::ubx::{class, id};
+
+    #[derive(Debug, PartialEq)]
+    enum Out {
+        Nmea(String),
+        Ubx(u8, u8),
+        Err(MuxError),
+    }
+
+    fn feed(mux: &mut ProtocolMux, bytes: &[u8]) -> Vec<Out> {
+        bytes
+            .iter()
+            .filter_map(|&byte| {
+                Some(match mux.push(byte)? {
+                    Ok(Packet::Nmea(sentence)) => Out::Nmea(sentence.into()),
+                    Ok(Packet::Ubx(frame)) => Out::Ubx(frame.class, frame.id),
+                    Err(e) => Out::Err(e),
+                })
+            })
+            .collect()
+    }
+
+    fn nmea(sentence: &[u8]) -> Out {
+        Out::Nmea(
+            String::from_utf8(sentence.to_vec())
+                .unwrap()
+                .trim_end()
+                .into(),
+        )
+    }
+
+    #[test]
+    fn separates_interleaved_stream() {
+        let mut mux = ProtocolMux::new();
+        let stream = [GGA, NAV_PVT, RMC, ACK_VALSET, GSV_1, NAV_SAT, GSV_2].concat();
+        assert_eq!(
+            feed(&mut mux, &stream),
+            [
+                nmea(GGA),
+                Out::Ubx(class::NAV, id::NAV_PVT),
+                nmea(RMC),
+                Out::Ubx(class::ACK, id::ACK_ACK),
+                nmea(GSV_1),
+                Out::Ubx(class::NAV, id::NAV_SAT),
+                nmea(GSV_2),
+            ]
+        );
+        assert_eq!(mux.error_count(), 0);
+    }
+
+    #[test]
+    fn frame_split_across_pushes() {
+        let stream = [NAV_PVT, GGA].concat();
+        for split in 1..NAV_PVT.len() {
+            let mut mux = ProtocolMux::new();
+            assert_eq!(feed(&mut mux, &stream[..split]), []);
+            assert_eq!(
+                feed(&mut mux, &stream[split..]),
+                [Out::Ubx(class::NAV, id::NAV_PVT), nmea(GGA)],
+                "split at {split}"
+            );
+        }
+    }
+
+    #[test]
+    fn resyncs_after_garbage_and_false_sync() {
+        let mut mux = ProtocolMux::new();
+        // Line noise after a baud rate change, then a stray sync byte right before a
+        // sentence and another one before a frame.
+        let stream = [
+            &[0x00, 0xFF, 0x62, 0x13, SYNC_1][..],
+            GGA,
+            &[SYNC_1, 0x20],
+            ACK_VALSET,
+        ]
+        .concat();
+        assert_eq!(
+            feed(&mut mux, &stream),
+            [nmea(GGA), Out::Ubx(class::ACK, id::ACK_ACK)]
+        );
+        assert_eq!(mux.error_count(), 0);
+    }
+
+    #[test]
+    fn reports_bad_checksum_and_recovers() {
+        let mut mux = ProtocolMux::new();
+        let mut corrupted = ACK_VALSET.to_vec();
+        corrupted[7] ^= 0x01;
+        let stream = [&corrupted[..], GGA, NAV_PVT].concat();
+        assert_eq!(
+            feed(&mut mux, &stream),
+            [
+                Out::Err(MuxError::Ubx(UbxError::ChecksumMismatch {
+                    class: class::ACK,
+                    id: id::ACK_ACK,
+                })),
+                nmea(GGA),
+                Out::Ubx(class::NAV, id::NAV_PVT),
+            ]
+        );
+        assert_eq!(mux.error_count(), 1);
+    }
+
+    #[test]
+    fn rejects_oversize_length_and_recovers() {
+        let mut mux = ProtocolMux::new();
+        let stream = [
+            &[SYNC_1, 0x62, class::NAV, id::NAV_PVT, 0xFF, 0xFF][..],
+            RMC,
+        ]
+        .concat();
+        assert_eq!(
+            feed(&mut mux, &stream),
+            [
+                Out::Err(MuxError::Ubx(UbxError::PayloadTooLarge(0xFFFF))),
+                nmea(RMC),
+            ]
+        );
+        assert_eq!(mux.error_count(), 1);
+    }
+
+    #[test]
+    fn truncated_sentence() {
+        let mut mux = ProtocolMux::new();
+        let stream = [&GGA[..20], ACK_VALSET, &GGA[..20], RMC].concat();
+        assert_eq!(
+            feed(&mut mux, &stream),
+            [
+                Out::Err(MuxError::TruncatedNmea),
+                Out::Ubx(class::ACK, id::ACK_ACK),
+                Out::Err(MuxError::TruncatedNmea),
+                nmea(RMC),
+            ]
+        );
+    }
+
+    #[test]
+    fn sentence_too_long() {
+        let mut mux = ProtocolMux::new();
+        let mut long = std::vec![b'$'];
+        long.resize(MAX_NMEA_LEN + 10, b'A');
+        long.extend_from_slice(b"\r\n");
+        let stream = [&long[..], GGA].concat();
+        assert_eq!(
+            feed(&mut mux, &stream),
+            [Out::Err(MuxError::NmeaTooLong), nmea(GGA)]
+        );
+    }
+}
@@ -0,0 +1,408 @@
+//! Power-save modes of the u-blox M10 receiver.
+//!
+//! The receiver can run at full power (`Normal`, `Eco`, `StaticHold`), in one of the
+//! two power save modes of CFG-PM (cyclic tracking or on/off operation), or be put
+//! into backup with UBX-RXM-PMREQ. Mode settings are written to RAM and BBR, so they
+//! survive the backup state. Every mode comes with an estimated current draw, so the
+//! application can trade position updates against battery life.
+
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use embedded_hal::digital::OutputPin;
+use embedded_io_async::{Read, Write};
+use log::{info, warn};
+
+use crate::cfg::{keys, CfgError, Layers, ValSet};
+use crate::mux::Packet;
+use crate::ubx::{self, UbxMessage};
+use crate::{Gps, ACK_TIMEOUT};
+
+/// Typical current draw while tracking at full power, in mA.
+const TRACKING_CURRENT_MA: f32 = 6.0;
+/// Typical current draw during acquisition, in mA.
+const ACQUISITION_CURRENT_MA: f32 = 9.5;
+/// Typical current draw in power optimized tracking between fixes, in mA.
+const POT_CURRENT_MA: f32 = 1.6;
+/// Typical current draw in the backup state, in mA.
+const BACKUP_CURRENT_MA: f32 = 0.04;
+/// Typical time needed to regain a hot fix after waking from the off state, in s.
+const HOT_START_S: f32 = 2.0;
+
+/// The static hold speed threshold used by `PowerMode::OnMovement`, in cm/s.
+pub const DEFAULT_STATIC_HOLD_SPEED_CM_S: u8 = 30;
+
+/// How many times `wake` tries to reach the receiver.
+const WAKE_ATTEMPTS: usize = 3;
+/// How long the receiver needs to start up after a wake-up edge on RXD.
+const WAKE_DELAY: Duration = Duration::from_millis(250);
+
+/// RXM-PMREQ flag: enter the backup state.
+const PMREQ_FLAG_BACKUP: u32 = 0x02;
+/// RXM-PMREQ flag: force the backup state even if the receiver is busy.
+const PMREQ_FLAG_FORCE: u32 = 0x04;
+/// RXM-PMREQ wakeup source: falling edge on UART RX.
+const PMREQ_WAKE_UARTRX: u32 = 0x08;
+/// RXM-PMREQ wakeup source: EXTINT0 pin.
+const PMREQ_WAKE_EXTINT0: u32 = 0x20;
+
+/// CFG-PM-OPERATEMODE values.
+const OPERATEMODE_FULL: u8 = 0;
+const OPERATEMODE_PSMOO: u8 = 1;
+const OPERATEMODE_PSMCT: u8 = 2;
+
+/// Represents the power mode of the GPS module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerMode {
+    /// Normal 1Hz updates.
+    Normal,
+    /// Full power with a reduced measurement rate.
+    Eco { update_rate_ms: u16 },
+    /// Full power 1Hz updates, holding the position while the receiver is static.
+    StaticHold {
+        /// Below this speed the position is frozen, in cm/s.
+        speed_threshold_cm_s: u8,
+        /// The position is released once it moves this far from the held one, in m.
+        distance_threshold_m: u16,
+    },
+    /// Power save mode with cyclic tracking (PSMCT), for update periods up to ~10 s.
+    CyclicTracking { update_rate_ms: u16 },
+    /// Power save mode on/off (PSMOO): the receiver switches off between fixes.
+    ///
+    /// Meant for update periods longer than 10 seconds. SBAS should be disabled, as
+    /// it cannot be used in this mode.
+    OnOff {
+        /// Time between position updates, in s.
+        update_period_s: u32,
+        /// Time to keep tracking after a fix, in s.
+        on_time_s: u16,
+    },
+    /// On/off operation with static hold: wakes up periodically and only reports a
+    /// non-zero speed once the device has moved by more than the threshold.
+    ///
+    /// The application should ignore updates with zero speed.
+    OnMovement {
+        /// Time between position updates, in s.
+        update_period_s: u32,
+        /// The movement needed to release the held position, in m.
+        distance_threshold_m: u16,
+    },
+    /// Backup state for the given time, after which the receiver wakes up by itself
+    /// and `Gps::power_mode` reports the previous running mode again. A duration of
+    /// zero keeps it in backup until it is woken up.
+    ///
+    /// The receiver also wakes up on UART activity, see `Gps::wake`.
+    Backup { duration_ms: u32 },
+    /// Software standby mode.
+    SoftwareStandby,
+}
+
+impl PowerMode {
+    /// Returns `true` if the receiver is off in this mode and has to be woken up
+    /// before it accepts commands.
+    pub fn is_asleep(&self) -> bool {
+        matches!(self, PowerMode::Backup { .. } | PowerMode::SoftwareStandby)
+    }
+
+    /// Estimates the average current draw of the receiver in this mode, in mA.
+    ///
+    /// Based on typical datasheet values with the default constellations and open
+    /// sky; the actual draw depends on signal conditions and enabled signals.
+    pub fn estimated_current_ma(&self) -> f32 {
+        match *self {
+            PowerMode::Normal | PowerMode::Eco { .. } | PowerMode::StaticHold { .. } => {
+                TRACKING_CURRENT_MA
+            }
+            PowerMode::CyclicTracking { update_rate_ms } => {
+                // Roughly 200 ms of full tracking per update, power optimized tracking in between.
+                let period_s = (update_rate_ms.max(1000) as f32) / 1000.0;
+                let on_s = 0.2_f32.min(period_s);
+                (TRACKING_CURRENT_MA * on_s + POT_CURRENT_MA * (period_s - on_s)) / period_s
+            }
+            PowerMode::OnOff {
+                update_period_s,
+                on_time_s,
+            } => on_off_current(update_period_s, on_time_s as f32),
+            PowerMode::OnMovement {
+                update_period_s, ..
+            } => on_off_current(update_period_s, 0.0),
+            PowerMode::Backup { .. } | PowerMode::SoftwareStandby => BACKUP_CURRENT_MA,
+        }
+    }
+}
+
+/// Average current of on/off operation: a hot start, `on_time_s` of tracking and the
+/// rest of the period in backup.
+fn on_off_current(update_period_s: u32, on_time_s: f32) -> f32 {
+    let period_s = (update_period_s as f32).max(1.0);
+    let acquisition_s = HOT_START_S.min(period_s);
+    let tracking_s = on_time_s.min(period_s - acquisition_s);
+    let off_s = period_s - acquisition_s - tracking_s;
+    (ACQUISITION_CURRENT_MA * acquisition_s
+        + TRACKING_CURRENT_MA * tracking_s
+        + BACKUP_CURRENT_MA * off_s)
+        / period_s
+}
+
+/// Builds the VALSET message for a power mode.
+///
+/// Returns `None` for `Backup` and `SoftwareStandby`, which are requested with
+/// UBX-RXM-PMREQ instead of being configured.
+fn power_mode_config(mode: PowerMode) -> Result<Option<ValSet>, CfgError> {
+    let mut config = ValSet::new(Layers::RAM | Layers::BBR);
+    let (operate_mode, rate_ms, static_hold) = match mode {
+        PowerMode::Normal => (OPERATEMODE_FULL, 1000, (0, 0)),
+        PowerMode::Eco { update_rate_ms } => (OPERATEMODE_FULL, update_rate_ms, (0, 0)),
+        PowerMode::StaticHold {
+            speed_threshold_cm_s,
+            distance_threshold_m,
+        } => (
+            OPERATEMODE_FULL,
+            1000,
+            (speed_threshold_cm_s, distance_threshold_m),
+        ),
+        PowerMode::CyclicTracking { update_rate_ms } => (OPERATEMODE_PSMCT, update_rate_ms, (0, 0)),
+        PowerMode::OnOff {
+            update_period_s,
+            on_time_s,
+        } => {
+            config
+                .set(keys::PM_POSUPDATEPERIOD, update_period_s)?
+                .set(keys::PM_ONTIME, on_time_s)?;
+            (OPERATEMODE_PSMOO, 1000, (0, 0))
+        }
+        PowerMode::OnMovement {
+            update_period_s,
+            distance_threshold_m,
+        } => {
+            config
+                .set(keys::PM_POSUPDATEPERIOD, update_period_s)?
+                .set(keys::PM_ONTIME, 0)?;
+            (
+                OPERATEMODE_PSMOO,
+                1000,
+                (DEFAULT_STATIC_HOLD_SPEED_CM_S, distance_threshold_m),
+            )
+        }
+        PowerMode::Backup { .. } | PowerMode::SoftwareStandby => return Ok(None),
+    };
+    config
+        .set(keys::PM_OPERATEMODE, operate_mode)?
+        .set(keys::RATE_MEAS, rate_ms)?
+        .set(keys::MOT_GNSSSPEED_THRS, static_hold.0)?
+        .set(keys::MOT_GNSSDIST_THRS, static_hold.1)?
+        .set(keys::MSGOUT_UBX_NAV_PVT_UART1, 1)?;
+    Ok(Some(config))
+}
+
+impl<UartType, PinType> Gps<UartType, PinType>
+where
+    UartType: Read + Write,
+    PinType: OutputPin,
+{
+    /// Returns the power mode last applied with `set_power_mode`.
+    ///
+    /// After `wake`, or once a `PowerMode::Backup` with a non-zero duration has
+    /// elapsed, this is the running mode that was active before the receiver was put
+    /// to sleep.
+    pub fn power_mode(&self) -> PowerMode {
+        match self.wake_deadline {
+            Some(deadline) if Instant::now() >= deadline => self.awake_power_mode,
+            _ => self.power_mode,
+        }
+    }
+
+    /// Records that the receiver is running again in its last running mode.
+    pub(crate) fn set_awake(&mut self) {
+        self.power_mode = self.awake_power_mode;
+        self.wake_deadline = None;
+    }
+
+    /// Sets the power mode of the GPS module.
+    ///
+    /// If the receiver is in backup or standby, it is woken up first.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The desired `PowerMode`.
+    pub async fn set_power_mode(&mut self, mode: PowerMode) -> Result<(), ()> {
+        if self.power_mode().is_asleep() {
+            self.wake().await?;
+        }
+
+        let config =
+            power_mode_config(mode).map_err(|e| log::error!("Invalid configuration: {e:?}"))?;
+        match config {
+            Some(config) => {
+                let mut unlock = ValSet::new(Layers::RAM);
+                unlock
+                    .set(keys::SEC_CFG_LOCK, false)
+                    .map_err(|e| log::error!("Invalid configuration: {e:?}"))?;
+                if self.set_config(&unlock).await.is_err() {
+                    warn!("Failed to unlock configuration");
+                }
+                self.set_config(&config).await?;
+            }
+            None => {
+                let duration_ms = match mode {
+                    PowerMode::Backup { duration_ms } => duration_ms,
+                    _ => 0,
+                };
+                self.enter_backup(duration_ms).await?;
+            }
+        }
+        info!("GPS power mode set to {mode:?}");
+        if !mode.is_asleep() {
+            self.awake_power_mode = mode;
+        }
+        self.power_mode = mode;
+        // The receiver wakes up by itself once a timed backup has elapsed.
+        self.wake_deadline = match mode {
+            PowerMode::Backup { duration_ms } if duration_ms > 0 => {
+                Some(Instant::now() + Duration::from_millis(duration_ms.into()))
+            }
+            _ => None,
+        };
+        Ok(())
+    }
+
+    /// Puts the receiver into the backup state with UBX-RXM-PMREQ.
+    ///
+    /// A `duration_ms` of zero keeps it there until it is woken up by UART activity
+    /// or the EXTINT pin.
+    async fn enter_backup(&mut self, duration_ms: u32) -> Result<(), ()> {
+        let flags = PMREQ_FLAG_BACKUP | PMREQ_FLAG_FORCE;
+        let wakeup_sources = PMREQ_WAKE_UARTRX | PMREQ_WAKE_EXTINT0;
+
+        let mut payload = [0u8; 16];
+        payload[0] = 0x00; // Version
+        payload[4..8].copy_from_slice(&duration_ms.to_le_bytes());
+        payload[8..12].copy_from_slice(&flags.to_le_bytes());
+        payload[12..16].copy_from_slice(&wakeup_sources.to_le_bytes());
+        self.send_ubx_message(ubx::class::RXM, ubx::id::RXM_PMREQ, &payload)
+            .await?;
+        Timer::after(Duration::from_millis(20)).await;
+        Ok(())
+    }
+
+    /// Wakes the receiver from backup or standby and waits until it responds.
+    ///
+    /// A falling edge on the receiver's RX line starts it up; the bytes sent to
+    /// create it are discarded. Responsiveness is checked by polling UBX-MON-VER.
+    pub async fn wake(&mut self) -> Result<(), ()> {
+        for attempt in 1..=WAKE_ATTEMPTS {
+            self.uart
+                .write_all(&[0xFF; 8])
+                .await
+                .map_err(|e| log::error!("UART Error: {e:?}"))?;
+            self.uart
+                .flush()
+                .await
+                .map_err(|e| log::error!("UART Error: {e:?}"))?;
+            Timer::after(WAKE_DELAY).await;
+            self.mux.reset();
+
+            if self.poll_version().await.is_ok() {
+                info!("GPS awake after {attempt} attempt(s)");
+                self.set_awake();
+                return Ok(());
+            }
+        }
+        warn!("GPS did not wake up");
+        Err(())
+    }
+
+    /// Polls UBX-MON-VER and waits for the response.
+    pub(crate) async fn poll_version(&mut self) -> Result<(), ()> {
+        self.send_ubx_message(ubx::class::MON, ubx::id::MON_VER, &[])
+            .await?;
+        with_timeout(ACK_TIMEOUT, async {
+            loop {
+                let byte = self.read_byte().await?;
+                if let Some(Ok(Packet::Ubx(frame))) = self.mux.push(byte) {
+                    if let Ok(UbxMessage::MonVer(version)) = UbxMessage::parse(frame) {
+                        info!(
+                            "GPS software {}, hardware {}",
+                            version.software_version(),
+                            version.hardware_version()
+                        );
+                        return Ok(());
+                    }
+                }
+            }
+        })
+        .await
+        .map_err(|_| ())?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::cfg::ValGetResponse;
+    use crate::mock::{MockPin, MockUart};
+    use crate::testdata::ACK_VALSET;
+
+    #[test]
+    fn sleep_modes_have_no_config() {
+        assert!(power_mode_config(PowerMode::Backup { duration_ms: 5000 })
+            .unwrap()
+            .is_none());
+        assert!(power_mode_config(PowerMode::SoftwareStandby)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn running_modes_are_configured() {
+        let config = power_mode_config(PowerMode::OnOff {
+            update_period_s: 60,
+            on_time_s: 5,
+        })
+        .unwrap()
+        .unwrap();
+        // A VALSET payload has the same layout as a VALGET response.
+        let values = ValGetResponse::parse(config.payload()).unwrap();
+        assert_eq!(values.get(keys::PM_OPERATEMODE), Some(OPERATEMODE_PSMOO));
+        assert_eq!(values.get(keys::PM_POSUPDATEPERIOD), Some(60));
+        assert_eq!(values.get(keys::PM_ONTIME), Some(5));
+
+        let config = power_mode_config(PowerMode::Eco {
+            update_rate_ms: 2000,
+        })
+        .unwrap()
+        .unwrap();
+        let values = ValGetResponse::parse(config.payload()).unwrap();
+        assert_eq!(values.get(keys::PM_OPERATEMODE), Some(OPERATEMODE_FULL));
+        assert_eq!(values.get(keys::RATE_MEAS), Some(2000));
+    }
+
+    #[test]
+    fn timed_backup_returns_to_running_mode() {
+        let eco = PowerMode::Eco {
+            update_rate_ms: 2000,
+        };
+        let rx = [ACK_VALSET, ACK_VALSET, ACK_VALSET, ACK_VALSET].concat();
+        let mut gps = Gps::with_uart(MockUart::new(&rx, 256), MockPin);
+        block_on(gps.set_power_mode(eco)).unwrap();
+
+        block_on(gps.set_power_mode(PowerMode::Backup { duration_ms: 100 })).unwrap();
+        assert!(gps.power_mode().is_asleep());
+        block_on(Timer::after_millis(150));
+        assert_eq!(gps.power_mode(), eco);
+
+        // The receiver woke up by itself, so it is configured without waking it.
+        let sent = gps.uart.tx.len();
+        block_on(gps.set_power_mode(PowerMode::Normal)).unwrap();
+        assert!(!gps.uart.tx[sent..].starts_with(&[0xFF]));
+        assert_eq!(gps.power_mode(), PowerMode::Normal);
+    }
+
+    #[test]
+    fn untimed_backup_stays_asleep() {
+        let mut gps = Gps::with_uart(MockUart::new(&[], 256), MockPin);
+        block_on(gps.set_power_mode(PowerMode::Backup { duration_ms: 0 })).unwrap();
+        block_on(Timer::after_millis(50));
+        assert_eq!(gps.power_mode(), PowerMode::Backup { duration_ms: 0 });
+    }
+}
@@ -0,0 +1,356 @@
+//! Satellites in view, as reported by NMEA GSV/GSA or UBX-NAV-SAT.
+//!
+//! A `SatelliteView` lists every satellite the receiver currently tracks with its
+//! position in the sky and signal strength, which is enough to draw a sky plot and
+//! signal bars.
+//!
+//! GSA sentences list the PRNs used in the solution without their constellation,
+//! and a multi-GNSS receiver sends one `GNGSA` per constellation. `UsedSatellites`
+//! collects them per constellation, so a GPS PRN 5 does not mark Galileo PRN 5 as
+//! used and the sentence of one constellation does not replace the others.
+
+use heapless::Vec;
+use nmea::sentences::GnssType;
+
+use crate::ubx::{GnssId, NavSat};
+
+/// The maximum number of satellites kept in a `SatelliteView`.
+pub const MAX_SATELLITES: usize = 64;
+
+/// A GNSS constellation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Constellation {
+    /// GPS (USA).
+    Gps,
+    /// GLONASS (Russia).
+    Glonass,
+    /// Galileo (EU).
+    Galileo,
+    /// BeiDou (China).
+    BeiDou,
+    /// QZSS (Japan).
+    Qzss,
+    /// NavIC (India).
+    NavIc,
+    /// Satellite-based augmentation systems.
+    Sbas,
+    /// An unknown constellation.
+    Unknown,
+}
+
+impl From<GnssType> for Constellation {
+    fn from(gnss_type: GnssType) -> Self {
+        match gnss_type {
+            GnssType::Gps => Constellation::Gps,
+            GnssType::Glonass => Constellation::Glonass,
+            GnssType::Galileo => Constellation::Galileo,
+            GnssType::Beidou => Constellation::BeiDou,
+            GnssType::Qzss => Constellation::Qzss,
+            GnssType::NavIC => Constellation::NavIc,
+        }
+    }
+}
+
+impl From<GnssId> for Constellation {
+    fn from(gnss_id: GnssId) -> Self {
+        match gnss_id {
+            GnssId::Gps => Constellation::Gps,
+            GnssId::Sbas => Constellation::Sbas,
+            GnssId::Galileo => Constellation::Galileo,
+            GnssId::BeiDou => Constellation::BeiDou,
+            GnssId::Qzss => Constellation::Qzss,
+            GnssId::Glonass => Constellation::Glonass,
+            GnssId::NavIc => Constellation::NavIc,
+            GnssId::Unknown(_) => Constellation::Unknown,
+        }
+    }
+}
+
+/// A single satellite in view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SatelliteInfo {
+    /// The constellation the satellite belongs to.
+    pub constellation: Constellation,
+    /// The satellite PRN / ID within its constellation.
+    pub prn: u32,
+    /// Elevation above the horizon in degrees (0-90), if known.
+    pub elevation: Option<f32>,
+    /// Azimuth from true north in degrees (0-359), if known.
+    pub azimuth: Option<f32>,
+    /// Signal to noise ratio in dBHz, if the satellite is tracked.
+    pub snr: Option<f32>,
+    /// The satellite is used in the navigation solution.
+    pub used: bool,
+}
+
+/// The satellites currently in view.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SatelliteView {
+    /// The satellites, in the order reported by the receiver.
+    pub satellites: Vec<SatelliteInfo, MAX_SATELLITES>,
+}
+
+impl SatelliteView {
+    /// Builds the view from the GSV sentences collected by the NMEA parser and the
+    /// satellites used according to the GSA sentences.
+    pub fn from_nmea(nmea: &nmea::Nmea, used: &UsedSatellites) -> Self {
+        let satellites = nmea
+            .satellites()
+            .iter()
+            .map(|satellite| {
+                let constellation = satellite.gnss_type().into();
+                SatelliteInfo {
+                    constellation,
+                    prn: satellite.prn(),
+                    elevation: satellite.elevation(),
+                    azimuth: satellite.azimuth(),
+                    snr: satellite.snr(),
+                    used: used.contains(constellation, satellite.prn()),
+                }
+            })
+            .take(MAX_SATELLITES)
+            .collect();
+        Self { satellites }
+    }
+
+    /// The number of satellites in view.
+    pub fn in_view(&self) -> usize {
+        self.satellites.len()
+    }
+
+    /// The number of satellites used in the navigation solution.
+    pub fn used(&self) -> usize {
+        self.satellites.iter().filter(|s| s.used).count()
+    }
+
+    /// The satellites of one constellation.
+    pub fn constellation(
+        &self,
+        constellation: Constellation,
+    ) -> impl Iterator<Item = &SatelliteInfo> + '_ {
+        self.satellites
+            .iter()
+            .filter(move |s| s.constellation == constellation)
+    }
+}
+
+/// The satellites used in the navigation solution, collected from the GSA sentences
+/// of all constellations.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct UsedSatellites {
+    satellites: Vec<(Constellation, u32), MAX_SATELLITES>,
+}
+
+impl UsedSatellites {
+    /// Replaces the used satellites of the constellation of the GSA `sentence` with
+    /// `prns`, the PRNs parsed from it.
+    ///
+    /// The constellation is taken from the talker ID, or for `GN` sentences from the
+    /// NMEA 4.10 system ID field. Without one it is guessed from the PRN ranges of
+    /// NMEA 4.0, and an empty sentence is ignored.
+    pub fn update(&mut self, sentence: &str, prns: &[u32]) {
+        let Some(constellation) = gsa_constellation(sentence, prns) else {
+            log::trace!("Unknown constellation in GSA sentence: {sentence}");
+            return;
+        };
+        self.satellites.retain(|(c, _)| *c != constellation);
+        for &prn in prns {
+            if self.satellites.push((constellation, prn)).is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Returns `true` if the satellite `prn` of `constellation` is used.
+    pub fn contains(&self, constellation: Constellation, prn: u32) -> bool {
+        self.satellites.contains(&(constellation, prn))
+    }
+
+    /// The number of satellites used over all constellations.
+    pub fn len(&self) -> usize {
+        self.satellites.len()
+    }
+
+    /// Returns `true` if no satellite is used.
+    pub fn is_empty(&self) -> bool {
+        self.satellites.is_empty()
+    }
+}
+
+/// Returns the constellation a GSA sentence reports on.
+fn gsa_constellation(sentence: &str, prns: &[u32]) -> Option<Constellation> {
+    let data = sentence.split('*').next()?;
+    let mut fields = data.split(',');
+    match fields.next()?.get(1..3)? {
+        "GP" => return Some(Constellation::Gps),
+        "GL" => return Some(Constellation::Glonass),
+        "GA" => return Some(Constellation::Galileo),
+        "GB" | "BD" => return Some(Constellation::BeiDou),
+        "GQ" => return Some(Constellation::Qzss),
+        "GI" => return Some(Constellation::NavIc),
+        _ => {}
+    }
+    // The system ID follows the mode, fix type, 12 PRNs and 3 DOP fields.
+    match fields.nth(17) {
+        Some("1") => Some(Constellation::Gps),
+        Some("2") => Some(Constellation::Glonass),
+        Some("3") => Some(Constellation::Galileo),
+        Some("4") => Some(Constellation::BeiDou),
+        Some("5") => Some(Constellation::Qzss),
+        Some("6") => Some(Constellation::NavIc),
+        _ => match prns.first()? {
+            // SBAS and QZSS are reported by GPGSV in NMEA 4.0.
+            1..=64 | 193..=202 => Some(Constellation::Gps),
+            65..=96 => Some(Constellation::Glonass),
+            301..=336 => Some(Constellation::Galileo),
+            401..=437 => Some(Constellation::BeiDou),
+            _ => None,
+        },
+    }
+}
+
+impl From<NavSat<'_>> for SatelliteView {
+    fn from(nav_sat: NavSat<'_>) -> Self {
+        let satellites = nav_sat
+            .satellites()
+            .map(|satellite| SatelliteInfo {
+                constellation: satellite.gnss_id.into(),
+                prn: satellite.sv_id as u32,
+                elevation: satellite.elevation.map(|e| e as f32),
+                azimuth: satellite.azimuth.map(|a| a as f32),
+                snr: (satellite.cno > 0).then_some(satellite.cno as f32),
+                used: satellite.used,
+            })
+            .take(MAX_SATELLITES)
+            .collect();
+        Self { satellites }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::{MockPin, MockUart};
+    use crate::testdata::*;
+    use crate::{Gps, GpsUpdate};
+
+    fn info(view: &SatelliteView, constellation: Constellation, prn: u32) -> SatelliteInfo {
+        *view
+            .constellation(constellation)
+            .find(|s| s.prn == prn)
+            .unwrap_or_else(|| panic!("{constellation:?} {prn} not in view"))
+    }
+
+    #[test]
+    fn multi_gnss_epoch() {
+        let log = [
+            GGA,
+            GSA_GPS,
+            GSA_GLONASS,
+            GSA_GALILEO,
+            GSA_BEIDOU,
+            GSV_1,
+            GSV_2,
+            GSV_GLONASS,
+            GSV_GALILEO,
+            GSV_BEIDOU,
+        ]
+        .concat();
+        let mut gps = Gps::with_uart(MockUart::new(&log, 64), MockPin);
+        let mut groups = 0;
+        block_on(async {
+            while let Ok(update) = gps.next_update().await {
+                groups += (update == GpsUpdate::Satellites) as usize;
+            }
+        });
+        assert_eq!(groups, 4);
+
+        let view = gps.satellite_view();
+        assert_eq!(view.in_view(), 12);
+        assert_eq!(view.used(), 7);
+        assert_eq!(view.constellation(Constellation::Gps).count(), 5);
+        assert_eq!(view.constellation(Constellation::Galileo).count(), 3);
+
+        // The same PRN in three constellations.
+        assert!(info(view, Constellation::Gps, 5).used);
+        assert!(!info(view, Constellation::Galileo, 5).used);
+        assert!(!info(view, Constellation::BeiDou, 5).used);
+
+        // Used satellites of every constellation survive the later GSA sentences.
+        assert!(info(view, Constellation::Gps, 23).used);
+        assert!(info(view, Constellation::Glonass, 65).used);
+        assert!(info(view, Constellation::Galileo, 13).used);
+        assert!(info(view, Constellation::BeiDou, 19).used);
+        assert!(!info(view, Constellation::Gps, 18).used);
+        assert!(!info(view, Constellation::Glonass, 72).used);
+
+        let gps_13 = info(view, Constellation::Gps, 13);
+        assert_eq!(gps_13.elevation, Some(24.0));
+        assert_eq!(gps_13.azimuth, Some(287.0));
+        assert_eq!(gps_13.snr, Some(31.0));
+        assert_eq!(info(view, Constellation::Galileo, 26).snr, None);
+    }
+
+    #[test]
+    fn gsa_replaces_only_its_constellation() {
+        let mut used = UsedSatellites::default();
+        used.update("$GNGSA,A,3,05,13,,,,,,,,,,,1.6,0.9,1.3,1*00", &[5, 13]);
+        used.update("$GNGSA,A,3,05,,,,,,,,,,,,1.6,0.9,1.3,3*00", &[5]);
+        assert_eq!(used.len(), 3);
+        assert!(used.contains(Constellation::Galileo, 5));
+        assert!(!used.contains(Constellation::Galileo, 13));
+
+        // The next epoch loses GPS 13.
+        used.update("$GNGSA,A,3,05,,,,,,,,,,,,1.6,0.9,1.3,1*00", &[5]);
+        assert_eq!(used.len(), 2);
+        assert!(used.contains(Constellation::Gps, 5));
+        assert!(!used.contains(Constellation::Gps, 13));
+        assert!(used.contains(Constellation::Galileo, 5));
+
+        used.update("$GNGSA,A,1,,,,,,,,,,,,,99.99,99.99,99.99,3*00", &[]);
+        assert_eq!(used.len(), 1);
+    }
+
+    #[test]
+    fn gsa_constellations() {
+        let cases: [(&str, &[u32], Option<Constellation>); 7] = [
+            (
+                "$GPGSA,A,3,05,13,,,,,,,,,,,2.1,1.2,1.7*00",
+                &[5, 13],
+                Some(Constellation::Gps),
+            ),
+            (
+                "$GLGSA,A,3,65,,,,,,,,,,,,2.1,1.2,1.7*00",
+                &[65],
+                Some(Constellation::Glonass),
+            ),
+            (
+                "$GBGSA,A,3,05,,,,,,,,,,,,2.1,1.2,1.7*00",
+                &[5],
+                Some(Constellation::BeiDou),
+            ),
+            (
+                "$GNGSA,A,3,05,,,,,,,,,,,,2.1,1.2,1.7,6*00",
+                &[5],
+                Some(Constellation::NavIc),
+            ),
+            // NMEA 4.0: no system ID, so the PRN ranges decide.
+            (
+                "$GNGSA,A,3,05,13,,,,,,,,,,,2.1,1.2,1.7*1D",
+                &[5, 13],
+                Some(Constellation::Gps),
+            ),
+            (
+                "$GNGSA,A,3,301,305,,,,,,,,,,,2.1,1.2,1.7*1E",
+                &[301, 305],
+                Some(Constellation::Galileo),
+            ),
+            ("$GNGSA,A,1,,,,,,,,,,,,,99.99,99.99,99.99*2E", &[], None),
+        ];
+        for (sentence, prns, expected) in cases {
+            assert_eq!(gsa_constellation(sentence, prns), expected, "{sentence}");
+        }
+    }
+}
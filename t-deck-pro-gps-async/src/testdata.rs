@@ -0,0 +1,71 @@
+//! Receiver output shared by the tests, as sent by the T-Deck's NEO-M10.
+//!
+//! The messages describe a 3D fix near Berlin at 2024-05-14 10:23:45 UTC; the
+//! `NO_FIX` variants are from the second before.
+
+pub(crate) const NAV_PVT: &[u8] = &[
+    0xB5, 0x62, 0x01, 0x07, 0x5C, 0x00, 0xB8, 0xB1, 0x61, 0x07, 0xE8, 0x07, 0x05, 0x0E, 0x0A, 0x17,
+    0x2D, 0x37, 0x19, 0x00, 0x00, 0x00, 0xC7, 0xCF, 0xFF, 0xFF, 0x03, 0x01, 0xEA, 0x0B, 0x04, 0x6F,
+    0xFD, 0x07, 0xC2, 0xEA, 0x4D, 0x1F, 0x83, 0x4C, 0x01, 0x00, 0x38, 0x96, 0x00, 0x00, 0x34, 0x08,
+    0x00, 0x00, 0x48, 0x0D, 0x00, 0x00, 0x78, 0x00, 0x00, 0x00, 0xAC, 0xFE, 0xFF, 0xFF, 0x0F, 0x00,
+    0x00, 0x00, 0x69, 0x01, 0x00, 0x00, 0xC0, 0x92, 0x83, 0x01, 0x9A, 0x01, 0x00, 0x00, 0x87, 0xD6,
+    0x12, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x19, 0xDC,
+];
+
+pub(crate) const NAV_PVT_NO_FIX: &[u8] = &[
+    0xB5, 0x62, 0x01, 0x07, 0x5C, 0x00, 0xD0, 0xAD, 0x61, 0x07, 0xE8, 0x07, 0x05, 0x0E, 0x0A, 0x17,
+    0x2C, 0x37, 0x19, 0x00, 0x00, 0x00, 0xC7, 0xCF, 0xFF, 0xFF, 0x00, 0x00, 0xEA, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x83, 0x4C, 0x01, 0x00, 0x38, 0x96, 0x00, 0x00, 0x34, 0x08,
+    0x00, 0x00, 0x48, 0x0D, 0x00, 0x00, 0x78, 0x00, 0x00, 0x00, 0xAC, 0xFE, 0xFF, 0xFF, 0x0F, 0x00,
+    0x00, 0x00, 0x69, 0x01, 0x00, 0x00, 0xC0, 0x92, 0x83, 0x01, 0x9A, 0x01, 0x00, 0x00, 0x87, 0xD6,
+    0x12, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x8E, 0x6B,
+];
+
+pub(crate) const NAV_SAT: &[u8] = &[
+    0xB5, 0x62, 0x01, 0x35, 0x2C, 0x00, 0xB8, 0xB1, 0x61, 0x07, 0x01, 0x03, 0x00, 0x00, 0x00, 0x05,
+    0x26, 0x34, 0x84, 0x00, 0xF4, 0xFF, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x0D, 0x1F, 0x18, 0x1F, 0x01,
+    0x19, 0x00, 0x1C, 0x00, 0x00, 0x00, 0x02, 0x0B, 0x00, 0xA5, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
+    0x00, 0x00, 0x78, 0xD2,
+];
+
+pub(crate) const MON_VER: &[u8] = &[
+    0xB5, 0x62, 0x0A, 0x04, 0xA0, 0x00, 0x52, 0x4F, 0x4D, 0x20, 0x53, 0x50, 0x47, 0x20, 0x35, 0x2E,
+    0x31, 0x30, 0x20, 0x28, 0x37, 0x62, 0x32, 0x30, 0x32, 0x65, 0x29, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x30, 0x30, 0x30, 0x41, 0x30, 0x30, 0x30, 0x30, 0x00, 0x00, 0x52, 0x4F,
+    0x4D, 0x20, 0x42, 0x41, 0x53, 0x45, 0x20, 0x30, 0x78, 0x31, 0x31, 0x38, 0x42, 0x32, 0x30, 0x36,
+    0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46, 0x57, 0x56, 0x45,
+    0x52, 0x3D, 0x53, 0x50, 0x47, 0x20, 0x35, 0x2E, 0x31, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x50, 0x52, 0x4F, 0x54, 0x56, 0x45,
+    0x52, 0x3D, 0x33, 0x34, 0x2E, 0x31, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x4D, 0x4F, 0x44, 0x3D, 0x4E, 0x45, 0x4F, 0x2D,
+    0x4D, 0x31, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x87, 0x64,
+];
+
+pub(crate) const ACK_VALSET: &[u8] = &[0xB5, 0x62, 0x05, 0x01, 0x02, 0x00, 0x06, 0x8A, 0x98, 0xC1];
+
+pub(crate) const NAK_VALSET: &[u8] = &[0xB5, 0x62, 0x05, 0x00, 0x02, 0x00, 0x06, 0x8A, 0x97, 0xBC];
+
+pub(crate) const ACK_VALGET: &[u8] = &[0xB5, 0x62, 0x05, 0x01, 0x02, 0x00, 0x06, 0x8B, 0x99, 0xC2];
+
+pub(crate) const GGA_NO_FIX: &[u8] = b"$GNGGA,102344.00,,,,,0,00,99.99,,,,,,*78\r\n";
+pub(crate) const GGA: &[u8] =
+    b"$GNGGA,102345.00,5231.20040,N,01324.29724,E,1,11,0.95,38.5,M,46.6,M,,*78\r\n";
+pub(crate) const RMC: &[u8] =
+    b"$GNRMC,102345.00,A,5231.20040,N,01324.29724,E,0.241,,140524,,,A,V*19\r\n";
+pub(crate) const GSV_1: &[u8] =
+    b"$GPGSV,2,1,05,05,52,132,38,13,24,287,31,15,65,061,40,18,10,320,22,1*6D\r\n";
+pub(crate) const GSV_2: &[u8] = b"$GPGSV,2,2,05,23,41,210,35,1*50\r\n";
+
+// One multi-GNSS epoch with NMEA 4.11 system IDs: GPS PRN 5 is used, while Galileo
+// PRN 5 and BeiDou PRN 5 are only in view.
+pub(crate) const GSA_GPS: &[u8] = b"$GNGSA,A,3,05,13,15,23,,,,,,,,,1.62,0.95,1.31,1*09\r\n";
+pub(crate) const GSA_GLONASS: &[u8] = b"$GNGSA,A,3,65,,,,,,,,,,,,1.62,0.95,1.31,2*0B\r\n";
+pub(crate) const GSA_GALILEO: &[u8] = b"$GNGSA,A,3,13,,,,,,,,,,,,1.62,0.95,1.31,3*0B\r\n";
+pub(crate) const GSA_BEIDOU: &[u8] = b"$GNGSA,A,3,19,,,,,,,,,,,,1.62,0.95,1.31,4*06\r\n";
+pub(crate) const GSV_GLONASS: &[u8] = b"$GLGSV,1,1,02,65,30,040,33,72,15,280,,1*75\r\n";
+pub(crate) const GSV_GALILEO: &[u8] =
+    b"$GAGSV,1,1,03,05,48,075,36,13,30,190,29,26,12,250,,7*4C\r\n";
+pub(crate) const GSV_BEIDOU: &[u8] = b"$GBGSV,1,1,02,05,20,120,30,19,55,300,41,1*7D\r\n";
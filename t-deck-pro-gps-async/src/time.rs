@@ -0,0 +1,425 @@
+//! GPS-disciplined time from the receiver's PPS output.
+//!
+//! The receiver pulses its time pulse pin (GPS_PPS, IO01 on the T-Deck) at the start
+//! of every UTC second once it has a fix. `capture_pulses` runs in its own task,
+//! timestamps each rising edge with `embassy_time::Instant` and latches it in a
+//! `PpsSignal`. `GpsTime` takes the latched pulse, labels it with the UTC second
+//! reported by the next RMC/GGA sentence or UBX-NAV-TIMEUTC/NAV-PVT message, and
+//! keeps that pair as an anchor. Consecutive pulses advance the anchor and measure the drift of the local
+//! clock, so UTC can be derived for any `Instant` and vice versa.
+//!
+//! GSA, VTG and GLL updates carry the time of the last RMC/GGA sentence, so an NMEA
+//! time only labels a pulse if it is later than the previous one. ZDA is not used:
+//! it is disabled in the receiver's default configuration and RMC already reports
+//! the time together with the date.
+
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant};
+use embedded_hal::digital::OutputPin;
+use embedded_hal_async::digital::Wait;
+use embedded_io_async::{Read, Write};
+use log::{debug, warn};
+
+use crate::update::GpsUpdate;
+use crate::{Date, Gps, Time};
+
+/// A time message must arrive within this time after a pulse to label it.
+const PAIRING_WINDOW: Duration = Duration::from_millis(900);
+/// How far a pulse interval may deviate from whole seconds to be trusted.
+const PULSE_TOLERANCE: Duration = Duration::from_millis(20);
+/// Drift measurements beyond this are treated as glitches, in ppm.
+const MAX_DRIFT_PPM: f32 = 500.0;
+/// Weight of a new drift measurement in the running average.
+const DRIFT_SMOOTHING: f32 = 0.1;
+
+/// The timestamp of the latest PPS pulse, set by `capture_pulses`.
+pub type PpsSignal<M> = Signal<M, Instant>;
+
+/// Timestamps rising edges of the PPS input pin and latches them in `signal`.
+///
+/// Run this in its own task so no edge is missed while updates are read or
+/// handled. Only returns if the pin reports an error.
+pub async fn capture_pulses<M: RawMutex, PpsType: Wait>(
+    pps: &mut PpsType,
+    signal: &PpsSignal<M>,
+) -> Result<(), ()> {
+    loop {
+        pps.wait_for_rising_edge()
+            .await
+            .map_err(|e| warn!("PPS Error: {e:?}"))?;
+        signal.signal(Instant::now());
+    }
+}
+
+/// A pulse labelled with the UTC second it marks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Anchor {
+    utc: NaiveDateTime,
+    instant: Instant,
+}
+
+/// Precise UTC time disciplined by the receiver's PPS output.
+pub struct GpsTime<'a, M: RawMutex> {
+    pps: &'a PpsSignal<M>,
+    anchor: Option<Anchor>,
+    last_pulse: Option<Instant>,
+    last_nmea_time: Option<NaiveDateTime>,
+    drift_ppm: Option<f32>,
+}
+
+impl<'a, M: RawMutex> GpsTime<'a, M> {
+    /// Creates a new time service on the pulses latched by `capture_pulses`.
+    pub fn new(pps: &'a PpsSignal<M>) -> Self {
+        Self {
+            pps,
+            anchor: None,
+            last_pulse: None,
+            last_nmea_time: None,
+            drift_ppm: None,
+        }
+    }
+
+    /// Waits for the next PPS pulse and returns its timestamp.
+    pub async fn wait_pulse(&mut self) -> Instant {
+        let instant = self.pps.wait().await;
+        self.on_pulse(instant);
+        instant
+    }
+
+    /// Waits for the next update from `gps`, records the pulse latched in the
+    /// meantime and feeds the update into the service before returning it.
+    pub async fn next_update<UartType, PinType>(
+        &mut self,
+        gps: &mut Gps<UartType, PinType>,
+    ) -> Result<GpsUpdate, ()>
+    where
+        UartType: Read + Write,
+        PinType: OutputPin,
+    {
+        let update = gps.next_update().await?;
+        if let Some(instant) = self.pps.try_take() {
+            self.on_pulse(instant);
+        }
+        self.on_update(&update);
+        Ok(update)
+    }
+
+    /// Records a pulse seen at `instant`.
+    ///
+    /// Called by `wait_pulse` and `next_update`; use it directly if pulses are
+    /// captured elsewhere, e.g. in an interrupt handler.
+    pub fn on_pulse(&mut self, instant: Instant) {
+        if let Some(previous) = self.last_pulse {
+            let interval = instant.saturating_duration_since(previous);
+            let seconds = (interval + Duration::from_millis(500)).as_secs();
+            let expected = Duration::from_secs(seconds);
+            let error = if interval > expected {
+                interval - expected
+            } else {
+                expected - interval
+            };
+            if seconds > 0 && error <= PULSE_TOLERANCE {
+                self.update_drift(interval, expected);
+                if let Some(anchor) = self.anchor.as_mut() {
+                    if anchor.instant == previous {
+                        anchor.utc += TimeDelta::seconds(seconds as i64);
+                        anchor.instant = instant;
+                    }
+                }
+            } else {
+                debug!("Irregular PPS interval: {} us", interval.as_micros());
+            }
+        }
+        self.last_pulse = Some(instant);
+    }
+
+    /// Labels the last pulse with the time reported by a receiver update.
+    ///
+    /// Uses `GpsUpdate::NavTimeUtc` and `GpsUpdate::NavPvt` with valid UTC, and
+    /// `GpsUpdate::Fix`/`GpsUpdate::NoFix` with both time and date. The latter are
+    /// only used if their time advanced, as sentences without a time field repeat
+    /// the time of an earlier sentence.
+    pub fn on_update(&mut self, update: &GpsUpdate) {
+        let utc = match update {
+            GpsUpdate::NavTimeUtc(time) if time.valid_utc => utc_from_parts(
+                time.year,
+                time.month,
+                time.day,
+                time.hour,
+                time.minute,
+                time.second,
+                time.nano,
+            ),
+            GpsUpdate::NavPvt(pvt) if pvt.valid_date && pvt.valid_time && pvt.fully_resolved => {
+                utc_from_parts(
+                    pvt.year, pvt.month, pvt.day, pvt.hour, pvt.minute, pvt.second, pvt.nano,
+                )
+            }
+            GpsUpdate::Fix(data) | GpsUpdate::NoFix(data) => {
+                let utc = match (data.fix_date, data.fix_time) {
+                    (Some(date), Some(time)) => utc_from_gps_data(date, time),
+                    _ => None,
+                };
+                if utc.is_none() || utc <= self.last_nmea_time {
+                    return;
+                }
+                self.last_nmea_time = utc;
+                utc
+            }
+            _ => None,
+        };
+        if let Some(utc) = utc {
+            self.on_time(utc);
+        }
+    }
+
+    /// Labels the last pulse with a UTC epoch time reported by the receiver.
+    ///
+    /// The time is rounded to the nearest second, which the pulse marks. It is
+    /// ignored if no pulse was seen within the pairing window.
+    pub fn on_time(&mut self, utc: NaiveDateTime) {
+        let Some(pulse) = self.last_pulse else {
+            return;
+        };
+        if Instant::now().saturating_duration_since(pulse) > PAIRING_WINDOW {
+            return;
+        }
+        let second = round_to_second(utc);
+        match self.anchor {
+            Some(anchor) if anchor.instant == pulse => {
+                if anchor.utc != second {
+                    warn!("GPS time {second} does not match PPS time {}", anchor.utc);
+                    self.anchor = Some(Anchor {
+                        utc: second,
+                        instant: pulse,
+                    });
+                }
+            }
+            _ => {
+                debug!("GPS time anchored at {second}");
+                self.anchor = Some(Anchor {
+                    utc: second,
+                    instant: pulse,
+                });
+            }
+        }
+    }
+
+    /// Returns `true` once a pulse has been labelled with UTC.
+    pub fn is_synchronized(&self) -> bool {
+        self.anchor.is_some()
+    }
+
+    /// The time since the anchor pulse, or `None` if not synchronized.
+    pub fn anchor_age(&self) -> Option<Duration> {
+        self.anchor
+            .map(|anchor| Instant::now().saturating_duration_since(anchor.instant))
+    }
+
+    /// The estimated drift of the local clock against GPS time, in ppm.
+    ///
+    /// Positive values mean the local clock runs fast.
+    pub fn drift_ppm(&self) -> Option<f32> {
+        self.drift_ppm
+    }
+
+    /// Returns the current UTC time.
+    pub fn now(&self) -> Option<NaiveDateTime> {
+        self.utc_at(Instant::now())
+    }
+
+    /// Returns the current UTC time as microseconds since the Unix epoch.
+    pub fn now_unix_micros(&self) -> Option<i64> {
+        self.now().map(|utc| utc.and_utc().timestamp_micros())
+    }
+
+    /// Returns the UTC time at `instant`, corrected for the estimated drift.
+    pub fn utc_at(&self, instant: Instant) -> Option<NaiveDateTime> {
+        let anchor = self.anchor?;
+        let local_us = instant.as_micros() as i64 - anchor.instant.as_micros() as i64;
+        let gps_us = local_us as f64 / (1.0 + self.drift_ppm.unwrap_or(0.0) as f64 * 1e-6);
+        anchor
+            .utc
+            .checked_add_signed(TimeDelta::microseconds(gps_us as i64))
+    }
+
+    /// Returns the `Instant` at which the local clock reaches `utc`, e.g. to
+    /// schedule a transmission. Returns `None` if not synchronized or if `utc` is
+    /// before the start of the local clock.
+    pub fn instant_at(&self, utc: NaiveDateTime) -> Option<Instant> {
+        let anchor = self.anchor?;
+        let gps_us = (utc - anchor.utc).num_microseconds()?;
+        let local_us = gps_us as f64 * (1.0 + self.drift_ppm.unwrap_or(0.0) as f64 * 1e-6);
+        let ticks = anchor.instant.as_micros() as i64 + local_us as i64;
+        (ticks >= 0).then(|| Instant::from_micros(ticks as u64))
+    }
+
+    fn update_drift(&mut self, interval: Duration, expected: Duration) {
+        let expected_us = expected.as_micros() as f32;
+        let ppm = (interval.as_micros() as f32 - expected_us) / expected_us * 1e6;
+        if ppm.abs() > MAX_DRIFT_PPM {
+            return;
+        }
+        self.drift_ppm = Some(match self.drift_ppm {
+            Some(drift) => drift + (ppm - drift) * DRIFT_SMOOTHING,
+            None => ppm,
+        });
+    }
+}
+
+fn utc_from_parts(
+    year: u16,
+    month: u8,
+    day: u8,
+    hour: u8,
+    minute: u8,
+    second: u8,
+    nano: i32,
+) -> Option<NaiveDateTime> {
+    let utc = NaiveDate::from_ymd_opt(year as i32, month as u32, day as u32)?.and_hms_opt(
+        hour as u32,
+        minute as u32,
+        second as u32,
+    )?;
+    utc.checked_add_signed(TimeDelta::nanoseconds(nano as i64))
+}
+
+fn utc_from_gps_data(date: Date, time: Time) -> Option<NaiveDateTime> {
+    utc_from_parts(
+        date.year,
+        date.month,
+        date.day,
+        time.hour,
+        time.minute,
+        time.second,
+        0,
+    )
+}
+
+fn round_to_second(utc: NaiveDateTime) -> NaiveDateTime {
+    let rounded = utc + TimeDelta::milliseconds(500);
+    rounded - TimeDelta::nanoseconds(rounded.and_utc().timestamp_subsec_nanos() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::mock::{MockPin, MockUart};
+    use crate::testdata::RMC;
+    use crate::GpsData;
+
+    fn nmea_update(second: u8) -> GpsUpdate {
+        GpsUpdate::NoFix(GpsData {
+            fix_time: Some(Time {
+                hour: 10,
+                minute: 23,
+                second,
+            }),
+            fix_date: Some(Date {
+                year: 2024,
+                month: 5,
+                day: 14,
+            }),
+            fix_type: None,
+            latitude: None,
+            longitude: None,
+            speed_over_ground: None,
+            altitude: None,
+            course_over_ground: None,
+            hdop: None,
+            vdop: None,
+            pdop: None,
+            satellites_used: None,
+        })
+    }
+
+    fn utc(second: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 5, 14)
+            .unwrap()
+            .and_hms_opt(10, 23, second)
+            .unwrap()
+    }
+
+    #[test]
+    fn labels_pulse_with_following_time() {
+        let pps = PpsSignal::<NoopRawMutex>::new();
+        let mut time = GpsTime::new(&pps);
+        let pulse = Instant::now();
+        time.on_pulse(pulse);
+        time.on_update(&nmea_update(45));
+        assert_eq!(time.utc_at(pulse), Some(utc(45)));
+
+        time.on_pulse(pulse + Duration::from_secs(1));
+        time.on_update(&nmea_update(46));
+        assert_eq!(time.utc_at(pulse + Duration::from_secs(1)), Some(utc(46)));
+        assert_eq!(time.drift_ppm(), Some(0.0));
+    }
+
+    #[test]
+    fn repeated_time_does_not_relabel() {
+        let pps = PpsSignal::<NoopRawMutex>::new();
+        let mut time = GpsTime::new(&pps);
+        let pulse = Instant::now();
+        time.on_pulse(pulse);
+        time.on_update(&nmea_update(45));
+
+        // A GSA after the next pulse still carries the time of the previous GGA.
+        let next = pulse + Duration::from_secs(1);
+        time.on_pulse(next);
+        time.on_update(&nmea_update(45));
+        assert_eq!(time.utc_at(next), Some(utc(46)));
+    }
+
+    #[test]
+    fn stale_time_before_first_label_is_ignored() {
+        let pps = PpsSignal::<NoopRawMutex>::new();
+        let mut time = GpsTime::new(&pps);
+        // The GGA arrives before any pulse was seen and cannot label one.
+        time.on_update(&nmea_update(44));
+        assert!(!time.is_synchronized());
+
+        // A VTG after the first pulse repeats that time.
+        time.on_pulse(Instant::now());
+        time.on_update(&nmea_update(44));
+        assert!(!time.is_synchronized());
+
+        time.on_update(&nmea_update(45));
+        assert_eq!(time.now().map(round_to_second), Some(utc(45)));
+    }
+
+    #[test]
+    fn pulses_advance_anchor() {
+        let pps = PpsSignal::<NoopRawMutex>::new();
+        let mut time = GpsTime::new(&pps);
+        let pulse = Instant::now();
+        time.on_pulse(pulse);
+        time.on_time(utc(45));
+        for seconds in 1..=3 {
+            time.on_pulse(pulse + Duration::from_secs(seconds));
+        }
+        assert_eq!(time.utc_at(pulse + Duration::from_secs(3)), Some(utc(48)));
+        assert_eq!(
+            time.instant_at(utc(47)),
+            Some(pulse + Duration::from_secs(2))
+        );
+    }
+
+    #[test]
+    fn next_update_labels_latched_pulse() {
+        let pps = PpsSignal::<NoopRawMutex>::new();
+        let mut time = GpsTime::new(&pps);
+        let mut gps = Gps::with_uart(MockUart::new(RMC, 16), MockPin);
+
+        // The pulse was latched by the pin task before the RMC was read.
+        let pulse = Instant::now();
+        pps.signal(pulse);
+        assert!(block_on(time.next_update(&mut gps)).is_ok());
+        assert_eq!(time.utc_at(pulse), Some(utc(45)));
+        assert!(!pps.signaled());
+    }
+}
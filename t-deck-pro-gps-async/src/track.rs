@@ -0,0 +1,617 @@
+//! Track logging in GPX 1.1 or NMEA format.
+//!
+//! A `TrackLogger` takes the fixes from `Gps::next_update`, drops those that are
+//! too close in time and distance to the last logged point, and writes the rest to
+//! any `embedded_io_async::Write` sink, e.g. a file on the SD card.
+//!
+//! GPX tracks are split into segments whenever the fix is lost or there is a long
+//! gap between points. A segment is closed and the next one opened in the same
+//! write, and the sink is flushed right after, so the log always ends inside an
+//! open segment. A file cut short by a reset ends after the last flushed point or
+//! segment break; appending `GPX_TRAILER` makes it a valid GPX file again.
+
+use core::fmt::Write as _;
+
+use chrono::NaiveDate;
+use embedded_io_async::Write;
+use heapless::String;
+use log::{debug, warn};
+
+use crate::geo::{self, Position};
+use crate::update::GpsUpdate;
+use crate::{FixType, GpsData};
+
+/// The start of a GPX 1.1 track log.
+pub const GPX_HEADER: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    "<gpx version=\"1.1\" creator=\"t-deck-pro-gps-async\" ",
+    "xmlns=\"http://www.topografix.com/GPX/1/1\">\n",
+    "<trk>\n"
+);
+/// Closes the open segment, the track and the GPX document.
+///
+/// `TrackLogger` keeps a segment open from the first point on, so this is the
+/// right ending for every flushed state of the log.
+pub const GPX_TRAILER: &str = "</trkseg>\n</trk>\n</gpx>\n";
+
+const SEGMENT_START: &str = "<trkseg>\n";
+/// Closes the current segment and opens the next one.
+const SEGMENT_BREAK: &str = "</trkseg>\n<trkseg>\n";
+
+/// The maximum length of a formatted track point or pair of NMEA sentences.
+const MAX_RECORD_LEN: usize = 256;
+
+/// The format of a track log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackFormat {
+    /// GPX 1.1 track with one `<trkseg>` per continuous stretch of fixes.
+    Gpx,
+    /// NMEA 0183 RMC and GGA sentences for every logged point.
+    Nmea,
+}
+
+/// Settings of a `TrackLogger`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackConfig {
+    /// The format of the log.
+    pub format: TrackFormat,
+    /// The minimum time between logged points in seconds.
+    pub min_interval_s: u32,
+    /// The minimum distance between logged points in meters.
+    pub min_distance_m: f32,
+    /// Start a new segment if no point was logged for this many seconds.
+    pub segment_gap_s: Option<u32>,
+    /// Flush the sink after this many points. Segments are always flushed when
+    /// they are closed.
+    pub flush_every: u32,
+}
+
+impl Default for TrackConfig {
+    /// A GPX log with points at least 5 seconds and 10 meters apart.
+    fn default() -> Self {
+        Self {
+            format: TrackFormat::Gpx,
+            min_interval_s: 5,
+            min_distance_m: 10.0,
+            segment_gap_s: Some(60),
+            flush_every: 10,
+        }
+    }
+}
+
+/// A logged position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TrackPoint {
+    position: Position,
+    timestamp: Option<i64>,
+}
+
+/// Writes a track log of GPS fixes to a sink.
+pub struct TrackLogger<SinkType: Write> {
+    sink: SinkType,
+    config: TrackConfig,
+    started: bool,
+    segment_points: u32,
+    last_point: Option<TrackPoint>,
+    points: u32,
+    unflushed: u32,
+}
+
+impl<SinkType: Write> TrackLogger<SinkType> {
+    /// Creates a new logger writing to `sink`. Nothing is written until the first
+    /// point is logged.
+    pub fn new(sink: SinkType, config: TrackConfig) -> Self {
+        Self {
+            sink,
+            config,
+            started: false,
+            segment_points: 0,
+            last_point: None,
+            points: 0,
+            unflushed: 0,
+        }
+    }
+
+    /// Returns the settings of the logger.
+    pub fn config(&self) -> TrackConfig {
+        self.config
+    }
+
+    /// The number of points logged so far.
+    pub fn points(&self) -> u32 {
+        self.points
+    }
+
+    /// Logs a GPS update.
+    ///
+    /// `GpsUpdate::Fix` is logged as a point unless it is dropped by decimation, a
+    /// `GpsUpdate::NoFix` without a valid fix closes the current segment, and other
+    /// updates are ignored. Returns `true` if a point was written.
+    pub async fn log(&mut self, update: &GpsUpdate) -> Result<bool, ()> {
+        match update {
+            GpsUpdate::Fix(data) => self.log_fix(data).await,
+            GpsUpdate::NoFix(data) if !data.has_fix() => {
+                self.close_segment().await?;
+                Ok(false)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Logs a fix as a point, unless it is too close in time and distance to the
+    /// last logged point. Returns `true` if the point was written.
+    pub async fn log_fix(&mut self, data: &GpsData) -> Result<bool, ()> {
+        let (Some(latitude), Some(longitude)) = (data.latitude, data.longitude) else {
+            return Ok(false);
+        };
+        let point = TrackPoint {
+            position: Position::new(latitude, longitude),
+            timestamp: timestamp(data),
+        };
+
+        if let Some(last) = self.last_point {
+            let elapsed = point.timestamp.zip(last.timestamp).map(|(t, l)| t - l);
+            if let (Some(gap), Some(elapsed)) = (self.config.segment_gap_s, elapsed) {
+                if elapsed > gap as i64 {
+                    debug!("Track gap of {elapsed} s, starting a new segment");
+                    self.close_segment().await?;
+                }
+            }
+            if self.segment_points > 0 {
+                let too_soon = elapsed.is_some_and(|e| e < self.config.min_interval_s as i64);
+                let too_close = geo::distance_m(last.position, point.position)
+                    < self.config.min_distance_m as f64;
+                if too_soon || too_close {
+                    return Ok(false);
+                }
+            }
+        }
+
+        self.write_point(data, &point).await?;
+        self.last_point = Some(point);
+        self.points += 1;
+        self.unflushed += 1;
+        if self.unflushed >= self.config.flush_every {
+            self.flush().await?;
+        }
+        Ok(true)
+    }
+
+    /// Closes the current GPX segment and flushes the sink. The next point goes into
+    /// a new segment, which is opened right away; nothing is written if the current
+    /// segment is still empty, and the sink is only flushed if there is unflushed
+    /// data, so repeated `GpsUpdate::NoFix` updates do not wear out flash storage.
+    pub async fn close_segment(&mut self) -> Result<(), ()> {
+        let mut pending = self.unflushed > 0;
+        if self.segment_points > 0 && self.config.format == TrackFormat::Gpx {
+            self.write(SEGMENT_BREAK).await?;
+            pending = true;
+        }
+        self.segment_points = 0;
+        if pending {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the points written so far to the sink.
+    pub async fn flush(&mut self) -> Result<(), ()> {
+        self.unflushed = 0;
+        self.sink
+            .flush()
+            .await
+            .map_err(|e| warn!("Track sink Error: {e:?}"))
+    }
+
+    /// Closes the log and returns the sink.
+    pub async fn finish(mut self) -> Result<SinkType, ()> {
+        if self.started && self.config.format == TrackFormat::Gpx {
+            self.write(GPX_TRAILER).await?;
+        }
+        self.flush().await?;
+        Ok(self.sink)
+    }
+
+    async fn write_point(&mut self, data: &GpsData, point: &TrackPoint) -> Result<(), ()> {
+        match self.config.format {
+            TrackFormat::Gpx => {
+                if !self.started {
+                    self.write(GPX_HEADER).await?;
+                    self.write(SEGMENT_START).await?;
+                    self.started = true;
+                }
+                let record = gpx_track_point(data, point)?;
+                self.write(&record).await?;
+            }
+            TrackFormat::Nmea => {
+                self.started = true;
+                let record = nmea_sentences(data, point)?;
+                self.write(&record).await?;
+            }
+        }
+        self.segment_points += 1;
+        Ok(())
+    }
+
+    async fn write(&mut self, text: &str) -> Result<(), ()> {
+        self.sink
+            .write_all(text.as_bytes())
+            .await
+            .map_err(|e| warn!("Track sink Error: {e:?}"))
+    }
+}
+
+/// Formats a point as a GPX `<trkpt>` element.
+fn gpx_track_point(data: &GpsData, point: &TrackPoint) -> Result<String<MAX_RECORD_LEN>, ()> {
+    let mut s = String::new();
+    format_gpx_track_point(&mut s, data, point).map_err(|_| warn!("Track point too long"))?;
+    Ok(s)
+}
+
+fn format_gpx_track_point(
+    s: &mut String<MAX_RECORD_LEN>,
+    data: &GpsData,
+    point: &TrackPoint,
+) -> core::fmt::Result {
+    write!(
+        s,
+        "<trkpt lat=\"{:.7}\" lon=\"{:.7}\">",
+        point.position.latitude, point.position.longitude
+    )?;
+    if let Some(altitude) = data.altitude {
+        write!(s, "<ele>{altitude:.1}</ele>")?;
+    }
+    if let (Some(date), Some(time)) = (data.fix_date, data.fix_time) {
+        write!(
+            s,
+            "<time>{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z</time>",
+            date.year, date.month, date.day, time.hour, time.minute, time.second
+        )?;
+    }
+    let fix = match data.fix_type {
+        Some(FixType::DGps) => Some("dgps"),
+        Some(FixType::Pps) => Some("pps"),
+        Some(FixType::NoFix) | None => None,
+        Some(_) if data.altitude.is_some() => Some("3d"),
+        Some(_) => Some("2d"),
+    };
+    if let Some(fix) = fix {
+        write!(s, "<fix>{fix}</fix>")?;
+    }
+    if let Some(satellites) = data.satellites_used {
+        write!(s, "<sat>{satellites}</sat>")?;
+    }
+    if let Some(hdop) = data.hdop {
+        write!(s, "<hdop>{hdop:.1}</hdop>")?;
+    }
+    if let Some(vdop) = data.vdop {
+        write!(s, "<vdop>{vdop:.1}</vdop>")?;
+    }
+    if let Some(pdop) = data.pdop {
+        write!(s, "<pdop>{pdop:.1}</pdop>")?;
+    }
+    s.write_str("</trkpt>\n")
+}
+
+/// Formats a point as NMEA RMC and GGA sentences.
+fn nmea_sentences(data: &GpsData, point: &TrackPoint) -> Result<String<MAX_RECORD_LEN>, ()> {
+    let mut s = String::new();
+    format_nmea_sentences(&mut s, data, point).map_err(|_| warn!("NMEA record too long"))?;
+    Ok(s)
+}
+
+fn format_nmea_sentences(
+    s: &mut String<MAX_RECORD_LEN>,
+    data: &GpsData,
+    point: &TrackPoint,
+) -> core::fmt::Result {
+    let mut time: String<16> = String::new();
+    if let Some(t) = data.fix_time {
+        write!(time, "{:02}{:02}{:02}.00", t.hour, t.minute, t.second)?;
+    }
+    let mut position: String<40> = String::new();
+    write_nmea_coordinate(&mut position, point.position.latitude, 2, ('N', 'S'))?;
+    position.push(',').map_err(|_| core::fmt::Error)?;
+    write_nmea_coordinate(&mut position, point.position.longitude, 3, ('E', 'W'))?;
+
+    let mut rmc: String<96> = String::new();
+    write!(rmc, "GPRMC,{time},A,{position},")?;
+    if let Some(speed) = data.speed_over_ground {
+        write!(rmc, "{speed:.1}")?;
+    }
+    rmc.push(',').map_err(|_| core::fmt::Error)?;
+    if let Some(course) = data.course_over_ground {
+        write!(rmc, "{course:.1}")?;
+    }
+    rmc.push(',').map_err(|_| core::fmt::Error)?;
+    if let Some(d) = data.fix_date {
+        write!(rmc, "{:02}{:02}{:02}", d.day, d.month, d.year % 100)?;
+    }
+    rmc.push_str(",,,A").map_err(|_| core::fmt::Error)?;
+    write_nmea_sentence(s, &rmc)?;
+
+    let quality = match data.fix_type {
+        Some(FixType::NoFix) | None => 0,
+        Some(FixType::Gps) => 1,
+        Some(FixType::DGps) => 2,
+        Some(FixType::Pps) => 3,
+        Some(FixType::Rtk) => 4,
+        Some(FixType::FloatRtk) => 5,
+        Some(FixType::Estimated) => 6,
+        Some(FixType::Manual) => 7,
+        Some(FixType::Simulation) => 8,
+    };
+    let mut gga: String<96> = String::new();
+    write!(
+        gga,
+        "GPGGA,{time},{position},{quality},{:02},",
+        data.satellites_used.unwrap_or(0)
+    )?;
+    if let Some(hdop) = data.hdop {
+        write!(gga, "{hdop:.1}")?;
+    }
+    gga.push(',').map_err(|_| core::fmt::Error)?;
+    if let Some(altitude) = data.altitude {
+        write!(gga, "{altitude:.1}")?;
+    }
+    gga.push_str(",M,,M,,").map_err(|_| core::fmt::Error)?;
+    write_nmea_sentence(s, &gga)
+}
+
+/// Writes `body` as a complete sentence with `$`, checksum and line ending.
+fn write_nmea_sentence(s: &mut String<MAX_RECORD_LEN>, body: &str) -> core::fmt::Result {
+    let checksum = body.bytes().fold(0u8, |acc, b| acc ^ b);
+    write!(s, "${body}*{checksum:02X}\r\n")
+}
+
+/// Writes a coordinate in NMEA `(d)ddmm.mmmmm,H` format.
+fn write_nmea_coordinate(
+    s: &mut String<40>,
+    value: f64,
+    degree_digits: usize,
+    hemispheres: (char, char),
+) -> core::fmt::Result {
+    let hemisphere = if value < 0.0 {
+        hemispheres.1
+    } else {
+        hemispheres.0
+    };
+    let value = libm::fabs(value);
+    let mut degrees = libm::floor(value) as u32;
+    let mut minutes = (value - degrees as f64) * 60.0;
+    // Avoid printing 60.00000 minutes after rounding.
+    if minutes >= 59.999995 {
+        degrees += 1;
+        minutes = 0.0;
+    }
+    write!(s, "{degrees:0degree_digits$}{minutes:08.5},{hemisphere}")
+}
+
+/// Seconds since the Unix epoch of the fix, if its date and time are known.
+fn timestamp(data: &GpsData) -> Option<i64> {
+    let (date, time) = (data.fix_date?, data.fix_time?);
+    let utc = NaiveDate::from_ymd_opt(date.year as i32, date.month as u32, date.day as u32)?
+        .and_hms_opt(time.hour as u32, time.minute as u32, time.second as u32)?;
+    Some(utc.and_utc().timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+    use std::vec::Vec;
+
+    use core::convert::Infallible;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::{Date, Time};
+
+    /// A sink that remembers how much of the data was flushed, i.e. would survive
+    /// a reset.
+    #[derive(Default)]
+    struct Sink {
+        data: Vec<u8>,
+        flushed: usize,
+        flushes: usize,
+    }
+
+    impl embedded_io_async::ErrorType for Sink {
+        type Error = Infallible;
+    }
+
+    impl Write for Sink {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            self.flushed = self.data.len();
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    fn fix(second: u8, latitude: f64) -> GpsData {
+        GpsData {
+            fix_time: Some(Time {
+                hour: 12,
+                minute: 0,
+                second,
+            }),
+            fix_date: Some(Date {
+                year: 2026,
+                month: 10,
+                day: 18,
+            }),
+            fix_type: Some(FixType::Gps),
+            latitude: Some(latitude),
+            longitude: Some(-19.5),
+            speed_over_ground: Some(1.5),
+            altitude: Some(101.5),
+            course_over_ground: Some(90.0),
+            hdop: Some(0.9),
+            vdop: None,
+            pdop: None,
+            satellites_used: Some(7),
+        }
+    }
+
+    fn no_fix() -> GpsUpdate {
+        GpsUpdate::NoFix(GpsData {
+            fix_type: Some(FixType::NoFix),
+            ..fix(0, 0.0)
+        })
+    }
+
+    /// Checks that `gpx` is a well-formed track and returns the number of points
+    /// in each segment.
+    fn parse_gpx(gpx: &str) -> Vec<usize> {
+        let body = gpx.strip_prefix(GPX_HEADER).expect("missing header");
+        let mut stack = std::vec!["gpx", "trk"];
+        let mut segments = Vec::new();
+        for tag in body.split('<').skip(1) {
+            let name = tag.split(['>', ' ']).next().unwrap();
+            if let Some(name) = name.strip_prefix('/') {
+                assert_eq!(stack.pop(), Some(name), "unbalanced </{name}> in\n{gpx}");
+            } else {
+                match (stack.last(), name) {
+                    (Some(&"trk"), "trkseg") => segments.push(0),
+                    (Some(&"trkseg"), "trkpt") => *segments.last_mut().unwrap() += 1,
+                    (Some(&"trkpt"), _) => {}
+                    (parent, _) => panic!("<{name}> inside {parent:?} in\n{gpx}"),
+                }
+                stack.push(name);
+            }
+        }
+        assert!(stack.is_empty(), "unclosed {stack:?} in\n{gpx}");
+        segments
+    }
+
+    /// The flushed part of the log with `GPX_TRAILER` appended, as recovered after a
+    /// reset.
+    fn recover(logger: &TrackLogger<Sink>) -> String {
+        let flushed = &logger.sink.data[..logger.sink.flushed];
+        String::from_utf8(flushed.to_vec()).unwrap() + GPX_TRAILER
+    }
+
+    fn config() -> TrackConfig {
+        TrackConfig {
+            flush_every: 1,
+            ..TrackConfig::default()
+        }
+    }
+
+    #[test]
+    fn decimates_points() {
+        let mut logger = TrackLogger::new(Sink::default(), TrackConfig::default());
+        block_on(async {
+            assert_eq!(logger.log(&GpsUpdate::Fix(fix(0, 47.0))).await, Ok(true));
+            // Too soon.
+            assert_eq!(logger.log(&GpsUpdate::Fix(fix(2, 47.001))).await, Ok(false));
+            // Too close.
+            assert_eq!(
+                logger.log(&GpsUpdate::Fix(fix(6, 47.000_01))).await,
+                Ok(false)
+            );
+            assert_eq!(logger.log(&GpsUpdate::Fix(fix(7, 47.001))).await, Ok(true));
+        });
+        assert_eq!(logger.points(), 2);
+    }
+
+    #[test]
+    fn finished_log_is_valid() {
+        let mut logger = TrackLogger::new(Sink::default(), config());
+        let sink = block_on(async {
+            logger.log(&GpsUpdate::Fix(fix(0, 47.0))).await.unwrap();
+            logger.log(&GpsUpdate::Fix(fix(10, 47.001))).await.unwrap();
+            logger.log(&no_fix()).await.unwrap();
+            logger.log(&no_fix()).await.unwrap();
+            logger.log(&GpsUpdate::Fix(fix(20, 47.002))).await.unwrap();
+            logger.finish().await.unwrap()
+        });
+        let gpx = String::from_utf8(sink.data).unwrap();
+        assert_eq!(parse_gpx(&gpx), [2, 1]);
+        assert!(gpx.contains(
+            "<trkpt lat=\"47.0010000\" lon=\"-19.5000000\"><ele>101.5</ele>\
+             <time>2026-10-18T12:00:10Z</time><fix>3d</fix><sat>7</sat><hdop>0.9</hdop>\
+             </trkpt>\n"
+        ));
+    }
+
+    #[test]
+    fn recovers_with_open_segment() {
+        let mut logger = TrackLogger::new(Sink::default(), config());
+        block_on(async {
+            logger.log(&GpsUpdate::Fix(fix(0, 47.0))).await.unwrap();
+            logger.log(&GpsUpdate::Fix(fix(10, 47.001))).await.unwrap();
+        });
+        assert_eq!(parse_gpx(&recover(&logger)), [2]);
+    }
+
+    #[test]
+    fn recovers_after_closed_segment() {
+        let mut logger = TrackLogger::new(Sink::default(), config());
+        block_on(async {
+            logger.log(&GpsUpdate::Fix(fix(0, 47.0))).await.unwrap();
+            logger.log(&no_fix()).await.unwrap();
+        });
+        assert_eq!(parse_gpx(&recover(&logger)), [1, 0]);
+
+        // The reset hits after the first point of the next segment.
+        block_on(logger.log(&GpsUpdate::Fix(fix(30, 47.001)))).unwrap();
+        assert_eq!(parse_gpx(&recover(&logger)), [1, 1]);
+    }
+
+    #[test]
+    fn no_fix_flushes_only_pending_data() {
+        let config = TrackConfig {
+            flush_every: 10,
+            ..TrackConfig::default()
+        };
+        let mut logger = TrackLogger::new(Sink::default(), config);
+        block_on(async {
+            // Nothing was written yet.
+            logger.log(&no_fix()).await.unwrap();
+            assert_eq!(logger.sink.flushes, 0);
+
+            // The segment break and the unflushed point are flushed once.
+            logger.log(&GpsUpdate::Fix(fix(0, 47.0))).await.unwrap();
+            logger.log(&no_fix()).await.unwrap();
+            assert_eq!(logger.sink.flushes, 1);
+            assert_eq!(logger.sink.flushed, logger.sink.data.len());
+
+            for _ in 0..5 {
+                logger.log(&no_fix()).await.unwrap();
+            }
+            assert_eq!(logger.sink.flushes, 1);
+        });
+    }
+
+    #[test]
+    fn nmea_log() {
+        let config = TrackConfig {
+            format: TrackFormat::Nmea,
+            ..config()
+        };
+        let mut logger = TrackLogger::new(Sink::default(), config);
+        let sink = block_on(async {
+            logger.log(&GpsUpdate::Fix(fix(0, 47.5))).await.unwrap();
+            logger.finish().await.unwrap()
+        });
+        let log = String::from_utf8(sink.data).unwrap();
+        let mut lines = log.lines();
+        assert_eq!(
+            lines.next(),
+            Some("$GPRMC,120000.00,A,4730.00000,N,01930.00000,W,1.5,90.0,181026,,,A*75")
+        );
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("$GPGGA,120000.00,4730.00000,N,"));
+        assert_eq!(lines.next(), None);
+    }
+}
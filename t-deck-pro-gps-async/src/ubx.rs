@@ -0,0 +1,1065 @@
+//! UBX binary protocol framing and typed messages.
+//!
+//! A UBX frame is `0xB5 0x62 class id len_lo len_hi payload... ck_a ck_b`, where the
+//! 8-bit Fletcher checksum covers everything from `class` to the end of the payload.
+//! `UbxDecoder` assembles frames from a byte stream, `UbxMessage::parse` turns a frame
+//! into one of the typed messages below.
+
+use heapless::Vec;
+
+/// First UBX sync character.
+pub const SYNC_1: u8 = 0xB5;
+/// Second UBX sync character.
+pub const SYNC_2: u8 = 0x62;
+/// Length of the frame header (sync, class, id and length).
+pub const HEADER_LEN: usize = 6;
+/// Length of the frame checksum.
+pub const CHECKSUM_LEN: usize = 2;
+/// The largest payload the decoder accepts.
+pub const MAX_PAYLOAD_LEN: usize = 1024;
+
+/// UBX message classes.
+pub mod class {
+    /// Navigation results.
+    pub const NAV: u8 = 0x01;
+    /// Receiver manager messages.
+    pub const RXM: u8 = 0x02;
+    /// Acknowledgement messages.
+    pub const ACK: u8 = 0x05;
+    /// Configuration messages.
+    pub const CFG: u8 = 0x06;
+    /// Monitoring messages.
+    pub const MON: u8 = 0x0A;
+    /// Timing messages.
+    pub const TIM: u8 = 0x0D;
+    /// Multiple GNSS assistance messages.
+    pub const MGA: u8 = 0x13;
+}
+
+/// UBX message IDs, grouped by class.
+pub mod id {
+    /// NAV-STATUS: receiver navigation status.
+    pub const NAV_STATUS: u8 = 0x03;
+    /// NAV-PVT: navigation position velocity time solution.
+    pub const NAV_PVT: u8 = 0x07;
+    /// NAV-TIMEUTC: UTC time solution.
+    pub const NAV_TIMEUTC: u8 = 0x21;
+    /// NAV-SAT: satellite information.
+    pub const NAV_SAT: u8 = 0x35;
+    /// ACK-NAK: message not acknowledged.
+    pub const ACK_NAK: u8 = 0x00;
+    /// ACK-ACK: message acknowledged.
+    pub const ACK_ACK: u8 = 0x01;
+    /// CFG-RST: reset receiver.
+    pub const CFG_RST: u8 = 0x04;
+    /// CFG-VALSET: set configuration values.
+    pub const CFG_VALSET: u8 = 0x8A;
+    /// CFG-VALGET: get configuration values.
+    pub const CFG_VALGET: u8 = 0x8B;
+    /// MON-VER: receiver and software version.
+    pub const MON_VER: u8 = 0x04;
+    /// RXM-PMREQ: power management request.
+    pub const RXM_PMREQ: u8 = 0x41;
+    /// MGA-ANO: AssistNow Offline data.
+    pub const MGA_ANO: u8 = 0x20;
+    /// MGA-INI: initial time, position and clock assistance.
+    pub const MGA_INI: u8 = 0x40;
+    /// MGA-ACK: acknowledgement of assistance data.
+    pub const MGA_ACK: u8 = 0x60;
+    /// MGA-DBD: navigation database dump entry.
+    pub const MGA_DBD: u8 = 0x80;
+}
+
+/// Errors produced while decoding UBX frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UbxError {
+    /// The frame checksum did not match.
+    ChecksumMismatch {
+        /// Class of the corrupted frame.
+        class: u8,
+        /// ID of the corrupted frame.
+        id: u8,
+    },
+    /// The announced payload length exceeds `MAX_PAYLOAD_LEN`.
+    PayloadTooLarge(u16),
+    /// The payload is too short for the message type.
+    InvalidLength {
+        /// Class of the message.
+        class: u8,
+        /// ID of the message.
+        id: u8,
+        /// The received payload length.
+        len: usize,
+    },
+    /// The output buffer is too small to hold the encoded frame.
+    BufferTooSmall,
+}
+
+/// Calculates the 8-bit Fletcher checksum used in UBX messages.
+pub fn checksum(data: &[u8]) -> (u8, u8) {
+    let mut ck_a: u8 = 0;
+    let mut ck_b: u8 = 0;
+    for byte in data {
+        ck_a = ck_a.wrapping_add(*byte);
+        ck_b = ck_b.wrapping_add(ck_a);
+    }
+    (ck_a, ck_b)
+}
+
+/// Encodes a UBX frame into `out` and returns its total length.
+pub fn encode(class: u8, id: u8, payload: &[u8], out: &mut [u8]) -> Result<usize, UbxError> {
+    let payload_end = HEADER_LEN + payload.len();
+    let total_len = payload_end + CHECKSUM_LEN;
+    if payload.len() > u16::MAX as usize || out.len() < total_len {
+        return Err(UbxError::BufferTooSmall);
+    }
+
+    out[0] = SYNC_1;
+    out[1] = SYNC_2;
+    out[2] = class;
+    out[3] = id;
+    out[4..HEADER_LEN].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+    out[HEADER_LEN..payload_end].copy_from_slice(payload);
+
+    let (ck_a, ck_b) = checksum(&out[2..payload_end]);
+    out[payload_end] = ck_a;
+    out[payload_end + 1] = ck_b;
+    Ok(total_len)
+}
+
+/// A raw, checksum-verified UBX frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UbxFrame<'a> {
+    /// Message class.
+    pub class: u8,
+    /// Message ID.
+    pub id: u8,
+    /// Message payload.
+    pub payload: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecoderState {
+    Sync1,
+    Sync2,
+    Class,
+    Id,
+    Len1,
+    Len2,
+    Payload,
+    CkA,
+    CkB,
+}
+
+/// A streaming UBX frame decoder.
+///
+/// Bytes are fed one at a time with `push`; anything that is not part of a UBX
+/// frame is skipped until the next sync sequence.
+pub struct UbxDecoder {
+    state: DecoderState,
+    class: u8,
+    id: u8,
+    len: u16,
+    ck_a: u8,
+    payload: Vec<u8, MAX_PAYLOAD_LEN>,
+}
+
+impl Default for UbxDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UbxDecoder {
+    /// Creates a new decoder waiting for a sync sequence.
+    pub const fn new() -> Self {
+        Self {
+            state: DecoderState::Sync1,
+            class: 0,
+            id: 0,
+            len: 0,
+            ck_a: 0,
+            payload: Vec::new(),
+        }
+    }
+
+    /// Discards any partially received frame.
+    pub fn reset(&mut self) {
+        self.state = DecoderState::Sync1;
+        self.payload.clear();
+    }
+
+    /// Returns `true` if the decoder is in the middle of a frame.
+    pub fn in_frame(&self) -> bool {
+        self.state != DecoderState::Sync1
+    }
+
+    /// Returns `true` if only the first sync character of a frame has been received.
+    pub fn awaiting_sync_2(&self) -> bool {
+        self.state == DecoderState::Sync2
+    }
+
+    /// Feeds one byte into the decoder.
+    ///
+    /// Returns a frame once its checksum byte has been received, or an error if the
+    /// frame turned out to be invalid.
+    pub fn push(&mut self, byte: u8) -> Option<Result<UbxFrame<'_>, UbxError>> {
+        match self.state {
+            DecoderState::Sync1 => {
+                if byte == SYNC_1 {
+                    self.state = DecoderState::Sync2;
+                }
+            }
+            DecoderState::Sync2 => {
+                self.state = match byte {
+                    SYNC_2 => DecoderState::Class,
+                    SYNC_1 => DecoderState::Sync2,
+                    _ => DecoderState::Sync1,
+                };
+            }
+            DecoderState::Class => {
+                self.class = byte;
+                self.state = DecoderState::Id;
+            }
+            DecoderState::Id => {
+                self.id = byte;
+                self.state = DecoderState::Len1;
+            }
+            DecoderState::Len1 => {
+                self.len = byte as u16;
+                self.state = DecoderState::Len2;
+            }
+            DecoderState::Len2 => {
+                self.len |= (byte as u16) << 8;
+                self.payload.clear();
+                if self.len as usize > MAX_PAYLOAD_LEN {
+                    self.state = DecoderState::Sync1;
+                    return Some(Err(UbxError::PayloadTooLarge(self.len)));
+                }
+                self.state = if self.len == 0 {
+                    DecoderState::CkA
+                } else {
+                    DecoderState::Payload
+                };
+            }
+            DecoderState::Payload => {
+                // Cannot overflow: the length was checked against the capacity.
+                let _ = self.payload.push(byte);
+                if self.payload.len() == self.len as usize {
+                    self.state = DecoderState::CkA;
+                }
+            }
+            DecoderState::CkA => {
+                self.ck_a = byte;
+                self.state = DecoderState::CkB;
+            }
+            DecoderState::CkB => {
+                self.state = DecoderState::Sync1;
+                let (ck_a, ck_b) = self.frame_checksum();
+                if ck_a != self.ck_a || ck_b != byte {
+                    return Some(Err(UbxError::ChecksumMismatch {
+                        class: self.class,
+                        id: self.id,
+                    }));
+                }
+                return Some(Ok(UbxFrame {
+                    class: self.class,
+                    id: self.id,
+                    payload: &self.payload,
+                }));
+            }
+        }
+        None
+    }
+
+    fn frame_checksum(&self) -> (u8, u8) {
+        let len = self.len.to_le_bytes();
+        let header = [self.class, self.id, len[0], len[1]];
+        let mut ck_a: u8 = 0;
+        let mut ck_b: u8 = 0;
+        for byte in header.iter().chain(self.payload.iter()) {
+            ck_a = ck_a.wrapping_add(*byte);
+            ck_b = ck_b.wrapping_add(ck_a);
+        }
+        (ck_a, ck_b)
+    }
+}
+
+/// Little-endian field accessors for UBX payloads.
+pub(crate) fn u16_at(payload: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([payload[offset], payload[offset + 1]])
+}
+
+pub(crate) fn i16_at(payload: &[u8], offset: usize) -> i16 {
+    u16_at(payload, offset) as i16
+}
+
+pub(crate) fn u32_at(payload: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        payload[offset],
+        payload[offset + 1],
+        payload[offset + 2],
+        payload[offset + 3],
+    ])
+}
+
+pub(crate) fn i32_at(payload: &[u8], offset: usize) -> i32 {
+    u32_at(payload, offset) as i32
+}
+
+/// The GNSS fix type reported by UBX navigation messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UbxFixType {
+    /// No fix.
+    NoFix,
+    /// Dead reckoning only.
+    DeadReckoning,
+    /// 2D fix.
+    Fix2D,
+    /// 3D fix.
+    Fix3D,
+    /// GNSS combined with dead reckoning.
+    GnssDeadReckoning,
+    /// Time only fix.
+    TimeOnly,
+}
+
+impl From<u8> for UbxFixType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => UbxFixType::DeadReckoning,
+            2 => UbxFixType::Fix2D,
+            3 => UbxFixType::Fix3D,
+            4 => UbxFixType::GnssDeadReckoning,
+            5 => UbxFixType::TimeOnly,
+            _ => UbxFixType::NoFix,
+        }
+    }
+}
+
+/// UBX-NAV-PVT: navigation position velocity time solution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavPvt {
+    /// GPS time of week in milliseconds.
+    pub itow: u32,
+    /// UTC year.
+    pub year: u16,
+    /// UTC month (1-12).
+    pub month: u8,
+    /// UTC day (1-31).
+    pub day: u8,
+    /// UTC hour (0-23).
+    pub hour: u8,
+    /// UTC minute (0-59).
+    pub minute: u8,
+    /// UTC second (0-60).
+    pub second: u8,
+    /// Fraction of a second in nanoseconds (-1e9..1e9).
+    pub nano: i32,
+    /// The UTC date is valid.
+    pub valid_date: bool,
+    /// The UTC time of day is valid.
+    pub valid_time: bool,
+    /// The UTC time of day is fully resolved (no seconds uncertainty).
+    pub fully_resolved: bool,
+    /// Time accuracy estimate in nanoseconds.
+    pub time_accuracy: u32,
+    /// The GNSS fix type.
+    pub fix_type: UbxFixType,
+    /// A valid fix within the DOP and accuracy masks.
+    pub gnss_fix_ok: bool,
+    /// Differential corrections were applied.
+    pub diff_soln: bool,
+    /// Power save mode state.
+    pub psm_state: u8,
+    /// Number of satellites used in the solution.
+    pub num_sv: u8,
+    /// Longitude in degrees.
+    pub longitude: f64,
+    /// Latitude in degrees.
+    pub latitude: f64,
+    /// Height above the ellipsoid in meters.
+    pub height: f32,
+    /// Height above mean sea level in meters.
+    pub height_msl: f32,
+    /// Horizontal accuracy estimate in meters.
+    pub horizontal_accuracy: f32,
+    /// Vertical accuracy estimate in meters.
+    pub vertical_accuracy: f32,
+    /// NED north velocity in m/s.
+    pub vel_north: f32,
+    /// NED east velocity in m/s.
+    pub vel_east: f32,
+    /// NED down velocity in m/s.
+    pub vel_down: f32,
+    /// Ground speed in m/s.
+    pub ground_speed: f32,
+    /// Heading of motion in degrees.
+    pub heading_of_motion: f32,
+    /// Speed accuracy estimate in m/s.
+    pub speed_accuracy: f32,
+    /// Heading accuracy estimate in degrees.
+    pub heading_accuracy: f32,
+    /// Position DOP.
+    pub pdop: f32,
+}
+
+impl NavPvt {
+    /// The payload length of NAV-PVT.
+    pub const LEN: usize = 92;
+
+    /// Parses a NAV-PVT payload.
+    pub fn parse(p: &[u8]) -> Result<Self, UbxError> {
+        if p.len() < Self::LEN {
+            return Err(UbxError::InvalidLength {
+                class: class::NAV,
+                id: id::NAV_PVT,
+                len: p.len(),
+            });
+        }
+        let valid = p[11];
+        let flags = p[21];
+        Ok(Self {
+            itow: u32_at(p, 0),
+            year: u16_at(p, 4),
+            month: p[6],
+            day: p[7],
+            hour: p[8],
+            minute: p[9],
+            second: p[10],
+            valid_date: valid & 0x01 != 0,
+            valid_time: valid & 0x02 != 0,
+            fully_resolved: valid & 0x04 != 0,
+            time_accuracy: u32_at(p, 12),
+            nano: i32_at(p, 16),
+            fix_type: p[20].into(),
+            gnss_fix_ok: flags & 0x01 != 0,
+            diff_soln: flags & 0x02 != 0,
+            psm_state: (flags >> 2) & 0x07,
+            num_sv: p[23],
+            longitude: i32_at(p, 24) as f64 * 1e-7,
+            latitude: i32_at(p, 28) as f64 * 1e-7,
+            height: i32_at(p, 32) as f32 / 1000.0,
+            height_msl: i32_at(p, 36) as f32 / 1000.0,
+            horizontal_accuracy: u32_at(p, 40) as f32 / 1000.0,
+            vertical_accuracy: u32_at(p, 44) as f32 / 1000.0,
+            vel_north: i32_at(p, 48) as f32 / 1000.0,
+            vel_east: i32_at(p, 52) as f32 / 1000.0,
+            vel_down: i32_at(p, 56) as f32 / 1000.0,
+            ground_speed: i32_at(p, 60) as f32 / 1000.0,
+            heading_of_motion: i32_at(p, 64) as f32 * 1e-5,
+            speed_accuracy: u32_at(p, 68) as f32 / 1000.0,
+            heading_accuracy: u32_at(p, 72) as f32 * 1e-5,
+            pdop: u16_at(p, 76) as f32 * 0.01,
+        })
+    }
+
+    /// Returns `true` if the solution is a valid 2D/3D fix.
+    pub fn has_fix(&self) -> bool {
+        self.gnss_fix_ok
+            && matches!(
+                self.fix_type,
+                UbxFixType::Fix2D | UbxFixType::Fix3D | UbxFixType::GnssDeadReckoning
+            )
+    }
+}
+
+/// The GNSS constellation a satellite belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GnssId {
+    /// GPS.
+    Gps,
+    /// SBAS.
+    Sbas,
+    /// Galileo.
+    Galileo,
+    /// BeiDou.
+    BeiDou,
+    /// QZSS.
+    Qzss,
+    /// GLONASS.
+    Glonass,
+    /// NavIC.
+    NavIc,
+    /// An unknown constellation identifier.
+    Unknown(u8),
+}
+
+impl From<u8> for GnssId {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => GnssId::Gps,
+            1 => GnssId::Sbas,
+            2 => GnssId::Galileo,
+            3 => GnssId::BeiDou,
+            5 => GnssId::Qzss,
+            6 => GnssId::Glonass,
+            7 => GnssId::NavIc,
+            other => GnssId::Unknown(other),
+        }
+    }
+}
+
+/// A single satellite entry of UBX-NAV-SAT.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NavSatInfo {
+    /// The constellation.
+    pub gnss_id: GnssId,
+    /// The satellite ID within the constellation.
+    pub sv_id: u8,
+    /// Carrier to noise ratio in dBHz.
+    pub cno: u8,
+    /// Elevation in degrees (-90..90), if known.
+    pub elevation: Option<i8>,
+    /// Azimuth in degrees (0..360), if known.
+    pub azimuth: Option<i16>,
+    /// Pseudorange residual in meters.
+    pub pr_residual: f32,
+    /// Signal quality indicator (0-7).
+    pub quality: u8,
+    /// The satellite is used in the navigation solution.
+    pub used: bool,
+    /// Signal health (0 unknown, 1 healthy, 2 unhealthy).
+    pub health: u8,
+}
+
+/// UBX-NAV-SAT: satellite information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NavSat<'a> {
+    /// GPS time of week in milliseconds.
+    pub itow: u32,
+    payload: &'a [u8],
+}
+
+impl<'a> NavSat<'a> {
+    const HEADER_LEN: usize = 8;
+    const BLOCK_LEN: usize = 12;
+
+    /// Parses a NAV-SAT payload.
+    pub fn parse(p: &'a [u8]) -> Result<Self, UbxError> {
+        let invalid = UbxError::InvalidLength {
+            class: class::NAV,
+            id: id::NAV_SAT,
+            len: p.len(),
+        };
+        if p.len() < Self::HEADER_LEN {
+            return Err(invalid);
+        }
+        let num_svs = p[5] as usize;
+        if p.len() < Self::HEADER_LEN + num_svs * Self::BLOCK_LEN {
+            return Err(invalid);
+        }
+        Ok(Self {
+            itow: u32_at(p, 0),
+            payload: p,
+        })
+    }
+
+    /// The number of satellites in the message.
+    pub fn num_svs(&self) -> usize {
+        self.payload[5] as usize
+    }
+
+    /// Iterates over the satellites in the message.
+    pub fn satellites(&self) -> impl Iterator<Item = NavSatInfo> + 'a {
+        let end = Self::HEADER_LEN + self.num_svs() * Self::BLOCK_LEN;
+        self.payload[Self::HEADER_LEN..end]
+            .chunks_exact(Self::BLOCK_LEN)
+            .map(|b| {
+                let flags = u32_at(b, 8);
+                let elevation = b[3] as i8;
+                let azimuth = i16_at(b, 4);
+                // Elevation and azimuth are reported as out of range when unknown.
+                let position_known =
+                    (-90..=90).contains(&elevation) && (0..=360).contains(&azimuth);
+                NavSatInfo {
+                    gnss_id: b[0].into(),
+                    sv_id: b[1],
+                    cno: b[2],
+                    elevation: position_known.then_some(elevation),
+                    azimuth: position_known.then_some(azimuth),
+                    pr_residual: i16_at(b, 6) as f32 * 0.1,
+                    quality: (flags & 0x07) as u8,
+                    used: flags & 0x08 != 0,
+                    health: ((flags >> 4) & 0x03) as u8,
+                }
+            })
+    }
+}
+
+/// UBX-NAV-STATUS: receiver navigation status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NavStatus {
+    /// GPS time of week in milliseconds.
+    pub itow: u32,
+    /// The GNSS fix type.
+    pub fix_type: UbxFixType,
+    /// A valid fix within the DOP and accuracy masks.
+    pub gps_fix_ok: bool,
+    /// Differential corrections were applied.
+    pub diff_soln: bool,
+    /// The GPS week number is valid.
+    pub week_valid: bool,
+    /// The GPS time of week is valid.
+    pub tow_valid: bool,
+    /// Power save mode state (0 acquisition, 1 tracking, 2 power optimized tracking, 3 inactive).
+    pub psm_state: u8,
+    /// Time to first fix in milliseconds.
+    pub ttff: u32,
+    /// Milliseconds since startup or reset.
+    pub msss: u32,
+}
+
+impl NavStatus {
+    /// The payload length of NAV-STATUS.
+    pub const LEN: usize = 16;
+
+    /// Parses a NAV-STATUS payload.
+    pub fn parse(p: &[u8]) -> Result<Self, UbxError> {
+        if p.len() < Self::LEN {
+            return Err(UbxError::InvalidLength {
+                class: class::NAV,
+                id: id::NAV_STATUS,
+                len: p.len(),
+            });
+        }
+        let flags = p[5];
+        Ok(Self {
+            itow: u32_at(p, 0),
+            fix_type: p[4].into(),
+            gps_fix_ok: flags & 0x01 != 0,
+            diff_soln: flags & 0x02 != 0,
+            week_valid: flags & 0x04 != 0,
+            tow_valid: flags & 0x08 != 0,
+            psm_state: p[7] & 0x03,
+            ttff: u32_at(p, 8),
+            msss: u32_at(p, 12),
+        })
+    }
+}
+
+/// UBX-NAV-TIMEUTC: UTC time solution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NavTimeUtc {
+    /// GPS time of week in milliseconds.
+    pub itow: u32,
+    /// Time accuracy estimate in nanoseconds.
+    pub time_accuracy: u32,
+    /// Fraction of a second in nanoseconds (-1e9..1e9).
+    pub nano: i32,
+    /// Year.
+    pub year: u16,
+    /// Month (1-12).
+    pub month: u8,
+    /// Day (1-31).
+    pub day: u8,
+    /// Hour (0-23).
+    pub hour: u8,
+    /// Minute (0-59).
+    pub minute: u8,
+    /// Second (0-60).
+    pub second: u8,
+    /// The time of week is valid.
+    pub valid_tow: bool,
+    /// The week number is valid.
+    pub valid_week: bool,
+    /// The UTC time is valid (leap seconds are known).
+    pub valid_utc: bool,
+}
+
+impl NavTimeUtc {
+    /// The payload length of NAV-TIMEUTC.
+    pub const LEN: usize = 20;
+
+    /// Parses a NAV-TIMEUTC payload.
+    pub fn parse(p: &[u8]) -> Result<Self, UbxError> {
+        if p.len() < Self::LEN {
+            return Err(UbxError::InvalidLength {
+                class: class::NAV,
+                id: id::NAV_TIMEUTC,
+                len: p.len(),
+            });
+        }
+        let valid = p[19];
+        Ok(Self {
+            itow: u32_at(p, 0),
+            time_accuracy: u32_at(p, 4),
+            nano: i32_at(p, 8),
+            year: u16_at(p, 12),
+            month: p[14],
+            day: p[15],
+            hour: p[16],
+            minute: p[17],
+            second: p[18],
+            valid_tow: valid & 0x01 != 0,
+            valid_week: valid & 0x02 != 0,
+            valid_utc: valid & 0x04 != 0,
+        })
+    }
+}
+
+/// UBX-MON-VER: receiver and software version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonVer<'a> {
+    payload: &'a [u8],
+}
+
+impl<'a> MonVer<'a> {
+    const SW_VERSION_LEN: usize = 30;
+    const HW_VERSION_LEN: usize = 10;
+    const EXTENSION_LEN: usize = 30;
+
+    /// Parses a MON-VER payload.
+    pub fn parse(p: &'a [u8]) -> Result<Self, UbxError> {
+        if p.len() < Self::SW_VERSION_LEN + Self::HW_VERSION_LEN {
+            return Err(UbxError::InvalidLength {
+                class: class::MON,
+                id: id::MON_VER,
+                len: p.len(),
+            });
+        }
+        Ok(Self { payload: p })
+    }
+
+    /// The software version string.
+    pub fn software_version(&self) -> &'a str {
+        nul_terminated(&self.payload[..Self::SW_VERSION_LEN])
+    }
+
+    /// The hardware version string.
+    pub fn hardware_version(&self) -> &'a str {
+        let start = Self::SW_VERSION_LEN;
+        nul_terminated(&self.payload[start..start + Self::HW_VERSION_LEN])
+    }
+
+    /// The extension strings, e.g. `"FWVER=SPG 5.10"` or `"PROTVER=34.10"`.
+    pub fn extensions(&self) -> impl Iterator<Item = &'a str> + 'a {
+        self.payload[Self::SW_VERSION_LEN + Self::HW_VERSION_LEN..]
+            .chunks_exact(Self::EXTENSION_LEN)
+            .map(nul_terminated)
+    }
+}
+
+fn nul_terminated(bytes: &[u8]) -> &str {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    core::str::from_utf8(&bytes[..end]).unwrap_or("")
+}
+
+/// UBX-MGA-ACK-DATA0: acknowledgement of an assistance message.
+///
+/// Only sent when CFG-NAVSPG-ACKAIDING is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MgaAck {
+    /// The receiver accepted the data.
+    pub accepted: bool,
+    /// The reason the data was not used (0 if accepted).
+    pub info_code: u8,
+    /// The message ID of the acknowledged MGA message.
+    pub msg_id: u8,
+    /// The first four bytes of the acknowledged message's payload.
+    pub payload_start: [u8; 4],
+}
+
+impl MgaAck {
+    /// The payload length of MGA-ACK-DATA0.
+    pub const LEN: usize = 8;
+
+    /// Parses an MGA-ACK-DATA0 payload.
+    pub fn parse(p: &[u8]) -> Result<Self, UbxError> {
+        if p.len() < Self::LEN {
+            return Err(UbxError::InvalidLength {
+                class: class::MGA,
+                id: id::MGA_ACK,
+                len: p.len(),
+            });
+        }
+        Ok(Self {
+            accepted: p[0] == 1,
+            info_code: p[2],
+            msg_id: p[3],
+            payload_start: [p[4], p[5], p[6], p[7]],
+        })
+    }
+}
+
+/// A typed UBX message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UbxMessage<'a> {
+    /// UBX-NAV-PVT.
+    NavPvt(NavPvt),
+    /// UBX-NAV-SAT.
+    NavSat(NavSat<'a>),
+    /// UBX-NAV-STATUS.
+    NavStatus(NavStatus),
+    /// UBX-NAV-TIMEUTC.
+    NavTimeUtc(NavTimeUtc),
+    /// UBX-MON-VER.
+    MonVer(MonVer<'a>),
+    /// UBX-MGA-ACK-DATA0.
+    MgaAck(MgaAck),
+    /// UBX-ACK-ACK for the given class and ID.
+    AckAck {
+        /// Class of the acknowledged message.
+        class: u8,
+        /// ID of the acknowledged message.
+        id: u8,
+    },
+    /// UBX-ACK-NAK for the given class and ID.
+    AckNak {
+        /// Class of the rejected message.
+        class: u8,
+        /// ID of the rejected message.
+        id: u8,
+    },
+    /// Any other message.
+    Other(UbxFrame<'a>),
+}
+
+impl<'a> UbxMessage<'a> {
+    /// Interprets a raw frame as a typed message.
+    pub fn parse(frame: UbxFrame<'a>) -> Result<Self, UbxError> {
+        let p = frame.payload;
+        Ok(match (frame.class, frame.id) {
+            (class::NAV, id::NAV_PVT) => UbxMessage::NavPvt(NavPvt::parse(p)?),
+            (class::NAV, id::NAV_SAT) => UbxMessage::NavSat(NavSat::parse(p)?),
+            (class::NAV, id::NAV_STATUS) => UbxMessage::NavStatus(NavStatus::parse(p)?),
+            (class::NAV, id::NAV_TIMEUTC) => UbxMessage::NavTimeUtc(NavTimeUtc::parse(p)?),
+            (class::MON, id::MON_VER) => UbxMessage::MonVer(MonVer::parse(p)?),
+            (class::MGA, id::MGA_ACK) => UbxMessage::MgaAck(MgaAck::parse(p)?),
+            (class::ACK, ack_id @ (id::ACK_ACK | id::ACK_NAK)) => {
+                if p.len() < 2 {
+                    return Err(UbxError::InvalidLength {
+                        class: class::ACK,
+                        id: ack_id,
+                        len: p.len(),
+                    });
+                }
+                if ack_id == id::ACK_ACK {
+                    UbxMessage::AckAck {
+                        class: p[0],
+                        id: p[1],
+                    }
+                } else {
+                    UbxMessage::AckNak {
+                        class: p[0],
+                        id: p[1],
+                    }
+                }
+            }
+            _ => UbxMessage::Other(frame),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::testdata::{ACK_VALSET, MON_VER, NAV_PVT, NAV_SAT};
+
+    /// Feeds `bytes` and returns every frame or error as `(class, id, payload len)`.
+    fn decode(decoder: &mut UbxDecoder, bytes: &[u8]) -> Vec<Result<(u8, u8, usize), UbxError>> {
+        bytes
+            .iter()
+            .filter_map(|&byte| {
+                let result = decoder.push(byte)?;
+                Some(result.map(|frame| (frame.class, frame.id, frame.payload.len())))
+            })
+            .collect()
+    }
+
+    fn parse_one(bytes: &[u8]) -> UbxMessage<'_> {
+        let payload = &bytes[HEADER_LEN..bytes.len() - CHECKSUM_LEN];
+        UbxMessage::parse(UbxFrame {
+            class: bytes[2],
+            id: bytes[3],
+            payload,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn decodes_captured_frames() {
+        let mut decoder = UbxDecoder::new();
+        let stream: Vec<u8> = [NAV_PVT, NAV_SAT, MON_VER, ACK_VALSET].concat();
+        assert_eq!(
+            decode(&mut decoder, &stream),
+            [
+                Ok((class::NAV, id::NAV_PVT, NavPvt::LEN)),
+                Ok((class::NAV, id::NAV_SAT, 44)),
+                Ok((class::MON, id::MON_VER, 160)),
+                Ok((class::ACK, id::ACK_ACK, 2)),
+            ]
+        );
+        assert!(!decoder.in_frame());
+    }
+
+    #[test]
+    fn parses_nav_pvt() {
+        let UbxMessage::NavPvt(pvt) = parse_one(NAV_PVT) else {
+            panic!("not a NAV-PVT");
+        };
+        assert_eq!((pvt.year, pvt.month, pvt.day), (2024, 5, 14));
+        assert_eq!((pvt.hour, pvt.minute, pvt.second), (10, 23, 45));
+        assert!(pvt.valid_date && pvt.valid_time && pvt.fully_resolved);
+        assert_eq!(pvt.nano, -12345);
+        assert_eq!(pvt.fix_type, UbxFixType::Fix3D);
+        assert!(pvt.has_fix());
+        assert_eq!(pvt.num_sv, 11);
+        assert!((pvt.latitude - 52.5200066).abs() < 1e-9);
+        assert!((pvt.longitude - 13.404954).abs() < 1e-9);
+        assert_eq!(pvt.height_msl, 38.456);
+        assert_eq!(pvt.horizontal_accuracy, 2.1);
+        assert_eq!(pvt.vel_east, -0.34);
+        assert!((pvt.heading_of_motion - 254.0).abs() < 1e-3);
+        assert!((pvt.pdop - 1.28).abs() < 1e-6);
+    }
+
+    #[test]
+    fn parses_nav_sat() {
+        let UbxMessage::NavSat(sat) = parse_one(NAV_SAT) else {
+            panic!("not a NAV-SAT");
+        };
+        assert_eq!(sat.itow, 123_843_000);
+        assert_eq!(sat.num_svs(), 3);
+        let sats: Vec<NavSatInfo> = sat.satellites().collect();
+        assert_eq!(
+            (sats[0].gnss_id, sats[0].sv_id, sats[0].cno),
+            (GnssId::Gps, 5, 38)
+        );
+        assert_eq!((sats[0].elevation, sats[0].azimuth), (Some(52), Some(132)));
+        assert_eq!(
+            (sats[0].quality, sats[0].used, sats[0].health),
+            (7, true, 1)
+        );
+        assert!((sats[0].pr_residual + 1.2).abs() < 1e-6);
+        assert_eq!(
+            (sats[1].sv_id, sats[1].quality, sats[1].used),
+            (13, 4, true)
+        );
+        assert_eq!((sats[2].gnss_id, sats[2].sv_id), (GnssId::Galileo, 11));
+        assert_eq!((sats[2].elevation, sats[2].azimuth), (None, None));
+        assert!(!sats[2].used);
+    }
+
+    #[test]
+    fn parses_mon_ver() {
+        let UbxMessage::MonVer(ver) = parse_one(MON_VER) else {
+            panic!("not a MON-VER");
+        };
+        assert_eq!(ver.software_version(), "ROM SPG 5.10 (7b202e)");
+        assert_eq!(ver.hardware_version(), "000A0000");
+        let extensions: Vec<&str> = ver.extensions().collect();
+        assert_eq!(
+            extensions,
+            [
+                "ROM BASE 0x118B2060",
+                "FWVER=SPG 5.10",
+                "PROTVER=34.10",
+                "MOD=NEO-M10"
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_ack() {
+        assert_eq!(
+            parse_one(ACK_VALSET),
+            UbxMessage::AckAck {
+                class: class::CFG,
+                id: id::CFG_VALSET,
+            }
+        );
+    }
+
+    #[test]
+    fn encodes_ack() {
+        let mut out = [0u8; 16];
+        let len = encode(
+            class::ACK,
+            id::ACK_ACK,
+            &[class::CFG, id::CFG_VALSET],
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(&out[..len], ACK_VALSET);
+        assert_eq!(
+            encode(class::ACK, id::ACK_ACK, &[0; 9], &mut out),
+            Err(UbxError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn resyncs_after_garbage_and_false_sync() {
+        let mut decoder = UbxDecoder::new();
+        // Garbage, a lone sync byte, a repeated sync byte right before the frame.
+        let stream: Vec<u8> =
+            [&[0x00, 0x62, SYNC_1, 0x00, 0x13][..], &[SYNC_1], ACK_VALSET].concat();
+        assert_eq!(
+            decode(&mut decoder, &stream),
+            [Ok((class::ACK, id::ACK_ACK, 2))]
+        );
+    }
+
+    #[test]
+    fn reports_bad_checksum_and_recovers() {
+        let mut decoder = UbxDecoder::new();
+        let mut corrupted = NAV_PVT.to_vec();
+        corrupted[HEADER_LEN + 30] ^= 0x01;
+        let stream: Vec<u8> = [&corrupted[..], ACK_VALSET].concat();
+        assert_eq!(
+            decode(&mut decoder, &stream),
+            [
+                Err(UbxError::ChecksumMismatch {
+                    class: class::NAV,
+                    id: id::NAV_PVT,
+                }),
+                Ok((class::ACK, id::ACK_ACK, 2)),
+            ]
+        );
+
+        let mut bad_ck_b = ACK_VALSET.to_vec();
+        *bad_ck_b.last_mut().unwrap() ^= 0xFF;
+        assert!(matches!(
+            decode(&mut decoder, &bad_ck_b)[..],
+            [Err(UbxError::ChecksumMismatch { .. })]
+        ));
+    }
+
+    #[test]
+    fn rejects_oversize_length_and_recovers() {
+        let mut decoder = UbxDecoder::new();
+        let stream: Vec<u8> = [
+            &[SYNC_1, SYNC_2, class::NAV, id::NAV_PVT, 0x01, 0x04][..],
+            ACK_VALSET,
+        ]
+        .concat();
+        assert_eq!(
+            decode(&mut decoder, &stream),
+            [
+                Err(UbxError::PayloadTooLarge(0x0401)),
+                Ok((class::ACK, id::ACK_ACK, 2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_short_payloads() {
+        let frame = UbxFrame {
+            class: class::NAV,
+            id: id::NAV_PVT,
+            payload: &NAV_PVT[HEADER_LEN..HEADER_LEN + 20],
+        };
+        assert_eq!(
+            UbxMessage::parse(frame),
+            Err(UbxError::InvalidLength {
+                class: class::NAV,
+                id: id::NAV_PVT,
+                len: 20,
+            })
+        );
+    }
+}
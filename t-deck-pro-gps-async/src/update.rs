@@ -0,0 +1,280 @@
+//! A stream of parsed receiver output.
+//!
+//! `Gps::next_update` reads from a receive buffer that persists between calls, so
+//! bytes following a complete sentence are kept for the next call instead of being
+//! dropped. Every NMEA sentence and UBX navigation message turns into a
+//! `GpsUpdate`; a `FixPolicy` decides which position updates count as a fix.
+
+use embedded_hal::digital::OutputPin;
+use embedded_io_async::{Read, Write};
+use log::trace;
+
+use crate::mux::Packet;
+use crate::satellites::SatelliteView;
+use crate::ubx::{NavPvt, NavStatus, NavTimeUtc, UbxMessage};
+use crate::{Gps, GpsData};
+
+/// Decides when a position update counts as a complete fix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixPolicy {
+    /// The fix type must be valid (not `FixType::NoFix`).
+    pub require_valid_fix: bool,
+    /// The time of the fix must be known.
+    pub require_time: bool,
+    /// The date of the fix must be known.
+    pub require_date: bool,
+    /// The altitude must be known, i.e. a 3D fix.
+    pub require_altitude: bool,
+    /// The minimum number of satellites used in the fix.
+    pub min_satellites: u32,
+    /// The maximum horizontal dilution of precision, if any.
+    pub max_hdop: Option<f32>,
+}
+
+impl Default for FixPolicy {
+    /// A valid fix with position, time and date.
+    fn default() -> Self {
+        Self {
+            require_valid_fix: true,
+            require_time: true,
+            require_date: true,
+            require_altitude: false,
+            min_satellites: 0,
+            max_hdop: None,
+        }
+    }
+}
+
+impl FixPolicy {
+    /// Returns `true` if `data` satisfies the policy. A position is always required.
+    pub fn is_satisfied(&self, data: &GpsData) -> bool {
+        data.latitude.is_some()
+            && data.longitude.is_some()
+            && (!self.require_valid_fix || data.has_fix())
+            && (!self.require_time || data.fix_time.is_some())
+            && (!self.require_date || data.fix_date.is_some())
+            && (!self.require_altitude || data.altitude.is_some())
+            && data.satellites_used.unwrap_or(0) >= self.min_satellites
+            && self
+                .max_hdop
+                .is_none_or(|max| data.hdop.is_some_and(|hdop| hdop <= max))
+    }
+}
+
+/// An update parsed from the receiver output.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GpsUpdate {
+    /// A position sentence completed a fix that satisfies the `FixPolicy`.
+    Fix(GpsData),
+    /// A position or time sentence was received, but the data does not satisfy the
+    /// `FixPolicy` (yet). The time and date may already be valid.
+    NoFix(GpsData),
+    /// A complete set of GSV sentences or a UBX-NAV-SAT message was received; the
+    /// new view is available from `Gps::satellite_view`.
+    Satellites,
+    /// A UBX-NAV-PVT message was received.
+    NavPvt(NavPvt),
+    /// A UBX-NAV-STATUS message was received.
+    NavStatus(NavStatus),
+    /// A UBX-NAV-TIMEUTC message was received.
+    NavTimeUtc(NavTimeUtc),
+}
+
+/// Returns `true` if `sentence` is the last GSV sentence of its group.
+fn is_last_gsv(sentence: &str) -> bool {
+    let mut fields = sentence.split(',').skip(1);
+    match (fields.next(), fields.next()) {
+        (Some(total), Some(number)) => total == number,
+        _ => false,
+    }
+}
+
+impl<UartType, PinType> Gps<UartType, PinType>
+where
+    UartType: Read + Write,
+    PinType: OutputPin,
+{
+    /// Returns the policy deciding what counts as a fix.
+    pub fn fix_policy(&self) -> FixPolicy {
+        self.fix_policy
+    }
+
+    /// Sets the policy deciding what counts as a fix.
+    pub fn set_fix_policy(&mut self, policy: FixPolicy) {
+        self.fix_policy = policy;
+    }
+
+    /// Waits for the next update from the receiver.
+    ///
+    /// Every parsed NMEA position sentence yields `GpsUpdate::Fix` or
+    /// `GpsUpdate::NoFix`, a complete GSV group or NAV-SAT message yields
+    /// `GpsUpdate::Satellites`. Other sentences and messages are skipped. Returns
+    /// `Err(())` on a UART error or when the UART reaches the end of its data.
+    pub async fn next_update(&mut self) -> Result<GpsUpdate, ()> {
+        loop {
+            let byte = self.read_byte().await?;
+            match self.mux.push(byte) {
+                Some(Ok(Packet::Nmea(sentence))) => {
+                    trace!("Received raw GPS message: {sentence}");
+                    let last_gsv = is_last_gsv(sentence);
+                    let sentence_type = match self.nmea.parse(sentence) {
+                        Ok(sentence_type) => sentence_type,
+                        Err(e) => {
+                            trace!("Error parsing NMEA sentence: {e:?}");
+                            if matches!(e, nmea::Error::ChecksumMismatch { .. }) {
+                                self.stats.nmea_checksum_errors += 1;
+                            }
+                            continue;
+                        }
+                    };
+                    match sentence_type {
+                        nmea::SentenceType::GSV if last_gsv => {
+                            self.satellites =
+                                SatelliteView::from_nmea(&self.nmea, &self.used_satellites);
+                            return Ok(GpsUpdate::Satellites);
+                        }
+                        nmea::SentenceType::GSA => {
+                            let prns = self.nmea.fix_satellites_prns.as_deref().unwrap_or(&[]);
+                            self.used_satellites.update(sentence, prns);
+                            return Ok(self.fix_update());
+                        }
+                        nmea::SentenceType::GGA
+                        | nmea::SentenceType::RMC
+                        | nmea::SentenceType::GLL
+                        | nmea::SentenceType::VTG => return Ok(self.fix_update()),
+                        _ => {}
+                    }
+                }
+                Some(Ok(Packet::Ubx(frame))) => match UbxMessage::parse(frame) {
+                    Ok(UbxMessage::NavPvt(pvt)) => return Ok(GpsUpdate::NavPvt(pvt)),
+                    Ok(UbxMessage::NavSat(sat)) => {
+                        self.satellites = SatelliteView::from(sat);
+                        return Ok(GpsUpdate::Satellites);
+                    }
+                    Ok(UbxMessage::NavStatus(status)) => return Ok(GpsUpdate::NavStatus(status)),
+                    Ok(UbxMessage::NavTimeUtc(time)) => return Ok(GpsUpdate::NavTimeUtc(time)),
+                    Ok(message) => trace!("Skipping UBX message: {message:?}"),
+                    Err(e) => trace!("Error parsing UBX message: {e:?}"),
+                },
+                Some(Err(e)) => trace!("Error receiving GPS message: {e:?}"),
+                None => {}
+            }
+        }
+    }
+
+    /// Returns `GpsUpdate::Fix` or `GpsUpdate::NoFix` for the current NMEA data.
+    fn fix_update(&self) -> GpsUpdate {
+        let data = self.gps_data();
+        if self.fix_policy.is_satisfied(&data) {
+            GpsUpdate::Fix(data)
+        } else {
+            GpsUpdate::NoFix(data)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::{MockPin, MockUart};
+    use crate::testdata::*;
+    use crate::ubx::UbxFixType;
+    use crate::{Date, FixType, Time};
+
+    /// A recorded log: no fix yet, then the first fix with NMEA and UBX output.
+    fn log() -> Vec<u8> {
+        [
+            GGA_NO_FIX,
+            NAV_PVT_NO_FIX,
+            GSV_1,
+            GSV_2,
+            GGA,
+            ACK_VALSET,
+            RMC,
+            NAV_SAT,
+            NAV_PVT,
+        ]
+        .concat()
+    }
+
+    fn replay(chunk: usize) {
+        let mut gps = Gps::with_uart(MockUart::new(&log(), chunk), MockPin);
+        block_on(async {
+            let GpsUpdate::NoFix(data) = gps.next_update().await.unwrap() else {
+                panic!("expected NoFix");
+            };
+            assert_eq!(data.fix_type, Some(FixType::NoFix));
+            assert_eq!(data.latitude, None);
+            assert_eq!(
+                data.fix_time,
+                Some(Time {
+                    hour: 10,
+                    minute: 23,
+                    second: 44
+                })
+            );
+
+            let GpsUpdate::NavPvt(pvt) = gps.next_update().await.unwrap() else {
+                panic!("expected NavPvt");
+            };
+            assert_eq!(pvt.fix_type, UbxFixType::NoFix);
+
+            assert_eq!(gps.next_update().await, Ok(GpsUpdate::Satellites));
+            assert_eq!(gps.satellite_view().in_view(), 5);
+
+            // GGA has a position but no date, which the default policy requires.
+            let GpsUpdate::NoFix(data) = gps.next_update().await.unwrap() else {
+                panic!("expected NoFix");
+            };
+            assert_eq!(data.fix_type, Some(FixType::Gps));
+            assert_eq!(data.fix_date, None);
+
+            // The ACK in between is skipped.
+            let GpsUpdate::Fix(data) = gps.next_update().await.unwrap() else {
+                panic!("expected Fix");
+            };
+            assert_eq!(
+                data.fix_date,
+                Some(Date {
+                    year: 2024,
+                    month: 5,
+                    day: 14
+                })
+            );
+            assert!((data.latitude.unwrap() - 52.520_006_7).abs() < 1e-6);
+            assert!((data.longitude.unwrap() - 13.404_954).abs() < 1e-6);
+            assert_eq!(data.satellites_used, Some(11));
+
+            assert_eq!(gps.next_update().await, Ok(GpsUpdate::Satellites));
+            assert_eq!(gps.satellite_view().in_view(), 3);
+
+            let GpsUpdate::NavPvt(pvt) = gps.next_update().await.unwrap() else {
+                panic!("expected NavPvt");
+            };
+            assert!(pvt.has_fix());
+
+            // End of the recording.
+            assert_eq!(gps.next_update().await, Err(()));
+        });
+        assert_eq!(gps.stats().framing_errors, 0);
+        assert_eq!(gps.stats().nmea_checksum_errors, 0);
+    }
+
+    #[test]
+    fn replays_log() {
+        replay(256);
+    }
+
+    #[test]
+    fn replays_log_in_small_reads() {
+        for chunk in [1, 7, 33] {
+            replay(chunk);
+        }
+    }
+}
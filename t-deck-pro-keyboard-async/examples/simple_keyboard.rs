@@ -16,7 +16,12 @@ use esp_hal::{
 };
 use esp_println::println;
 use log::{debug, error, info, warn};
-use t_deck_pro_keyboard_async::keyboard::KeyboardController;
+use t_deck_pro_keyboard_async::keyboard::{
+    KeyboardController, RepeatConfig, TDeckKeyboardController,
+};
+use t_deck_pro_keyboard_async::keymap::Keymap;
+use t_deck_pro_keyboard_async::modifiers::StickyConfig;
+use t_deck_pro_keyboard_async::text::TextInput;
 
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
@@ -70,6 +75,8 @@ async fn main(spawner: Spawner) {
         Ok(_) => log::debug!("Keyboard controller initialized."),
         Err(_) => log::warn!("Error initializing keyboard controller."),
     };
+    keyboard_controller.set_sticky_config(StickyConfig::sticky());
+    keyboard_controller.set_repeat_config(Some(RepeatConfig::default()));
 
     spawner.spawn(read_keys(keyboard_controller)).unwrap();
 
@@ -81,13 +88,10 @@ async fn main(spawner: Spawner) {
 
 /// A task that continuously reads key events and logs them.
 #[embassy_executor::task]
-async fn read_keys(
-    mut keyboard_controller: KeyboardController<
-        'static,
-        I2c<'static, esp_hal::Async>,
-        esp_hal::i2c::master::Error,
-    >,
-) {
+async fn read_keys(mut keyboard_controller: TDeckKeyboardController<'static>) {
+    // Hungarian accents on the sym layer, as an example of a custom layout.
+    let keymap = Keymap::parse("sym a=*´ o=*¨ u=*˝").unwrap();
+    let mut text_input = TextInput::new(keymap);
     loop {
         if let Ok(Ok(keys)) = with_timeout(
             Duration::from_secs(5),
@@ -95,8 +99,11 @@ async fn read_keys(
         )
         .await
         {
-            if !keys.is_empty() {
-                info!("Key events detected {keys:?}");
+            for event in keys {
+                info!(
+                    "Key event {event:?}, text {:?}",
+                    text_input.translate(&event)
+                );
             }
         } else {
             log::debug!("No keys.");
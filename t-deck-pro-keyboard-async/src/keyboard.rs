@@ -1,26 +1,35 @@
 //! Core implementation of the TCA8418 keyboard scanner driver.
-
-use embassy_time::{Duration, Timer};
+//!
+//! `KeyboardController` works with any `embedded_hal_async` I2C bus, any INT pin
+//! implementing `Wait` and `InputPin`, and any `OutputPin` as the optional reset
+//! pin, so it can run against a mock TCA8418 on the host. With the `t-deck` feature
+//! (enabled by default), `TDeckKeyboardController` names the esp-hal types used on
+//! the T-Deck.
+
+use core::convert::Infallible;
+
+use embassy_time::{with_deadline, Duration, Instant, Timer};
+use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
+use embedded_hal_async::digital::Wait;
 use embedded_hal_async::i2c::{I2c, SevenBitAddress};
-use esp_hal::gpio::{Input, Output};
 use heapless::Vec;
 
-const I2C_ADDRESS: u8 = 0x34;
+use crate::modifiers::{Modifier, ModifierState, ModifierTracker, StickyConfig};
+
+pub(crate) const I2C_ADDRESS: u8 = 0x34;
 
 // TCA8418 Registers
 const REG_CFG: u8 = 0x01;
-const REG_INT_STAT: u8 = 0x02;
-const REG_KEY_LCK_EC: u8 = 0x03;
-const REG_KEY_EVENT_A: u8 = 0x04;
+pub(crate) const REG_INT_STAT: u8 = 0x02;
+pub(crate) const REG_KEY_LCK_EC: u8 = 0x03;
+pub(crate) const REG_KEY_EVENT_A: u8 = 0x04;
 const REG_KP_GPIO1: u8 = 0x1D;
 const REG_KP_GPIO2: u8 = 0x1E;
 const REG_KP_GPIO3: u8 = 0x1F;
 
-// Special character definitions for modifier keys.
-// These are not expected to be received by themselves.
-const ALT: char = '\u{1}';
-const L_SHIFT: char = '\u{2}';
-const R_SHIFT: char = '\u{3}';
+// INT_STAT bits
+const INT_STAT_K: u8 = 0x01;
+const INT_STAT_OVR: u8 = 0x08;
 
 /// The backspace key character.
 pub const BACKSPACE: char = '\u{8}';
@@ -33,26 +42,196 @@ pub const SPACE: char = ' ';
 /// The symbol key character.
 pub const SYM: char = '\u{5}';
 
-// Base keymap for the T-Deck keyboard.
-// '\0' is used for keys that are not physically present or should not produce output.
-const KEYMAP: [[char; 10]; 4] = [
-    ['q', 'w', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p'],
-    ['a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', BACKSPACE],
-    [ALT, 'z', 'x', 'c', 'v', 'b', 'n', 'm', '$', ENTER],
-    [
-        '\0', '\0', '0', '\0', '(', L_SHIFT, MIC, SPACE, SYM, R_SHIFT,
-    ],
-];
-
-// Keymap when the ALT modifier is active.
-const KEYMAP_ALT: [[char; 10]; 4] = [
-    ['#', '1', '2', '3', '(', ')', '_', '-', '+', '@'],
-    ['*', '4', '5', '6', '/', ':', ';', '\'', '"', BACKSPACE],
-    [ALT, '7', '8', '9', '?', '!', ',', '.', '%', ENTER],
-    [
-        '\0', '\0', '0', '\0', '(', L_SHIFT, MIC, SPACE, SYM, R_SHIFT,
-    ],
-];
+/// The number of rows of the keypad matrix.
+pub const ROWS: u8 = 4;
+/// The number of columns of the keypad matrix.
+pub const COLS: u8 = 10;
+
+/// A physical key of the T-Deck keyboard, named after its legend in the base layer.
+///
+/// The discriminant is the key's index in the matrix, `row * COLS + col`, which is
+/// also its bit in `KeyboardController::pressed_keys`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum KeyCode {
+    Q = 0,
+    W = 1,
+    E = 2,
+    R = 3,
+    T = 4,
+    Y = 5,
+    U = 6,
+    I = 7,
+    O = 8,
+    P = 9,
+    A = 10,
+    S = 11,
+    D = 12,
+    F = 13,
+    G = 14,
+    H = 15,
+    J = 16,
+    K = 17,
+    L = 18,
+    Backspace = 19,
+    Alt = 20,
+    Z = 21,
+    X = 22,
+    C = 23,
+    V = 24,
+    B = 25,
+    N = 26,
+    M = 27,
+    Dollar = 28,
+    Enter = 29,
+    Zero = 32,
+    LeftParen = 34,
+    LeftShift = 35,
+    Mic = 36,
+    Space = 37,
+    Sym = 38,
+    RightShift = 39,
+}
+
+impl KeyCode {
+    /// All keys, in matrix order.
+    pub const ALL: [KeyCode; 37] = [
+        KeyCode::Q,
+        KeyCode::W,
+        KeyCode::E,
+        KeyCode::R,
+        KeyCode::T,
+        KeyCode::Y,
+        KeyCode::U,
+        KeyCode::I,
+        KeyCode::O,
+        KeyCode::P,
+        KeyCode::A,
+        KeyCode::S,
+        KeyCode::D,
+        KeyCode::F,
+        KeyCode::G,
+        KeyCode::H,
+        KeyCode::J,
+        KeyCode::K,
+        KeyCode::L,
+        KeyCode::Backspace,
+        KeyCode::Alt,
+        KeyCode::Z,
+        KeyCode::X,
+        KeyCode::C,
+        KeyCode::V,
+        KeyCode::B,
+        KeyCode::N,
+        KeyCode::M,
+        KeyCode::Dollar,
+        KeyCode::Enter,
+        KeyCode::Zero,
+        KeyCode::LeftParen,
+        KeyCode::LeftShift,
+        KeyCode::Mic,
+        KeyCode::Space,
+        KeyCode::Sym,
+        KeyCode::RightShift,
+    ];
+
+    /// Returns the key at `row` and `col` of the matrix, or `None` if there is no
+    /// key at that position.
+    pub fn from_position(row: u8, col: u8) -> Option<Self> {
+        if row >= ROWS || col >= COLS {
+            return None;
+        }
+        Self::from_index(row * COLS + col)
+    }
+
+    /// Returns the key with the matrix index `index`, or `None` if there is no key
+    /// at that index.
+    pub fn from_index(index: u8) -> Option<Self> {
+        Self::ALL.iter().copied().find(|code| code.index() == index)
+    }
+
+    /// Returns the lower case name of the key, as used in keymap files.
+    pub const fn name(self) -> &'static str {
+        match self {
+            KeyCode::Q => "q",
+            KeyCode::W => "w",
+            KeyCode::E => "e",
+            KeyCode::R => "r",
+            KeyCode::T => "t",
+            KeyCode::Y => "y",
+            KeyCode::U => "u",
+            KeyCode::I => "i",
+            KeyCode::O => "o",
+            KeyCode::P => "p",
+            KeyCode::A => "a",
+            KeyCode::S => "s",
+            KeyCode::D => "d",
+            KeyCode::F => "f",
+            KeyCode::G => "g",
+            KeyCode::H => "h",
+            KeyCode::J => "j",
+            KeyCode::K => "k",
+            KeyCode::L => "l",
+            KeyCode::Backspace => "backspace",
+            KeyCode::Alt => "alt",
+            KeyCode::Z => "z",
+            KeyCode::X => "x",
+            KeyCode::C => "c",
+            KeyCode::V => "v",
+            KeyCode::B => "b",
+            KeyCode::N => "n",
+            KeyCode::M => "m",
+            KeyCode::Dollar => "dollar",
+            KeyCode::Enter => "enter",
+            KeyCode::Zero => "zero",
+            KeyCode::LeftParen => "leftparen",
+            KeyCode::LeftShift => "leftshift",
+            KeyCode::Mic => "mic",
+            KeyCode::Space => "space",
+            KeyCode::Sym => "sym",
+            KeyCode::RightShift => "rightshift",
+        }
+    }
+
+    /// Returns the key called `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|code| code.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the index of the key in the matrix, `row * COLS + col`.
+    pub const fn index(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the matrix row of the key.
+    pub const fn row(self) -> u8 {
+        self.index() / COLS
+    }
+
+    /// Returns the matrix column of the key.
+    pub const fn col(self) -> u8 {
+        self.index() % COLS
+    }
+
+    /// Returns the bit of the key in a `pressed_keys` bitmap.
+    pub const fn mask(self) -> u64 {
+        1 << self.index()
+    }
+
+    /// Returns the modifier bit (e.g. `MOD_ALT`) if the key is a modifier, or 0.
+    pub const fn modifier(self) -> u8 {
+        match self {
+            KeyCode::LeftShift => MOD_L_SHIFT,
+            KeyCode::RightShift => MOD_R_SHIFT,
+            KeyCode::Alt => MOD_ALT,
+            KeyCode::Sym => MOD_SYM,
+            _ => 0,
+        }
+    }
+}
 
 /// Represents the state of a key.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -61,6 +240,8 @@ pub enum KeyState {
     Down,
     /// A key was released.
     Up,
+    /// A key is still held and auto-repeat fired.
+    Repeat,
 }
 
 /// Bitmask for the left shift modifier.
@@ -69,39 +250,112 @@ pub const MOD_L_SHIFT: u8 = 0b0000_0001;
 pub const MOD_R_SHIFT: u8 = 0b0000_0010;
 /// Bitmask for the alt modifier.
 pub const MOD_ALT: u8 = 0b0000_0100;
+/// Bitmask for the symbol modifier.
+pub const MOD_SYM: u8 = 0b0000_1000;
 
-/// Represents a single, complete key event, including the character and its state.
-#[derive(Debug, Clone, Copy)]
+/// A single press or release of a physical key.
+///
+/// Use `text::translate` to turn an event into the character it types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct KeyEvent {
-    /// The character generated by the key press.
-    pub key: char,
+    /// The key that changed state.
+    pub code: KeyCode,
+    /// The matrix row of the key.
+    pub row: u8,
+    /// The matrix column of the key.
+    pub col: u8,
     /// The state of the key (pressed or released).
     pub state: KeyState,
-    /// A bitmask of the active modifiers (e.g., `MOD_L_SHIFT`).
+    /// A bitmask of the modifiers held after the event (e.g., `MOD_L_SHIFT`).
     pub modifiers: u8,
 }
 
+/// A placeholder reset pin for a `KeyboardController` without one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NoReset;
+
+impl ErrorType for NoReset {
+    type Error = Infallible;
+}
+
+impl OutputPin for NoReset {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// The `KeyboardController` wired up as on the T-Deck.
+#[cfg(feature = "t-deck")]
+pub type TDeckKeyboardController<'d> = KeyboardController<
+    esp_hal::i2c::master::I2c<'d, esp_hal::Async>,
+    esp_hal::gpio::Input<'d>,
+    esp_hal::gpio::Output<'d>,
+>;
+
+/// The timing of the software key auto-repeat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepeatConfig {
+    /// How long a key has to be held before it starts repeating.
+    pub delay: Duration,
+    /// The time between repeats.
+    pub interval: Duration,
+}
+
+impl Default for RepeatConfig {
+    /// Repeats after 500 ms, 20 times a second.
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(500),
+            interval: Duration::from_millis(50),
+        }
+    }
+}
+
+/// The key being auto-repeated.
+#[derive(Debug, Clone, Copy)]
+struct Repeating {
+    code: KeyCode,
+    /// The modifiers of the original key press.
+    modifiers: u8,
+    next: Instant,
+}
+
 /// A controller for the TCA8418 keyboard scanner.
-pub struct KeyboardController<
-    'a,
-    I2cType: I2c<SevenBitAddress, Error = ErrorType>,
-    ErrorType: embedded_hal_async::i2c::Error,
-> {
+pub struct KeyboardController<I2cType, IntType, RstType = NoReset> {
     i2c: I2cType,
-    int: Input<'a>,
-    rst: Option<Output<'a>>,
-    l_shift_pressed: bool,
-    r_shift_pressed: bool,
-    alt_pressed: bool,
-    first_key_event: Option<KeyEvent>,
-    keys_down_count: u8,
+    int: IntType,
+    rst: Option<RstType>,
+    pressed: u64,
+    sticky: ModifierTracker,
+    repeat: Option<RepeatConfig>,
+    repeating: Option<Repeating>,
+}
+
+impl<I2cType, IntType> KeyboardController<I2cType, IntType, NoReset>
+where
+    I2cType: I2c<SevenBitAddress>,
+    IntType: Wait + InputPin,
+{
+    /// Creates a new `KeyboardController` without a reset pin.
+    ///
+    /// # Arguments
+    ///
+    /// * `i2c` - An I2C peripheral that implements `embedded-hal-async::i2c::I2c`.
+    /// * `int` - The interrupt input pin from the keyboard controller.
+    pub fn without_reset(i2c: I2cType, int: IntType) -> Self {
+        Self::new(i2c, int, None)
+    }
 }
 
-impl<
-        'a,
-        I2cType: I2c<SevenBitAddress, Error = ErrorType>,
-        ErrorType: embedded_hal_async::i2c::Error,
-    > KeyboardController<'a, I2cType, ErrorType>
+impl<I2cType, IntType, RstType> KeyboardController<I2cType, IntType, RstType>
+where
+    I2cType: I2c<SevenBitAddress>,
+    IntType: Wait + InputPin,
+    RstType: OutputPin,
 {
     /// Creates a new `KeyboardController`.
     ///
@@ -110,22 +364,77 @@ impl<
     /// * `i2c` - An I2C peripheral that implements `embedded-hal-async::i2c::I2c`.
     /// * `int` - The interrupt input pin from the keyboard controller.
     /// * `rst` - An optional output pin for resetting the controller.
-    pub fn new(i2c: I2cType, int: Input<'a>, rst: Option<Output<'a>>) -> Self {
+    pub fn new(i2c: I2cType, int: IntType, rst: Option<RstType>) -> Self {
         Self {
             i2c,
             int,
             rst,
-            l_shift_pressed: false,
-            r_shift_pressed: false,
-            alt_pressed: false,
-            first_key_event: None,
-            keys_down_count: 0,
+            pressed: 0,
+            sticky: ModifierTracker::new(StickyConfig::default()),
+            repeat: None,
+            repeating: None,
         }
     }
 
-    /// Checks if a key is currently pressed by reading the interrupt pin state.
-    pub fn is_key_pressed(&self) -> bool {
-        self.int.is_low()
+    /// Returns a bitmap of the keys currently held down, with the bit of each key
+    /// given by `KeyCode::mask`.
+    pub fn pressed_keys(&self) -> u64 {
+        self.pressed
+    }
+
+    /// Returns `true` if `code` is currently held down.
+    pub fn is_down(&self, code: KeyCode) -> bool {
+        self.pressed & code.mask() != 0
+    }
+
+    /// Returns the bitmask of the modifiers currently in effect, whether held down,
+    /// one-shot or locked.
+    pub fn modifiers(&self) -> u8 {
+        KeyCode::ALL
+            .iter()
+            .filter(|code| self.is_down(**code))
+            .fold(self.sticky.latched(), |acc, code| acc | code.modifier())
+    }
+
+    /// Returns the state of `modifier`, e.g. to show a caps lock indicator.
+    pub fn modifier_state(&self, modifier: Modifier) -> ModifierState {
+        self.sticky.state(modifier)
+    }
+
+    /// Returns the one-shot and lock configuration of the modifiers.
+    pub fn sticky_config(&self) -> &StickyConfig {
+        self.sticky.config()
+    }
+
+    /// Configures which modifiers latch when tapped and lock when double tapped.
+    ///
+    /// By default, all modifiers only apply while held.
+    pub fn set_sticky_config(&mut self, config: StickyConfig) {
+        self.sticky.set_config(config);
+    }
+
+    /// Returns the auto-repeat timing, or `None` if auto-repeat is disabled.
+    pub fn repeat_config(&self) -> Option<RepeatConfig> {
+        self.repeat
+    }
+
+    /// Sets the auto-repeat timing, or disables auto-repeat with `None`.
+    ///
+    /// Auto-repeat is off by default, enable it with e.g.
+    /// `Some(RepeatConfig::default())`.
+    pub fn set_repeat_config(&mut self, config: Option<RepeatConfig>) {
+        self.repeat = config;
+        self.repeating = None;
+    }
+
+    /// Releases all one-shot and locked modifiers.
+    pub fn clear_modifiers(&mut self) {
+        self.sticky.clear();
+    }
+
+    /// Checks if an interrupt is pending by reading the interrupt pin state.
+    pub fn is_key_pressed(&mut self) -> bool {
+        self.int.is_low().unwrap_or(false)
     }
 
     /// Initializes the keyboard controller.
@@ -134,9 +443,13 @@ impl<
     /// enables interrupts, and clears the initial interrupt status.
     pub async fn init(&mut self) -> Result<(), ()> {
         if let Some(rst) = &mut self.rst {
-            rst.set_low();
+            if rst.set_low().is_err() {
+                log::warn!("Failed to clear the keyboard reset pin");
+            }
             Timer::after(Duration::from_millis(10)).await;
-            rst.set_high();
+            if rst.set_high().is_err() {
+                log::warn!("Failed to set the keyboard reset pin");
+            }
             Timer::after(Duration::from_millis(100)).await;
         }
 
@@ -157,111 +470,166 @@ impl<
 
     /// Reads and processes key events from the controller's FIFO buffer.
     ///
-    /// This function waits for an interrupt, reads the event buffer from the
-    /// TCA8418, and translates the raw key codes into `KeyEvent`s. It handles
-    /// modifier keys (Shift, Alt) and returns a vector of events.
+    /// This function waits for an interrupt, drains the event FIFO of the
+    /// TCA8418, and returns a `KeyEvent` for every key press and release, in the
+    /// order they happened. The pressed keys and modifiers are tracked across calls.
+    /// At most 10 events are returned; any further events stay in the FIFO and are
+    /// returned by the next call.
+    ///
+    /// With auto-repeat enabled by `set_repeat_config`, while a key other than a
+    /// modifier is held, a `KeyState::Repeat` event for it is returned after the
+    /// auto-repeat delay and then at the repeat interval, until it is released or
+    /// another key is pressed.
     pub async fn read_key_events(&mut self) -> Result<Vec<KeyEvent, 10>, ()> {
-        self.int.wait_for_low().await;
-
         let mut events = Vec::new();
+        let interrupt = match self.repeating {
+            Some(repeating) => with_deadline(repeating.next, self.int.wait_for_low())
+                .await
+                .map_err(|_| repeating),
+            None => Ok(self.int.wait_for_low().await),
+        };
+        match interrupt {
+            Ok(result) => result
+                .map_err(|err| log::warn!("Error waiting for the keyboard interrupt: {err:?}"))?,
+            Err(repeating) => {
+                events.push(self.repeat_event(repeating)).ok();
+                return Ok(events);
+            }
+        }
+
         let mut int_stat = [0u8];
         self.i2c
             .write_read(I2C_ADDRESS, &[REG_INT_STAT], &mut int_stat)
             .await
             .map_err(|_| ())?;
 
-        // Check if it's a Key Event Interrupt
-        if (int_stat[0] & 0x01) != 0 {
-            let mut key_lck_ec = [0u8];
-            self.i2c
-                .write_read(I2C_ADDRESS, &[REG_KEY_LCK_EC], &mut key_lck_ec)
-                .await
-                .map_err(|_| ())?;
-
-            let event_count = key_lck_ec[0] & 0x0F;
+        if (int_stat[0] & INT_STAT_OVR) != 0 {
+            // Releases may have been lost, start over from a clean state.
+            log::warn!("Keyboard event FIFO overflowed");
+            self.pressed = 0;
+            self.sticky.release_all();
+            self.repeating = None;
+        }
 
-            for _ in 0..event_count {
-                let mut key_event_buf = [0u8];
+        // Check if it's a Key Event Interrupt
+        let mut drained = true;
+        if (int_stat[0] & INT_STAT_K) != 0 {
+            // Keys pressed while the FIFO is read are queued behind the counted
+            // events, so read until the count drops to zero. Once `events` is full
+            // the rest is left in the FIFO for the next call rather than decoded and
+            // dropped, which would lose releases.
+            'fifo: loop {
+                let mut key_lck_ec = [0u8];
                 self.i2c
-                    .write_read(I2C_ADDRESS, &[REG_KEY_EVENT_A], &mut key_event_buf)
+                    .write_read(I2C_ADDRESS, &[REG_KEY_LCK_EC], &mut key_lck_ec)
                     .await
                     .map_err(|_| ())?;
 
-                let key_event = key_event_buf[0];
-                if key_event != 0 {
-                    let state = if key_event & 0x80 != 0 {
-                        KeyState::Down
-                    } else {
-                        KeyState::Up
-                    };
-                    let key_code = (key_event & 0x7F) - 1;
-                    let row = (key_code / 10) as usize;
-                    let col = 9 - (key_code % 10) as usize;
-
-                    if row < 4 && col < 10 {
-                        let mut key = if self.alt_pressed {
-                            KEYMAP_ALT[row][col]
-                        } else {
-                            KEYMAP[row][col]
-                        };
-
-                        match state {
-                            KeyState::Down => self.keys_down_count += 1,
-                            KeyState::Up => {
-                                self.keys_down_count = self.keys_down_count.saturating_sub(1)
-                            }
-                        }
-
-                        match key {
-                            L_SHIFT => self.l_shift_pressed = state == KeyState::Down,
-                            R_SHIFT => self.r_shift_pressed = state == KeyState::Down,
-                            ALT => self.alt_pressed = state == KeyState::Down,
-                            _ => {
-                                if key != '\0'
-                                    && state == KeyState::Down
-                                    && self.first_key_event.is_none()
-                                {
-                                    let shift_pressed =
-                                        self.l_shift_pressed || self.r_shift_pressed;
-                                    if shift_pressed && key.is_ascii_alphabetic() {
-                                        key = key.to_ascii_uppercase();
-                                    }
-
-                                    let mut modifiers = 0;
-                                    if self.l_shift_pressed {
-                                        modifiers |= MOD_L_SHIFT;
-                                    }
-                                    if self.r_shift_pressed {
-                                        modifiers |= MOD_R_SHIFT;
-                                    }
-                                    if self.alt_pressed {
-                                        modifiers |= MOD_ALT;
-                                    }
-
-                                    self.first_key_event = Some(KeyEvent {
-                                        key,
-                                        state: KeyState::Down,
-                                        modifiers,
-                                    });
-                                }
-                            }
-                        }
+                let event_count = key_lck_ec[0] & 0x0F;
+                if event_count == 0 {
+                    break;
+                }
+
+                for _ in 0..event_count {
+                    if events.is_full() {
+                        drained = false;
+                        break 'fifo;
+                    }
+                    let mut key_event_buf = [0u8];
+                    self.i2c
+                        .write_read(I2C_ADDRESS, &[REG_KEY_EVENT_A], &mut key_event_buf)
+                        .await
+                        .map_err(|_| ())?;
+
+                    if let Some(event) = self.decode_event(key_event_buf[0]) {
+                        // Cannot fail: checked above.
+                        let _ = events.push(event);
                     }
                 }
             }
+        }
 
-            // Clear the interrupt
-            self.write_reg(REG_INT_STAT, 0xFF).await?;
+        // Clear the interrupt. With events left in the FIFO the key event interrupt
+        // stays asserted, so the next call returns right away.
+        let clear = if drained { 0xFF } else { !INT_STAT_K };
+        if int_stat[0] & clear != 0 {
+            self.write_reg(REG_INT_STAT, clear).await?;
         }
 
-        if self.keys_down_count == 0 {
-            if let Some(mut event) = self.first_key_event.take() {
-                event.state = KeyState::Up;
-                events.push(event).unwrap();
+        Ok(events)
+    }
+
+    /// Turns a raw KEY_EVENT register value into a `KeyEvent` and updates the
+    /// pressed keys.
+    fn decode_event(&mut self, key_event: u8) -> Option<KeyEvent> {
+        let state = if key_event & 0x80 != 0 {
+            KeyState::Down
+        } else {
+            KeyState::Up
+        };
+        // Key codes count from 1, with the columns wired in reverse.
+        let key_code = (key_event & 0x7F).checked_sub(1)?;
+        let row = key_code / COLS;
+        let col = COLS - 1 - key_code % COLS;
+        let code = KeyCode::from_position(row, col)?;
+
+        match state {
+            KeyState::Down => self.pressed |= code.mask(),
+            KeyState::Up => self.pressed &= !code.mask(),
+            KeyState::Repeat => {}
+        }
+        self.sticky.key_event(code, state, Instant::now());
+
+        let event = KeyEvent {
+            code,
+            row,
+            col,
+            state,
+            modifiers: self.modifiers(),
+        };
+        self.sticky.consume(code, state);
+        self.update_repeat(&event);
+        Some(event)
+    }
+
+    /// Starts auto-repeat for a pressed key, or cancels it on a release or when a
+    /// modifier is pressed.
+    fn update_repeat(&mut self, event: &KeyEvent) {
+        match event.state {
+            KeyState::Down => {
+                self.repeating = match self.repeat {
+                    Some(config) if event.code.modifier() == 0 => Some(Repeating {
+                        code: event.code,
+                        modifiers: event.modifiers,
+                        next: Instant::now() + config.delay,
+                    }),
+                    _ => None,
+                };
+            }
+            KeyState::Up => {
+                if self.repeating.is_some_and(|r| r.code == event.code) {
+                    self.repeating = None;
+                }
             }
+            KeyState::Repeat => {}
         }
+    }
 
-        Ok(events)
+    /// Returns the event for the key being auto-repeated and schedules the next one.
+    fn repeat_event(&mut self, repeating: Repeating) -> KeyEvent {
+        let interval = self.repeat.unwrap_or_default().interval;
+        self.repeating = Some(Repeating {
+            // Skip missed repeats rather than sending a burst if the caller is slow.
+            next: (repeating.next + interval).max(Instant::now()),
+            ..repeating
+        });
+        KeyEvent {
+            code: repeating.code,
+            row: repeating.code.row(),
+            col: repeating.code.col(),
+            state: KeyState::Repeat,
+            modifiers: repeating.modifiers,
+        }
     }
 
     /// Writes a value to a specific register on the TCA8418.
@@ -274,3 +642,264 @@ impl<
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock::{raw_event, MockInt, MockReset, MockTca8418};
+
+    type MockController = KeyboardController<MockTca8418, MockInt, MockReset>;
+
+    fn controller(tca8418: MockTca8418, pulses: usize) -> MockController {
+        let int = MockInt {
+            pulses,
+            ..MockInt::default()
+        };
+        KeyboardController::new(tca8418, int, None)
+    }
+
+    fn codes(events: &[KeyEvent]) -> Vec<(KeyCode, KeyState)> {
+        events.iter().map(|e| (e.code, e.state)).collect()
+    }
+
+    #[test]
+    fn init_resets_and_configures_the_matrix() {
+        let mut keyboard = KeyboardController::new(
+            MockTca8418::default(),
+            MockInt::default(),
+            Some(MockReset::default()),
+        );
+        block_on(keyboard.init()).unwrap();
+        assert_eq!(keyboard.rst.unwrap().levels, [false, true]);
+        assert_eq!(
+            keyboard.i2c.writes,
+            [
+                (REG_KP_GPIO1, 0x0F),
+                (REG_KP_GPIO2, 0xFF),
+                (REG_KP_GPIO3, 0x03),
+                (REG_CFG, 0x09),
+                (REG_INT_STAT, 0xFF),
+            ]
+        );
+    }
+
+    #[test]
+    fn drains_the_fifo() {
+        let mut tca8418 =
+            MockTca8418::with_events(&[raw_event(KeyCode::A, true), raw_event(KeyCode::A, false)]);
+        // Pressed while the first two events are read.
+        tca8418.late = std::vec![raw_event(KeyCode::S, true)];
+        let mut keyboard = controller(tca8418, 1);
+
+        let events = block_on(keyboard.read_key_events()).unwrap();
+        assert_eq!(
+            codes(&events),
+            [
+                (KeyCode::A, KeyState::Down),
+                (KeyCode::A, KeyState::Up),
+                (KeyCode::S, KeyState::Down),
+            ]
+        );
+        assert!(keyboard.i2c.fifo.is_empty());
+        assert_eq!(keyboard.i2c.int_stat, 0);
+        assert_eq!(keyboard.pressed_keys(), KeyCode::S.mask());
+    }
+
+    #[test]
+    fn leaves_events_beyond_capacity_in_the_fifo() {
+        let keys = [
+            KeyCode::Q,
+            KeyCode::W,
+            KeyCode::E,
+            KeyCode::R,
+            KeyCode::T,
+            KeyCode::Y,
+        ];
+        let presses: Vec<u8> = keys.iter().map(|&code| raw_event(code, true)).collect();
+        let releases: Vec<u8> = keys.iter().map(|&code| raw_event(code, false)).collect();
+        // A full FIFO of 10 events, and two more arriving while it is read.
+        let mut tca8418 = MockTca8418::with_events(&[&presses[..], &releases[..4]].concat());
+        tca8418.late = releases[4..].to_vec();
+        let mut keyboard = controller(tca8418, 2);
+
+        let events = block_on(keyboard.read_key_events()).unwrap();
+        assert_eq!(events.len(), 10);
+        assert_eq!(
+            keyboard.pressed_keys(),
+            KeyCode::T.mask() | KeyCode::Y.mask()
+        );
+        // The key event interrupt stays asserted for the rest.
+        assert_eq!(keyboard.i2c.int_stat, INT_STAT_K);
+        assert_eq!(keyboard.i2c.fifo.len(), 2);
+
+        let events = block_on(keyboard.read_key_events()).unwrap();
+        assert_eq!(
+            codes(&events),
+            [(KeyCode::T, KeyState::Up), (KeyCode::Y, KeyState::Up)]
+        );
+        assert_eq!(keyboard.pressed_keys(), 0);
+        assert_eq!(keyboard.i2c.int_stat, 0);
+    }
+
+    #[test]
+    fn event_modifiers() {
+        let tca8418 = MockTca8418::with_events(&[
+            raw_event(KeyCode::LeftShift, true),
+            raw_event(KeyCode::A, true),
+            raw_event(KeyCode::A, false),
+            raw_event(KeyCode::LeftShift, false),
+            raw_event(KeyCode::Sym, true),
+            raw_event(KeyCode::Alt, true),
+            raw_event(KeyCode::B, true),
+            raw_event(KeyCode::Sym, false),
+            raw_event(KeyCode::RightShift, true),
+            raw_event(KeyCode::C, true),
+        ]);
+        let mut keyboard = controller(tca8418, 1);
+
+        let events = block_on(keyboard.read_key_events()).unwrap();
+        let modifiers: Vec<_> = events.iter().map(|e| (e.code, e.modifiers)).collect();
+        assert_eq!(
+            modifiers,
+            [
+                (KeyCode::LeftShift, MOD_L_SHIFT),
+                (KeyCode::A, MOD_L_SHIFT),
+                (KeyCode::A, MOD_L_SHIFT),
+                (KeyCode::LeftShift, 0),
+                (KeyCode::Sym, MOD_SYM),
+                (KeyCode::Alt, MOD_SYM | MOD_ALT),
+                (KeyCode::B, MOD_SYM | MOD_ALT),
+                (KeyCode::Sym, MOD_ALT),
+                (KeyCode::RightShift, MOD_ALT | MOD_R_SHIFT),
+                (KeyCode::C, MOD_ALT | MOD_R_SHIFT),
+            ]
+        );
+        assert_eq!(keyboard.modifiers(), MOD_ALT | MOD_R_SHIFT);
+    }
+
+    #[test]
+    fn key_code_positions() {
+        for code in KeyCode::ALL {
+            assert_eq!(code.index(), code.row() * COLS + code.col());
+            assert_eq!(KeyCode::from_position(code.row(), code.col()), Some(code));
+            assert_eq!(KeyCode::from_index(code.index()), Some(code));
+
+            let mut keyboard = controller(MockTca8418::default(), 0);
+            let event = keyboard.decode_event(raw_event(code, true)).unwrap();
+            assert_eq!(
+                (event.code, event.row, event.col),
+                (code, code.row(), code.col())
+            );
+        }
+        // The TCA8418 numbers the keys from 1 with the columns reversed.
+        assert_eq!(raw_event(KeyCode::Q, true), 0x80 | 10);
+        assert_eq!(raw_event(KeyCode::P, false), 1);
+        assert_eq!(raw_event(KeyCode::RightShift, true), 0x80 | 31);
+
+        assert_eq!(KeyCode::from_position(0, 9), Some(KeyCode::P));
+        assert_eq!(KeyCode::from_position(3, 1), None);
+        assert_eq!(KeyCode::from_position(4, 0), None);
+        assert_eq!(KeyCode::from_position(0, 10), None);
+        assert_eq!(KeyCode::from_name("LeftShift"), Some(KeyCode::LeftShift));
+        assert_eq!(KeyCode::from_name("shift"), None);
+    }
+
+    #[test]
+    fn ignores_empty_and_unknown_events() {
+        let mut keyboard = controller(MockTca8418::default(), 0);
+        // 0 is an empty FIFO, 0x80 a press of the non-existent key 0.
+        assert_eq!(keyboard.decode_event(0), None);
+        assert_eq!(keyboard.decode_event(0x80), None);
+        // Past the 4x10 matrix, and a matrix position without a key.
+        assert_eq!(keyboard.decode_event(0x80 | 41), None);
+        assert_eq!(keyboard.decode_event(0x80 | 39), None);
+        assert_eq!(keyboard.pressed_keys(), 0);
+
+        let tca8418 = MockTca8418::with_events(&[0x80, raw_event(KeyCode::Q, true), 0]);
+        let mut keyboard = controller(tca8418, 1);
+        let events = block_on(keyboard.read_key_events()).unwrap();
+        assert_eq!(codes(&events), [(KeyCode::Q, KeyState::Down)]);
+    }
+
+    #[test]
+    fn no_auto_repeat_by_default() {
+        let tca8418 = MockTca8418::with_events(&[raw_event(KeyCode::A, true)]);
+        let mut keyboard = controller(tca8418, 1);
+        assert_eq!(keyboard.repeat_config(), None);
+
+        block_on(keyboard.read_key_events()).unwrap();
+        // Holding the key only waits for the next interrupt.
+        assert_eq!(block_on(keyboard.read_key_events()), Err(()));
+    }
+
+    #[test]
+    fn auto_repeat_when_enabled() {
+        let tca8418 = MockTca8418::with_events(&[
+            raw_event(KeyCode::LeftShift, true),
+            raw_event(KeyCode::A, true),
+        ]);
+        let int = MockInt {
+            pulses: 1,
+            idle: true,
+        };
+        let mut keyboard = KeyboardController::new(tca8418, int, None::<MockReset>);
+        keyboard.set_repeat_config(Some(RepeatConfig {
+            delay: Duration::from_millis(20),
+            interval: Duration::from_millis(10),
+        }));
+
+        let events = block_on(keyboard.read_key_events()).unwrap();
+        assert_eq!(events.len(), 2);
+        for _ in 0..2 {
+            let events = block_on(keyboard.read_key_events()).unwrap();
+            assert_eq!(codes(&events), [(KeyCode::A, KeyState::Repeat)]);
+            assert_eq!(events[0].modifiers, MOD_L_SHIFT);
+        }
+    }
+
+    #[test]
+    fn reports_latched_modifiers() {
+        let tca8418 = MockTca8418::with_events(&[
+            raw_event(KeyCode::LeftShift, true),
+            raw_event(KeyCode::LeftShift, false),
+            raw_event(KeyCode::Sym, true),
+            raw_event(KeyCode::Sym, false),
+            raw_event(KeyCode::Sym, true),
+            raw_event(KeyCode::Sym, false),
+        ]);
+        let mut keyboard = controller(tca8418, 2);
+        keyboard.set_sticky_config(StickyConfig::sticky());
+
+        block_on(keyboard.read_key_events()).unwrap();
+        assert_eq!(
+            keyboard.modifier_state(Modifier::Shift),
+            ModifierState::OneShot
+        );
+        assert_eq!(
+            keyboard.modifier_state(Modifier::Sym),
+            ModifierState::Locked
+        );
+        assert_eq!(keyboard.modifier_state(Modifier::Alt), ModifierState::Off);
+        assert_eq!(keyboard.modifiers(), MOD_L_SHIFT | MOD_SYM);
+
+        keyboard.i2c.int_stat = INT_STAT_K;
+        keyboard.i2c.fifo.push_back(raw_event(KeyCode::A, true));
+        let events = block_on(keyboard.read_key_events()).unwrap();
+        assert_eq!(events[0].modifiers, MOD_L_SHIFT | MOD_SYM);
+        assert_eq!(keyboard.modifier_state(Modifier::Shift), ModifierState::Off);
+        assert_eq!(
+            keyboard.modifier_state(Modifier::Sym),
+            ModifierState::Locked
+        );
+
+        keyboard.clear_modifiers();
+        assert_eq!(keyboard.modifier_state(Modifier::Sym), ModifierState::Off);
+        assert_eq!(keyboard.modifiers(), 0);
+    }
+}
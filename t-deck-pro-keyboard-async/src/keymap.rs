@@ -0,0 +1,567 @@
+//! Keymaps with layers, per-key overrides and dead keys.
+//!
+//! A `Keymap` assigns a `KeyAction` to every key in each of four `Layer`s. The held
+//! modifiers select the layer: ALT the alt layer, SYM the sym layer and either shift
+//! key the shift layer. `Keymap::US` is the layout printed on the keyboard.
+//!
+//! Layouts can be loaded at runtime from a compact text format that lists the keys
+//! differing from `Keymap::US`, one layer per line:
+//!
+//! ```text
+//! # German
+//! base y=z z=y
+//! shift y=Z z=Y
+//! sym a=ä o=ö u=ü s=ß e=€ dollar=*¨
+//! ```
+//!
+//! Keys are named as by `KeyCode::name`. A value is a single character, `*` followed
+//! by a spacing accent (e.g. `*´`) for a dead key, or nothing to unmap the key.
+//! `\s`, `\n`, `\b`, `\*`, `\\` and `\u{..}` escape characters that cannot be
+//! written directly. Lines starting with `#` are comments.
+
+use core::fmt::{self, Write};
+
+use crate::keyboard::{
+    KeyCode, BACKSPACE, COLS, ENTER, MIC, MOD_ALT, MOD_L_SHIFT, MOD_R_SHIFT, MOD_SYM, ROWS, SPACE,
+};
+
+/// The number of entries in a layer, one per matrix position.
+const KEYS: usize = (ROWS * COLS) as usize;
+
+// Keymaps printed on the T-Deck keyboard.
+// '\0' is used for keys that are not physically present or should not produce output.
+const BASE: [[char; 10]; 4] = [
+    ['q', 'w', 'e', 'r', 't', 'y', 'u', 'i', 'o', 'p'],
+    ['a', 's', 'd', 'f', 'g', 'h', 'j', 'k', 'l', BACKSPACE],
+    ['\0', 'z', 'x', 'c', 'v', 'b', 'n', 'm', '$', ENTER],
+    ['\0', '\0', '0', '\0', '(', '\0', MIC, SPACE, '\0', '\0'],
+];
+
+const SHIFT: [[char; 10]; 4] = [
+    ['Q', 'W', 'E', 'R', 'T', 'Y', 'U', 'I', 'O', 'P'],
+    ['A', 'S', 'D', 'F', 'G', 'H', 'J', 'K', 'L', BACKSPACE],
+    ['\0', 'Z', 'X', 'C', 'V', 'B', 'N', 'M', '$', ENTER],
+    ['\0', '\0', '0', '\0', '(', '\0', MIC, SPACE, '\0', '\0'],
+];
+
+const ALT: [[char; 10]; 4] = [
+    ['#', '1', '2', '3', '(', ')', '_', '-', '+', '@'],
+    ['*', '4', '5', '6', '/', ':', ';', '\'', '"', BACKSPACE],
+    ['\0', '7', '8', '9', '?', '!', ',', '.', '%', ENTER],
+    ['\0', '\0', '0', '\0', '(', '\0', MIC, SPACE, '\0', '\0'],
+];
+
+const SYM: [[char; 10]; 4] = [
+    ['~', '`', '|', '\\', '{', '}', '[', ']', '<', '>'],
+    ['=', '&', '^', '€', '£', '¥', '§', '°', '¬', BACKSPACE],
+    ['\0', '«', '»', '¡', '¿', '©', '®', 'µ', '¤', ENTER],
+    ['\0', '\0', '0', '\0', '(', '\0', MIC, SPACE, '\0', '\0'],
+];
+
+/// A layer of a `Keymap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    /// No modifier held.
+    Base,
+    /// Either shift key held.
+    Shift,
+    /// ALT held.
+    Alt,
+    /// SYM held.
+    Sym,
+}
+
+impl Layer {
+    /// All layers, in the order they are stored.
+    pub const ALL: [Layer; 4] = [Layer::Base, Layer::Shift, Layer::Alt, Layer::Sym];
+
+    /// Returns the layer selected by a bitmask of modifiers. ALT takes precedence
+    /// over SYM, which takes precedence over shift.
+    pub fn from_modifiers(modifiers: u8) -> Self {
+        if modifiers & MOD_ALT != 0 {
+            Layer::Alt
+        } else if modifiers & MOD_SYM != 0 {
+            Layer::Sym
+        } else if modifiers & (MOD_L_SHIFT | MOD_R_SHIFT) != 0 {
+            Layer::Shift
+        } else {
+            Layer::Base
+        }
+    }
+
+    /// Returns the name of the layer, as used in keymap files.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Layer::Base => "base",
+            Layer::Shift => "shift",
+            Layer::Alt => "alt",
+            Layer::Sym => "sym",
+        }
+    }
+
+    /// Returns the layer called `name`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|layer| layer.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// The accent of a dead key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Accent {
+    /// ´, as in á.
+    Acute,
+    /// `, as in à.
+    Grave,
+    /// ^, as in â.
+    Circumflex,
+    /// ¨, as in ä.
+    Diaeresis,
+    /// ˝, as in ő.
+    DoubleAcute,
+    /// ~, as in ñ.
+    Tilde,
+    /// ¸, as in ç.
+    Cedilla,
+    /// ˇ, as in č.
+    Caron,
+    /// ˚, as in å.
+    Ring,
+}
+
+impl Accent {
+    /// All accents.
+    pub const ALL: [Accent; 9] = [
+        Accent::Acute,
+        Accent::Grave,
+        Accent::Circumflex,
+        Accent::Diaeresis,
+        Accent::DoubleAcute,
+        Accent::Tilde,
+        Accent::Cedilla,
+        Accent::Caron,
+        Accent::Ring,
+    ];
+
+    /// Returns the accent on its own, as typed by the dead key followed by space.
+    pub const fn spacing(self) -> char {
+        match self {
+            Accent::Acute => '´',
+            Accent::Grave => '`',
+            Accent::Circumflex => '^',
+            Accent::Diaeresis => '¨',
+            Accent::DoubleAcute => '˝',
+            Accent::Tilde => '~',
+            Accent::Cedilla => '¸',
+            Accent::Caron => 'ˇ',
+            Accent::Ring => '˚',
+        }
+    }
+
+    /// Returns the accent whose spacing form is `c`.
+    pub fn from_spacing(c: char) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|accent| accent.spacing() == c)
+    }
+
+    /// Returns `base` with the accent, or `None` if there is no such character.
+    pub fn compose(self, base: char) -> Option<char> {
+        let upper = base.is_uppercase();
+        let composed = match (self, base.to_lowercase().next()?) {
+            (Accent::Acute, 'a') => 'á',
+            (Accent::Acute, 'c') => 'ć',
+            (Accent::Acute, 'e') => 'é',
+            (Accent::Acute, 'i') => 'í',
+            (Accent::Acute, 'l') => 'ĺ',
+            (Accent::Acute, 'n') => 'ń',
+            (Accent::Acute, 'o') => 'ó',
+            (Accent::Acute, 'r') => 'ŕ',
+            (Accent::Acute, 's') => 'ś',
+            (Accent::Acute, 'u') => 'ú',
+            (Accent::Acute, 'y') => 'ý',
+            (Accent::Acute, 'z') => 'ź',
+            (Accent::Grave, 'a') => 'à',
+            (Accent::Grave, 'e') => 'è',
+            (Accent::Grave, 'i') => 'ì',
+            (Accent::Grave, 'o') => 'ò',
+            (Accent::Grave, 'u') => 'ù',
+            (Accent::Circumflex, 'a') => 'â',
+            (Accent::Circumflex, 'c') => 'ĉ',
+            (Accent::Circumflex, 'e') => 'ê',
+            (Accent::Circumflex, 'g') => 'ĝ',
+            (Accent::Circumflex, 'h') => 'ĥ',
+            (Accent::Circumflex, 'i') => 'î',
+            (Accent::Circumflex, 'j') => 'ĵ',
+            (Accent::Circumflex, 'o') => 'ô',
+            (Accent::Circumflex, 's') => 'ŝ',
+            (Accent::Circumflex, 'u') => 'û',
+            (Accent::Circumflex, 'w') => 'ŵ',
+            (Accent::Circumflex, 'y') => 'ŷ',
+            (Accent::Diaeresis, 'a') => 'ä',
+            (Accent::Diaeresis, 'e') => 'ë',
+            (Accent::Diaeresis, 'i') => 'ï',
+            (Accent::Diaeresis, 'o') => 'ö',
+            (Accent::Diaeresis, 'u') => 'ü',
+            (Accent::Diaeresis, 'y') => 'ÿ',
+            (Accent::DoubleAcute, 'o') => 'ő',
+            (Accent::DoubleAcute, 'u') => 'ű',
+            (Accent::Tilde, 'a') => 'ã',
+            (Accent::Tilde, 'n') => 'ñ',
+            (Accent::Tilde, 'o') => 'õ',
+            (Accent::Cedilla, 'c') => 'ç',
+            (Accent::Cedilla, 's') => 'ş',
+            (Accent::Cedilla, 't') => 'ţ',
+            (Accent::Caron, 'c') => 'č',
+            (Accent::Caron, 'd') => 'ď',
+            (Accent::Caron, 'e') => 'ě',
+            (Accent::Caron, 'n') => 'ň',
+            (Accent::Caron, 'r') => 'ř',
+            (Accent::Caron, 's') => 'š',
+            (Accent::Caron, 't') => 'ť',
+            (Accent::Caron, 'z') => 'ž',
+            (Accent::Ring, 'a') => 'å',
+            (Accent::Ring, 'u') => 'ů',
+            _ => return None,
+        };
+        if upper {
+            composed.to_uppercase().next()
+        } else {
+            Some(composed)
+        }
+    }
+}
+
+/// What a key does in a layer of a `Keymap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyAction {
+    /// The key types nothing.
+    #[default]
+    None,
+    /// The key types a character.
+    Char(char),
+    /// The key adds an accent to the next character typed.
+    Dead(Accent),
+}
+
+/// An error in a keymap file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeymapError {
+    /// The line does not start with a layer name.
+    UnknownLayer {
+        /// The 1-based line number.
+        line: usize,
+    },
+    /// An entry names a key that does not exist.
+    UnknownKey {
+        /// The 1-based line number.
+        line: usize,
+    },
+    /// An entry is not of the form `key=value`.
+    InvalidEntry {
+        /// The 1-based line number.
+        line: usize,
+    },
+    /// The value of an entry is not a single character, dead key or empty.
+    InvalidValue {
+        /// The 1-based line number.
+        line: usize,
+    },
+}
+
+/// Assigns a `KeyAction` to every key in each `Layer`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    layers: [[KeyAction; KEYS]; 4],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::US
+    }
+}
+
+impl Keymap {
+    /// The layout printed on the T-Deck keyboard, with a sym layer of extra symbols.
+    pub const US: Keymap = Keymap {
+        layers: [
+            Self::layer(&BASE),
+            Self::layer(&SHIFT),
+            Self::layer(&ALT),
+            Self::layer(&SYM),
+        ],
+    };
+
+    /// Builds a layer from a table of characters.
+    const fn layer(map: &[[char; COLS as usize]; ROWS as usize]) -> [KeyAction; KEYS] {
+        let mut layer = [KeyAction::None; KEYS];
+        let mut i = 0;
+        while i < KEYS {
+            let c = map[i / COLS as usize][i % COLS as usize];
+            if c != '\0' {
+                layer[i] = KeyAction::Char(c);
+            }
+            i += 1;
+        }
+        layer
+    }
+
+    /// Parses a keymap file, applying it on top of `Keymap::US`.
+    pub fn parse(layout: &str) -> Result<Self, KeymapError> {
+        let mut keymap = Self::US;
+        keymap.apply(layout)?;
+        Ok(keymap)
+    }
+
+    /// Returns the action of `code` in `layer`.
+    pub fn get(&self, layer: Layer, code: KeyCode) -> KeyAction {
+        self.layers[layer as usize][code.index() as usize]
+    }
+
+    /// Overrides the action of `code` in `layer`.
+    pub fn set(&mut self, layer: Layer, code: KeyCode, action: KeyAction) -> &mut Self {
+        self.layers[layer as usize][code.index() as usize] = action;
+        self
+    }
+
+    /// Returns the action of `code` in the layer selected by `modifiers`.
+    pub fn action(&self, code: KeyCode, modifiers: u8) -> KeyAction {
+        self.get(Layer::from_modifiers(modifiers), code)
+    }
+
+    /// Applies the entries of a keymap file on top of this keymap.
+    ///
+    /// On error, the entries before the faulty one have already been applied.
+    pub fn apply(&mut self, layout: &str) -> Result<&mut Self, KeymapError> {
+        for (index, line) in layout.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut tokens = line.split_whitespace();
+            let layer = tokens
+                .next()
+                .and_then(Layer::from_name)
+                .ok_or(KeymapError::UnknownLayer { line: number })?;
+            for token in tokens {
+                let (key, value) = token
+                    .split_once('=')
+                    .ok_or(KeymapError::InvalidEntry { line: number })?;
+                let code =
+                    KeyCode::from_name(key).ok_or(KeymapError::UnknownKey { line: number })?;
+                let action =
+                    parse_value(value).ok_or(KeymapError::InvalidValue { line: number })?;
+                self.set(layer, code, action);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Writes the keys that differ from `Keymap::US` in the keymap file format.
+    pub fn write<W: Write>(&self, w: &mut W) -> fmt::Result {
+        for layer in Layer::ALL {
+            let mut changed = KeyCode::ALL
+                .iter()
+                .filter(|code| self.get(layer, **code) != Self::US.get(layer, **code))
+                .peekable();
+            if changed.peek().is_none() {
+                continue;
+            }
+            w.write_str(layer.name())?;
+            for code in changed {
+                write!(w, " {}=", code.name())?;
+                write_value(w, self.get(layer, *code))?;
+            }
+            w.write_char('\n')?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the value of a keymap file entry.
+fn parse_value(value: &str) -> Option<KeyAction> {
+    if value.is_empty() {
+        return Some(KeyAction::None);
+    }
+    if let Some(accent) = value.strip_prefix('*') {
+        let mut chars = accent.chars();
+        let accent = Accent::from_spacing(chars.next()?)?;
+        return chars.next().is_none().then_some(KeyAction::Dead(accent));
+    }
+    let mut chars = value.chars();
+    let c = match chars.next()? {
+        '\\' => match chars.next()? {
+            's' => SPACE,
+            'n' => ENTER,
+            'b' => BACKSPACE,
+            '*' => '*',
+            '\\' => '\\',
+            'u' => {
+                let (hex, rest) = chars.as_str().strip_prefix('{')?.split_once('}')?;
+                chars = rest.chars();
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)?
+            }
+            _ => return None,
+        },
+        c => c,
+    };
+    chars.next().is_none().then_some(KeyAction::Char(c))
+}
+
+/// Writes the value of a keymap file entry.
+fn write_value<W: Write>(w: &mut W, action: KeyAction) -> fmt::Result {
+    match action {
+        KeyAction::None => Ok(()),
+        KeyAction::Dead(accent) => write!(w, "*{}", accent.spacing()),
+        KeyAction::Char(SPACE) => w.write_str("\\s"),
+        KeyAction::Char(ENTER) => w.write_str("\\n"),
+        KeyAction::Char(BACKSPACE) => w.write_str("\\b"),
+        KeyAction::Char('*') => w.write_str("\\*"),
+        KeyAction::Char('\\') => w.write_str("\\\\"),
+        KeyAction::Char(c) if c.is_control() || c.is_whitespace() => {
+            write!(w, "\\u{{{:x}}}", c as u32)
+        }
+        KeyAction::Char(c) => w.write_char(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::string::String;
+
+    use super::*;
+
+    /// The sample from the module documentation.
+    const GERMAN: &str = "# German
+base y=z z=y
+shift y=Z z=Y
+sym a=ä o=ö u=ü s=ß e=€ dollar=*¨
+";
+
+    #[test]
+    fn parses_layout() {
+        let keymap = Keymap::parse(GERMAN).unwrap();
+        assert_eq!(keymap.get(Layer::Base, KeyCode::Y), KeyAction::Char('z'));
+        assert_eq!(keymap.get(Layer::Shift, KeyCode::Z), KeyAction::Char('Y'));
+        assert_eq!(keymap.get(Layer::Sym, KeyCode::S), KeyAction::Char('ß'));
+        assert_eq!(
+            keymap.get(Layer::Sym, KeyCode::Dollar),
+            KeyAction::Dead(Accent::Diaeresis)
+        );
+        // Keys that are not listed keep their US action.
+        assert_eq!(keymap.get(Layer::Base, KeyCode::Q), KeyAction::Char('q'));
+        assert_eq!(keymap.action(KeyCode::A, MOD_SYM), KeyAction::Char('ä'));
+        assert_eq!(
+            keymap.action(KeyCode::A, MOD_ALT | MOD_SYM),
+            KeyAction::Char('*')
+        );
+    }
+
+    #[test]
+    fn write_round_trips() {
+        let keymap = Keymap::parse(GERMAN).unwrap();
+        let mut layout = String::new();
+        keymap.write(&mut layout).unwrap();
+        // Keys are written in matrix order, layers without changes are left out.
+        assert_eq!(
+            layout,
+            "base y=z z=y\nshift y=Z z=Y\nsym e=€ u=ü o=ö a=ä s=ß dollar=*¨\n"
+        );
+        assert_eq!(Keymap::parse(&layout), Ok(keymap));
+    }
+
+    #[test]
+    fn us_layout_writes_nothing() {
+        let mut layout = String::new();
+        Keymap::US.write(&mut layout).unwrap();
+        assert_eq!(layout, "");
+    }
+
+    #[test]
+    fn parses_escapes() {
+        let keymap =
+            Keymap::parse(r"alt q=\s w=\n e=\b r=\* t=\\ y=\u{e9} u=\u{7} i= o=*´").unwrap();
+        let actions = [
+            (KeyCode::Q, KeyAction::Char(SPACE)),
+            (KeyCode::W, KeyAction::Char(ENTER)),
+            (KeyCode::E, KeyAction::Char(BACKSPACE)),
+            (KeyCode::R, KeyAction::Char('*')),
+            (KeyCode::T, KeyAction::Char('\\')),
+            (KeyCode::Y, KeyAction::Char('é')),
+            (KeyCode::U, KeyAction::Char('\u{7}')),
+            (KeyCode::I, KeyAction::None),
+            (KeyCode::O, KeyAction::Dead(Accent::Acute)),
+        ];
+        for (code, action) in actions {
+            assert_eq!(keymap.get(Layer::Alt, code), action, "{code:?}");
+        }
+
+        let mut layout = String::new();
+        keymap.write(&mut layout).unwrap();
+        assert_eq!(
+            layout,
+            "alt q=\\s w=\\n e=\\b r=\\* t=\\\\ y=é u=\\u{7} i= o=*´\n"
+        );
+        assert_eq!(Keymap::parse(&layout), Ok(keymap));
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        let cases = [
+            ("# comment\nfoo q=a", KeymapError::UnknownLayer { line: 2 }),
+            (
+                "base q=a\n\nbase nope=a",
+                KeymapError::UnknownKey { line: 3 },
+            ),
+            ("base q", KeymapError::InvalidEntry { line: 1 }),
+            ("base q=ab", KeymapError::InvalidValue { line: 1 }),
+            ("base q=*", KeymapError::InvalidValue { line: 1 }),
+            ("base q=*x", KeymapError::InvalidValue { line: 1 }),
+            ("base q=*´´", KeymapError::InvalidValue { line: 1 }),
+            ("base q=\\x", KeymapError::InvalidValue { line: 1 }),
+            ("base q=\\u{zz}", KeymapError::InvalidValue { line: 1 }),
+            ("base q=\\u{e9", KeymapError::InvalidValue { line: 1 }),
+            ("base q=\\u{d800}", KeymapError::InvalidValue { line: 1 }),
+            ("base q=\\u{e9}x", KeymapError::InvalidValue { line: 1 }),
+        ];
+        for (layout, error) in cases {
+            assert_eq!(Keymap::parse(layout), Err(error), "{layout:?}");
+        }
+    }
+
+    #[test]
+    fn apply_keeps_entries_before_an_error() {
+        let mut keymap = Keymap::US;
+        assert_eq!(
+            keymap.apply("base q=x w").map(|_| ()),
+            Err(KeymapError::InvalidEntry { line: 1 })
+        );
+        assert_eq!(keymap.get(Layer::Base, KeyCode::Q), KeyAction::Char('x'));
+        assert_eq!(keymap.get(Layer::Base, KeyCode::W), KeyAction::Char('w'));
+    }
+
+    #[test]
+    fn compose_keeps_case() {
+        assert_eq!(Accent::Acute.compose('e'), Some('é'));
+        assert_eq!(Accent::Acute.compose('E'), Some('É'));
+        assert_eq!(Accent::Diaeresis.compose('U'), Some('Ü'));
+        assert_eq!(Accent::Ring.compose('A'), Some('Å'));
+        assert_eq!(Accent::Caron.compose('Z'), Some('Ž'));
+        assert_eq!(Accent::Circumflex.compose('x'), None);
+        assert_eq!(Accent::Tilde.compose('1'), None);
+    }
+
+    #[test]
+    fn accents_round_trip_through_spacing() {
+        for accent in Accent::ALL {
+            assert_eq!(Accent::from_spacing(accent.spacing()), Some(accent));
+        }
+        assert_eq!(Accent::from_spacing('a'), None);
+    }
+}
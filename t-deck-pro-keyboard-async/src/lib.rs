@@ -1,15 +1,19 @@
 //! An asynchronous, `no_std` driver for the T-Deck's keyboard.
 //!
 //! This driver provides a `KeyboardController` to interact with the TCA8418 I2C
-//! keyboard scanner IC. It allows for initializing the keyboard and reading key
-//! press and release events.
+//! keyboard scanner IC. It allows for initializing the keyboard and reading every
+//! key press and release as a physical `KeyCode`. The `text` module translates
+//! events into characters using a `keymap::Keymap`, which can be loaded at runtime
+//! to support other layouts and dead keys.
 //!
 //! # Usage
 //!
 //! To use this driver, you need an I2C peripheral implementation that satisfies the
-//! `embedded-hal-async::i2c::I2c` trait, along with the interrupt and reset GPIO pins.
+//! `embedded-hal-async::i2c::I2c` trait, an interrupt pin implementing the
+//! `embedded-hal-async` `Wait` and `embedded-hal` `InputPin` traits, and optionally an
+//! `embedded-hal` `OutputPin` as the reset pin.
 //!
-//! ```no_run
+//! ```ignore
 //! # #![no_std]
 //! # #![no_main]
 //! # use esp_hal::prelude::*;
@@ -36,7 +40,7 @@
 //!         .into_async();
 //!
 //!     // Create and initialize the keyboard controller
-//!     let mut keyboard_controller = KeyboardController::new(i2c, keyboard_int, None);
+//!     let mut keyboard_controller = KeyboardController::without_reset(i2c, keyboard_int);
 //!     keyboard_controller.init().await.unwrap();
 //!
 //!     // Spawn a task to read key events
@@ -44,7 +48,7 @@
 //! }
 //!
 //! #[embassy_executor::task]
-//! async fn read_keys(mut keyboard_controller: KeyboardController<'static, I2c<'static, esp_hal::Async>, esp_hal::i2c::master::Error>) {
+//! async fn read_keys(mut keyboard_controller: KeyboardController<I2c<'static, esp_hal::Async>, Input<'static>>) {
 //!     loop {
 //!         if let Ok(events) = keyboard_controller.read_key_events().await {
 //!             for event in events {
@@ -58,3 +62,9 @@
 #![no_std]
 
 pub mod keyboard;
+pub mod keymap;
+pub mod modifiers;
+pub mod text;
+
+#[cfg(test)]
+mod mock;
@@ -0,0 +1,184 @@
+//! Test doubles for the TCA8418 I2C bus, its INT pin and the reset pin.
+
+extern crate std;
+
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+use embedded_hal::digital::{self, InputPin, OutputPin};
+use embedded_hal_async::digital::Wait;
+use embedded_hal_async::i2c::{self, I2c, Operation, SevenBitAddress};
+
+use crate::keyboard::{KeyCode, COLS, I2C_ADDRESS, REG_INT_STAT, REG_KEY_EVENT_A, REG_KEY_LCK_EC};
+
+/// Returns the raw KEY_EVENT value the TCA8418 reports for `code`.
+pub(crate) fn raw_event(code: KeyCode, down: bool) -> u8 {
+    let key = code.row() * COLS + (COLS - 1 - code.col()) + 1;
+    if down {
+        key | 0x80
+    } else {
+        key
+    }
+}
+
+/// A TCA8418 with a key event FIFO that records every register write.
+#[derive(Debug, Default)]
+pub(crate) struct MockTca8418 {
+    pub(crate) int_stat: u8,
+    pub(crate) fifo: VecDeque<u8>,
+    /// Events that arrive in the FIFO right after KEY_LCK_EC was read, i.e. while
+    /// the driver is still draining it.
+    pub(crate) late: Vec<u8>,
+    pub(crate) writes: Vec<(u8, u8)>,
+}
+
+impl MockTca8418 {
+    /// Creates a TCA8418 with a pending key event interrupt for `events`.
+    pub(crate) fn with_events(events: &[u8]) -> Self {
+        Self {
+            int_stat: 0x01,
+            fifo: events.iter().copied().collect(),
+            ..Self::default()
+        }
+    }
+
+    fn read_reg(&mut self, reg: u8) -> Result<u8, i2c::ErrorKind> {
+        match reg {
+            REG_INT_STAT => Ok(self.int_stat),
+            REG_KEY_LCK_EC => {
+                let count = self.fifo.len() as u8;
+                self.fifo.extend(self.late.drain(..));
+                Ok(count)
+            }
+            // An empty FIFO reads as 0.
+            REG_KEY_EVENT_A => Ok(self.fifo.pop_front().unwrap_or(0)),
+            _ => Err(i2c::ErrorKind::Other),
+        }
+    }
+
+    fn write_reg(&mut self, reg: u8, value: u8) {
+        if reg == REG_INT_STAT {
+            // Write 1 to clear.
+            self.int_stat &= !value;
+        }
+        self.writes.push((reg, value));
+    }
+}
+
+impl i2c::ErrorType for MockTca8418 {
+    type Error = i2c::ErrorKind;
+}
+
+impl I2c<SevenBitAddress> for MockTca8418 {
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if address != I2C_ADDRESS {
+            return Err(i2c::ErrorKind::NoAcknowledge(
+                i2c::NoAcknowledgeSource::Address,
+            ));
+        }
+        // The first written byte selects the register, following bytes are written
+        // to it. Only single byte reads are used by the driver.
+        let mut pointer = None;
+        for operation in operations {
+            match operation {
+                Operation::Write(bytes) => {
+                    for &byte in bytes.iter() {
+                        match pointer {
+                            None => pointer = Some(byte),
+                            Some(reg) => self.write_reg(reg, byte),
+                        }
+                    }
+                }
+                Operation::Read(buf) => {
+                    let reg = pointer.ok_or(i2c::ErrorKind::Other)?;
+                    for byte in buf.iter_mut() {
+                        *byte = self.read_reg(reg)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An INT pin that delivers a fixed number of pulses and then fails, so a test
+/// waiting for a pulse that never comes ends instead of hanging. With `idle` set it
+/// waits forever instead, for tests that rely on a timeout.
+#[derive(Debug, Default)]
+pub(crate) struct MockInt {
+    pub(crate) pulses: usize,
+    pub(crate) idle: bool,
+}
+
+impl MockInt {
+    async fn pulse(&mut self) -> Result<(), digital::ErrorKind> {
+        match self.pulses.checked_sub(1) {
+            Some(pulses) => self.pulses = pulses,
+            None if self.idle => core::future::pending().await,
+            None => return Err(digital::ErrorKind::Other),
+        }
+        Ok(())
+    }
+}
+
+impl digital::ErrorType for MockInt {
+    type Error = digital::ErrorKind;
+}
+
+impl InputPin for MockInt {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.pulses == 0)
+    }
+
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        Ok(self.pulses > 0)
+    }
+}
+
+impl Wait for MockInt {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        self.pulse().await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        self.pulse().await
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.pulse().await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.pulse().await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        self.pulse().await
+    }
+}
+
+/// A reset pin that records the levels it was set to, `true` for high.
+#[derive(Debug, Default)]
+pub(crate) struct MockReset {
+    pub(crate) levels: Vec<bool>,
+}
+
+impl digital::ErrorType for MockReset {
+    type Error = digital::ErrorKind;
+}
+
+impl OutputPin for MockReset {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.levels.push(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.levels.push(true);
+        Ok(())
+    }
+}
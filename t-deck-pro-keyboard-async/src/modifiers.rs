@@ -0,0 +1,429 @@
+//! Sticky and locking modifiers.
+//!
+//! Holding shift while typing on the small keyboard is awkward, so each modifier can
+//! be configured to latch: tapping it on its own applies it to the next key only
+//! (one-shot), and tapping it twice within `StickyConfig::double_tap` locks it until
+//! it is tapped again, like caps lock. Using a modifier as a chord, i.e. pressing
+//! another key while holding it, never latches it.
+
+use embassy_time::{Duration, Instant};
+
+use crate::keyboard::{KeyCode, KeyState, MOD_ALT, MOD_L_SHIFT, MOD_R_SHIFT, MOD_SYM};
+
+/// A modifier that can be configured to latch. Both shift keys share one modifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    /// Either shift key.
+    Shift,
+    /// The ALT key.
+    Alt,
+    /// The SYM key.
+    Sym,
+}
+
+impl Modifier {
+    /// All modifiers.
+    pub const ALL: [Modifier; 3] = [Modifier::Shift, Modifier::Alt, Modifier::Sym];
+
+    /// Returns the modifier of a key, or `None` if it is not a modifier key.
+    pub fn from_key(code: KeyCode) -> Option<Self> {
+        match code {
+            KeyCode::LeftShift | KeyCode::RightShift => Some(Modifier::Shift),
+            KeyCode::Alt => Some(Modifier::Alt),
+            KeyCode::Sym => Some(Modifier::Sym),
+            _ => None,
+        }
+    }
+
+    /// Returns the modifier bits (e.g. `MOD_ALT`) belonging to the modifier.
+    pub const fn mask(self) -> u8 {
+        match self {
+            Modifier::Shift => MOD_L_SHIFT | MOD_R_SHIFT,
+            Modifier::Alt => MOD_ALT,
+            Modifier::Sym => MOD_SYM,
+        }
+    }
+}
+
+/// How a modifier reacts to being tapped on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ModifierConfig {
+    /// A tap applies the modifier to the next key.
+    pub one_shot: bool,
+    /// A double tap locks the modifier until the next tap.
+    pub lock: bool,
+}
+
+impl ModifierConfig {
+    /// The modifier only applies while it is held.
+    pub const HELD: Self = Self {
+        one_shot: false,
+        lock: false,
+    };
+    /// A tap applies the modifier to the next key.
+    pub const ONE_SHOT: Self = Self {
+        one_shot: true,
+        lock: false,
+    };
+    /// A tap applies the modifier to the next key, a double tap locks it.
+    pub const STICKY: Self = Self {
+        one_shot: true,
+        lock: true,
+    };
+}
+
+/// The latching behaviour of all modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StickyConfig {
+    /// The behaviour of the shift keys.
+    pub shift: ModifierConfig,
+    /// The behaviour of the ALT key.
+    pub alt: ModifierConfig,
+    /// The behaviour of the SYM key.
+    pub sym: ModifierConfig,
+    /// The longest time between two taps that still counts as a double tap.
+    pub double_tap: Duration,
+}
+
+impl Default for StickyConfig {
+    /// All modifiers only apply while held, as on a regular keyboard.
+    fn default() -> Self {
+        Self {
+            shift: ModifierConfig::HELD,
+            alt: ModifierConfig::HELD,
+            sym: ModifierConfig::HELD,
+            double_tap: Duration::from_millis(400),
+        }
+    }
+}
+
+impl StickyConfig {
+    /// All modifiers are one-shot and lock on a double tap.
+    pub fn sticky() -> Self {
+        Self {
+            shift: ModifierConfig::STICKY,
+            alt: ModifierConfig::STICKY,
+            sym: ModifierConfig::STICKY,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the behaviour of `modifier`.
+    pub fn get(&self, modifier: Modifier) -> ModifierConfig {
+        match modifier {
+            Modifier::Shift => self.shift,
+            Modifier::Alt => self.alt,
+            Modifier::Sym => self.sym,
+        }
+    }
+
+    /// Sets the behaviour of `modifier`.
+    pub fn set(&mut self, modifier: Modifier, config: ModifierConfig) -> &mut Self {
+        match modifier {
+            Modifier::Shift => self.shift = config,
+            Modifier::Alt => self.alt = config,
+            Modifier::Sym => self.sym = config,
+        }
+        self
+    }
+}
+
+/// The state of a modifier, e.g. for a status bar indicator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModifierState {
+    /// Not active.
+    Off,
+    /// Active while the key is held.
+    Held,
+    /// Latched for the next key.
+    OneShot,
+    /// Locked until tapped again.
+    Locked,
+}
+
+/// The tracked state of a single modifier.
+#[derive(Debug, Clone, Copy)]
+struct Slot {
+    /// `ModifierState::OneShot`, `ModifierState::Locked` or `ModifierState::Off`.
+    latch: ModifierState,
+    /// The modifier bit of the key that latched the modifier.
+    latch_mask: u8,
+    down: bool,
+    /// Another key was pressed while the modifier was held.
+    chorded: bool,
+    last_tap: Option<Instant>,
+}
+
+impl Slot {
+    const IDLE: Self = Self {
+        latch: ModifierState::Off,
+        latch_mask: 0,
+        down: false,
+        chorded: false,
+        last_tap: None,
+    };
+}
+
+/// Tracks taps, one-shot and locked modifiers.
+#[derive(Debug, Clone)]
+pub(crate) struct ModifierTracker {
+    config: StickyConfig,
+    slots: [Slot; 3],
+}
+
+impl ModifierTracker {
+    pub(crate) fn new(config: StickyConfig) -> Self {
+        Self {
+            config,
+            slots: [Slot::IDLE; 3],
+        }
+    }
+
+    pub(crate) fn config(&self) -> &StickyConfig {
+        &self.config
+    }
+
+    /// Changes the configuration. Latches of modifiers that can no longer latch are
+    /// released.
+    pub(crate) fn set_config(&mut self, config: StickyConfig) {
+        self.config = config;
+        for modifier in Modifier::ALL {
+            let config = config.get(modifier);
+            let slot = &mut self.slots[modifier as usize];
+            let keep = match slot.latch {
+                ModifierState::OneShot => config.one_shot,
+                ModifierState::Locked => config.lock,
+                _ => true,
+            };
+            if !keep {
+                slot.latch = ModifierState::Off;
+            }
+        }
+    }
+
+    /// Returns the state of `modifier`.
+    pub(crate) fn state(&self, modifier: Modifier) -> ModifierState {
+        let slot = &self.slots[modifier as usize];
+        match slot.latch {
+            ModifierState::Off if slot.down => ModifierState::Held,
+            latch => latch,
+        }
+    }
+
+    /// Returns the modifier bits of latched and locked modifiers.
+    pub(crate) fn latched(&self) -> u8 {
+        self.slots
+            .iter()
+            .filter(|slot| slot.latch != ModifierState::Off)
+            .fold(0, |acc, slot| acc | slot.latch_mask)
+    }
+
+    /// Releases all latched and locked modifiers.
+    pub(crate) fn clear(&mut self) {
+        for slot in &mut self.slots {
+            slot.latch = ModifierState::Off;
+            slot.last_tap = None;
+        }
+    }
+
+    /// Forgets which modifier keys are held, e.g. after events were lost.
+    pub(crate) fn release_all(&mut self) {
+        for slot in &mut self.slots {
+            slot.down = false;
+        }
+    }
+
+    /// Updates the modifiers with a key event. Must be called before the event's
+    /// modifiers are computed; call `consume` afterwards.
+    pub(crate) fn key_event(&mut self, code: KeyCode, state: KeyState, now: Instant) {
+        let Some(modifier) = Modifier::from_key(code) else {
+            if state == KeyState::Down {
+                for slot in self.slots.iter_mut().filter(|slot| slot.down) {
+                    slot.chorded = true;
+                }
+            }
+            return;
+        };
+        let config = self.config.get(modifier);
+        let double_tap = self.config.double_tap;
+        let slot = &mut self.slots[modifier as usize];
+        match state {
+            KeyState::Down => {
+                slot.down = true;
+                slot.chorded = false;
+            }
+            KeyState::Up if slot.chorded => slot.down = false,
+            KeyState::Up => {
+                slot.down = false;
+                let double = slot
+                    .last_tap
+                    .is_some_and(|last| now.saturating_duration_since(last) <= double_tap);
+                slot.last_tap = Some(now);
+                slot.latch = match slot.latch {
+                    ModifierState::Locked => ModifierState::Off,
+                    _ if double && config.lock => {
+                        slot.last_tap = None;
+                        ModifierState::Locked
+                    }
+                    ModifierState::OneShot => ModifierState::Off,
+                    _ if config.one_shot => ModifierState::OneShot,
+                    _ => ModifierState::Off,
+                };
+                slot.latch_mask = code.modifier();
+            }
+            KeyState::Repeat => {}
+        }
+    }
+
+    /// Releases one-shot modifiers after a key event has been reported with them.
+    pub(crate) fn consume(&mut self, code: KeyCode, state: KeyState) {
+        if state != KeyState::Down || Modifier::from_key(code).is_some() {
+            return;
+        }
+        for slot in &mut self.slots {
+            if slot.latch == ModifierState::OneShot {
+                slot.latch = ModifierState::Off;
+            }
+            slot.last_tap = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(ms: u64) -> Instant {
+        Instant::from_millis(ms)
+    }
+
+    /// Presses and releases a modifier key at `ms`.
+    fn tap(tracker: &mut ModifierTracker, code: KeyCode, ms: u64) {
+        tracker.key_event(code, KeyState::Down, at(ms));
+        tracker.key_event(code, KeyState::Up, at(ms + 50));
+    }
+
+    /// Presses a regular key at `ms`, as the controller reports it.
+    fn press(tracker: &mut ModifierTracker, code: KeyCode, ms: u64) {
+        tracker.key_event(code, KeyState::Down, at(ms));
+        tracker.consume(code, KeyState::Down);
+    }
+
+    #[test]
+    fn held_modifiers_never_latch() {
+        let mut tracker = ModifierTracker::new(StickyConfig::default());
+        tracker.key_event(KeyCode::Alt, KeyState::Down, at(0));
+        assert_eq!(tracker.state(Modifier::Alt), ModifierState::Held);
+        tracker.key_event(KeyCode::Alt, KeyState::Up, at(50));
+        tap(&mut tracker, KeyCode::Alt, 100);
+        assert_eq!(tracker.state(Modifier::Alt), ModifierState::Off);
+        assert_eq!(tracker.latched(), 0);
+    }
+
+    #[test]
+    fn one_shot_applies_to_next_key() {
+        let mut tracker = ModifierTracker::new(StickyConfig::sticky());
+        tap(&mut tracker, KeyCode::RightShift, 0);
+        assert_eq!(tracker.state(Modifier::Shift), ModifierState::OneShot);
+        // Only the shift key that was tapped is reported.
+        assert_eq!(tracker.latched(), MOD_R_SHIFT);
+
+        // Releases and modifier keys do not use it up.
+        tracker.consume(KeyCode::A, KeyState::Up);
+        tracker.consume(KeyCode::Alt, KeyState::Down);
+        assert_eq!(tracker.latched(), MOD_R_SHIFT);
+
+        press(&mut tracker, KeyCode::A, 1000);
+        assert_eq!(tracker.state(Modifier::Shift), ModifierState::Off);
+        assert_eq!(tracker.latched(), 0);
+    }
+
+    #[test]
+    fn second_tap_releases_one_shot() {
+        let mut tracker = ModifierTracker::new(StickyConfig::sticky());
+        tap(&mut tracker, KeyCode::Sym, 0);
+        // Too late for a double tap.
+        tap(&mut tracker, KeyCode::Sym, 1000);
+        assert_eq!(tracker.state(Modifier::Sym), ModifierState::Off);
+    }
+
+    #[test]
+    fn double_tap_locks_and_tap_unlocks() {
+        let mut tracker = ModifierTracker::new(StickyConfig::sticky());
+        tap(&mut tracker, KeyCode::LeftShift, 0);
+        tap(&mut tracker, KeyCode::LeftShift, 300);
+        assert_eq!(tracker.state(Modifier::Shift), ModifierState::Locked);
+        assert_eq!(tracker.latched(), MOD_L_SHIFT);
+
+        // A locked modifier survives other keys.
+        press(&mut tracker, KeyCode::A, 1000);
+        press(&mut tracker, KeyCode::B, 1100);
+        assert_eq!(tracker.state(Modifier::Shift), ModifierState::Locked);
+
+        tap(&mut tracker, KeyCode::LeftShift, 2000);
+        assert_eq!(tracker.state(Modifier::Shift), ModifierState::Off);
+        assert_eq!(tracker.latched(), 0);
+    }
+
+    #[test]
+    fn double_tap_without_lock_stays_one_shot() {
+        let mut config = StickyConfig::default();
+        config.set(Modifier::Alt, ModifierConfig::ONE_SHOT);
+        let mut tracker = ModifierTracker::new(config);
+        tap(&mut tracker, KeyCode::Alt, 0);
+        tap(&mut tracker, KeyCode::Alt, 100);
+        assert_eq!(tracker.state(Modifier::Alt), ModifierState::Off);
+        tap(&mut tracker, KeyCode::Alt, 200);
+        assert_eq!(tracker.state(Modifier::Alt), ModifierState::OneShot);
+    }
+
+    #[test]
+    fn chorded_modifier_does_not_latch() {
+        let mut tracker = ModifierTracker::new(StickyConfig::sticky());
+        tracker.key_event(KeyCode::LeftShift, KeyState::Down, at(0));
+        press(&mut tracker, KeyCode::A, 50);
+        tracker.key_event(KeyCode::LeftShift, KeyState::Up, at(100));
+        assert_eq!(tracker.state(Modifier::Shift), ModifierState::Off);
+
+        // Nor does the chord count as the first tap of a double tap.
+        tap(&mut tracker, KeyCode::LeftShift, 200);
+        assert_eq!(tracker.state(Modifier::Shift), ModifierState::OneShot);
+    }
+
+    #[test]
+    fn set_config_drops_disallowed_latches() {
+        let mut tracker = ModifierTracker::new(StickyConfig::sticky());
+        tap(&mut tracker, KeyCode::LeftShift, 0);
+        tap(&mut tracker, KeyCode::LeftShift, 100);
+        tap(&mut tracker, KeyCode::Alt, 200);
+        tap(&mut tracker, KeyCode::Sym, 300);
+
+        let mut config = StickyConfig::sticky();
+        config
+            .set(Modifier::Shift, ModifierConfig::ONE_SHOT)
+            .set(Modifier::Alt, ModifierConfig::HELD);
+        tracker.set_config(config);
+        assert_eq!(tracker.config(), &config);
+        assert_eq!(tracker.state(Modifier::Shift), ModifierState::Off);
+        assert_eq!(tracker.state(Modifier::Alt), ModifierState::Off);
+        assert_eq!(tracker.state(Modifier::Sym), ModifierState::OneShot);
+        assert_eq!(tracker.latched(), MOD_SYM);
+    }
+
+    #[test]
+    fn clear_releases_latches() {
+        let mut tracker = ModifierTracker::new(StickyConfig::sticky());
+        tap(&mut tracker, KeyCode::Alt, 0);
+        tap(&mut tracker, KeyCode::Alt, 100);
+        tap(&mut tracker, KeyCode::Sym, 200);
+        tracker.clear();
+        assert_eq!(tracker.latched(), 0);
+        for modifier in Modifier::ALL {
+            assert_eq!(tracker.state(modifier), ModifierState::Off);
+        }
+
+        // The tap before `clear` does not start a double tap.
+        tap(&mut tracker, KeyCode::Sym, 300);
+        assert_eq!(tracker.state(Modifier::Sym), ModifierState::OneShot);
+    }
+}
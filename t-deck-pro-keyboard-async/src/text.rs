@@ -0,0 +1,176 @@
+//! Translation of key events into text.
+//!
+//! The driver reports physical keys; this layer maps them to characters with a
+//! `Keymap`. `translate` uses the layout printed on the keyboard, while `TextInput`
+//! takes any keymap and composes dead keys with the following character.
+
+use crate::keyboard::{KeyEvent, KeyState, SPACE};
+use crate::keymap::{Accent, KeyAction, Keymap};
+
+/// Returns the character typed by `event` with `Keymap::US`, or `None` for key
+/// releases and modifier keys. Repeats type the character again.
+pub fn translate(event: &KeyEvent) -> Option<char> {
+    match (event.state, Keymap::US.action(event.code, event.modifiers)) {
+        (KeyState::Down | KeyState::Repeat, KeyAction::Char(c)) => Some(c),
+        _ => None,
+    }
+}
+
+/// Turns key events into text with a configurable keymap and dead keys.
+#[derive(Debug, Clone, Default)]
+pub struct TextInput {
+    keymap: Keymap,
+    pending: Option<Accent>,
+}
+
+impl TextInput {
+    /// Creates a new `TextInput` using `keymap`.
+    pub fn new(keymap: Keymap) -> Self {
+        Self {
+            keymap,
+            pending: None,
+        }
+    }
+
+    /// Returns the keymap in use.
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    /// Replaces the keymap and drops a pending dead key.
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+        self.pending = None;
+    }
+
+    /// Returns the accent of a dead key waiting for the next character.
+    pub fn pending(&self) -> Option<Accent> {
+        self.pending
+    }
+
+    /// Returns the character typed by `event`, if any.
+    ///
+    /// A dead key types nothing, but accents the next character. If that character
+    /// cannot be accented it is typed as is; space or the same dead key again types
+    /// the accent on its own. Repeats type the character again.
+    pub fn translate(&mut self, event: &KeyEvent) -> Option<char> {
+        let action = self.keymap.action(event.code, event.modifiers);
+        match (event.state, action) {
+            (KeyState::Up, _) | (_, KeyAction::None) => None,
+            // Dead keys were handled by the original press.
+            (KeyState::Repeat, KeyAction::Dead(_)) => None,
+            (KeyState::Repeat, KeyAction::Char(c)) => Some(c),
+            (KeyState::Down, KeyAction::Dead(accent)) if self.pending == Some(accent) => {
+                self.pending = None;
+                Some(accent.spacing())
+            }
+            (KeyState::Down, KeyAction::Dead(accent)) => {
+                self.pending = Some(accent);
+                None
+            }
+            (KeyState::Down, KeyAction::Char(c)) => match self.pending.take() {
+                Some(accent) if c == SPACE => Some(accent.spacing()),
+                Some(accent) => Some(accent.compose(c).unwrap_or(c)),
+                None => Some(c),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keyboard::{KeyCode, MOD_L_SHIFT};
+
+    fn event(code: KeyCode, state: KeyState, modifiers: u8) -> KeyEvent {
+        KeyEvent {
+            code,
+            row: code.row(),
+            col: code.col(),
+            state,
+            modifiers,
+        }
+    }
+
+    fn down(code: KeyCode) -> KeyEvent {
+        event(code, KeyState::Down, 0)
+    }
+
+    /// The US layout with an acute dead key on `$`.
+    fn input() -> TextInput {
+        TextInput::new(Keymap::parse("base dollar=*´").unwrap())
+    }
+
+    #[test]
+    fn translates_us_layout() {
+        assert_eq!(translate(&down(KeyCode::A)), Some('a'));
+        assert_eq!(
+            translate(&event(KeyCode::A, KeyState::Down, MOD_L_SHIFT)),
+            Some('A')
+        );
+        assert_eq!(
+            translate(&event(KeyCode::A, KeyState::Repeat, 0)),
+            Some('a')
+        );
+        assert_eq!(translate(&event(KeyCode::A, KeyState::Up, 0)), None);
+        assert_eq!(translate(&down(KeyCode::LeftShift)), None);
+    }
+
+    #[test]
+    fn dead_key_accents_next_letter() {
+        let mut input = input();
+        assert_eq!(input.translate(&down(KeyCode::Dollar)), None);
+        assert_eq!(input.pending(), Some(Accent::Acute));
+        // Neither the release nor a repeat of the dead key types anything.
+        assert_eq!(
+            input.translate(&event(KeyCode::Dollar, KeyState::Repeat, 0)),
+            None
+        );
+        assert_eq!(
+            input.translate(&event(KeyCode::Dollar, KeyState::Up, 0)),
+            None
+        );
+        assert_eq!(input.translate(&down(KeyCode::E)), Some('é'));
+        assert_eq!(input.pending(), None);
+        assert_eq!(input.translate(&down(KeyCode::E)), Some('e'));
+
+        input.translate(&down(KeyCode::Dollar));
+        assert_eq!(
+            input.translate(&event(KeyCode::E, KeyState::Down, MOD_L_SHIFT)),
+            Some('É')
+        );
+    }
+
+    #[test]
+    fn dead_key_passes_through_letters_without_accent() {
+        let mut input = input();
+        input.translate(&down(KeyCode::Dollar));
+        assert_eq!(input.translate(&down(KeyCode::Q)), Some('q'));
+        assert_eq!(input.pending(), None);
+    }
+
+    #[test]
+    fn dead_key_then_space_types_accent() {
+        let mut input = input();
+        input.translate(&down(KeyCode::Dollar));
+        assert_eq!(input.translate(&down(KeyCode::Space)), Some('´'));
+        assert_eq!(input.pending(), None);
+    }
+
+    #[test]
+    fn dead_key_twice_types_accent() {
+        let mut input = input();
+        input.translate(&down(KeyCode::Dollar));
+        assert_eq!(input.translate(&down(KeyCode::Dollar)), Some('´'));
+        assert_eq!(input.pending(), None);
+    }
+
+    #[test]
+    fn set_keymap_drops_pending_accent() {
+        let mut input = input();
+        input.translate(&down(KeyCode::Dollar));
+        input.set_keymap(Keymap::US);
+        assert_eq!(input.pending(), None);
+        assert_eq!(input.translate(&down(KeyCode::E)), Some('e'));
+    }
+}